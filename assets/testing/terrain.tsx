@@ -0,0 +1,24 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" tiledversion="1.10.2" name="terrain" tilewidth="16" tileheight="16" tilecount="8" columns="4">
+ <image source="tiny.png" width="64" height="32"/>
+ <tile id="1" probability="3"/>
+ <tile id="2" probability="0">
+  <properties>
+   <property name="cracked" type="bool" value="true"/>
+  </properties>
+ </tile>
+ <tile id="6" type="floor"/>
+ <tile id="7" type="floor" probability="2"/>
+ <wangsets>
+  <wangset name="ground" type="corner" tile="-1">
+   <wangcolor name="grass" color="#00ff00" tile="-1" probability="1"/>
+   <wangcolor name="dirt" color="#804000" tile="-1" probability="1"/>
+   <wangtile tileid="0" wangid="0,1,0,1,0,1,0,1"/>
+   <wangtile tileid="1" wangid="0,1,0,1,0,1,0,1"/>
+   <wangtile tileid="2" wangid="0,1,0,1,0,1,0,1"/>
+   <wangtile tileid="3" wangid="0,2,0,2,0,2,0,2"/>
+   <wangtile tileid="4" wangid="0,2,0,1,0,1,0,2"/>
+   <wangtile tileid="5" wangid="0,2,0,2,0,2,0,2"/>
+  </wangset>
+ </wangsets>
+</tileset>
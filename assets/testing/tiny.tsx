@@ -0,0 +1,27 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" tiledversion="1.10.2" name="tiny" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <image source="tiny.png" width="32" height="32"/>
+ <tile id="0">
+  <animation>
+   <frame tileid="0" duration="100"/>
+   <frame tileid="1" duration="100"/>
+  </animation>
+ </tile>
+ <tile id="2" type="wall">
+  <properties>
+   <property name="material" value="stone"/>
+   <property name="solid" type="bool" value="true"/>
+  </properties>
+  <objectgroup draworder="index" id="2">
+   <object id="1" x="0" y="8" width="16" height="8"/>
+   <object id="2" x="0" y="0">
+    <polygon points="0,0 16,0 0,8"/>
+   </object>
+  </objectgroup>
+ </tile>
+ <tile id="3" type="casts_shadow">
+  <properties>
+   <property name="night_tile" type="int" value="1"/>
+  </properties>
+ </tile>
+</tileset>
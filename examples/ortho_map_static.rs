@@ -1,5 +1,7 @@
 use std::path::Path;
 
+use coarsetime::Instant;
+
 use macroquad::color::LIGHTGRAY;
 use macroquad::input::{is_key_down, is_key_pressed, KeyCode};
 use macroquad::math::{Rect, vec2};
@@ -11,7 +13,7 @@ use macroquad_tiled_redux::{Map};
 #[macroquad::main("Texture")]
 async fn main() {
 
-    let tilemap = Map::new_async(Path::new("assets/grass/map1.tmx"))
+    let mut tilemap = Map::new_async(Path::new("assets/grass/map1.tmx"))
         .await
         .expect("Error loading map");
 
@@ -38,6 +40,9 @@ async fn main() {
             screen_height(),
         );
 
+        tilemap.update(Instant::now());
+        tilemap.draw_background(screen);
+
         let mut source = screen;
         let mut dest = screen;
 
@@ -47,12 +52,19 @@ async fn main() {
 
         dest.scale(zoom, zoom);
         for (i, _layer) in tilemap.map.layers().enumerate() {
-            tilemap.draw_tiles(i, dest, Some(source));
+            tilemap.draw_tiles_px(i, dest, Some(source));
         }
 
         if is_key_down(KeyCode::Q) {
             break;
         }
+        if is_key_pressed(KeyCode::P) {
+            if tilemap.clock.is_paused() {
+                tilemap.resume_animations();
+            } else {
+                tilemap.pause_animations();
+            }
+        }
         if is_key_pressed(KeyCode::KpAdd) || is_key_down(KeyCode::Key9) {
             zoom *= 2.0;
         }
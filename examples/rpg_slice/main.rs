@@ -0,0 +1,361 @@
+//! A slice of a top-down RPG built only on the crate: a spawn point and triggers from
+//! the objects of the map, collisions from tile properties, a character walking tile by
+//! tile, drawn between the rows of the props layer, a camera following it, and saves.
+//!
+//! Arrows walk, F5 saves, F9 loads, Q quits.
+//! Run with `cargo run --example rpg_slice --features ron`.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use coarsetime::{Duration, Instant};
+use macroquad::color::{Color, BLACK, WHITE};
+use macroquad::input::{is_key_down, is_key_pressed, KeyCode};
+use macroquad::math::{ivec2, vec2, IVec2, Rect, Vec2};
+use macroquad::shapes::draw_rectangle;
+use macroquad::text::draw_text;
+use macroquad::window::{clear_background, next_frame, screen_height, screen_width};
+use serde::{Deserialize, Serialize};
+use tiled::{LayerType, Loader, ObjectShape, PropertyValue};
+
+use macroquad_tiled_redux::collision::{CollisionGrid, SOLID_PROPERTY};
+use macroquad_tiled_redux::events::{MapEvent, TRIGGER_CLASS};
+use macroquad_tiled_redux::prelude::*;
+use macroquad_tiled_redux::properties::PropertiesExt;
+use macroquad_tiled_redux::rng::MapRng;
+use macroquad_tiled_redux::stable_ids::MapManifest;
+
+const MAP_PATH: &str = "assets/rpg_slice/village.tmx";
+const CHARACTER_PATH: &str = "assets/uLPC-drake.tsx";
+const SAVE_PATH: &str = "rpg_slice.sav.ron";
+/// Bool tile property of the grass tileset, the other tiles block.
+const WALKABLE_PROPERTY: &str = "walkable";
+/// How long a message stays on the screen.
+const MESSAGE_SECS: u64 = 3;
+/// The entity id of the character, for trigger zones.
+const PLAYER: u64 = 0;
+
+/// An object of class "trigger": walking into it shows its "message", and places
+/// its "tile", if any, on the props layer, once.
+struct Trigger {
+    cell: IVec2,
+    message: String,
+    tile: Option<u32>,
+    /// The props tile before it fired, put back by loading a save from before.
+    original: Option<TileHandle>,
+}
+
+/// What the save file holds. Object ids and layer names are checked against the map
+/// on load, so that an updated map doesn't misplace old saves.
+#[derive(Serialize, Deserialize)]
+struct SaveGame {
+    manifest: MapManifest,
+    position: (i32, i32),
+    facing: String,
+    fired: Vec<u32>,
+    rng: MapRng,
+}
+
+struct Game {
+    map: Map,
+    props: usize,
+    grid: CollisionGrid,
+    /// By object id.
+    triggers: HashMap<u32, Trigger>,
+    fired: Vec<u32>,
+    character: TileSet,
+    animations: AnimationRegistry,
+    controller: AnimationController,
+    /// In world tiles, where the character is or is walking to.
+    position: IVec2,
+    camera: PixelCamera,
+    message: Option<(String, Instant)>,
+}
+
+impl Game {
+    async fn load() -> Self {
+        let mut map = Map::new_async(Path::new(MAP_PATH))
+            .await
+            .expect("Error loading map");
+        // The grass tileset marks what can be walked on, the collision grid wants
+        // what can't: mark the other tiles solid at runtime.
+        map.for_tiles_matching(|map, layer, pos, _| {
+            !map.tile_bool_at(layer, pos, WALKABLE_PROPERTY)
+                .unwrap_or(false)
+        })
+        .set_runtime_property(SOLID_PROPERTY, PropertyValue::BoolValue(true));
+        let grid = map.collision_grid();
+        let props = map.layer_by_name("props").expect("No props layer");
+        map.enable_events();
+
+        let (spawn, triggers) = read_objects(&map, props);
+
+        let tileset = Loader::new()
+            .load_tsx_tileset(Path::new(CHARACTER_PATH))
+            .expect("Error loading the character");
+        let character = TileSet::new_async(tileset)
+            .await
+            .expect("Error loading the character image");
+        let animations = AnimationRegistry::load(&character.tileset);
+
+        let mut controller = AnimationController::new();
+        for (facing, name) in [
+            (Facing::North, "walk-n"),
+            (Facing::East, "walk-e"),
+            (Facing::South, "walk-s"),
+            (Facing::West, "walk-w"),
+        ] {
+            if let Some(tile_id) = animations.get_animation_id(name) {
+                controller.set_facing_fallback(facing, tile_id);
+            }
+        }
+        let position = map.world_px_to_tile(spawn);
+        let start = map.tile_to_world_px(position);
+        controller.set_position(Instant::now(), (start.x, start.y));
+
+        Self {
+            map,
+            props,
+            grid,
+            triggers,
+            fired: vec![],
+            character,
+            animations,
+            controller,
+            position,
+            camera: PixelCamera::new(start, 3.0),
+            message: None,
+        }
+    }
+
+    fn update(&mut self, now: Instant) {
+        self.map.update(now);
+        let frame = self.controller.update(now);
+        // The camera follows the character, smoothly as it walks.
+        if let Some(frame) = frame {
+            self.camera.position = Vec2::from(frame.position) + self.map.tile_size_px() / 2.0;
+        }
+
+        if self.controller.is_empty() {
+            self.walk(now);
+        }
+        if is_key_pressed(KeyCode::F5) {
+            self.save(now);
+        }
+        if is_key_pressed(KeyCode::F9) {
+            self.restore(now);
+        }
+        if matches!(&self.message, Some((_, shown)) if now.duration_since(*shown) > Duration::from_secs(MESSAGE_SECS))
+        {
+            self.message = None;
+        }
+    }
+
+    /// Starts walking to the next tile in the direction held, unless it's solid.
+    fn walk(&mut self, now: Instant) {
+        let Some((facing, step, name)) = [
+            (KeyCode::Up, Facing::North, ivec2(0, -1), "walk-n"),
+            (KeyCode::Right, Facing::East, ivec2(1, 0), "walk-e"),
+            (KeyCode::Down, Facing::South, ivec2(0, 1), "walk-s"),
+            (KeyCode::Left, Facing::West, ivec2(-1, 0), "walk-w"),
+        ]
+        .into_iter()
+        .find(|(key, ..)| is_key_down(*key))
+        .map(|(_, facing, step, name)| (facing, step, name)) else {
+            return;
+        };
+        self.controller.set_facing(facing);
+        let target = self.position + step;
+        if !self.map.contains(target) || self.grid.is_solid(target) {
+            return;
+        }
+
+        if let Some(template) = self.animations.get_template(name) {
+            let from = self.map.tile_to_world_px(self.position);
+            let movement = step.as_vec2() * self.map.tile_size_px();
+            self.controller.add_animation(
+                now,
+                template,
+                (movement.x, movement.y),
+                (from.x, from.y),
+            );
+        }
+        self.position = target;
+        let center = self.map.tile_to_world_px(target) + self.map.tile_size_px() / 2.0;
+        self.map.update_zones(PLAYER, center);
+        for event in self.map.drain_events() {
+            if let MapEvent::ZoneEntered { object, .. } = event {
+                self.enter(object, now);
+            }
+        }
+    }
+
+    /// Fires the trigger `object` the character walked into.
+    fn enter(&mut self, object: u32, now: Instant) {
+        let Some(trigger) = self.triggers.get(&object) else {
+            return;
+        };
+        self.message = Some((trigger.message.clone(), now));
+        if self.fired.contains(&object) {
+            return;
+        }
+        self.fired.push(object);
+        if let Some(tile) = trigger.tile {
+            let tile = TileHandle::new("grass_biome", tile);
+            self.map.set_tile(self.props, trigger.cell, Some(tile));
+        }
+    }
+
+    fn save(&mut self, now: Instant) {
+        let save = SaveGame {
+            manifest: self.map.manifest(),
+            position: (self.position.x, self.position.y),
+            facing: format!("{:?}", self.controller.facing()),
+            fired: self.fired.clone(),
+            rng: self.map.rng().clone(),
+        };
+        let text = ron::to_string(&save).expect("A save is always serializable");
+        let message = match std::fs::write(SAVE_PATH, text) {
+            Ok(()) => "Saved.".to_string(),
+            Err(e) => format!("Couldn't save: {}", e),
+        };
+        self.message = Some((message, now));
+    }
+
+    fn restore(&mut self, now: Instant) {
+        let save: SaveGame = match std::fs::read_to_string(SAVE_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|text| ron::from_str(&text).map_err(|e| e.to_string()))
+        {
+            Ok(save) => save,
+            Err(e) => {
+                self.message = Some((format!("Couldn't load: {}", e), now));
+                return;
+            }
+        };
+        if !self.map.check_manifest(&save.manifest).is_clean() {
+            self.message = Some(("The save is from another version of the map.".into(), now));
+            return;
+        }
+
+        self.map.set_rng(save.rng);
+        // Triggers fired since the save are reverted too.
+        for (id, trigger) in &self.triggers {
+            let Some(tile) = trigger.tile else {
+                continue;
+            };
+            let tile = match save.fired.contains(id) {
+                true => Some(TileHandle::new("grass_biome", tile)),
+                false => trigger.original.clone(),
+            };
+            self.map.set_tile(self.props, trigger.cell, tile);
+        }
+        self.fired = save.fired;
+        self.position = ivec2(save.position.0, save.position.1);
+        let center = self.map.tile_to_world_px(self.position) + self.map.tile_size_px() / 2.0;
+        // Already in the zone it was saved in, without firing it again.
+        self.map.update_zones(PLAYER, center);
+        self.map.drain_events();
+        let facing = match save.facing.as_str() {
+            "North" => Facing::North,
+            "East" => Facing::East,
+            "West" => Facing::West,
+            _ => Facing::South,
+        };
+        self.controller.set_facing(facing);
+        let at = self.map.tile_to_world_px(self.position);
+        self.controller.set_position(now, (at.x, at.y));
+        self.camera.position = at + self.map.tile_size_px() / 2.0;
+        self.message = Some(("Loaded.".into(), now));
+    }
+
+    fn draw(&self, now: Instant) {
+        clear_background(BLACK);
+        let dest = Rect::new(0., 0., screen_width(), screen_height());
+        let source = self.camera.source(dest, now);
+        let ground = self.map.layer_by_name("ground").expect("No ground layer");
+        self.map.draw_tiles(ground, dest, source);
+
+        // The character is drawn after the row of its feet: the props of the rows below,
+        // e.g. trees, hide it.
+        let frame = self.controller.get_frame(now);
+        let tile_size = self.map.tile_size_px();
+        self.map
+            .draw_tiles_rows(self.props, dest, source, |row_bottom| {
+                let Some(frame) = frame else {
+                    return;
+                };
+                let feet = frame.position.1 + tile_size.y;
+                if feet > row_bottom - tile_size.y && feet <= row_bottom {
+                    let at = world_px_to_screen(Vec2::from(frame.position), source, dest);
+                    let size = tile_size * dest.w / source.w;
+                    self.character
+                        .spr(frame.tile_id, Rect::new(at.x, at.y, size.x, size.y));
+                }
+            });
+
+        if let Some((message, _)) = &self.message {
+            let box_rect = Rect::new(16., dest.h - 56., dest.w - 32., 40.);
+            draw_rectangle(
+                box_rect.x,
+                box_rect.y,
+                box_rect.w,
+                box_rect.h,
+                Color::new(0., 0., 0., 0.7),
+            );
+            draw_text(message, box_rect.x + 12., box_rect.y + 26., 24., WHITE);
+        }
+    }
+}
+
+/// The "spawn" point, in world pixels, and the triggers of the "objects" layer,
+/// by object id.
+fn read_objects(map: &Map, props: usize) -> (Vec2, HashMap<u32, Trigger>) {
+    let mut spawn = Vec2::ZERO;
+    let mut triggers = HashMap::new();
+    for layer in map.map.layers() {
+        let LayerType::Objects(objects) = layer.layer_type() else {
+            continue;
+        };
+        for object in objects.objects() {
+            let center = match object.shape {
+                ObjectShape::Rect { width, height } => {
+                    vec2(object.x + width / 2.0, object.y + height / 2.0)
+                }
+                _ => vec2(object.x, object.y),
+            };
+            if object.name == "spawn" {
+                spawn = center;
+            }
+            if object.user_type == TRIGGER_CLASS {
+                let cell = map.world_px_to_tile(center);
+                let trigger = Trigger {
+                    cell,
+                    message: object
+                        .properties
+                        .get_string("message")
+                        .unwrap_or_default()
+                        .to_string(),
+                    tile: object.properties.get_int("tile").map(|tile| tile as u32),
+                    original: map.tile_at(props, cell),
+                };
+                triggers.insert(object.id(), trigger);
+            }
+        }
+    }
+    (spawn, triggers)
+}
+
+#[macroquad::main("RPG slice")]
+async fn main() {
+    let mut game = Game::load().await;
+    loop {
+        let now = Instant::now();
+        game.update(now);
+        game.draw(now);
+        if is_key_down(KeyCode::Q) {
+            break;
+        }
+        next_frame().await
+    }
+}
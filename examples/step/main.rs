@@ -1,14 +1,16 @@
 use std::path::Path;
 
-use macroquad::color::LIGHTGRAY;
+use macroquad::color::{BLACK, LIGHTGRAY};
 use macroquad::input::{is_key_down, is_key_pressed, KeyCode};
-use macroquad::math::{Rect, vec2};
+use macroquad::math::{ivec2, Rect, vec2};
+use macroquad::text::draw_text;
 use macroquad::window::{clear_background, next_frame, screen_height, screen_width};
-use tiled::TileId;
+use tiled::{PropertyValue, TileId};
 
 use macroquad_tiled_redux::{Map};
 
 
+#[allow(dead_code)]
 trait WangWalls {
     fn is_wall_s(&self, tile_id: TileId) -> bool;
 }
@@ -100,9 +102,15 @@ async fn main() {
             tilemap.draw_tiles(i, dest, Some(source));
         }
 
-        // let layer0 = tilemap.map.get_layer(0).unwrap().as_tile_layer().unwrap();
-        // let tile = layer0.get_tile(10, 10).unwrap();
-        // let i = tile.tileset_index();
+        // The tile under the camera, with its properties.
+        let under_camera = ivec2(
+            (camera.0 / tilemap.map.tile_width as f32) as i32,
+            (camera.1 / tilemap.map.tile_height as f32) as i32);
+        if let Some(tile) = tilemap.get_tile(0, under_camera) {
+            let solid = matches!(tile.property("solid"), Some(PropertyValue::BoolValue(true)));
+            let text = format!("{} #{} solid: {}", tile.tile.tileset, tile.id(), solid);
+            draw_text(&text, 10.0, 20.0, 20.0, BLACK);
+        }
 
         if is_key_down(KeyCode::Q) {
             break;
@@ -8,22 +8,12 @@ use macroquad::window::{clear_background, next_frame, screen_height, screen_widt
 
 use tiled::Loader;
 
-use macroquad_tiled_redux::animation_controller::{AnimationController, AnimationRegistry};
-use macroquad_tiled_redux::{world_px_to_screen, Map, TileSet};
-
-#[derive(Debug, Copy, Clone)]
-enum Direction {
-    North,
-    East,
-    South,
-    West,
-}
+use macroquad_tiled_redux::prelude::*;
 
 struct GameState {
     // In world tiles.
     pub position: IVec2,
     pub char_animation: AnimationController,
-    pub facing: Direction,
     // In world pixels.
     pub camera: Vec2,
     pub zoom: f32,
@@ -38,12 +28,12 @@ struct Resources {
 }
 
 impl Resources {
-    fn direction_animation(&self, dir: Direction) -> Option<u32> {
+    fn direction_animation(&self, dir: Facing) -> Option<u32> {
         match dir {
-            Direction::North => self.char_animations.get_animation_id("walk-n"),
-            Direction::East => self.char_animations.get_animation_id("walk-e"),
-            Direction::South => self.char_animations.get_animation_id("walk-s"),
-            Direction::West => self.char_animations.get_animation_id("walk-w"),
+            Facing::North => self.char_animations.get_animation_id("walk-n"),
+            Facing::East => self.char_animations.get_animation_id("walk-e"),
+            Facing::South => self.char_animations.get_animation_id("walk-s"),
+            Facing::West => self.char_animations.get_animation_id("walk-w"),
         }
     }
 }
@@ -82,38 +72,34 @@ impl GameState {
 
         // TODO: Check if the terrain is walkable.
         if (is_key_pressed(KeyCode::Left)
-            || (self.char_animation.get_frame(Instant::now()).is_none()
-                && is_key_down(KeyCode::Left)))
+            || (self.char_animation.is_empty() && is_key_down(KeyCode::Left)))
             && self.position.x >= 1
         {
-            self.facing = Direction::West;
+            self.char_animation.set_facing(Facing::West);
             direction_name = Some('w');
             direction_offset = ivec2(-1, 0);
         }
         if (is_key_pressed(KeyCode::Right)
-            || (self.char_animation.get_frame(Instant::now()).is_none()
-                && is_key_down(KeyCode::Right)))
+            || (self.char_animation.is_empty() && is_key_down(KeyCode::Right)))
             && self.position.x < resources.map.map.width as i32
         {
-            self.facing = Direction::East;
+            self.char_animation.set_facing(Facing::East);
             direction_name = Some('e');
             direction_offset = ivec2(1, 0);
         }
         if (is_key_pressed(KeyCode::Up)
-            || (self.char_animation.get_frame(Instant::now()).is_none()
-                && is_key_down(KeyCode::Up)))
+            || (self.char_animation.is_empty() && is_key_down(KeyCode::Up)))
             && self.position.y >= 1
         {
-            self.facing = Direction::North;
+            self.char_animation.set_facing(Facing::North);
             direction_name = Some('n');
             direction_offset = ivec2(0, -1);
         }
         if (is_key_pressed(KeyCode::Down)
-            || (self.char_animation.get_frame(Instant::now()).is_none()
-                && is_key_down(KeyCode::Down)))
+            || (self.char_animation.is_empty() && is_key_down(KeyCode::Down)))
             && self.position.x < resources.map.map.height as i32
         {
-            self.facing = Direction::South;
+            self.char_animation.set_facing(Facing::South);
             direction_name = Some('s');
             direction_offset = ivec2(0, 1);
         }
@@ -174,7 +160,8 @@ impl GameState {
             resources.map.draw_tiles(i, dest, Some(source));
 
             // Draw the character.
-            if i == 0 {
+            let char_world_rect = Rect::new(self.camera.x, self.camera.y, tile_size.x, tile_size.y);
+            if i == 0 && resources.map.is_world_rect_visible(char_world_rect, source) {
                 let char_screen_pos = world_px_to_screen(self.camera, source, dest);
 
                 let char_dest = Rect::new(
@@ -185,23 +172,8 @@ impl GameState {
                     tile_size.y * self.zoom,
                 );
 
-                match &char_frame {
-                    // animated
-                    Some(frame) => {
-                        // let char_dest = char_dest.offset(Vec2::from(movement) * self.zoom);
-                        resources.char_tileset.spr(frame.tile_id, char_dest);
-                    }
-
-                    // static
-                    None => {
-                        let direction_sprite = resources.direction_animation(self.facing);
-
-                        if let Some(gid) = direction_sprite {
-                            resources.char_tileset.spr(gid, char_dest);
-                        } else {
-                            println!("error: no sprite for {:?}", self.facing);
-                        }
-                    }
+                if let Some(frame) = &char_frame {
+                    resources.char_tileset.spr(frame.tile_id, char_dest);
                 }
             }
         }
@@ -239,18 +211,30 @@ async fn main() {
         resources.map.map.tile_height as i32,
     );
 
+    let mut char_animation = AnimationController::new();
+    for facing in [Facing::North, Facing::East, Facing::South, Facing::West] {
+        if let Some(tile_id) = resources.direction_animation(facing) {
+            char_animation.set_facing_fallback(facing, tile_id);
+        }
+    }
+    char_animation.set_position(
+        Instant::now(),
+        (
+            (position.x * tile_size.x) as f32,
+            (position.y * tile_size.y) as f32,
+        ),
+    );
+
     let mut state = GameState {
         position,
-        char_animation: AnimationController::new(),
-        facing: Direction::South,
+        char_animation,
         camera: ivec2_to_vec2(position * tile_size),
         zoom: 2.0,
         tile_size,
     };
 
     loop {
-        state.char_animation.update(Instant::now());
-        let frame = state.char_animation.get_frame(Instant::recent());
+        let frame = state.char_animation.update(Instant::now());
 
         if let Some(frame) = frame {
             state.camera = Vec2::from(frame.position);
@@ -0,0 +1,187 @@
+use coarsetime::Duration;
+use macroquad::color::Color;
+use macroquad::math::{vec2, IVec2, Rect};
+
+use crate::animation_controller::{AnimationFrame, AnimationTemplate, OutputFrame};
+use crate::map::{Map, TileRef};
+use crate::rng::MapRng;
+use crate::tileset::TileSet;
+
+/// A sprite of an `AmbientCrowd`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientSprite {
+    /// Index in `AmbientCrowd::templates`.
+    pub template: usize,
+    pub cell: IVec2,
+    /// In world pixels, somewhere in the bounding box of the cell.
+    pub position: (f32, f32),
+    /// How far into its loop the sprite is at time 0, so that the crowd isn't in sync.
+    pub phase: Duration,
+}
+
+/// Looping animated sprites scattered over matching tiles, e.g. butterflies over grass
+/// or fireflies over water: ambient life without per-entity game code.
+/// Sprites are ordered row by row, for overlap.
+#[derive(Clone, Debug, Default)]
+pub struct AmbientCrowd {
+    pub templates: Vec<AnimationTemplate>,
+    pub sprites: Vec<AmbientSprite>,
+}
+
+impl AmbientCrowd {
+    /// Scatters at most `count` sprites, one per cell, over the cells of `layer` whose tile
+    /// matches `filter(tile, tile data)`, e.g. of class "grass". `density` is the fraction
+    /// of the matching cells which may get one, 0 to 1. Each sprite loops one of `templates`.
+    /// The picks are random, but the same for the same map and `rng` seed, see
+    /// `MapRng::cell_hash()`, e.g. `map.rng()`, or `map.rng().derive(salt)` for crowds
+    /// of their own.
+    pub fn scatter(
+        map: &Map,
+        layer: usize,
+        templates: Vec<AnimationTemplate>,
+        count: usize,
+        density: f32,
+        rng: &MapRng,
+        mut filter: impl FnMut(&TileRef, Option<tiled::Tile>) -> bool,
+    ) -> Self {
+        if templates.is_empty() {
+            return Self::default();
+        }
+
+        let threshold = density.clamp(0.0, 1.0) as f64 * u32::MAX as f64;
+        let mut cells: Vec<(u32, IVec2)> = map
+            .layer_tiles(layer)
+            .filter_map(|(pos, tile)| {
+                let tile = tile?;
+                let roll = rng.cell_hash(pos, 0);
+                ((roll as f64) < threshold && filter(&tile, map.tile_data(tile.tileset, tile.id)))
+                    .then_some((roll, pos))
+            })
+            .collect();
+        // The lowest rolls, so that `count` doesn't favor the top of the map.
+        cells.sort_unstable_by_key(|(roll, pos)| (*roll, pos.y, pos.x));
+        cells.truncate(count);
+        cells.sort_unstable_by_key(|(_, pos)| (pos.y, pos.x));
+
+        let tile_size = map.tile_size_px();
+        let sprites = cells
+            .into_iter()
+            .map(|(_, cell)| {
+                let template = rng.cell_hash(cell, 1) as usize % templates.len();
+                let loop_ticks = loop_duration(&templates[template].frames).as_ticks();
+                let phase = rng.cell_hash(cell, 2) as u64 % loop_ticks.max(1);
+                let offset = vec2(
+                    rng.cell_hash(cell, 3) as f32 / u32::MAX as f32,
+                    rng.cell_hash(cell, 4) as f32 / u32::MAX as f32,
+                ) * tile_size;
+                let position = map.tile_to_world_px(cell) + offset;
+                AmbientSprite {
+                    template,
+                    cell,
+                    position: (position.x, position.y),
+                    phase: Duration::from_ticks(phase),
+                }
+            })
+            .collect();
+
+        Self { templates, sprites }
+    }
+
+    /// The frames of the sprites at `elapsed`, e.g. `map.clock.elapsed()`.
+    pub fn frames(&self, elapsed: Duration) -> impl Iterator<Item = OutputFrame> + '_ {
+        self.sprites.iter().map(move |sprite| OutputFrame {
+            tile_id: loop_frame(&self.templates[sprite.template], elapsed + sprite.phase),
+            position: sprite.position,
+        })
+    }
+
+    /// Draws the sprites at `elapsed` with `tileset`, tinted with `color`.
+    /// `dest(position)` places a frame on the screen.
+    pub fn draw(
+        &self,
+        tileset: &TileSet,
+        elapsed: Duration,
+        color: Color,
+        dest: impl Fn((f32, f32)) -> Rect,
+    ) {
+        for frame in self.frames(elapsed) {
+            tileset.spr_color(frame.tile_id, dest(frame.position), color);
+        }
+    }
+}
+
+fn loop_duration(frames: &[AnimationFrame]) -> Duration {
+    Duration::from_ticks(frames.iter().map(|frame| frame.duration.as_ticks()).sum())
+}
+
+/// The tile of `template` at `elapsed`, looping.
+fn loop_frame(template: &AnimationTemplate, elapsed: Duration) -> u32 {
+    let total = loop_duration(&template.frames).as_ticks();
+    if total == 0 {
+        return template
+            .frames
+            .first()
+            .map(|frame| frame.tile_id)
+            .unwrap_or(template.gid);
+    }
+    let mut dt = elapsed.as_ticks() % total;
+    for frame in &template.frames {
+        if dt < frame.duration.as_ticks() {
+            return frame.tile_id;
+        }
+        dt -= frame.duration.as_ticks();
+    }
+    template.gid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{mock_frames1243, mock_template, tiny_map};
+
+    #[test]
+    fn test_scatter() {
+        let map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let templates = vec![mock_template(mock_frames1243(1..=4), 0)];
+        let walls = |_: &TileRef, tile: Option<tiled::Tile>| {
+            tile.is_some_and(|tile| tile.user_type.as_deref() == Some("wall"))
+        };
+
+        let rng = MapRng::new(7);
+        let crowd = AmbientCrowd::scatter(&map, ground, templates.clone(), 100, 1.0, &rng, walls);
+        // The 12 walls around the floor.
+        assert_eq!(crowd.sprites.len(), 12);
+        for sprite in &crowd.sprites {
+            let floor = (1..=2).contains(&sprite.cell.x) && (1..=2).contains(&sprite.cell.y);
+            assert!(!floor);
+            let cell = Rect::new(
+                sprite.cell.x as f32 * 16.,
+                sprite.cell.y as f32 * 16.,
+                16.,
+                16.,
+            );
+            assert!(cell.contains(vec2(sprite.position.0, sprite.position.1)));
+        }
+        let phases: Vec<_> = crowd.sprites.iter().map(|sprite| sprite.phase).collect();
+        assert!(phases.iter().any(|phase| *phase != phases[0]));
+
+        let few = AmbientCrowd::scatter(&map, ground, templates.clone(), 5, 1.0, &rng, walls);
+        assert_eq!(few.sprites.len(), 5);
+        let again = AmbientCrowd::scatter(&map, ground, templates.clone(), 5, 1.0, &rng, walls);
+        assert_eq!(few.sprites, again.sprites);
+        let none = AmbientCrowd::scatter(&map, ground, templates, 100, 0.0, &rng, walls);
+        assert!(none.sprites.is_empty());
+    }
+
+    #[test]
+    fn test_loop_frame() {
+        let template = mock_template(mock_frames1243(1..=4), 0);
+        let at = |ticks| loop_frame(&template, Duration::from_ticks(ticks));
+        assert_eq!(at(50), 1);
+        assert_eq!(at(250), 2);
+        assert_eq!(at(650), 3);
+        assert_eq!(at(950), 4);
+        assert_eq!(at(1050), 1);
+    }
+}
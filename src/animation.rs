@@ -105,4 +105,20 @@ impl AnimatedSpriteState {
 
 impl AnimatedTile {
     pub fn new(id: u32, animation: Animation) -> Self { Self { id, animation } }
+
+    /// The tile to show `elapsed` after the animation start, looping.
+    pub fn frame_at(&self, elapsed: Duration) -> u32 {
+        let animation = &self.animation;
+        if animation.duration.as_ticks() == 0 {
+            return animation.frames.first().map(|frame| frame.tile_id).unwrap_or(self.id);
+        }
+        let mut dt = elapsed.as_ticks() % animation.duration.as_ticks();
+        for frame in &animation.frames {
+            if dt < frame.duration.as_ticks() {
+                return frame.tile_id;
+            }
+            dt -= frame.duration.as_ticks();
+        }
+        self.id
+    }
 }
@@ -1,14 +1,16 @@
 use std::collections::HashMap;
 
 use coarsetime::{Duration, Instant};
-use macroquad::math::{Rect, Vec2};
+use macroquad::math::{ivec2, Rect, Vec2};
 use tiled::Frame;
-use tiled::PropertyValue;
 use tiled::Tileset;
+use tiled::{Properties, PropertyValue};
 
+use crate::properties::{inherit_properties, PropertiesExt};
+use crate::rng::MapRng;
 use crate::world_px_to_screen;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct OutputFrame {
     pub tile_id: u32,
     pub position: (f32, f32),
@@ -29,7 +31,47 @@ impl From<&Frame> for AnimationFrame {
     }
 }
 
+/// A cue attached to an animation frame, like a sound or a visual effect.
+/// Fires once, when the frame starts playing.
+/// In Tiled, it's a tile property like `trigger_frame_2` = `sfx:sword_hit`.
+/// "shake" and "zoom" triggers are camera effects, see `camera::CameraCue`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimationTrigger {
+    pub frame: u32,
+    /// The part before ':', e.g. "sfx". Empty if there's no ':'.
+    pub kind: String,
+    /// The part after ':', e.g. "sword_hit".
+    pub payload: String,
+}
+
+impl AnimationTrigger {
+    const PROPERTY_PREFIX: &'static str = "trigger_frame_";
+
+    /// Parses a `trigger_frame_<frame>` tile property.
+    pub fn from_property(name: &str, value: &PropertyValue) -> Option<Self> {
+        let frame = name.strip_prefix(Self::PROPERTY_PREFIX)?.parse().ok()?;
+        let PropertyValue::StringValue(value) = value else {
+            return None;
+        };
+        let (kind, payload) = value.split_once(':').unwrap_or(("", value));
+        Some(Self {
+            frame,
+            kind: kind.to_string(),
+            payload: payload.to_string(),
+        })
+    }
+}
+
+/// Int property of an animation tile, or of its tileset for all of them,
+/// see `AnimationTemplate::max_compression`.
+pub const MAX_COMPRESSION_PROPERTY: &str = "max_compression";
+/// Bool property, see `AnimationTemplate::blocks_turn`.
+pub const BLOCKS_TURN_PROPERTY: &str = "blocks_turn";
+/// Int property, see `AnimationTemplate::cancel_frame`.
+pub const CANCEL_FRAME_PROPERTY: &str = "cancel_frame";
+
 /// An animation "template", shared between
+#[derive(Clone, Debug)]
 pub struct AnimationTemplate {
     /// Animation name, stored in Properties -> "name": String
     pub name: String,
@@ -51,8 +93,8 @@ pub struct AnimationTemplate {
     /// * add_compressed(damaged_animation, dur2) on each combatant,
     /// * add_compressed(damaged_animation, dur2) on each combatant,
     /// * somehow add blood decal, delayed. Either we also need Animation
-    /// to spawn decals, or other delayed way to spawn things. I certainly don't want
-    /// to wait for animations to end to do something else.
+    ///   to spawn decals, or other delayed way to spawn things. I certainly don't want
+    ///   to wait for animations to end to do something else.
     pub ordering: u8,
 
     /// Speed compression properties. Depending on the size of the animations queue,
@@ -70,11 +112,30 @@ pub struct AnimationTemplate {
     /// Frame# after which this animation can be cancelled.
     /// Default: None
     pub cancel_frame: Option<u32>,
+    /// For idle animations: a looping offset from the idle position, e.g. a hover bob
+    /// or breathing. It's the amplitude of a sine wave over one play of the animation,
+    /// in the same units as `movement`.
+    /// Default: (0, 0)
+    pub idle_movement: (f32, f32),
+    /// Cues to fire on specific frames, see `AnimationController::drain_triggers()`.
+    pub triggers: Vec<AnimationTrigger>,
     // Nice to have: depending on compression level, change move animation
     // from step to walk to running.
 }
 
 impl AnimationTemplate {
+    fn apply_properties(&mut self, properties: &Properties) {
+        if let Some(max_compression) = properties.get_int(MAX_COMPRESSION_PROPERTY) {
+            self.max_compression = max_compression.max(0) as u32;
+        }
+        if let Some(blocks_turn) = properties.get_bool(BLOCKS_TURN_PROPERTY) {
+            self.blocks_turn = blocks_turn;
+        }
+        if let Some(cancel_frame) = properties.get_int(CANCEL_FRAME_PROPERTY) {
+            self.cancel_frame = u32::try_from(cancel_frame).ok();
+        }
+    }
+
     pub fn new(name: String, gid: u32) -> Self {
         Self::new_frames(name, gid, vec![])
     }
@@ -85,10 +146,12 @@ impl AnimationTemplate {
             gid,
             frames,
             ordering: 0,
-            // todo: read these from Properties.
+            // Tiles and tilesets can override these, see `AnimationRegistry::load()`.
             max_compression: 40,
             blocks_turn: true,
             cancel_frame: None,
+            idle_movement: (0., 0.),
+            triggers: vec![],
         }
     }
 }
@@ -111,6 +174,15 @@ struct AnimationInstance {
     pub start_position: (f32, f32),
     pub max_compression: u32,
     pub is_compressed: bool,
+    /// See `AnimationTemplate::cancel_frame`.
+    pub cancel_frame: Option<u32>,
+
+    pub triggers: Vec<AnimationTrigger>,
+    /// Triggers of the frames before this one have fired already.
+    pub fired_until: u32,
+    /// How many frames `compress()` dropped from the front of `frames`,
+    /// to map the remaining ones back to template frame numbers.
+    pub frame_offset: u32,
 }
 
 impl AnimationInstance {
@@ -134,6 +206,10 @@ impl AnimationInstance {
             start_position,
             max_compression: template.max_compression,
             is_compressed: false,
+            cancel_frame: template.cancel_frame,
+            triggers: template.triggers.clone(),
+            fired_until: 0,
+            frame_offset: 0,
         }
     }
 
@@ -151,6 +227,7 @@ impl AnimationInstance {
             let new_duration;
             if start + frame.duration <= current_time {
                 start += frame.duration;
+                self.frame_offset += 1;
                 continue;
             } else if start < current_time && start + frame.duration > current_time {
                 new_duration =
@@ -184,6 +261,28 @@ impl AnimationInstance {
     }
 }
 
+/// The direction an entity faces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Facing {
+    North,
+    East,
+    #[default]
+    South,
+    West,
+}
+
+impl Facing {
+    /// Unit vector of the direction, y pointing down like in world tiles.
+    pub fn to_vec2(self) -> Vec2 {
+        match self {
+            Facing::North => Vec2::new(0.0, -1.0),
+            Facing::East => Vec2::new(1.0, 0.0),
+            Facing::South => Vec2::new(0.0, 1.0),
+            Facing::West => Vec2::new(-1.0, 0.0),
+        }
+    }
+}
+
 /// Per-entity object that controls its animations.
 #[derive(Clone, Default, Debug)]
 pub struct AnimationController {
@@ -194,6 +293,19 @@ pub struct AnimationController {
     /// Idle animations get interrupted immediately.
     idle_animations: Vec<IdleInstance>,
     idle_start: Option<IdleStart>,
+    /// Picks each idle animation, see `set_idle_rng()`. The first one if `None`.
+    idle_rng: Option<MapRng>,
+    /// Memo of the last `update()`: its time and the frame for that moment.
+    /// `get_frame()` is called several times per frame (camera, draw), so repeat
+    /// queries for the same moment are free.
+    last_frame: Option<(Instant, Option<OutputFrame>)>,
+    /// Fired and not yet drained triggers.
+    triggers: Vec<AnimationTrigger>,
+    facing: Facing,
+    /// Shown when there's neither an animation nor an idle animation to play.
+    fallback_tile: Option<u32>,
+    /// Same as `fallback_tile`, but for a specific facing. Takes precedence.
+    facing_fallbacks: HashMap<Facing, u32>,
 }
 
 impl AnimationController {
@@ -201,39 +313,167 @@ impl AnimationController {
         Self::default()
     }
 
-    /// Discards the animations whose time is gone.
-    pub fn update(&mut self, time: Instant) {
+    /// Discards the animations whose time is gone, and returns the frame to show
+    /// at `time`. The frame is memoized, so `get_frame(time)` with the same `time`
+    /// doesn't compute it again.
+    pub fn update(&mut self, time: Instant) -> Option<OutputFrame> {
+        self.fire_triggers(time);
         if !self.animations.is_empty() {
             self.animations
                 .retain(|i| i.animation_start + i.duration >= time);
         }
+        let frame = self.compute_frame(time);
+        self.last_frame = Some((time, frame));
+        frame
     }
 
     /// Returns OutputFrame for the given time moment, if there is
     /// a frame to show, otherwise None.
     /// Only goes down to current or next frame.
     pub fn get_frame(&self, time: Instant) -> Option<OutputFrame> {
+        match self.last_frame {
+            Some((memo_time, frame)) if memo_time == time => frame,
+            _ => self.compute_frame(time),
+        }
+    }
+
+    fn compute_frame(&self, time: Instant) -> Option<OutputFrame> {
         match self.animations.first() {
             Some(instance) => {
-                let Some(tile_id) = Self::get_tile_id(time, instance) else {
-                    return None;
-                };
+                let tile_id = Self::get_tile_id(time, instance)?;
                 let position = Self::get_position(time, instance);
                 let animation_output_frame = OutputFrame { tile_id, position };
                 Some(animation_output_frame)
             }
-            None => self.get_idle_animation(time),
+            None => self
+                .get_idle_animation(time)
+                .or_else(|| self.get_fallback_frame()),
         }
     }
 
+    /// Returns the triggers fired since the last call, in order.
+    /// Triggers fire in `update()`, so call this after it, once per frame.
+    pub fn drain_triggers(&mut self) -> Vec<AnimationTrigger> {
+        std::mem::take(&mut self.triggers)
+    }
+
+    fn fire_triggers(&mut self, time: Instant) {
+        for instance in &mut self.animations {
+            if instance.animation_start > time {
+                break;
+            }
+            let reached = match Self::get_frame_index(time, instance) {
+                Some(index) => instance.frame_offset + index as u32 + 1,
+                None => instance.frame_offset + instance.frames.len() as u32,
+            };
+            for trigger in &instance.triggers {
+                if trigger.frame >= instance.fired_until && trigger.frame < reached {
+                    self.triggers.push(trigger.clone());
+                }
+            }
+            instance.fired_until = instance.fired_until.max(reached);
+        }
+    }
+
+    pub fn facing(&self) -> Facing {
+        self.facing
+    }
+
+    pub fn set_facing(&mut self, facing: Facing) {
+        self.facing = facing;
+        self.last_frame = None;
+    }
+
+    /// Makes `get_frame()` return `tile_id` when nothing else plays,
+    /// unless there's a fallback for the current facing.
+    /// Needs a position: either from a finished animation, or from `set_position()`.
+    pub fn set_fallback_tile(&mut self, tile_id: u32) {
+        self.fallback_tile = Some(tile_id);
+        self.last_frame = None;
+    }
+
+    /// Same as `set_fallback_tile()`, used only when facing `facing`.
+    pub fn set_facing_fallback(&mut self, facing: Facing, tile_id: u32) {
+        self.facing_fallbacks.insert(facing, tile_id);
+        self.last_frame = None;
+    }
+
+    /// Places the entity at `position` without animating, e.g. when it spawns.
+    /// Idle animations count their interval from `now`.
+    pub fn set_position(&mut self, now: Instant, position: (f32, f32)) {
+        self.idle_start = Some(IdleStart::new(now, position));
+        self.last_frame = None;
+    }
+
+    /// Moves all positions by `offset`, queued animations and the idle position included,
+    /// e.g. when the entity moves to another map of a `World`. The animations go on as before.
+    pub fn translate(&mut self, offset: (f32, f32)) {
+        let shift = |position: &mut (f32, f32)| {
+            position.0 += offset.0;
+            position.1 += offset.1;
+        };
+        for instance in &mut self.animations {
+            shift(&mut instance.start_position);
+        }
+        if let Some(idle_start) = &mut self.idle_start {
+            shift(&mut idle_start.position);
+        }
+        if let Some((_, Some(frame))) = &mut self.last_frame {
+            shift(&mut frame.position);
+        }
+    }
+
+    /// Skips the queued animations, placing the entity where the last one ends,
+    /// e.g. for entities too far away to be seen moving. Their triggers don't fire.
+    pub fn finish(&mut self, now: Instant) {
+        if let Some(last) = self.animations.last() {
+            let end = (
+                last.start_position.0 + last.movement.0,
+                last.start_position.1 + last.movement.1,
+            );
+            self.animations.clear();
+            self.set_position(now, end);
+        }
+    }
+
+    /// Position of the frame of the last `update()`.
+    pub fn last_position(&self) -> Option<(f32, f32)> {
+        self.last_frame
+            .and_then(|(_, frame)| frame)
+            .map(|frame| frame.position)
+    }
+
+    /// Time of the last `update()`.
+    pub fn last_update(&self) -> Option<Instant> {
+        self.last_frame.map(|(time, _)| time)
+    }
+
+    fn get_fallback_frame(&self) -> Option<OutputFrame> {
+        let tile_id = self
+            .facing_fallbacks
+            .get(&self.facing)
+            .copied()
+            .or(self.fallback_tile)?;
+        let idle_start = self.idle_start?;
+        Some(OutputFrame {
+            tile_id,
+            position: idle_start.position,
+        })
+    }
+
     pub fn len(&self) -> usize {
         self.animations.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
     /// Removes the first animation.
     pub fn dequeue(&mut self) {
         if !self.animations.is_empty() {
             self.animations.remove(0);
+            self.last_frame = None;
         }
     }
 
@@ -270,6 +510,80 @@ impl AnimationController {
         );
         self.idle_start = Some(IdleStart::new(end_time, end_position));
         self.animations.push(new_instance);
+        self.last_frame = None;
+    }
+
+    /// Plays `template` as soon as possible, e.g. for hurt or death animations, which
+    /// shouldn't wait behind queued walk steps.
+    /// The queued animations that haven't started yet are dropped if they have a
+    /// `cancel_frame`, unless a queued animation without one follows: those must play,
+    /// and so does everything before them. The one playing, if nothing is kept behind it,
+    /// is cut if it's past its `cancel_frame`, otherwise it's played to the end.
+    /// `start_position` is only used if nothing is playing nor kept.
+    pub fn add_animation_interrupting(
+        &mut self,
+        start_time: Instant,
+        template: &AnimationTemplate,
+        movement: (f32, f32),
+        start_position: (f32, f32),
+    ) {
+        self.fire_triggers(start_time);
+        // Whether update() was called or not, finished animations are gone.
+        self.animations
+            .retain(|instance| instance.animation_start + instance.duration > start_time);
+        let playing = self
+            .animations
+            .iter()
+            .take_while(|instance| instance.animation_start <= start_time)
+            .count();
+        let kept = self
+            .animations
+            .iter()
+            .rposition(|instance| {
+                instance.animation_start > start_time && instance.cancel_frame.is_none()
+            })
+            .map_or(playing, |last| last + 1);
+        self.animations.truncate(kept);
+
+        let (new_start_time, new_start_position) = match self.animations.as_slice() {
+            [] => (start_time, start_position),
+            [current] if Self::is_past_cancel_frame(start_time, current) => {
+                let position = Self::get_position(start_time, current);
+                self.animations.clear();
+                (start_time, position)
+            }
+            [.., last] => (
+                last.animation_start + last.duration,
+                (
+                    last.start_position.0 + last.movement.0,
+                    last.start_position.1 + last.movement.1,
+                ),
+            ),
+        };
+
+        let instance =
+            AnimationInstance::new(new_start_time, template, movement, new_start_position);
+        let end_position = (
+            new_start_position.0 + movement.0,
+            new_start_position.1 + movement.1,
+        );
+        self.idle_start = Some(IdleStart::new(
+            instance.animation_start + instance.duration,
+            end_position,
+        ));
+        self.animations.push(instance);
+        self.last_frame = None;
+    }
+
+    /// If `instance` is playing at `time`, and can be cut there, see `cancel_frame`.
+    fn is_past_cancel_frame(time: Instant, instance: &AnimationInstance) -> bool {
+        if instance.animation_start > time {
+            return false;
+        }
+        match (Self::get_frame_index(time, instance), instance.cancel_frame) {
+            (Some(frame), Some(cancel_frame)) => frame as u32 >= cancel_frame,
+            _ => false,
+        }
     }
 
     fn compress(&mut self, time: Instant) {
@@ -280,6 +594,17 @@ impl AnimationController {
         }
     }
 
+    fn get_frame_index(finish_time: Instant, instance: &AnimationInstance) -> Option<usize> {
+        let mut time = finish_time - instance.animation_start;
+        for (index, frame) in instance.frames.iter().enumerate() {
+            if time < frame.duration {
+                return Some(index);
+            }
+            time -= frame.duration;
+        }
+        None
+    }
+
     fn get_tile_id(finish_time: Instant, instance: &AnimationInstance) -> Option<u32> {
         let start_time = instance.animation_start;
         let mut time = finish_time - start_time;
@@ -319,6 +644,7 @@ impl AnimationController {
         self.idle_interval = Some(interval);
         let animation = IdleInstance::new(template);
         self.idle_animations.push(animation);
+        self.last_frame = None;
     }
 
     // "set" supposes a singular entity. "add" supposes a collection of entities.
@@ -328,28 +654,47 @@ impl AnimationController {
         self.add_idle_animation(template, interval);
     }
 
+    /// Plays a random one of the idle animations every time, rather than the first one,
+    /// picked under the seed of `rng`, e.g. `map.rng().derive(entity_id)`: the same
+    /// seed plays the same ones, e.g. in replays.
+    pub fn set_idle_rng(&mut self, rng: Option<MapRng>) {
+        self.idle_rng = rng;
+        self.last_frame = None;
+    }
+
+    /// The idle animation played the `cycle`-th time since the idle start.
+    fn idle_instance(&self, cycle: i32) -> Option<&IdleInstance> {
+        let index = match &self.idle_rng {
+            Some(rng) if !self.idle_animations.is_empty() => {
+                rng.cell_hash(ivec2(cycle, 0), 0) as usize % self.idle_animations.len()
+            }
+            _ => 0,
+        };
+        self.idle_animations.get(index)
+    }
+
     fn get_idle_animation(&self, now: Instant) -> Option<OutputFrame> {
-        match (
-            self.idle_interval,
-            self.idle_animations.first(),
-            self.idle_start,
-        ) {
-            (Some(interval), Some(instance), Some(idle_start)) => {
+        match (self.idle_interval, self.idle_instance(0), self.idle_start) {
+            (Some(interval), Some(mut instance), Some(idle_start)) => {
                 let mut start = idle_start.start_time;
+                let mut cycle = 0;
                 while start + interval + instance.duration <= now {
                     start += interval + instance.duration;
+                    cycle += 1;
+                    instance = self.idle_instance(cycle)?;
                 }
                 let animation_start = start + interval;
                 if animation_start > now {
                     return None;
                 }
                 let mut time = now - animation_start;
+                let position = instance.get_position(time, idle_start.position);
                 let mut output_frame = None;
                 for frame in &instance.frames {
                     if time < frame.duration {
                         output_frame = Some(OutputFrame {
                             tile_id: frame.tile_id,
-                            position: idle_start.position,
+                            position,
                         });
                         break;
                     }
@@ -381,6 +726,8 @@ impl IdleStart {
 struct IdleInstance {
     pub frames: Vec<AnimationFrame>,
     pub duration: Duration,
+    /// See `AnimationTemplate::idle_movement`.
+    pub movement: (f32, f32),
 }
 
 impl IdleInstance {
@@ -393,8 +740,21 @@ impl IdleInstance {
         Self {
             duration: Duration::from_ticks(total_ticks),
             frames: template.frames.clone(),
+            movement: template.idle_movement,
         }
     }
+
+    /// Position `elapsed` into the animation, relative to the idle `anchor` position.
+    fn get_position(&self, elapsed: Duration, anchor: (f32, f32)) -> (f32, f32) {
+        if self.movement == (0., 0.) || self.duration.as_ticks() == 0 {
+            return anchor;
+        }
+        let phase = elapsed.as_ticks() as f32 / self.duration.as_ticks() as f32;
+        let k = (phase * 2.0 * std::f32::consts::PI).sin();
+        let x = anchor.0 + self.movement.0 * k;
+        let y = anchor.1 + self.movement.1 * k;
+        (x.round(), y.round())
+    }
 }
 
 pub struct AnimationRegistry {
@@ -405,29 +765,36 @@ pub struct AnimationRegistry {
 }
 
 impl AnimationRegistry {
+    /// Templates of the tiles with a "name" property and an animation. Their
+    /// `MAX_COMPRESSION_PROPERTY`, `BLOCKS_TURN_PROPERTY` and `CANCEL_FRAME_PROPERTY`
+    /// default to those of the tileset.
     pub fn load(tileset: &Tileset) -> Self {
         let mut animations: HashMap<String, u32> = HashMap::new();
         let mut templates = HashMap::new();
 
         for (tile_id, tile) in tileset.tiles() {
-            if let Some(value) = tile.properties.get("name") {
-                if let (PropertyValue::StringValue(name), Some(frames)) = (value, &tile.animation) {
-                    animations.insert(name.clone(), tile_id);
-
-                    let template = AnimationTemplate::new_frames(
-                        name.clone(),
-                        tile_id,
-                        frames.iter().map(|it| it.into()).collect(),
-                    );
+            let (Some(name), Some(frames)) = (tile.properties.get_string("name"), &tile.animation)
+            else {
+                continue;
+            };
+            animations.insert(name.to_string(), tile_id);
 
-                    templates.insert(tile_id, template);
-                }
-            }
+            let mut template = AnimationTemplate::new_frames(
+                name.to_string(),
+                tile_id,
+                frames.iter().map(|it| it.into()).collect(),
+            );
+            template.triggers = tile
+                .properties
+                .iter()
+                .filter_map(|(name, value)| AnimationTrigger::from_property(name, value))
+                .collect();
+            template.triggers.sort_by_key(|trigger| trigger.frame);
+            template.apply_properties(&inherit_properties(&tileset.properties, &tile.properties));
+
+            templates.insert(tile_id, template);
         }
 
-        // TODO: Add custom properties for other template fields:
-        // compression, blocks_turn, cancel_frame
-
         Self {
             animations,
             templates,
@@ -473,147 +840,13 @@ pub fn from_offset_into_tile_rect(
 
 #[cfg(test)]
 mod tests {
-    use coarsetime::{Duration, Instant};
-    use std::ops::RangeInclusive;
+    use coarsetime::Duration;
 
     use super::*;
+    use crate::testing::{assert_pos_almost_eq, mock_frames1243, mock_template, AnimationTest};
 
-    fn mock_template(frames: Vec<AnimationFrame>, max_compression: u32) -> AnimationTemplate {
-        let mut template = AnimationTemplate::new_frames("dummy".to_string(), 1, frames);
-        template.max_compression = max_compression;
-        template
-    }
-
-    //total duration: 1000 tics, for 4 frames.
-    fn mock_frames1243(ids: RangeInclusive<u32>) -> Vec<AnimationFrame> {
-        let mut result = vec![];
-        let durations = [100, 200, 400, 300];
-        for (index, tile_id) in ids.enumerate() {
-            result.push(AnimationFrame {
-                tile_id,
-                duration: Duration::from_ticks(durations[index % durations.len()]),
-            });
-        }
-        result
-    }
-
-    macro_rules! assert_pos_almost_eq {
-        ($x:expr, $y:expr, $d:expr, $arg:tt) => {
-            if (($x.0 - $y.0).abs() > $d || ($x.1 - $y.1).abs() > $d) {
-                panic!(
-                    "Expected {}: {:?} and actual: {:?} are different",
-                    $arg, $x, $y
-                );
-            }
-        };
-    }
-
-    struct TestState {
-        pub controller: AnimationController,
-        pub start_time: Instant,
-        pub now: Instant,
-    }
-
-    impl TestState {
-        pub fn new() -> Self {
-            Self {
-                controller: AnimationController::new(),
-                start_time: Instant::now(),
-                now: Instant::recent(),
-            }
-        }
-
-        pub fn assert_frame_at(
-            &mut self,
-            now_in_ticks: u64,
-            tile_id: u32,
-            expected_pos: (f32, f32),
-        ) {
-            self.now = self.start_time + Duration::from_ticks(now_in_ticks);
-            self.controller.update(self.now);
-
-            let frame_now = self.controller.get_frame(self.now).expect("Frame expected");
-
-            assert_eq!(
-                frame_now.tile_id, tile_id,
-                "tiles_id differ: expected {}, got {}",
-                tile_id, frame_now.tile_id
-            );
-            assert_pos_almost_eq!(expected_pos, frame_now.position, 1.1, "position");
-        }
-
-        pub fn assert_in_interval(
-            &mut self,
-            now_in_ticks: u64,
-            expected_tile_id: u32,
-            expected_pos: (f32, f32),
-        ) {
-            let mut start: u64 = 0;
-            if now_in_ticks > 5 {
-                start = now_in_ticks - 5;
-            }
-
-            //if expected tile_id is present in time interval now_in_ticks +- 5 ms
-            let mut tile_id = false;
-            let mut got_tile_id = None;
-            for i in start..(start + 11) {
-                self.now = self.start_time + Duration::from_ticks(i);
-                self.controller.update(self.now);
-                if self.controller.get_frame(self.now).is_none() {
-                    continue;
-                }
-                let frame_now = self.controller.get_frame(self.now).unwrap();
-                if frame_now.tile_id == expected_tile_id {
-                    tile_id = true;
-                    break;
-                }
-                if i == now_in_ticks {
-                    got_tile_id = Some(frame_now.tile_id);
-                }
-            }
-            assert!(
-                tile_id,
-                "At {} tile_id is not {}, it is {}",
-                now_in_ticks,
-                expected_tile_id,
-                got_tile_id.unwrap()
-            );
-
-            //if real position == expected position +- 1
-            let mut pos = false;
-            let mut got_frame_pos = (0., 0.);
-            for i in start..(start + 11) {
-                self.now = self.start_time + Duration::from_ticks(i);
-                self.controller.update(self.now);
-                if self.controller.get_frame(self.now).is_none() {
-                    continue;
-                }
-                let frame_now = self.controller.get_frame(self.now).unwrap();
-                let frame_pos = frame_now.position;
-                if (expected_pos.0 - frame_pos.0).abs() <= 1.
-                    && (expected_pos.1 - frame_pos.1).abs() <= 1.
-                {
-                    pos = true;
-                    break;
-                }
-                if i == now_in_ticks {
-                    got_frame_pos = frame_pos;
-                }
-            }
-            assert!(
-                pos,
-                "At {} position is not {:?}, it is {:?}",
-                now_in_ticks, expected_pos, got_frame_pos
-            );
-        }
-
-        pub fn assert_empty_at(&mut self, now_in_ticks: u64) {
-            self.now = self.start_time + Duration::from_ticks(now_in_ticks);
-            self.controller.update(self.now);
-            assert!(self.controller.get_frame(self.now).is_none())
-        }
-
-        pub fn assert_animation_characteristics(
+    impl AnimationTest {
+        fn assert_animation_characteristics(
             &mut self,
             number: usize,
             frames_len: usize,
@@ -646,15 +879,15 @@ mod tests {
                     duration,
                     animation.duration.as_ticks()
                 );
-                assert_pos_almost_eq!(start_pos, animation.start_position, 0.05, "start position");
-                assert_pos_almost_eq!(movement, animation.movement, 0.05, "movement");
+                assert_pos_almost_eq(start_pos, animation.start_position, 0.05, "start position");
+                assert_pos_almost_eq(movement, animation.movement, 0.05, "movement");
             }
         }
     }
 
     #[test]
     pub fn test_movement() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 100);
         state
@@ -672,7 +905,7 @@ mod tests {
 
     #[test]
     pub fn test_movement_in_interval() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 100);
         state
@@ -693,7 +926,7 @@ mod tests {
 
     #[test]
     fn test_2_instances() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 100);
         state
@@ -714,7 +947,7 @@ mod tests {
 
     #[test]
     fn test_right_up_compressed() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 50);
         state
@@ -744,7 +977,7 @@ mod tests {
 
     #[test]
     fn test_right_up_compressed_inflight() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 50);
         state
@@ -777,7 +1010,7 @@ mod tests {
 
     #[test]
     fn test_right_up_compressed_when_frame_starts() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 50);
         state
@@ -808,7 +1041,7 @@ mod tests {
 
     #[test]
     fn test_add_with_zero_compression() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         //add with 0 compression at the beginning
         let template = mock_template(mock_frames1243(1..=4), 0);
@@ -858,7 +1091,7 @@ mod tests {
 
     #[test]
     fn test_square_walking() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 100);
         state
@@ -893,7 +1126,7 @@ mod tests {
 
     #[test]
     fn test_idle_animation() {
-        let mut state = TestState::new();
+        let mut state = AnimationTest::new();
 
         let template = mock_template(mock_frames1243(1..=4), 100);
         state
@@ -928,6 +1161,244 @@ mod tests {
         state.assert_empty_at(Duration::from_millis(20000).as_ticks() + 4000);
     }
 
+    #[test]
+    fn test_idle_rng() {
+        let mut controller = AnimationController::default();
+        controller.add_idle_animation(&mock_template(mock_frames1243(101..=104), 100), 10);
+        controller.add_idle_animation(&mock_template(mock_frames1243(201..=204), 100), 10);
+        let picks = |controller: &AnimationController| -> Vec<u32> {
+            (0..32)
+                .map(|cycle| controller.idle_instance(cycle).unwrap().frames[0].tile_id)
+                .collect()
+        };
+        assert!(picks(&controller).iter().all(|tile_id| *tile_id == 101));
+
+        controller.set_idle_rng(Some(MapRng::new(7)));
+        let seeded = picks(&controller);
+        assert!(seeded.contains(&101) && seeded.contains(&201));
+        let mut other = controller.clone();
+        other.set_idle_rng(Some(MapRng::new(7)));
+        assert_eq!(picks(&other), seeded);
+    }
+
+    #[test]
+    fn test_idle_movement() {
+        let mut state = AnimationTest::new();
+
+        let template = mock_template(mock_frames1243(1..=4), 100);
+        state
+            .controller
+            .add_animation(state.now, &template, (0., 0.), (100., 100.));
+        let mut template = mock_template(mock_frames1243(101..=104), 100);
+        template.idle_movement = (0., -10.);
+        state.controller.add_idle_animation(&template, 10);
+
+        let idle_start = Duration::from_millis(10000).as_ticks() + 1000;
+        state.assert_frame_at(idle_start, 101, (100., 100.));
+        // A quarter of the animation: the top of the bob.
+        state.assert_frame_at(idle_start + 250, 102, (100., 90.));
+        state.assert_frame_at(idle_start + 500, 103, (100., 100.));
+        state.assert_frame_at(idle_start + 750, 104, (100., 110.));
+    }
+
+    #[test]
+    fn test_fallback_tile() {
+        let mut state = AnimationTest::new();
+        state.assert_empty_at(0);
+
+        state.controller.set_fallback_tile(50);
+        // No position yet.
+        state.assert_empty_at(0);
+
+        state.controller.set_position(state.start_time, (10., 20.));
+        state.assert_frame_at(0, 50, (10., 20.));
+
+        state.controller.set_facing_fallback(Facing::West, 51);
+        state.assert_frame_at(0, 50, (10., 20.));
+        state.controller.set_facing(Facing::West);
+        state.assert_frame_at(0, 51, (10., 20.));
+
+        let template = mock_template(mock_frames1243(1..=4), 100);
+        state
+            .controller
+            .add_animation(state.start_time, &template, (100., 0.), (10., 20.));
+        state.assert_frame_at(500, 3, (60., 20.));
+        state.assert_frame_at(1100, 51, (110., 20.));
+    }
+
+    #[test]
+    fn test_interrupting() {
+        let mut state = AnimationTest::new();
+
+        let mut walk = mock_template(mock_frames1243(1..=4), 100);
+        walk.cancel_frame = Some(3);
+        for _ in 0..3 {
+            state
+                .controller
+                .add_animation(state.now, &walk, (100., 0.), (0., 0.));
+        }
+        assert_eq!(3, state.controller.len());
+
+        state.assert_frame_at(150, 2, (15., 0.));
+        let death = mock_template(mock_frames1243(5..=8), 100);
+        state
+            .controller
+            .add_animation_interrupting(state.now, &death, (0., 0.), (0., 0.));
+
+        // The walk can't be cancelled yet, so it ends, then death plays.
+        // The queued walks are dropped.
+        assert_eq!(2, state.controller.len());
+        state.assert_in_interval(990, 4, (99., 0.));
+        state.assert_in_interval(1010, 5, (100., 0.));
+        state.assert_empty_at(2010);
+    }
+
+    #[test]
+    fn test_interrupting_keeps_uncancellable() {
+        let mut state = AnimationTest::new();
+
+        let walk = mock_template(mock_frames1243(1..=4), 100);
+        let mut step = walk.clone();
+        step.cancel_frame = Some(0);
+        for template in [&walk, &step, &walk, &step] {
+            state
+                .controller
+                .add_animation(state.now, template, (100., 0.), (0., 0.));
+        }
+
+        state.assert_frame_at(150, 2, (15., 0.));
+        let death = mock_template(mock_frames1243(5..=8), 100);
+        state
+            .controller
+            .add_animation_interrupting(state.now, &death, (0., 0.), (0., 0.));
+
+        // Everything up to the last walk without a cancel frame still plays,
+        // only the cancellable step at the end is dropped.
+        assert_eq!(4, state.controller.len());
+        state.assert_in_interval(2990, 4, (299., 0.));
+        state.assert_in_interval(3010, 5, (300., 0.));
+        state.assert_empty_at(4010);
+    }
+
+    #[test]
+    fn test_interrupting_after_finished() {
+        let mut state = AnimationTest::new();
+
+        let mut walk = mock_template(mock_frames1243(1..=4), 100);
+        walk.cancel_frame = Some(0);
+        state
+            .controller
+            .add_animation(state.now, &walk, (100., 0.), (0., 0.));
+
+        // No update() since the walk ended: it must not be mistaken for the one playing.
+        state.now = state.start_time + Duration::from_ticks(1500);
+        let death = mock_template(mock_frames1243(5..=8), 100);
+        state
+            .controller
+            .add_animation_interrupting(state.now, &death, (0., 0.), (100., 0.));
+        assert_eq!(1, state.controller.len());
+        state.assert_in_interval(1510, 5, (100., 0.));
+    }
+
+    #[test]
+    fn test_interrupting_cancellable() {
+        let mut state = AnimationTest::new();
+
+        let mut walk = mock_template(mock_frames1243(1..=4), 100);
+        walk.cancel_frame = Some(1);
+        state
+            .controller
+            .add_animation(state.now, &walk, (100., 0.), (0., 0.));
+        state
+            .controller
+            .add_animation(state.now, &walk, (100., 0.), (0., 0.));
+
+        // Frame 0 is not cancellable yet.
+        state.assert_frame_at(50, 1, (5., 0.));
+        let hurt = mock_template(mock_frames1243(5..=8), 100);
+        state
+            .controller
+            .add_animation_interrupting(state.now, &hurt, (0., 0.), (0., 0.));
+        assert_eq!(2, state.controller.len());
+
+        state.assert_in_interval(1010, 5, (100., 0.));
+        state.assert_empty_at(2100);
+
+        // Frame 1 is.
+        state
+            .controller
+            .add_animation(state.now, &walk, (100., 0.), (100., 0.));
+        state.assert_frame_at(2250, 2, (115., 0.));
+        state
+            .controller
+            .add_animation_interrupting(state.now, &hurt, (0., 0.), (0., 0.));
+        assert_eq!(1, state.controller.len());
+        state.assert_in_interval(2260, 5, (115., 0.));
+    }
+
+    #[test]
+    fn test_triggers() {
+        let mut state = AnimationTest::new();
+
+        let mut template = mock_template(mock_frames1243(1..=4), 50);
+        template.triggers = vec![
+            AnimationTrigger::from_property(
+                "trigger_frame_0",
+                &PropertyValue::StringValue("sfx:swing".to_string()),
+            )
+            .unwrap(),
+            AnimationTrigger::from_property(
+                "trigger_frame_3",
+                &PropertyValue::StringValue("vfx:spark".to_string()),
+            )
+            .unwrap(),
+        ];
+        assert_eq!("sfx", template.triggers[0].kind);
+        assert_eq!("swing", template.triggers[0].payload);
+
+        state
+            .controller
+            .add_animation(state.now, &template, (0., 0.), (0., 0.));
+        state.assert_frame_at(50, 1, (0., 0.));
+        let triggers = state.controller.drain_triggers();
+        assert_eq!(
+            vec!["swing"],
+            triggers.iter().map(|t| &t.payload).collect::<Vec<_>>()
+        );
+        assert!(state.controller.drain_triggers().is_empty());
+
+        // Compression drops the played frames, frame numbers must still match.
+        state.assert_frame_at(350, 3, (0., 0.));
+        state
+            .controller
+            .add_animation(state.now, &template, (0., 0.), (0., 0.));
+        assert!(state.controller.drain_triggers().is_empty());
+        state.assert_in_interval(673, 4, (0., 0.));
+        let triggers = state.controller.drain_triggers();
+        assert_eq!(
+            vec!["spark"],
+            triggers.iter().map(|t| &t.payload).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_frame_memo() {
+        let mut state = AnimationTest::new();
+
+        let template = mock_template(mock_frames1243(1..=4), 100);
+        state
+            .controller
+            .add_animation(state.now, &template, (100., 0.), (0., 0.));
+
+        state.assert_frame_at(150, 2, (15., 0.));
+        let frame = state.controller.update(state.now);
+        assert_eq!(state.controller.get_frame(state.now), frame);
+
+        // Changing the queue invalidates the memo.
+        state.controller.dequeue();
+        assert_eq!(state.controller.get_frame(state.now), None);
+    }
+
     #[test]
     fn test_duration() {
         for d in [100, 1000, 5000, 10000] {
@@ -0,0 +1,133 @@
+use coarsetime::{Duration, Instant};
+
+use crate::animation_controller::{AnimationController, OutputFrame};
+
+/// Beyond `distance` from the camera, entities update every `interval` at most,
+/// and with `snap`, skip their queued animations to where they end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodBand {
+    /// In the units of the entity positions.
+    pub distance: f32,
+    pub interval: Duration,
+    pub snap: bool,
+}
+
+/// Many animated entities, e.g. thousands of ambient critters, updated at reduced rates
+/// the farther they are from the camera, see `LodBand`. Entities are indexed in the order
+/// they're added.
+#[derive(Clone, Debug, Default)]
+pub struct AnimationWorld {
+    controllers: Vec<AnimationController>,
+    /// Band of each entity, 0 being full rate and `n` being beyond `bands[n - 1]`.
+    lod: Vec<usize>,
+    /// By increasing distance.
+    bands: Vec<LodBand>,
+    /// How far past a band edge an entity must go to change band, so that entities
+    /// on the edge don't flicker between rates.
+    pub hysteresis: f32,
+}
+
+impl AnimationWorld {
+    pub fn new(mut bands: Vec<LodBand>, hysteresis: f32) -> Self {
+        bands.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        Self {
+            bands,
+            hysteresis,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the entity index.
+    pub fn add(&mut self, controller: AnimationController) -> usize {
+        self.controllers.push(controller);
+        self.lod.push(0);
+        self.controllers.len() - 1
+    }
+
+    pub fn get(&self, entity: usize) -> Option<&AnimationController> {
+        self.controllers.get(entity)
+    }
+
+    pub fn get_mut(&mut self, entity: usize) -> Option<&mut AnimationController> {
+        self.controllers.get_mut(entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.controllers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.controllers.is_empty()
+    }
+
+    /// 0 is full rate, see `LodBand`.
+    pub fn band_of(&self, entity: usize) -> Option<usize> {
+        self.lod.get(entity).copied()
+    }
+
+    /// Updates the entities due at `now`, given the `camera` position.
+    /// Entities not updated yet are at full rate.
+    pub fn update(&mut self, now: Instant, camera: (f32, f32)) {
+        for (controller, band) in self.controllers.iter_mut().zip(&mut self.lod) {
+            if let Some(position) = controller.last_position() {
+                let distance = (position.0 - camera.0).hypot(position.1 - camera.1);
+                *band = pick_band(&self.bands, *band, distance, self.hysteresis);
+            }
+            let Some(lod) = band.checked_sub(1).map(|band| self.bands[band]) else {
+                controller.update(now);
+                continue;
+            };
+            let due = match controller.last_update() {
+                Some(last_update) => now.duration_since(last_update) >= lod.interval,
+                None => true,
+            };
+            if due {
+                if lod.snap {
+                    controller.finish(now);
+                }
+                controller.update(now);
+            }
+        }
+    }
+
+    /// The frame of `entity` as of its last update: far entities stay on it between updates.
+    pub fn get_frame(&self, entity: usize) -> Option<OutputFrame> {
+        let controller = self.controllers.get(entity)?;
+        controller.get_frame(controller.last_update()?)
+    }
+}
+
+/// The band at `distance` for an entity in band `current`.
+fn pick_band(bands: &[LodBand], current: usize, distance: f32, hysteresis: f32) -> usize {
+    let mut band = current.min(bands.len());
+    while band < bands.len() && distance > bands[band].distance + hysteresis {
+        band += 1;
+    }
+    while band > 0 && distance < bands[band - 1].distance - hysteresis {
+        band -= 1;
+    }
+    band
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_band() {
+        let band = |distance| LodBand {
+            distance,
+            interval: Duration::from_ticks(0),
+            snap: false,
+        };
+        let bands = [band(100.0), band(200.0)];
+
+        assert_eq!(pick_band(&bands, 0, 50.0, 10.0), 0);
+        assert_eq!(pick_band(&bands, 0, 250.0, 10.0), 2);
+        // Within the hysteresis of an edge, entities keep their band.
+        assert_eq!(pick_band(&bands, 0, 105.0, 10.0), 0);
+        assert_eq!(pick_band(&bands, 1, 95.0, 10.0), 1);
+        assert_eq!(pick_band(&bands, 1, 85.0, 10.0), 0);
+        assert_eq!(pick_band(&bands, 2, 195.0, 10.0), 2);
+    }
+}
@@ -0,0 +1,141 @@
+use macroquad::color::Color;
+use macroquad::math::{IVec2, Rect, Vec2};
+use macroquad::shapes::draw_triangle;
+
+use crate::map::{world_px_to_screen, Map};
+use crate::raycast::raycast;
+use crate::shapes::{ellipse_cells, line_cells};
+
+/// The standard area of effect templates of tactical games, in tiles.
+/// See `cells()` for the cells they hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AoeShape {
+    /// The center and `arm` cells along each axis from it, e.g. a cross-shaped blast.
+    Cross { arm: i32 },
+    /// The cells within `radius` tiles of the center, as `ellipse_cells()`, e.g. a grenade.
+    Circle { radius: i32 },
+    /// The cells in front of the caster, within `range` tiles and a full width of `angle`
+    /// radians around `direction`, e.g. a breath. The caster's cell is left out.
+    Cone {
+        direction: Vec2,
+        angle: f32,
+        range: i32,
+    },
+    /// `length` cells from the caster along `direction`, e.g. a lightning bolt.
+    /// The caster's cell is left out.
+    Line { direction: Vec2, length: i32 },
+}
+
+impl AoeShape {
+    /// The cells hit from `origin`: those of the template which a ray from the center
+    /// of `origin` reaches, see `raycast()`, before a cell for which `blocks(cell)`,
+    /// e.g. `CollisionGrid::is_solid()`. Blocking cells are hit, not what's behind them.
+    pub fn cells(&self, origin: IVec2, blocks: impl Fn(IVec2) -> bool) -> Vec<IVec2> {
+        let template = match *self {
+            AoeShape::Cross { arm } => {
+                let mut cells = vec![origin];
+                for side in [IVec2::X, -IVec2::X, IVec2::Y, -IVec2::Y] {
+                    cells.extend((1..=arm).map(|i| origin + side * i));
+                }
+                cells
+            }
+            AoeShape::Circle { radius } => ellipse_cells(origin, IVec2::splat(radius), true),
+            AoeShape::Cone {
+                direction,
+                angle,
+                range,
+            } => ellipse_cells(origin, IVec2::splat(range), true)
+                .into_iter()
+                .filter(|cell| {
+                    let offset = (*cell - origin).as_vec2();
+                    // A little slack, for the cells right on the edges.
+                    *cell != origin && offset.angle_between(direction).abs() <= angle / 2.0 + 1e-4
+                })
+                .collect(),
+            AoeShape::Line { direction, length } => {
+                let end = direction.normalize_or_zero() * length as f32;
+                let mut cells = line_cells(origin, origin + end.round().as_ivec2());
+                cells.remove(0);
+                cells
+            }
+        };
+        template
+            .into_iter()
+            .filter(|cell| reaches(origin, *cell, &blocks))
+            .collect()
+    }
+}
+
+/// If a ray from the center of `origin` to the center of `cell` isn't blocked before it.
+fn reaches(origin: IVec2, cell: IVec2, blocks: &impl Fn(IVec2) -> bool) -> bool {
+    let (from, to) = (origin.as_vec2() + 0.5, cell.as_vec2() + 0.5);
+    let hit = raycast(from, to - from, from.distance(to), |crossed| {
+        crossed != origin && blocks(crossed)
+    });
+    hit.cell.is_none_or(|hit| hit == cell)
+}
+
+/// Fills `cells` with `color`, e.g. a translucent one to preview the cells of an `AoeShape`
+/// under the cursor. `source_px` and `dest` are the same as for `Map::draw_tiles()`.
+pub fn draw_highlight(map: &Map, cells: &[IVec2], source_px: Rect, dest: Rect, color: Color) {
+    for cell in cells {
+        let outline: Vec<_> = map
+            .cell_outline(*cell)
+            .into_iter()
+            .map(|corner| world_px_to_screen(corner, source_px, dest))
+            .collect();
+        for pair in outline[1..].windows(2) {
+            draw_triangle(outline[0], pair[0], pair[1], color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::{ivec2, vec2};
+
+    #[test]
+    fn test_cells() {
+        let open = |_: IVec2| false;
+        assert_eq!(AoeShape::Cross { arm: 2 }.cells(ivec2(5, 5), open).len(), 9);
+        assert_eq!(
+            AoeShape::Circle { radius: 1 }
+                .cells(ivec2(5, 5), open)
+                .len(),
+            9
+        );
+
+        let line = AoeShape::Line {
+            direction: vec2(1., 0.),
+            length: 3,
+        };
+        assert_eq!(
+            line.cells(ivec2(0, 0), open),
+            vec![ivec2(1, 0), ivec2(2, 0), ivec2(3, 0)]
+        );
+
+        let cone = AoeShape::Cone {
+            direction: vec2(0., 1.),
+            angle: std::f32::consts::FRAC_PI_2,
+            range: 2,
+        };
+        let cells = cone.cells(ivec2(0, 0), open);
+        assert!(cells.contains(&ivec2(0, 2)) && cells.contains(&ivec2(1, 1)));
+        assert!(!cells.contains(&ivec2(0, -1)) && !cells.contains(&ivec2(2, 1)));
+    }
+
+    #[test]
+    fn test_blocked() {
+        // A wall at x = 2.
+        let wall = |cell: IVec2| cell.x == 2;
+        let cross = AoeShape::Cross { arm: 3 }.cells(ivec2(0, 0), wall);
+        assert!(cross.contains(&ivec2(2, 0)));
+        assert!(!cross.contains(&ivec2(3, 0)));
+        assert!(cross.contains(&ivec2(-3, 0)));
+
+        let circle = AoeShape::Circle { radius: 3 }.cells(ivec2(0, 0), wall);
+        assert!(circle.iter().all(|cell| cell.x <= 2));
+        assert!(circle.contains(&ivec2(0, 3)));
+    }
+}
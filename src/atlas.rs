@@ -0,0 +1,231 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use macroquad::color::Color;
+use macroquad::math::{uvec2, vec2, UVec2};
+use macroquad::texture::{FilterMode, Image, Texture2D};
+use macroquad::window::next_frame;
+
+use crate::map::Map;
+
+/// The largest atlas `Map::build_atlas()` makes, in pixels per side, which most GPUs support.
+/// Tilesets that don't fit keep their own textures.
+pub const MAX_ATLAS_SIZE: u32 = 4096;
+
+impl Map {
+    /// Packs the images of the tilesets into one texture, the atlas, which they all draw
+    /// from: macroquad batches draws of the same texture, maps of many tilesets then
+    /// don't switch textures between their tiles. Call it once after loading, it reads
+    /// the textures back from the GPU. Returns the number of tilesets packed.
+    /// Tilesets of the same image, see `TextureCache`, share its place in the atlas.
+    ///
+    /// Tilesets still streaming, see `LoadOptions::stream_textures`, and those whose
+    /// texture was replaced by one of another size, are left out, as are those which
+    /// don't fit in `MAX_ATLAS_SIZE`. `TileSet::set_texture()` takes a tileset out.
+    pub fn build_atlas(&mut self) -> usize {
+        let images = self
+            .atlas_groups()
+            .into_iter()
+            .filter_map(|group| self.group_image(group))
+            .collect();
+        self.pack_atlas(images)
+    }
+
+    /// Same as `build_atlas()`, as a coroutine reading back `tilesets_per_frame` textures
+    /// then waiting for the next frame, e.g.
+    /// `start_coroutine(async move { map.build_atlas_async(2).await; map })`.
+    pub async fn build_atlas_async(&mut self, tilesets_per_frame: usize) -> usize {
+        let mut images = vec![];
+        for (i, group) in self.atlas_groups().into_iter().enumerate() {
+            if i > 0 && i % tilesets_per_frame.max(1) == 0 {
+                next_frame().await;
+            }
+            images.extend(self.group_image(group));
+        }
+        self.pack_atlas(images)
+    }
+
+    /// The tilesets to pack, grouped by image, each read back and packed once, in the same
+    /// order whatever the order of the map, for the same atlas.
+    fn atlas_groups(&self) -> Vec<Vec<String>> {
+        let mut names: Vec<&String> = self.tilesets.keys().collect();
+        names.sort();
+        let mut groups: Vec<Vec<String>> = vec![];
+        let mut group_of: HashMap<_, usize> = HashMap::new();
+        for name in names {
+            match self.tilesets[name].texture_key() {
+                Some(key) => match group_of.entry(key) {
+                    Entry::Occupied(group) => groups[*group.get()].push(name.clone()),
+                    Entry::Vacant(group) => {
+                        group.insert(groups.len());
+                        groups.push(vec![name.clone()]);
+                    }
+                },
+                None => groups.push(vec![name.clone()]),
+            }
+        }
+        groups
+    }
+
+    /// The image of the tilesets of `group` which draw it at full res, and those tilesets.
+    fn group_image(&self, group: Vec<String>) -> Option<(Vec<String>, Image)> {
+        let group: Vec<String> = group
+            .into_iter()
+            .filter(|name| self.tilesets[name].fits_atlas())
+            .collect();
+        let image = self.tilesets[group.first()?].atlas_image()?;
+        Some((group, image))
+    }
+
+    /// Packs `images`, (tilesets, image), and draws their tilesets from the atlas.
+    /// Returns the number of tilesets packed.
+    fn pack_atlas(&mut self, images: Vec<(Vec<String>, Image)>) -> usize {
+        let sizes: Vec<_> = images
+            .iter()
+            .map(|(_, image)| uvec2(image.width as u32, image.height as u32))
+            .collect();
+        let (places, size) = pack_shelves(&sizes, MAX_ATLAS_SIZE);
+        // A single image gains nothing.
+        if places.iter().flatten().count() < 2 {
+            return 0;
+        }
+
+        let atlas = compose_atlas(images.iter().map(|(_, image)| image), &places, size);
+        let texture = Texture2D::from_image(&atlas);
+        texture.set_filter(FilterMode::Nearest);
+
+        let mut packed = 0;
+        for ((names, _), place) in images.iter().zip(&places) {
+            let Some(place) = place else {
+                continue;
+            };
+            for name in names {
+                if let Some(tileset) = self.tilesets.get_mut(name) {
+                    let offset = vec2(place.x as f32, place.y as f32);
+                    tileset.set_atlas(texture.clone(), offset, size.as_vec2());
+                    packed += 1;
+                }
+            }
+        }
+        // Baked meshes hold the textures of the tilesets.
+        self.textures_changed();
+        packed
+    }
+}
+
+/// An atlas of `size` pixels with `images` copied at `places`, see `pack_shelves()`.
+fn compose_atlas<'a>(
+    images: impl Iterator<Item = &'a Image>,
+    places: &[Option<UVec2>],
+    size: UVec2,
+) -> Image {
+    let mut atlas =
+        Image::gen_image_color(size.x as u16, size.y as u16, Color::new(0.0, 0.0, 0.0, 0.0));
+    for (image, place) in images.zip(places) {
+        let Some(place) = place else {
+            continue;
+        };
+        let row_bytes = image.width as usize * 4;
+        for y in 0..image.height as usize {
+            let from = y * row_bytes;
+            let to = ((place.y as usize + y) * size.x as usize + place.x as usize) * 4;
+            atlas.bytes[to..to + row_bytes].copy_from_slice(&image.bytes[from..from + row_bytes]);
+        }
+    }
+    atlas
+}
+
+/// Places rectangles of `sizes` in rows, tallest first, in an atlas at most `max` pixels
+/// per side, about square. Returns where each one is, None if it doesn't fit,
+/// and the size of the atlas.
+fn pack_shelves(sizes: &[UVec2], max: u32) -> (Vec<Option<UVec2>>, UVec2) {
+    let area: u32 = sizes.iter().map(|size| size.x * size.y).sum();
+    let widest = sizes.iter().map(|size| size.x).max().unwrap_or(0);
+    let width = ((area as f32).sqrt().ceil() as u32).max(widest).min(max);
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].y));
+    let mut places = vec![None; sizes.len()];
+    let (mut cursor, mut shelf_height, mut used) = (UVec2::ZERO, 0, UVec2::ZERO);
+    for i in order {
+        let size = sizes[i];
+        if size.x > width {
+            continue;
+        }
+        if cursor.x + size.x > width {
+            cursor = uvec2(0, cursor.y + shelf_height);
+            shelf_height = 0;
+        }
+        if cursor.y + size.y > max {
+            continue;
+        }
+        places[i] = Some(cursor);
+        used = used.max(cursor + size);
+        cursor.x += size.x;
+        shelf_height = shelf_height.max(size.y);
+    }
+    (places, used)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::stand_in_map;
+
+    #[test]
+    fn test_pack_shelves() {
+        let sizes = [uvec2(64, 32), uvec2(64, 64), uvec2(32, 32), uvec2(200, 8)];
+        let (places, size) = pack_shelves(&sizes, 128);
+        assert_eq!(places[1], Some(uvec2(0, 0)));
+        assert_eq!(places[0], Some(uvec2(64, 0)));
+        assert_eq!(places[2], Some(uvec2(0, 64)));
+        // Wider than the atlas.
+        assert_eq!(places[3], None);
+        assert_eq!(size, uvec2(128, 96));
+    }
+
+    #[test]
+    fn test_atlas_groups() {
+        let map = stand_in_map(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="1" height="1" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" source="tiny.tsx"/>
+ <tileset firstgid="5" name="copy" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiny.png" width="32" height="32"/>
+ </tileset>
+ <tileset firstgid="9" name="other" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="other.png" width="32" height="32"/>
+ </tileset>
+</map>"#,
+        );
+        // "copy" and "tiny" cut the same image: it's read back and packed once.
+        assert_eq!(
+            map.atlas_groups(),
+            vec![
+                vec!["copy".to_string(), "tiny".to_string()],
+                vec!["other".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compose_atlas() {
+        let (red, blue) = (
+            Color::from_rgba(255, 0, 0, 255),
+            Color::from_rgba(0, 0, 255, 255),
+        );
+        let (wide, tall) = (
+            Image::gen_image_color(2, 1, red),
+            Image::gen_image_color(1, 2, blue),
+        );
+        let places = [Some(uvec2(1, 0)), Some(uvec2(0, 1)), None];
+        let atlas = compose_atlas([&wide, &tall, &wide].into_iter(), &places, uvec2(3, 3));
+        assert_eq!((atlas.width, atlas.height), (3, 3));
+        assert_eq!(atlas.get_pixel(1, 0), red);
+        assert_eq!(atlas.get_pixel(2, 0), red);
+        assert_eq!(atlas.get_pixel(0, 1), blue);
+        assert_eq!(atlas.get_pixel(0, 2), blue);
+        assert_eq!(atlas.get_pixel(0, 0).a, 0.0);
+        assert_eq!(atlas.get_pixel(2, 2).a, 0.0);
+    }
+}
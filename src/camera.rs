@@ -0,0 +1,335 @@
+use coarsetime::{Duration, Instant};
+use macroquad::math::{ivec2, vec2, IVec2, Rect, Vec2};
+
+use crate::animation_controller::AnimationTrigger;
+
+/// How often the shake picks a new offset, in milliseconds.
+const SHAKE_STEP_MS: u64 = 33;
+
+/// A camera for pixel art. Positions are quantized to integer world pixels,
+/// and screen shake moves by whole world pixels only, so there's no sub-pixel jitter.
+#[derive(Clone, Debug)]
+pub struct PixelCamera {
+    /// Center of the view, in world pixels.
+    pub position: Vec2,
+    /// Screen pixels per world pixel. Keep it integer for crisp pixels,
+    /// see `resolution::integer_zoom()`.
+    pub zoom: f32,
+    shake: Option<Shake>,
+    pan: Option<Pan>,
+    punch: Option<Punch>,
+}
+
+/// A transition from `from` to `position`, see `PixelCamera::pan_to()`.
+#[derive(Clone, Copy, Debug)]
+struct Pan {
+    from: Vec2,
+    start: Instant,
+    duration: Duration,
+}
+
+/// A brief zoom in, see `PixelCamera::zoom_punch()`.
+#[derive(Clone, Copy, Debug)]
+struct Punch {
+    start: Instant,
+    duration: Duration,
+    /// Zoom multiplier at the start. Decays linearly to 1.
+    factor: f32,
+}
+
+/// A camera effect fired by an animation frame, for hits that feel heavy without
+/// game code, see `PixelCamera::apply_cues()`. It's an `AnimationTrigger` of kind
+/// "shake" with "amplitude,ms", e.g. `trigger_frame_2 = "shake:4,200"`, or of kind
+/// "zoom" with "factor,ms", e.g. `trigger_frame_2 = "zoom:1.1,150"`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraCue {
+    /// See `PixelCamera::shake()`.
+    Shake { amplitude: f32, ms: u64 },
+    /// See `PixelCamera::zoom_punch()`.
+    ZoomPunch { factor: f32, ms: u64 },
+}
+
+impl CameraCue {
+    /// `None` if `trigger` is not a camera cue, or is malformed.
+    pub fn from_trigger(trigger: &AnimationTrigger) -> Option<Self> {
+        let (value, ms) = trigger.payload.split_once(',')?;
+        let (value, ms) = (value.trim().parse().ok()?, ms.trim().parse().ok()?);
+        match trigger.kind.as_str() {
+            "shake" => Some(CameraCue::Shake {
+                amplitude: value,
+                ms,
+            }),
+            "zoom" => Some(CameraCue::ZoomPunch { factor: value, ms }),
+            _ => None,
+        }
+    }
+
+    /// The trigger firing this cue on `frame`, e.g. for `AnimationTemplate::triggers`.
+    pub fn to_trigger(&self, frame: u32) -> AnimationTrigger {
+        let (kind, value, ms) = match *self {
+            CameraCue::Shake { amplitude, ms } => ("shake", amplitude, ms),
+            CameraCue::ZoomPunch { factor, ms } => ("zoom", factor, ms),
+        };
+        AnimationTrigger {
+            frame,
+            kind: kind.to_string(),
+            payload: format!("{},{}", value, ms),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Shake {
+    start: Instant,
+    duration: Duration,
+    /// Max offset at the start, in world pixels. Decays linearly to 0.
+    amplitude: f32,
+    seed: u32,
+}
+
+impl PixelCamera {
+    pub fn new(position: Vec2, zoom: f32) -> Self {
+        Self {
+            position,
+            zoom,
+            shake: None,
+            pan: None,
+            punch: None,
+        }
+    }
+
+    /// Moves the camera to `target` smoothly over `duration`, e.g. after a teleport.
+    /// `position` is the target right away, the view gets there over time.
+    pub fn pan_to(&mut self, now: Instant, target: Vec2, duration: Duration) {
+        self.pan = Some(Pan {
+            from: self.position_at(now),
+            start: now,
+            duration,
+        });
+        self.position = target;
+    }
+
+    pub fn is_panning(&self, now: Instant) -> bool {
+        matches!(self.pan, Some(pan) if now < pan.start + pan.duration)
+    }
+
+    /// Where the view is centered at `now`, in world pixels: `position`, unless panning.
+    pub fn position_at(&self, now: Instant) -> Vec2 {
+        let Some(pan) = self.pan else {
+            return self.position;
+        };
+        if now >= pan.start + pan.duration || pan.duration.as_ticks() == 0 {
+            return self.position;
+        }
+        let elapsed = now.duration_since(pan.start).as_ticks() as f32;
+        let t = elapsed / pan.duration.as_ticks() as f32;
+        // Smoothstep: no jolt at either end.
+        pan.from.lerp(self.position, t * t * (3.0 - 2.0 * t))
+    }
+
+    /// Moves the camera and its pan by `offset`, in world pixels, without a visible jump
+    /// if the world moves by the same offset, e.g. when changing maps of a `World`.
+    pub fn translate(&mut self, offset: Vec2) {
+        self.position += offset;
+        if let Some(pan) = &mut self.pan {
+            pan.from += offset;
+        }
+    }
+
+    /// Starts shaking, replacing the previous shake if any.
+    pub fn shake(&mut self, now: Instant, amplitude: f32, duration: Duration) {
+        let seed = self
+            .shake
+            .map(|shake| shake.seed.wrapping_add(1))
+            .unwrap_or(0);
+        self.shake = Some(Shake {
+            start: now,
+            duration,
+            amplitude,
+            seed,
+        });
+    }
+
+    pub fn is_shaking(&self, now: Instant) -> bool {
+        matches!(self.shake, Some(shake) if now < shake.start + shake.duration)
+    }
+
+    /// Zooms in by `factor` at once, then back over `duration`, e.g. 1.1 on a heavy hit.
+    /// Replaces the previous punch if any. The zoom isn't integer meanwhile.
+    pub fn zoom_punch(&mut self, now: Instant, factor: f32, duration: Duration) {
+        self.punch = Some(Punch {
+            start: now,
+            duration,
+            factor,
+        });
+    }
+
+    /// Screen pixels per world pixel at `now`: `zoom`, unless punched.
+    pub fn zoom_at(&self, now: Instant) -> f32 {
+        let Some(punch) = self.punch else {
+            return self.zoom;
+        };
+        if now < punch.start || now >= punch.start + punch.duration {
+            return self.zoom;
+        }
+        let elapsed = (now - punch.start).as_ticks();
+        let left = 1.0 - elapsed as f32 / punch.duration.as_ticks() as f32;
+        self.zoom * (1.0 + (punch.factor - 1.0) * left)
+    }
+
+    /// Starts the effect of `cue`.
+    pub fn apply_cue(&mut self, now: Instant, cue: CameraCue) {
+        match cue {
+            CameraCue::Shake { amplitude, ms } => {
+                self.shake(now, amplitude, Duration::from_millis(ms))
+            }
+            CameraCue::ZoomPunch { factor, ms } => {
+                self.zoom_punch(now, factor, Duration::from_millis(ms))
+            }
+        }
+    }
+
+    /// Starts the camera cues among `triggers`, e.g. of `AnimationController::drain_triggers()`,
+    /// and removes them, leaving the others to the game.
+    pub fn apply_cues(&mut self, now: Instant, triggers: &mut Vec<AnimationTrigger>) {
+        triggers.retain(|trigger| match CameraCue::from_trigger(trigger) {
+            Some(cue) => {
+                self.apply_cue(now, cue);
+                false
+            }
+            None => true,
+        });
+    }
+
+    /// The current shake offset, in whole world pixels.
+    pub fn shake_offset(&self, now: Instant) -> IVec2 {
+        let Some(shake) = self.shake else {
+            return IVec2::ZERO;
+        };
+        if now < shake.start || now >= shake.start + shake.duration {
+            return IVec2::ZERO;
+        }
+
+        let elapsed = (now - shake.start).as_ticks();
+        let left = 1.0 - elapsed as f32 / shake.duration.as_ticks() as f32;
+        let amplitude = shake.amplitude * left;
+
+        let step = (elapsed / Duration::from_millis(SHAKE_STEP_MS).as_ticks()) as u32;
+        let x = noise(shake.seed, step * 2);
+        let y = noise(shake.seed, step * 2 + 1);
+        ivec2(
+            (x * amplitude).round() as i32,
+            (y * amplitude).round() as i32,
+        )
+    }
+
+    /// Camera center in whole world pixels, shake included.
+    pub fn quantized_position(&self, now: Instant) -> Vec2 {
+        let offset = self.shake_offset(now);
+        self.position_at(now).round() + vec2(offset.x as f32, offset.y as f32)
+    }
+
+    /// The `source` rect for `Map::draw_tiles()` when drawing into `dest`, in world pixels.
+    /// Its top-left corner is always a whole world pixel.
+    pub fn source(&self, dest: Rect, now: Instant) -> Rect {
+        let size = dest.size() / self.zoom_at(now);
+        let top_left = (self.quantized_position(now) - size / 2.0).round();
+        Rect::new(top_left.x, top_left.y, size.x, size.y)
+    }
+}
+
+/// Deterministic noise in [-1, 1].
+fn noise(seed: u32, n: u32) -> f32 {
+    // A couple of rounds of integer hashing, good enough for a shake.
+    let mut x = seed.wrapping_mul(0x9E37_79B9) ^ n;
+    x = (x ^ (x >> 16)).wrapping_mul(0x7FEB_352D);
+    x = (x ^ (x >> 15)).wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_is_pixel_aligned() {
+        let camera = PixelCamera::new(vec2(100.3, 50.7), 3.0);
+        let dest = Rect::new(0., 0., 800., 600.);
+        let source = camera.source(dest, Instant::now());
+
+        assert_eq!(source.x, source.x.round());
+        assert_eq!(source.y, source.y.round());
+        assert_eq!(source.size(), dest.size() / 3.0);
+    }
+
+    #[test]
+    fn test_pan() {
+        let mut camera = PixelCamera::new(vec2(0., 0.), 1.0);
+        let start = Instant::now();
+        camera.pan_to(start, vec2(100., 0.), Duration::from_millis(1000));
+        assert_eq!(camera.position_at(start), vec2(0., 0.));
+        // coarsetime rounds milliseconds a bit.
+        let halfway = camera.position_at(start + Duration::from_millis(500));
+        assert!((halfway.x - 50.).abs() < 2.);
+        assert!(camera.is_panning(start + Duration::from_millis(999)));
+        assert_eq!(
+            camera.position_at(start + Duration::from_millis(1000)),
+            vec2(100., 0.)
+        );
+
+        camera.translate(vec2(-100., 0.));
+        let moved = camera.position_at(start + Duration::from_millis(500));
+        assert_eq!(moved, halfway - vec2(100., 0.));
+    }
+
+    #[test]
+    fn test_camera_cues() {
+        let mut camera = PixelCamera::new(vec2(0., 0.), 2.0);
+        let start = Instant::now();
+        let sfx = AnimationTrigger {
+            frame: 1,
+            kind: "sfx".to_string(),
+            payload: "hit".to_string(),
+        };
+        let zoom = CameraCue::ZoomPunch {
+            factor: 1.5,
+            ms: 1000,
+        };
+        let shake = CameraCue::Shake {
+            amplitude: 4.0,
+            ms: 1000,
+        };
+        assert_eq!(CameraCue::from_trigger(&zoom.to_trigger(1)), Some(zoom));
+        assert_eq!(CameraCue::from_trigger(&sfx), None);
+
+        let mut triggers = vec![shake.to_trigger(1), sfx.clone(), zoom.to_trigger(1)];
+        camera.apply_cues(start, &mut triggers);
+        assert_eq!(triggers, vec![sfx]);
+        assert!(camera.is_shaking(start));
+        assert_eq!(camera.zoom_at(start), 3.0);
+        let later = camera.zoom_at(start + Duration::from_millis(500));
+        assert!(later > 2.0 && later < 3.0);
+        assert_eq!(camera.zoom_at(start + Duration::from_millis(1000)), 2.0);
+    }
+
+    #[test]
+    fn test_shake_decays() {
+        let mut camera = PixelCamera::new(vec2(0., 0.), 1.0);
+        let start = Instant::now();
+        camera.shake(start, 4.0, Duration::from_millis(1000));
+
+        let mut moved = false;
+        for ms in (0..1000).step_by(10) {
+            let now = start + Duration::from_millis(ms);
+            let offset = camera.shake_offset(now);
+            assert!(offset.x.abs() <= 4 && offset.y.abs() <= 4);
+            moved |= offset != IVec2::ZERO;
+        }
+        assert!(moved);
+
+        let after = start + Duration::from_millis(1000);
+        assert_eq!(camera.shake_offset(after), IVec2::ZERO);
+        assert!(!camera.is_shaking(after));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+
+use coarsetime::Duration;
+use macroquad::math::{ivec2, IVec2};
+
+use crate::editor::TileEdit;
+use crate::map::{Map, TileHandle};
+
+/// How a tile moves in a `CellularSim`. Tiles only move into empty cells of the layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellBehavior {
+    /// Falls down, or slides down diagonally, e.g. sand.
+    Powder,
+    /// Falls like powder, or else spreads sideways, e.g. water.
+    Liquid,
+}
+
+/// Falling-sand style simulation on a layer: tiles with a behavior move one cell per tick.
+/// Only cells near the last changes are checked every tick, and moves go through
+/// `Map::set_tile()`, which invalidates the chunks they touch.
+#[derive(Clone, Debug)]
+pub struct CellularSim {
+    pub layer: usize,
+    /// Time between updates.
+    pub tick: Duration,
+    behaviors: HashMap<(String, u32), CellBehavior>,
+    /// Cells to check on the next tick.
+    active: HashSet<IVec2>,
+    /// Time not yet simulated.
+    pending: Duration,
+    ticks: u64,
+}
+
+impl CellularSim {
+    pub fn new(layer: usize, tick: Duration) -> Self {
+        Self {
+            layer,
+            tick,
+            behaviors: HashMap::new(),
+            active: HashSet::new(),
+            pending: Duration::from_ticks(0),
+            ticks: 0,
+        }
+    }
+
+    /// Makes `tile` move, whatever its flips.
+    pub fn set_behavior(&mut self, tile: &TileHandle, behavior: CellBehavior) {
+        self.behaviors
+            .insert((tile.tileset.clone(), tile.id), behavior);
+    }
+
+    /// Checks `pos` and its neighbors on the next tick, e.g. after editing the layer.
+    pub fn wake(&mut self, pos: IVec2) {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                self.active.insert(pos + ivec2(x, y));
+            }
+        }
+    }
+
+    /// Checks every tile of the layer on the next tick, e.g. after loading the map.
+    pub fn wake_layer(&mut self, map: &Map) {
+        self.active.extend(
+            map.layer_tiles(self.layer)
+                .filter(|(_, tile)| tile.is_some())
+                .map(|(pos, _)| pos),
+        );
+    }
+
+    /// If nothing moved on the last tick, until the next `wake()`.
+    pub fn is_settled(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    /// Runs the ticks due after `dt` more time. Returns the changed cells.
+    pub fn update(&mut self, map: &mut Map, dt: Duration) -> Vec<TileEdit> {
+        self.pending += dt;
+        let mut edits = vec![];
+        while self.pending >= self.tick && self.tick.as_ticks() > 0 {
+            self.pending -= self.tick;
+            edits.extend(self.step(map));
+        }
+        edits
+    }
+
+    /// Runs a single tick. Returns the changed cells.
+    pub fn step(&mut self, map: &mut Map) -> Vec<TileEdit> {
+        self.ticks += 1;
+        let mut cells: Vec<IVec2> = self.active.drain().collect();
+        // Bottom up, so that a column of sand falls together.
+        cells.sort_by_key(|pos| (-pos.y, pos.x));
+
+        let mut moved = HashSet::new();
+        let mut edits = vec![];
+        for pos in cells {
+            if moved.contains(&pos) {
+                continue;
+            }
+            let Some(tile) = map.tile_at(self.layer, pos) else {
+                continue;
+            };
+            let Some(behavior) = self.behaviors.get(&(tile.tileset.clone(), tile.id)) else {
+                continue;
+            };
+            let is_empty =
+                |cell: IVec2| map.contains(cell) && map.tile_ref_at(self.layer, cell).is_none();
+            // Alternate sides, so that piles grow evenly.
+            let left_first = (self.ticks as i64 + pos.x as i64) % 2 == 0;
+            let Some(to) = next_cell(*behavior, pos, is_empty, left_first) else {
+                continue;
+            };
+
+            map.set_tile(self.layer, pos, None);
+            map.set_tile(self.layer, to, Some(tile.clone()));
+            edits.push(TileEdit {
+                layer: self.layer,
+                pos,
+                before: Some(tile.clone()),
+                after: None,
+            });
+            edits.push(TileEdit {
+                layer: self.layer,
+                pos: to,
+                before: None,
+                after: Some(tile),
+            });
+            moved.insert(to);
+            self.wake(pos);
+            self.wake(to);
+        }
+        edits
+    }
+}
+
+/// Where a tile at `pos` moves, if anywhere. +y is down.
+fn next_cell(
+    behavior: CellBehavior,
+    pos: IVec2,
+    is_empty: impl Fn(IVec2) -> bool,
+    left_first: bool,
+) -> Option<IVec2> {
+    let (first, second) = if left_first { (-1, 1) } else { (1, -1) };
+    let mut moves = vec![ivec2(0, 1), ivec2(first, 1), ivec2(second, 1)];
+    if behavior == CellBehavior::Liquid {
+        moves.extend([ivec2(first, 0), ivec2(second, 0)]);
+    }
+    moves
+        .into_iter()
+        .map(|offset| pos + offset)
+        .find(|cell| is_empty(*cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_cell() {
+        // A floor at y = 1, and a wall at x = 1.
+        let is_empty = |cell: IVec2| cell.y < 1 && cell.x != 1;
+        let above_floor = ivec2(0, 0);
+        assert_eq!(
+            next_cell(CellBehavior::Powder, ivec2(0, -1), is_empty, true),
+            Some(above_floor)
+        );
+        assert_eq!(
+            next_cell(CellBehavior::Powder, above_floor, is_empty, true),
+            None
+        );
+        assert_eq!(
+            next_cell(CellBehavior::Liquid, above_floor, is_empty, false),
+            Some(ivec2(-1, 0))
+        );
+    }
+}
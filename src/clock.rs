@@ -0,0 +1,109 @@
+use coarsetime::{Duration, Instant};
+
+/// Map-wide animation time, which can be paused, slowed down, sped up or stepped,
+/// e.g. for pause menus, photo mode or frame-by-frame debugging.
+/// Call `tick()` once per frame, see `Map::update()`.
+#[derive(Clone, Debug)]
+pub struct MapClock {
+    /// Animation time passed so far.
+    elapsed: Duration,
+    last_tick: Option<Instant>,
+    speed: f32,
+    paused: bool,
+}
+
+impl Default for MapClock {
+    fn default() -> Self {
+        Self {
+            elapsed: Duration::from_ticks(0),
+            last_tick: None,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl MapClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the clock by the real time since the previous tick, times the speed.
+    /// The first tick only starts counting.
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(last_tick) = self.last_tick {
+            if !self.paused && now > last_tick {
+                self.elapsed += scale(now - last_tick, self.speed);
+            }
+        }
+        self.last_tick = Some(now);
+    }
+
+    /// Animation time passed so far.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// 1.0 is real time, 0.5 is twice slower. Negative speeds are clamped to 0.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Advances the clock by `dt`, ignoring the speed, even when paused.
+    pub fn step(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+}
+
+fn scale(duration: Duration, speed: f32) -> Duration {
+    Duration::from_ticks((duration.as_ticks() as f64 * speed as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_clock() {
+        let start = Instant::now();
+        let mut clock = MapClock::new();
+        clock.tick(start);
+        clock.tick(start + ms(100));
+        assert_eq!(clock.elapsed(), ms(100));
+
+        clock.set_speed(0.5);
+        clock.tick(start + ms(300));
+        assert_eq!(clock.elapsed(), ms(200));
+
+        clock.pause();
+        clock.tick(start + ms(1000));
+        assert_eq!(clock.elapsed(), ms(200));
+        clock.step(ms(16));
+        assert_eq!(clock.elapsed(), ms(216));
+
+        // Time spent paused doesn't count after resuming.
+        clock.resume();
+        clock.tick(start + ms(1200));
+        assert_eq!(clock.elapsed(), ms(316));
+    }
+}
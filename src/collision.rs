@@ -0,0 +1,432 @@
+use std::fmt;
+
+use macroquad::math::{ivec2, IVec2};
+
+use crate::map::Map;
+use crate::walls::WallPart;
+
+/// Tile property marking a tile as an obstacle.
+pub const SOLID_PROPERTY: &str = "solid";
+/// Tile property with the movement cost over a tile, 1.0 if missing.
+pub const COST_PROPERTY: &str = "cost";
+
+const MAGIC: &[u8; 4] = b"MTCG";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 4 + 1 + 4 * 4;
+
+/// Collision and navigation data of a map, one cell per tile.
+/// It's plain data, so dedicated servers or external AI tools can consume it without
+/// linking Macroquad: export it with `to_bytes()`, or with serde behind the "serde" feature.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawCollisionGrid"))]
+pub struct CollisionGrid {
+    /// The top-left cell, in world tiles.
+    pub origin: (i32, i32),
+    pub width: u32,
+    pub height: u32,
+    /// Row-major, `width * height` cells.
+    pub solid: Vec<bool>,
+    /// Movement costs, row-major, `width * height` cells.
+    pub costs: Vec<f32>,
+}
+
+/// What deserializes into a `CollisionGrid`, before its cells are checked against
+/// its size.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawCollisionGrid {
+    origin: (i32, i32),
+    width: u32,
+    height: u32,
+    solid: Vec<bool>,
+    costs: Vec<f32>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawCollisionGrid> for CollisionGrid {
+    type Error = GridDecodeError;
+
+    fn try_from(raw: RawCollisionGrid) -> Result<Self, Self::Error> {
+        let size = cell_count(raw.width, raw.height)?;
+        if raw.solid.len() != size || raw.costs.len() != size {
+            return Err(GridDecodeError::SizeMismatch);
+        }
+        Ok(Self {
+            origin: raw.origin,
+            width: raw.width,
+            height: raw.height,
+            solid: raw.solid,
+            costs: raw.costs,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GridDecodeError {
+    /// Not a collision grid, or a newer version of the format.
+    BadHeader,
+    /// The data is shorter than the header says.
+    Truncated,
+    /// The width times the height doesn't fit in memory.
+    TooLarge,
+    /// The cells don't match the width and height.
+    SizeMismatch,
+}
+
+impl fmt::Display for GridDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridDecodeError::BadHeader => write!(f, "Not a collision grid of version {VERSION}"),
+            GridDecodeError::Truncated => write!(f, "Collision grid data is truncated"),
+            GridDecodeError::TooLarge => write!(f, "Collision grid is too large"),
+            GridDecodeError::SizeMismatch => {
+                write!(f, "Collision grid cells don't match its width and height")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GridDecodeError {}
+
+/// `width * height`, if it fits.
+fn cell_count(width: u32, height: u32) -> Result<usize, GridDecodeError> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .ok_or(GridDecodeError::TooLarge)
+}
+
+impl CollisionGrid {
+    /// A grid of walkable cells with cost 1.
+    ///
+    /// # Panics
+    /// If `width * height` cells don't fit in memory, see `try_new()`.
+    pub fn new(origin: (i32, i32), width: u32, height: u32) -> Self {
+        Self::try_new(origin, width, height).expect("Collision grid too large")
+    }
+
+    /// A grid of walkable cells with cost 1, or `TooLarge` if `width * height` overflows.
+    pub fn try_new(origin: (i32, i32), width: u32, height: u32) -> Result<Self, GridDecodeError> {
+        let size = cell_count(width, height)?;
+        Ok(Self {
+            origin,
+            width,
+            height,
+            solid: vec![false; size],
+            costs: vec![1.0; size],
+        })
+    }
+
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        let (x, y) = (pos.x - self.origin.0, pos.y - self.origin.1);
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        Some(y as usize * self.width as usize + x as usize)
+    }
+
+    /// If the cell at `pos`, in world tiles, is an obstacle. Cells outside of the grid are.
+    pub fn is_solid(&self, pos: IVec2) -> bool {
+        self.index(pos)
+            .and_then(|i| self.solid.get(i).copied())
+            .unwrap_or(true)
+    }
+
+    /// Movement cost of the cell at `pos`, `None` outside of the grid.
+    pub fn cost(&self, pos: IVec2) -> Option<f32> {
+        self.index(pos).and_then(|i| self.costs.get(i).copied())
+    }
+
+    /// Cells outside of the grid are ignored.
+    pub fn set(&mut self, pos: IVec2, solid: bool, cost: f32) {
+        if let Some(i) = self.index(pos) {
+            self.solid[i] = solid;
+            self.costs[i] = cost;
+        }
+    }
+
+    /// Encodes the grid in a compact little-endian binary format:
+    /// "MTCG", version, origin x & y, width, height, solid bits, costs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let size = self.solid.len();
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + size.div_ceil(8) + size * 4);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.origin.0.to_le_bytes());
+        bytes.extend_from_slice(&self.origin.1.to_le_bytes());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        for bits in self.solid.chunks(8) {
+            let byte = bits
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, solid)| byte | ((*solid as u8) << i));
+            bytes.push(byte);
+        }
+        for cost in &self.costs {
+            bytes.extend_from_slice(&cost.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a grid encoded with `to_bytes()`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GridDecodeError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return Err(GridDecodeError::BadHeader);
+        }
+        let word = |i: usize| {
+            let start = 5 + i * 4;
+            [
+                bytes[start],
+                bytes[start + 1],
+                bytes[start + 2],
+                bytes[start + 3],
+            ]
+        };
+        let origin = (i32::from_le_bytes(word(0)), i32::from_le_bytes(word(1)));
+        let width = u32::from_le_bytes(word(2));
+        let height = u32::from_le_bytes(word(3));
+
+        let size = cell_count(width, height)?;
+        let bits = &bytes[HEADER_SIZE..];
+        let solid_bytes = size.div_ceil(8);
+        let expected = size
+            .checked_mul(4)
+            .and_then(|costs| costs.checked_add(solid_bytes))
+            .ok_or(GridDecodeError::TooLarge)?;
+        if bits.len() != expected {
+            return Err(GridDecodeError::Truncated);
+        }
+        let solid = (0..size)
+            .map(|i| bits[i / 8] & (1 << (i % 8)) != 0)
+            .collect();
+        let costs = bits[solid_bytes..]
+            .chunks_exact(4)
+            .map(|cost| f32::from_le_bytes([cost[0], cost[1], cost[2], cost[3]]))
+            .collect();
+
+        Ok(Self {
+            origin,
+            width,
+            height,
+            solid,
+            costs,
+        })
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("A grid is always serializable")
+    }
+
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[cfg(feature = "ron")]
+    pub fn to_ron(&self) -> String {
+        ron::to_string(self).expect("A grid is always serializable")
+    }
+
+    #[cfg(feature = "ron")]
+    pub fn from_ron(text: &str) -> Result<Self, ron::de::SpannedError> {
+        ron::from_str(text)
+    }
+}
+
+impl Map {
+    /// Builds the collision grid from tile properties of all tile layers:
+    /// a cell is solid if any of its tiles has `solid = true`, or is the front of a wall,
+    /// see `WallPart`, and its cost is the highest `cost` set on its tiles, 1 if none is.
+    /// Runtime overrides and tileset properties count, see `tile_property_at()`.
+    /// Infinite maps get a grid around their tiles.
+    /// With the "rayon" feature, rows are scanned in parallel.
+    pub fn collision_grid(&self) -> CollisionGrid {
+        let cells = self.fold_tiles(
+            Vec::new,
+            |cells, layer, pos, _, _| {
+                let (solid, cost) = self.tile_collision(layer, pos);
+                if solid || cost.is_some() {
+                    cells.push((pos, (solid, cost)));
+                }
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+            |_| {},
+        );
+
+        let mut grid = if self.map.infinite() && cells.is_empty() {
+            CollisionGrid::new((0, 0), 0, 0)
+        } else if self.map.infinite() {
+            let min = cells
+                .iter()
+                .fold(ivec2(i32::MAX, i32::MAX), |min, (pos, ..)| min.min(*pos));
+            let max = cells
+                .iter()
+                .fold(ivec2(i32::MIN, i32::MIN), |max, (pos, ..)| max.max(*pos));
+            let size = max - min + ivec2(1, 1);
+            CollisionGrid::new((min.x, min.y), size.x as u32, size.y as u32)
+        } else {
+            CollisionGrid::new((0, 0), self.map.width, self.map.height)
+        };
+
+        let mut merged = vec![None; grid.solid.len()];
+        for (pos, collision) in cells {
+            let Some(i) = grid.index(pos) else {
+                continue;
+            };
+            let cell: &mut Option<_> = &mut merged[i];
+            *cell = Some(cell.map_or(collision, |cell| merge_collision(cell, collision)));
+        }
+        for (i, cell) in merged.into_iter().enumerate() {
+            if let Some((solid, cost)) = cell {
+                grid.solid[i] = solid;
+                grid.costs[i] = cost.unwrap_or(1.0);
+            }
+        }
+        grid
+    }
+
+    /// Whether the cell `pos` is solid, and its cost if a tile sets one, as
+    /// `collision_grid()` has it, from the tiles of all layers.
+    #[cfg(feature = "editor")]
+    pub(crate) fn cell_collision(&self, pos: IVec2) -> (bool, Option<f32>) {
+        (0..self.layer_count())
+            .map(|layer| self.tile_collision(layer, pos))
+            .fold((false, None), merge_collision)
+    }
+
+    /// Whether the tile of `layer` at `pos` is solid, and its cost if it sets one.
+    fn tile_collision(&self, layer: usize, pos: IVec2) -> (bool, Option<f32>) {
+        let solid = self
+            .tile_bool_at(layer, pos, SOLID_PROPERTY)
+            .unwrap_or(false)
+            || self.wall_part_at(layer, pos) == Some(WallPart::Front);
+        (solid, self.tile_float_at(layer, pos, COST_PROPERTY))
+    }
+}
+
+/// The collision of two tiles of a cell: solid if either is, and the highest cost set.
+fn merge_collision(a: (bool, Option<f32>), b: (bool, Option<f32>)) -> (bool, Option<f32>) {
+    let cost = match (a.1, b.1) {
+        (Some(a), Some(b)) => Some(f32::max(a, b)),
+        (a, b) => a.or(b),
+    };
+    (a.0 || b.0, cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+
+    fn grid() -> CollisionGrid {
+        let mut grid = CollisionGrid::new((-2, 3), 5, 3);
+        grid.set(ivec2(-2, 3), true, 1.0);
+        grid.set(ivec2(2, 5), false, 2.5);
+        grid
+    }
+
+    #[test]
+    fn test_grid_access() {
+        let grid = grid();
+        assert!(grid.is_solid(ivec2(-2, 3)));
+        assert!(!grid.is_solid(ivec2(-1, 3)));
+        assert!(grid.is_solid(ivec2(0, 0)));
+        assert_eq!(grid.cost(ivec2(2, 5)), Some(2.5));
+        assert_eq!(grid.cost(ivec2(3, 5)), None);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let grid = grid();
+        let bytes = grid.to_bytes();
+        assert_eq!(CollisionGrid::from_bytes(&bytes), Ok(grid));
+        assert_eq!(
+            CollisionGrid::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(GridDecodeError::Truncated)
+        );
+        assert_eq!(
+            CollisionGrid::from_bytes(b"nope"),
+            Err(GridDecodeError::BadHeader)
+        );
+
+        // A forged header with a huge size must not overflow nor allocate.
+        let mut forged = bytes[..HEADER_SIZE].to_vec();
+        forged[13..21].copy_from_slice(&[0xff; 8]);
+        assert!(CollisionGrid::from_bytes(&forged).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_roundtrip() {
+        let grid = grid();
+        assert_eq!(CollisionGrid::from_json(&grid.to_json()).unwrap(), grid);
+
+        let short = r#"{"origin":[0,0],"width":2,"height":2,"solid":[false],"costs":[1.0]}"#;
+        assert!(CollisionGrid::from_json(short).is_err());
+    }
+
+    #[cfg(feature = "ron")]
+    #[test]
+    fn test_ron_roundtrip() {
+        let grid = grid();
+        assert_eq!(CollisionGrid::from_ron(&grid.to_ron()).unwrap(), grid);
+
+        let short = "(origin: (0, 0), width: 2, height: 2, solid: [false], costs: [1.0])";
+        assert!(CollisionGrid::from_ron(short).is_err());
+    }
+
+    #[test]
+    fn test_grid_costs() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let cost = |cost| Some(tiled::PropertyValue::FloatValue(cost));
+        map.set_runtime_property(ground, ivec2(0, 0), COST_PROPERTY, cost(0.5));
+        map.set_runtime_property(ground, ivec2(1, 1), COST_PROPERTY, cost(3.0));
+        let grid = map.collision_grid();
+        // The cost set on a wall, 1 by default.
+        assert!(grid.is_solid(ivec2(0, 0)));
+        assert_eq!(grid.cost(ivec2(0, 0)), Some(0.5));
+        assert_eq!(grid.cost(ivec2(1, 0)), Some(1.0));
+        assert_eq!(grid.cost(ivec2(1, 1)), Some(3.0));
+
+        assert_eq!(
+            merge_collision((true, None), (false, Some(2.0))),
+            (true, Some(2.0))
+        );
+        assert_eq!(
+            merge_collision((false, Some(4.0)), (false, Some(2.0))),
+            (false, Some(4.0))
+        );
+    }
+
+    #[cfg(feature = "editor")]
+    #[test]
+    fn test_cell_collision() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_runtime_property(
+            ground,
+            ivec2(1, 1),
+            COST_PROPERTY,
+            Some(tiled::PropertyValue::FloatValue(3.0)),
+        );
+        let grid = map.collision_grid();
+        for y in 0..4 {
+            for x in 0..4 {
+                let pos = ivec2(x, y);
+                let (solid, cost) = map.cell_collision(pos);
+                assert_eq!(solid, grid.is_solid(pos), "{}", pos);
+                assert_eq!(cost.or(Some(1.0)), grid.cost(pos), "{}", pos);
+            }
+        }
+        // Walls around a floor.
+        assert_eq!(map.cell_collision(ivec2(0, 0)), (true, None));
+        assert_eq!(map.cell_collision(ivec2(1, 1)), (false, Some(3.0)));
+        assert_eq!(map.cell_collision(ivec2(2, 2)), (false, None));
+    }
+}
@@ -0,0 +1,228 @@
+use macroquad::math::{vec2, IVec2, Rect, Vec2};
+use tiled::{ObjectData, ObjectShape, TileId};
+
+use crate::map::Map;
+use crate::tileset::TileSet;
+
+/// A collision shape of a tile, drawn in the collision editor of Tiled,
+/// see `TileSet::collision_shapes()`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Rect(Rect),
+    /// Within the bounding rect.
+    Ellipse(Rect),
+    Polygon(Vec<Vec2>),
+    Polyline(Vec<Vec2>),
+    Point(Vec2),
+}
+
+impl Shape {
+    /// `object` as a shape, its points relative to its position made absolute.
+    /// Rotated rects become polygons, ellipses ignore rotation. `None` for texts.
+    pub fn from_object(object: &ObjectData) -> Option<Self> {
+        let origin = vec2(object.x, object.y);
+        let rotation = Vec2::from_angle(object.rotation.to_radians());
+        let point = |x: f32, y: f32| origin + rotation.rotate(vec2(x, y));
+        let points = |points: &[(f32, f32)]| points.iter().map(|(x, y)| point(*x, *y)).collect();
+        Some(match &object.shape {
+            ObjectShape::Rect { width, height } if object.rotation != 0.0 => Shape::Polygon(vec![
+                point(0.0, 0.0),
+                point(*width, 0.0),
+                point(*width, *height),
+                point(0.0, *height),
+            ]),
+            ObjectShape::Rect { width, height } => {
+                Shape::Rect(Rect::new(origin.x, origin.y, *width, *height))
+            }
+            ObjectShape::Ellipse { width, height } => {
+                Shape::Ellipse(Rect::new(origin.x, origin.y, *width, *height))
+            }
+            ObjectShape::Polygon { points: shape } => Shape::Polygon(points(shape)),
+            ObjectShape::Polyline { points: shape } => Shape::Polyline(points(shape)),
+            ObjectShape::Point(..) => Shape::Point(origin),
+            ObjectShape::Text { .. } => return None,
+        })
+    }
+
+    /// The shape with its points moved by `transform`, which must keep rects axis-aligned,
+    /// e.g. offsets, scales, flips and transpositions.
+    pub fn map_points(&self, transform: impl Fn(Vec2) -> Vec2) -> Self {
+        let rect = |rect: &Rect| {
+            let (a, b) = (
+                transform(rect.point()),
+                transform(rect.point() + rect.size()),
+            );
+            let min = a.min(b);
+            let size = a.max(b) - min;
+            Rect::new(min.x, min.y, size.x, size.y)
+        };
+        match self {
+            Shape::Rect(bounds) => Shape::Rect(rect(bounds)),
+            Shape::Ellipse(bounds) => Shape::Ellipse(rect(bounds)),
+            Shape::Polygon(points) => {
+                Shape::Polygon(points.iter().copied().map(transform).collect())
+            }
+            Shape::Polyline(points) => {
+                Shape::Polyline(points.iter().copied().map(transform).collect())
+            }
+            Shape::Point(point) => Shape::Point(transform(*point)),
+        }
+    }
+
+    /// If `point` is inside of the shape. Polylines and points have no inside.
+    pub fn contains(&self, point: Vec2) -> bool {
+        match self {
+            Shape::Rect(bounds) => bounds.contains(point),
+            Shape::Ellipse(bounds) => {
+                let radius = bounds.size() / 2.0;
+                let offset = (point - bounds.center()) / radius;
+                radius.min_element() > 0.0 && offset.length_squared() <= 1.0
+            }
+            // Even-odd rule.
+            Shape::Polygon(points) => {
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    if (a.y > point.y) != (b.y > point.y)
+                        && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+            Shape::Polyline(_) | Shape::Point(_) => false,
+        }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Shape::Rect(bounds) | Shape::Ellipse(bounds) => *bounds,
+            Shape::Point(point) => Rect::new(point.x, point.y, 0.0, 0.0),
+            Shape::Polygon(points) | Shape::Polyline(points) => {
+                let Some(first) = points.first() else {
+                    return Rect::default();
+                };
+                let (min, max) = points
+                    .iter()
+                    .fold((*first, *first), |(min, max), p| (min.min(*p), max.max(*p)));
+                Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+            }
+        }
+    }
+}
+
+/// Where a point of a tile of `size` ends up once the tile is flipped like in Tiled:
+/// diagonally first, then horizontally, then vertically.
+pub fn flip_point(point: Vec2, size: Vec2, flip_h: bool, flip_v: bool, flip_d: bool) -> Vec2 {
+    let mut point = if flip_d {
+        vec2(point.y, point.x)
+    } else {
+        point
+    };
+    if flip_h {
+        point.x = size.x - point.x;
+    }
+    if flip_v {
+        point.y = size.y - point.y;
+    }
+    point
+}
+
+impl TileSet {
+    /// The collision shapes of a tile, in pixels from its top-left corner.
+    pub fn collision_shapes(&self, id: TileId) -> Vec<Shape> {
+        self.tileset
+            .get_tile(id)
+            .and_then(|tile| {
+                tile.collision.as_ref().map(|collision| {
+                    collision
+                        .object_data()
+                        .iter()
+                        .filter_map(Shape::from_object)
+                        .collect()
+                })
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Map {
+    /// The collision shapes of the tile at `pos` of `layer`, in world pixels, flipped with
+    /// the tile and stretched to its cell like it's drawn on orthogonal maps.
+    pub fn collision_shapes_at(&self, layer: usize, pos: IVec2) -> Vec<Shape> {
+        let Some(tile) = self.tile_ref_at(layer, pos) else {
+            return vec![];
+        };
+        let Some(tileset) = self.tilesets.get(tile.tileset) else {
+            return vec![];
+        };
+        let tile_size = vec2(
+            tileset.tileset.tile_width as f32,
+            tileset.tileset.tile_height as f32,
+        );
+        let scale = self.tile_size_px() / tile_size;
+        let origin = self.tile_to_world_px(pos);
+        tileset
+            .collision_shapes(tile.id)
+            .iter()
+            .map(|shape| {
+                shape.map_points(|point| {
+                    let point = flip_point(point, tile_size, tile.flip_h, tile.flip_v, tile.flip_d);
+                    origin + point * scale
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileHandle;
+    use crate::testing::tiny_map;
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_flip_point() {
+        let size = vec2(16.0, 16.0);
+        let point = vec2(2.0, 4.0);
+        assert_eq!(flip_point(point, size, false, false, false), point);
+        assert_eq!(flip_point(point, size, true, false, false), vec2(14.0, 4.0));
+        assert_eq!(flip_point(point, size, false, true, false), vec2(2.0, 12.0));
+        // 90° clockwise in Tiled: diagonal and horizontal.
+        assert_eq!(flip_point(point, size, true, false, true), vec2(12.0, 2.0));
+    }
+
+    #[test]
+    fn test_contains() {
+        let triangle = Shape::Polygon(vec![vec2(0.0, 0.0), vec2(16.0, 0.0), vec2(0.0, 8.0)]);
+        assert!(triangle.contains(vec2(2.0, 2.0)));
+        assert!(!triangle.contains(vec2(14.0, 6.0)));
+        let ellipse = Shape::Ellipse(Rect::new(0.0, 0.0, 16.0, 8.0));
+        assert!(ellipse.contains(vec2(8.0, 4.0)));
+        assert!(!ellipse.contains(vec2(1.0, 1.0)));
+        assert!(!Shape::Point(vec2(1.0, 1.0)).contains(vec2(1.0, 1.0)));
+    }
+
+    #[test]
+    fn test_collision_shapes() {
+        let mut map = tiny_map();
+        let shapes = map.tilesets["tiny"].collision_shapes(2);
+        assert_eq!(shapes[0], Shape::Rect(Rect::new(0.0, 8.0, 16.0, 8.0)));
+        assert_eq!(
+            shapes[1],
+            Shape::Polygon(vec![vec2(0.0, 0.0), vec2(16.0, 0.0), vec2(0.0, 8.0)])
+        );
+        assert!(map.tilesets["tiny"].collision_shapes(1).is_empty());
+
+        // In world pixels, flipped with the tile.
+        let at = map.collision_shapes_at(0, ivec2(1, 0));
+        assert_eq!(at[0], Shape::Rect(Rect::new(16.0, 8.0, 16.0, 8.0)));
+        let flipped = TileHandle::new("tiny", 2).flipped(false, true, false);
+        map.set_tile(0, ivec2(1, 0), Some(flipped));
+        let at = map.collision_shapes_at(0, ivec2(1, 0));
+        assert_eq!(at[0], Shape::Rect(Rect::new(16.0, 0.0, 16.0, 8.0)));
+        assert_eq!(at[1].bounds(), Rect::new(16.0, 8.0, 16.0, 8.0));
+    }
+}
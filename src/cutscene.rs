@@ -0,0 +1,237 @@
+use coarsetime::Duration;
+use macroquad::math::{ivec2, vec2, Vec2};
+
+use crate::map::{Map, TileHandle};
+
+/// A step of a `Cutscene`. Times are in milliseconds of map time, see `Map::clock`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CutsceneStep {
+    /// Pans the camera to `target`, in world pixels, over `ms`, without waiting for it.
+    MoveCamera { target: (f32, f32), ms: u64 },
+    /// Plays the animation `template` on the entity `controller`.
+    PlayAnimation {
+        controller: String,
+        template: String,
+    },
+    /// Places a tile, or erases it with `None`, see `Map::set_tile()`.
+    SetTile {
+        layer: String,
+        pos: (i32, i32),
+        tile: Option<TileHandle>,
+    },
+    /// See `Map::set_layer_visible()`.
+    ShowLayer { layer: String, visible: bool },
+    /// Holds the next steps back for `ms`.
+    Wait { ms: u64 },
+}
+
+/// What a cutscene does outside of the map: the game owns the camera and the entities.
+pub trait CutsceneActor {
+    fn move_camera(&mut self, target: Vec2, duration: Duration);
+
+    /// `controller` is a name given by the game, e.g. "hero".
+    fn play_animation(&mut self, controller: &str, template: &str);
+}
+
+/// Scripted steps played over map time, e.g. a level intro: steps run in order,
+/// all at once up to the next `Wait`. Build it step by step, or load a list of steps
+/// from RON with the "ron" feature. Pausing the map clock pauses the cutscene.
+/// Steps on unknown layers are skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cutscene {
+    steps: Vec<CutsceneStep>,
+    /// Index of the next step to run.
+    next: usize,
+    /// Map time when the current wait ends, set by the first `update()`.
+    resume_at: Option<Duration>,
+}
+
+impl Cutscene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_steps(steps: Vec<CutsceneStep>) -> Self {
+        Self {
+            steps,
+            ..Default::default()
+        }
+    }
+
+    /// Parses a list of steps, e.g.
+    /// `[MoveCamera(target: (320, 96), ms: 1000), Wait(ms: 1000), ShowLayer(layer: "roof", visible: false)]`.
+    #[cfg(feature = "ron")]
+    pub fn from_ron(text: &str) -> Result<Self, ron::de::SpannedError> {
+        ron::from_str(text).map(Self::from_steps)
+    }
+
+    pub fn move_camera(self, target: Vec2, ms: u64) -> Self {
+        self.then(CutsceneStep::MoveCamera {
+            target: (target.x, target.y),
+            ms,
+        })
+    }
+
+    pub fn play_animation(self, controller: &str, template: &str) -> Self {
+        self.then(CutsceneStep::PlayAnimation {
+            controller: controller.to_string(),
+            template: template.to_string(),
+        })
+    }
+
+    pub fn set_tile(self, layer: &str, pos: (i32, i32), tile: Option<TileHandle>) -> Self {
+        self.then(CutsceneStep::SetTile {
+            layer: layer.to_string(),
+            pos,
+            tile,
+        })
+    }
+
+    pub fn show_layer(self, layer: &str, visible: bool) -> Self {
+        self.then(CutsceneStep::ShowLayer {
+            layer: layer.to_string(),
+            visible,
+        })
+    }
+
+    pub fn wait(self, ms: u64) -> Self {
+        self.then(CutsceneStep::Wait { ms })
+    }
+
+    pub fn then(mut self, step: CutsceneStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn steps(&self) -> &[CutsceneStep] {
+        &self.steps
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.steps.len()
+    }
+
+    /// Runs the steps due by the map time. Call it every frame, after `Map::update()`.
+    /// The first call starts the cutscene.
+    pub fn update(&mut self, map: &mut Map, actor: &mut dyn CutsceneActor) {
+        let now = map.clock.elapsed();
+        // Waits end at their scheduled time rather than at the frame that noticed,
+        // so that long cutscenes don't drift.
+        let mut time = *self.resume_at.get_or_insert(now);
+        while now >= time {
+            let Some(step) = self.steps.get(self.next).cloned() else {
+                return;
+            };
+            self.next += 1;
+            if let CutsceneStep::Wait { ms } = step {
+                time += Duration::from_millis(ms);
+                self.resume_at = Some(time);
+            } else {
+                run_step(step, map, actor);
+            }
+        }
+    }
+
+    /// Runs all the remaining steps at once, ignoring waits, e.g. when the player skips.
+    pub fn skip(&mut self, map: &mut Map, actor: &mut dyn CutsceneActor) {
+        while let Some(step) = self.steps.get(self.next).cloned() {
+            self.next += 1;
+            if !matches!(step, CutsceneStep::Wait { .. }) {
+                run_step(step, map, actor);
+            }
+        }
+    }
+}
+
+fn run_step(step: CutsceneStep, map: &mut Map, actor: &mut dyn CutsceneActor) {
+    match step {
+        CutsceneStep::MoveCamera { target, ms } => {
+            actor.move_camera(vec2(target.0, target.1), Duration::from_millis(ms))
+        }
+        CutsceneStep::PlayAnimation {
+            controller,
+            template,
+        } => actor.play_animation(&controller, &template),
+        CutsceneStep::SetTile { layer, pos, tile } => {
+            if let Some(layer) = map.layer_by_name(&layer) {
+                map.set_tile(layer, ivec2(pos.0, pos.1), tile);
+            }
+        }
+        CutsceneStep::ShowLayer { layer, visible } => {
+            if let Some(layer) = map.layer_by_name(&layer) {
+                map.set_layer_visible(layer, visible);
+            }
+        }
+        CutsceneStep::Wait { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+    }
+
+    impl CutsceneActor for Recorder {
+        fn move_camera(&mut self, target: Vec2, duration: Duration) {
+            assert_eq!(duration, Duration::from_millis(500));
+            self.calls.push(format!("camera {} {}", target.x, target.y));
+        }
+
+        fn play_animation(&mut self, controller: &str, template: &str) {
+            self.calls.push(format!("{} {}", controller, template));
+        }
+    }
+
+    #[test]
+    fn test_cutscene() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let mut actor = Recorder::default();
+        let mut cutscene = Cutscene::new()
+            .move_camera(vec2(32., 32.), 500)
+            .wait(500)
+            .play_animation("hero", "wave")
+            .set_tile("ground", (1, 1), None)
+            .wait(200)
+            .show_layer("ground", false);
+
+        cutscene.update(&mut map, &mut actor);
+        assert_eq!(actor.calls, vec!["camera 32 32"]);
+
+        map.clock.step(Duration::from_millis(499));
+        cutscene.update(&mut map, &mut actor);
+        assert_eq!(actor.calls.len(), 1);
+
+        // Both waits are over.
+        map.clock.step(Duration::from_millis(300));
+        cutscene.update(&mut map, &mut actor);
+        assert_eq!(actor.calls, vec!["camera 32 32", "hero wave"]);
+        assert_eq!(map.tile_at(ground, ivec2(1, 1)), None);
+        assert!(!map.is_layer_visible(ground));
+        assert!(cutscene.is_done());
+    }
+
+    #[cfg(feature = "ron")]
+    #[test]
+    fn test_from_ron() {
+        let cutscene =
+            Cutscene::from_ron(r#"[Wait(ms: 100), ShowLayer(layer: "roof", visible: false)]"#)
+                .unwrap();
+        assert_eq!(
+            cutscene.steps(),
+            &[
+                CutsceneStep::Wait { ms: 100 },
+                CutsceneStep::ShowLayer {
+                    layer: "roof".to_string(),
+                    visible: false
+                }
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use crate::map::Map;
+use crate::properties::PropertiesExt;
+
+/// Int tile property, the id of the variant of the tile shown at night in the same tileset,
+/// e.g. a lit window for a dark one. See `Map::set_night()`.
+pub const NIGHT_TILE_PROPERTY: &str = "night_tile";
+
+/// How tiles change to their night variants, see `Map::set_day_night_blend()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DayNightBlend {
+    /// The night variant replaces the tile when the night is past its middle, 0.5.
+    #[default]
+    Swap,
+    /// The night variant is drawn over the tile, as opaque as the night is dark.
+    CrossFade,
+}
+
+/// How dark it is at `hour`, from 0 to 24: day from 7 to 18, night from 20 to 5,
+/// and linear dusk and dawn in between. For `Map::set_night()`.
+pub fn night_from_hour(hour: f32) -> f32 {
+    let hour = hour.rem_euclid(24.0);
+    let night = if hour < 5.0 {
+        1.0
+    } else if hour < 7.0 {
+        (7.0 - hour) / 2.0
+    } else if hour < 18.0 {
+        0.0
+    } else if hour < 20.0 {
+        (hour - 18.0) / 2.0
+    } else {
+        1.0
+    };
+    night.clamp(0.0, 1.0)
+}
+
+impl Map {
+    /// The night variant of `tile_id`, see `NIGHT_TILE_PROPERTY`.
+    pub fn night_tile_id(&self, tileset: &str, tile_id: u32) -> Option<u32> {
+        self.tile_data(tileset, tile_id)?
+            .properties
+            .get_int(NIGHT_TILE_PROPERTY)
+            .map(|id| id as u32)
+    }
+
+    /// The tile to draw for `tile_id` now, and the night variant to draw over it, if any,
+    /// with its alpha.
+    pub(crate) fn day_night_tile(&self, tileset: &str, tile_id: u32) -> (u32, Option<(u32, f32)>) {
+        let night = self.night();
+        if night <= 0.0 {
+            return (tile_id, None);
+        }
+        let Some(night_id) = self.night_tile_id(tileset, tile_id) else {
+            return (tile_id, None);
+        };
+        match self.day_night_blend() {
+            DayNightBlend::Swap if night >= 0.5 => (night_id, None),
+            DayNightBlend::Swap => (tile_id, None),
+            DayNightBlend::CrossFade if night >= 1.0 => (night_id, None),
+            DayNightBlend::CrossFade => (tile_id, Some((night_id, night))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+
+    #[test]
+    fn test_night_from_hour() {
+        assert_eq!(night_from_hour(12.0), 0.0);
+        assert_eq!(night_from_hour(19.0), 0.5);
+        assert_eq!(night_from_hour(23.0), 1.0);
+        assert_eq!(night_from_hour(6.5), 0.25);
+        assert_eq!(night_from_hour(-1.0), 1.0);
+    }
+
+    #[test]
+    fn test_day_night_tile() {
+        let mut map = tiny_map();
+        // Tile 3 turns into tile 1 at night.
+        assert_eq!(map.night_tile_id("tiny", 3), Some(1));
+        assert_eq!(map.day_night_tile("tiny", 3), (3, None));
+        map.set_night(0.4);
+        assert_eq!(map.day_night_tile("tiny", 3), (3, None));
+        map.set_night(0.6);
+        assert_eq!(map.day_night_tile("tiny", 3), (1, None));
+        assert_eq!(map.day_night_tile("tiny", 2), (2, None));
+
+        map.set_day_night_blend(DayNightBlend::CrossFade);
+        assert_eq!(map.day_night_tile("tiny", 3), (3, Some((1, 0.6))));
+        map.set_night(2.0);
+        assert_eq!(map.day_night_tile("tiny", 3), (1, None));
+    }
+}
@@ -0,0 +1,107 @@
+use macroquad::color::{Color, WHITE, YELLOW};
+use macroquad::math::{IVec2, Rect};
+use macroquad::shapes::draw_line;
+use macroquad::text::draw_text;
+
+use crate::map::{world_px_to_screen, Map};
+
+/// The tile grid and coordinates of the cells, drawn over a layer with the same
+/// `source_px` and `dest` as `Map::draw_tiles()`, for diagnosing camera and culling math:
+/// the lines fall on the edges of the cells, and the cells drawn are the ones
+/// `draw_tiles()` considers, margin included.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugOverlay {
+    /// `None` for no grid.
+    pub grid: Option<Color>,
+    /// In screen pixels.
+    pub thickness: f32,
+    /// The color of the "x,y" labels of the cells, `None` for none.
+    pub labels: Option<Color>,
+    /// Labels are left out of cells narrower than three times the font size.
+    pub font_size: f32,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            grid: Some(Color { a: 0.5, ..WHITE }),
+            thickness: 1.0,
+            labels: Some(YELLOW),
+            font_size: 12.0,
+        }
+    }
+}
+
+impl DebugOverlay {
+    /// The cells of `layer`, after its parallax and offset, see `Map::layer_source_px()`,
+    /// or of the map grid if `None`. Needs a macroquad window.
+    pub fn draw(&self, map: &Map, layer: Option<usize>, dest: Rect, source_px: Rect) {
+        let source_px = match layer {
+            Some(layer) => map.layer_source_px(layer, source_px),
+            None => source_px,
+        };
+        let to_screen = |world_px| world_px_to_screen(world_px, source_px, dest);
+        for cell in overlay_cells(map, source_px) {
+            let outline: Vec<_> = map.cell_outline(cell).into_iter().map(to_screen).collect();
+            if let Some(color) = self.grid {
+                for (i, from) in outline.iter().enumerate() {
+                    let to = outline[(i + 1) % outline.len()];
+                    draw_line(from.x, from.y, to.x, to.y, self.thickness, color);
+                }
+            }
+            let Some(color) = self.labels else {
+                continue;
+            };
+            let top_left = to_screen(map.tile_to_world_px(cell));
+            let size = to_screen(map.tile_to_world_px(cell) + map.tile_size_px()) - top_left;
+            if size.x < self.font_size * 3.0 {
+                continue;
+            }
+            let label = format!("{},{}", cell.x, cell.y);
+            let width = label.len() as f32 * self.font_size / 2.0;
+            let center = top_left + size / 2.0;
+            draw_text(
+                &label,
+                center.x - width / 2.0,
+                center.y + self.font_size / 3.0,
+                self.font_size,
+                color,
+            );
+        }
+    }
+}
+
+/// The cells of the map `draw_tiles()` considers for `source_px`.
+fn overlay_cells(map: &Map, source_px: Rect) -> impl Iterator<Item = IVec2> + '_ {
+    let (min, max) = map.visible_tile_range(source_px);
+    (min.y..=max.y)
+        .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+        .filter(|cell| map.contains(*cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+    use macroquad::math::{ivec2, vec2};
+
+    #[test]
+    fn test_overlay_cells() {
+        let map = tiny_map();
+        // The top-left cell, with a cell of margin, clamped to the map.
+        let cells: Vec<_> = overlay_cells(&map, Rect::new(0., 0., 16., 16.)).collect();
+        assert_eq!(cells.len(), 9);
+        assert_eq!(cells[0], ivec2(0, 0));
+        assert_eq!(cells[8], ivec2(2, 2));
+
+        assert_eq!(
+            map.cell_outline(ivec2(1, 2)),
+            vec![
+                vec2(16., 48.),
+                vec2(16., 32.),
+                vec2(32., 32.),
+                vec2(32., 48.)
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use macroquad::math::{IVec2, Rect, Vec2};
+use tiled::{LayerType, ObjectData, ObjectShape, Properties};
+
+use crate::map::{screen_to_world_px, Map, TileRef};
+
+/// What's under a map cell: for debug tooltips and editor UIs.
+#[derive(Clone, Debug)]
+pub struct TileDescription {
+    /// The cell, in world tiles.
+    pub tile: IVec2,
+    /// Tiles at that cell, in the drawing order.
+    pub layers: Vec<LayerTileInfo>,
+    /// Objects whose bounds overlap the cell.
+    pub objects: Vec<ObjectInfo>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LayerTileInfo {
+    pub layer: usize,
+    pub layer_name: String,
+    pub tileset: String,
+    pub tile_id: u32,
+    /// Tiled class, aka type.
+    pub class: Option<String>,
+    pub properties: Properties,
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjectInfo {
+    pub layer: usize,
+    pub id: u32,
+    pub name: String,
+    pub class: String,
+    /// In world pixels.
+    pub bounds: Rect,
+    pub properties: Properties,
+}
+
+impl Map {
+    /// The map cell under `screen_pos`, when drawing `source_px` into `dest`.
+    /// The cell may be outside of the map.
+    pub fn pick_tile(&self, screen_pos: Vec2, source_px: Rect, dest: Rect) -> IVec2 {
+        self.world_px_to_tile(screen_to_world_px(screen_pos, source_px, dest))
+    }
+
+    /// Describes the cell under `screen_pos`, see `pick_tile()`.
+    pub fn describe_tile(&self, screen_pos: Vec2, source_px: Rect, dest: Rect) -> TileDescription {
+        self.describe_cell(self.pick_tile(screen_pos, source_px, dest))
+    }
+
+    /// Describes the map cell `tile`, in world tiles.
+    pub fn describe_cell(&self, tile: IVec2) -> TileDescription {
+        let tile_size = (self.map.tile_width as f32, self.map.tile_height as f32);
+        let cell = Rect::new(
+            tile.x as f32 * tile_size.0,
+            tile.y as f32 * tile_size.1,
+            tile_size.0,
+            tile_size.1,
+        );
+
+        let mut layers = vec![];
+        let mut objects = vec![];
+
+        for index in self.layer_order.order().iter().map(|layer| layer.index) {
+            let layer_type = self.map.get_layer(index).map(|layer| layer.layer_type());
+            match layer_type {
+                // Runtime layers are tile layers.
+                Some(LayerType::Tiles(_)) | None => {
+                    let Some(TileRef {
+                        tileset,
+                        id: tile_id,
+                        ..
+                    }) = self.tile_ref_at(index, tile)
+                    else {
+                        continue;
+                    };
+                    let tile_data = self.tile_data(tileset, tile_id);
+                    layers.push(LayerTileInfo {
+                        layer: index,
+                        layer_name: self.layer_name(index).unwrap_or_default(),
+                        tileset: tileset.to_string(),
+                        tile_id,
+                        class: tile_data.as_ref().and_then(|t| t.user_type.clone()),
+                        properties: tile_data.map(|t| t.properties.clone()).unwrap_or_default(),
+                    });
+                }
+                Some(LayerType::Objects(object_layer)) => {
+                    for object in object_layer.objects() {
+                        let bounds = object_bounds(&object);
+                        if !bounds_touch_cell(bounds, cell) {
+                            continue;
+                        }
+                        objects.push(ObjectInfo {
+                            layer: index,
+                            id: object.id(),
+                            name: object.name.clone(),
+                            class: object.user_type.clone(),
+                            bounds,
+                            properties: object.properties.clone(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        TileDescription {
+            tile,
+            layers,
+            objects,
+        }
+    }
+
+    /// One line describing the cell under `screen_pos`, for logs and accessibility
+    /// layers, see `describe_cell_text()`.
+    pub fn describe_tile_text(&self, screen_pos: Vec2, source_px: Rect, dest: Rect) -> String {
+        self.describe_cell_text(self.pick_tile(screen_pos, source_px, dest))
+    }
+
+    /// One line describing the map cell `tile`, e.g.
+    /// "Cell 4, 7: grass (ground), fence (props). Zones: market. Objects: sign. Blocked."
+    /// Tiles go by their class, zones are the objects with an area over the cell,
+    /// the other objects are points and tiles, both going by their name, or else class.
+    /// With the "collision" feature, ends with the walkability and move cost of the cell,
+    /// same as in `collision_grid()`.
+    pub fn describe_cell_text(&self, tile: IVec2) -> String {
+        let description = self.describe_cell(tile);
+        let mut text = format!("Cell {}, {}", tile.x, tile.y);
+
+        let tiles: Vec<_> = description
+            .layers
+            .iter()
+            .map(|info| {
+                let what = match &info.class {
+                    Some(class) if !class.is_empty() => class.clone(),
+                    _ => format!("{} #{}", info.tileset, info.tile_id),
+                };
+                format!("{} ({})", what, info.layer_name)
+            })
+            .collect();
+        text += &match tiles.is_empty() {
+            true => ": empty.".to_string(),
+            false => format!(": {}.", tiles.join(", ")),
+        };
+
+        let name = |object: &ObjectInfo| match (object.name.as_str(), object.class.as_str()) {
+            ("", "") => format!("object #{}", object.id),
+            ("", class) => class.to_string(),
+            (name, _) => name.to_string(),
+        };
+        let (zones, objects): (Vec<_>, Vec<_>) = description
+            .objects
+            .iter()
+            .partition(|object| object.bounds.w > 0.0 && object.bounds.h > 0.0);
+        for (title, objects) in [("Zones", zones), ("Objects", objects)] {
+            if !objects.is_empty() {
+                let names: Vec<_> = objects.into_iter().map(name).collect();
+                text += &format!(" {}: {}.", title, names.join(", "));
+            }
+        }
+
+        #[cfg(feature = "collision")]
+        text.push_str(&self.walkability_text(tile));
+        text
+    }
+
+    /// " Blocked.", " Walkable." or " Walkable, cost 2.", see `collision_grid()`.
+    #[cfg(feature = "collision")]
+    fn walkability_text(&self, tile: IVec2) -> String {
+        match self.cell_collision(tile) {
+            (true, _) => " Blocked.".to_string(),
+            (false, Some(cost)) if cost != 1.0 => format!(" Walkable, cost {}.", cost),
+            (false, _) => " Walkable.".to_string(),
+        }
+    }
+}
+
+/// Like `Rect::overlaps()`, but objects merely touching the cell don't count,
+/// while points and zero-sized objects do.
+pub(crate) fn bounds_touch_cell(bounds: Rect, cell: Rect) -> bool {
+    fn overlaps(start: f32, end: f32, cell_start: f32, cell_end: f32) -> bool {
+        start < cell_end && (end > cell_start || (start == end && start >= cell_start))
+    }
+    overlaps(bounds.x, bounds.right(), cell.x, cell.right())
+        && overlaps(bounds.y, bounds.bottom(), cell.y, cell.bottom())
+}
+
+/// Bounding box of an object, in world pixels.
+pub(crate) fn object_bounds(object: &ObjectData) -> Rect {
+    match &object.shape {
+        ObjectShape::Rect { width, height } | ObjectShape::Ellipse { width, height } => {
+            if object.tile_data().is_some() {
+                // Tile objects are anchored at their bottom-left corner.
+                Rect::new(object.x, object.y - height, *width, *height)
+            } else {
+                Rect::new(object.x, object.y, *width, *height)
+            }
+        }
+        ObjectShape::Polyline { points } | ObjectShape::Polygon { points } => {
+            let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+            let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+            for (x, y) in points {
+                min_x = min_x.min(*x);
+                min_y = min_y.min(*y);
+                max_x = max_x.max(*x);
+                max_y = max_y.max(*y);
+            }
+            Rect::new(
+                object.x + min_x,
+                object.y + min_y,
+                max_x - min_x,
+                max_y - min_y,
+            )
+        }
+        // Points have no size, and TMX doesn't store the size of text.
+        ObjectShape::Point(..) | ObjectShape::Text { .. } => Rect::new(object.x, object.y, 0., 0.),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::tiny_map;
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_describe_cell_text() {
+        let map = tiny_map();
+        let wall = map.describe_cell_text(ivec2(0, 0));
+        let floor = map.describe_cell_text(ivec2(1, 1));
+        #[cfg(feature = "collision")]
+        {
+            assert_eq!(wall, "Cell 0, 0: wall (ground). Blocked.");
+            assert_eq!(
+                floor,
+                "Cell 1, 1: tiny #0 (ground). Objects: spawn. Walkable."
+            );
+        }
+        #[cfg(not(feature = "collision"))]
+        {
+            assert_eq!(wall, "Cell 0, 0: wall (ground).");
+            assert_eq!(floor, "Cell 1, 1: tiny #0 (ground). Objects: spawn.");
+        }
+        assert!(map
+            .describe_cell_text(ivec2(9, 9))
+            .starts_with("Cell 9, 9: empty."));
+    }
+}
@@ -0,0 +1,171 @@
+use std::cell::RefCell;
+
+use macroquad::color::Color;
+use macroquad::material::{gl_use_default_material, gl_use_material, Material};
+use macroquad::math::Rect;
+use macroquad::texture::DrawTextureParams;
+
+use crate::tileset::TileSet;
+
+thread_local! {
+    /// The material set with `use_material()`, which layers go back to.
+    static MATERIAL: RefCell<Option<Material>> = const { RefCell::new(None) };
+}
+
+/// Draws with `material` from now on, or with the default one if `None`, like
+/// `gl_use_material()`, and keeps it for the layers drawn with materials of their own
+/// to go back to, e.g. for a CRT shader over the whole screen,
+/// see `Map::set_layer_material()`. Otherwise, they go back to the default material.
+pub fn use_material(material: Option<&Material>) {
+    MATERIAL.with(|current| *current.borrow_mut() = material.cloned());
+    match material {
+        Some(material) => gl_use_material(material),
+        None => gl_use_default_material(),
+    }
+}
+
+/// How a region of a tileset image is drawn, see `DrawBackend`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawParams {
+    pub color: Color,
+    /// Radians, around the center of `dest`.
+    pub rotation: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+/// Where `Map::draw_tiles_with()` draws: culling, ordering, animations and caching stay
+/// in the map, only the actual draws go through the backend. Implemented for macroquad
+/// by `MacroquadBackend`, and by `DrawRecorder` for tests without a window.
+pub trait DrawBackend {
+    /// Draws `region` of the image of `tileset`, in pixels of the full-res image,
+    /// into `dest`, in screen pixels.
+    fn draw_texture(&mut self, tileset: &TileSet, region: Rect, dest: Rect, params: DrawParams);
+
+    /// Whether static layers may be drawn as macroquad meshes, see `LayerBackend::Static`.
+    /// Otherwise, they're drawn tile by tile like animated layers.
+    fn draws_meshes(&self) -> bool {
+        false
+    }
+
+    /// Draws with `material` from now on, see `Map::set_layer_material()`, or if `None`,
+    /// goes back to drawing as the caller did, see `use_material()`. Ignored by default.
+    fn use_material(&mut self, _material: Option<&Material>) {}
+}
+
+/// Draws with macroquad, what `Map::draw_tiles()` does.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MacroquadBackend;
+
+impl DrawBackend for MacroquadBackend {
+    fn draw_texture(&mut self, tileset: &TileSet, region: Rect, dest: Rect, params: DrawParams) {
+        let texture_params = DrawTextureParams {
+            dest_size: Some(dest.size()),
+            source: Some(region),
+            rotation: params.rotation,
+            flip_x: params.flip_x,
+            flip_y: params.flip_y,
+            pivot: None,
+        };
+        tileset.spr_ex_color(texture_params, dest.point(), params.color);
+    }
+
+    fn draws_meshes(&self) -> bool {
+        true
+    }
+
+    fn use_material(&mut self, material: Option<&Material>) {
+        match material {
+            Some(material) => gl_use_material(material),
+            None => MATERIAL.with(|current| match &*current.borrow() {
+                Some(material) => gl_use_material(material),
+                None => gl_use_default_material(),
+            }),
+        }
+    }
+}
+
+/// A draw recorded by `DrawRecorder`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawCall {
+    pub tileset: String,
+    pub region: Rect,
+    pub dest: Rect,
+    pub params: DrawParams,
+}
+
+/// Records draws instead of drawing, e.g. to compare them against golden ones in tests.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DrawRecorder {
+    pub calls: Vec<DrawCall>,
+    /// The materials switched to, `None` going back to the caller's, in order.
+    pub materials: Vec<Option<Material>>,
+}
+
+impl DrawBackend for DrawRecorder {
+    fn draw_texture(&mut self, tileset: &TileSet, region: Rect, dest: Rect, params: DrawParams) {
+        self.calls.push(DrawCall {
+            tileset: tileset.tileset.name.clone(),
+            region,
+            dest,
+            params,
+        });
+    }
+
+    fn use_material(&mut self, material: Option<&Material>) {
+        self.materials.push(material.cloned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+    use macroquad::color::WHITE;
+
+    #[test]
+    fn test_record() {
+        let map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let mut recorder = DrawRecorder::default();
+        // Twice the size: 32x32 px tiles.
+        map.draw_tiles_with(&mut recorder, ground, Rect::new(0., 0., 128., 128.), None);
+
+        assert_eq!(recorder.calls.len(), 16);
+        // Row by row, the wall at (0, 0) first, and the floor at (2, 1) later.
+        assert_eq!(
+            recorder.calls[0],
+            DrawCall {
+                tileset: "tiny".to_string(),
+                region: Rect::new(0., 16., 16., 16.),
+                dest: Rect::new(0., 0., 32., 32.),
+                params: DrawParams {
+                    color: WHITE,
+                    rotation: 0.0,
+                    flip_x: false,
+                    flip_y: false,
+                },
+            }
+        );
+        assert_eq!(recorder.calls[6].region, Rect::new(16., 16., 16., 16.));
+        assert_eq!(recorder.calls[6].dest, Rect::new(64., 32., 32., 32.));
+    }
+
+    #[test]
+    fn test_materials() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let dest = Rect::new(0., 0., 64., 64.);
+        // Without a layer material, the material the caller draws with is left alone.
+        let mut recorder = DrawRecorder::default();
+        map.draw_tiles_with(&mut recorder, ground, dest, None);
+        assert_eq!(recorder.calls.len(), 16);
+        assert!(recorder.materials.is_empty());
+
+        map.set_layer_material(ground, None);
+        assert_eq!(map.layer_material(ground), None);
+        let mut again = DrawRecorder::default();
+        map.draw_tiles_with(&mut again, ground, dest, None);
+        assert_eq!(again, recorder);
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use macroquad::math::IVec2;
+
+use crate::editor::{EditTile, TileEdit};
+use crate::map::{Map, TileHandle, TileRef};
+
+/// Tile edits planned while reading the map, e.g. an explosion crater or terraforming,
+/// then applied at once by `Map::commit()`: plan against a `&Map`, commit with a `&mut Map`,
+/// without cloning the map. A later edit of a cell replaces the earlier one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EditPlan {
+    edits: HashMap<(usize, IVec2), EditTile>,
+    /// Cells in planning order, so that commits are deterministic.
+    order: Vec<(usize, IVec2)>,
+}
+
+impl EditPlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plans placing `tile` at `pos`, or erasing it if `None`.
+    pub fn set_tile(&mut self, layer: usize, pos: IVec2, tile: EditTile) {
+        if self.edits.insert((layer, pos), tile).is_none() {
+            self.order.push((layer, pos));
+        }
+    }
+
+    /// The planned tile of a cell: `None` if unplanned, `Some(None)` if erased.
+    pub fn planned(&self, layer: usize, pos: IVec2) -> Option<&EditTile> {
+        self.edits.get(&(layer, pos))
+    }
+
+    /// The tile at `pos` once committed, so that planning sees its earlier steps:
+    /// the planned one, or else the one on `map`.
+    pub fn tile_at<'a>(&'a self, map: &'a Map, layer: usize, pos: IVec2) -> Option<TileRef<'a>> {
+        match self.planned(layer, pos) {
+            Some(tile) => tile.as_ref().map(TileHandle::tile_ref),
+            None => map.tile_ref_at(layer, pos),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl Map {
+    /// Applies `plan`, in planning order. Like `set_tile()`, edits outside of the map,
+    /// and of layers without tiles, are ignored.
+    /// Returns the edits which changed something, e.g. for `EditJournal::record()`.
+    ///
+    /// Panics:
+    /// * If the tileset of a planned tile does not exist.
+    pub fn commit(&mut self, mut plan: EditPlan) -> Vec<TileEdit> {
+        let mut edits = vec![];
+        for (layer, pos) in plan.order {
+            let after = plan.edits.remove(&(layer, pos)).flatten();
+            edits.extend(self.edit_tile(layer, pos, after));
+        }
+        edits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_commit() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+
+        // A crater: erase the walls next to the floor tile 0, reading the map meanwhile.
+        let mut plan = EditPlan::new();
+        for pos in [ivec2(0, 1), ivec2(1, 0), ivec2(1, 1), ivec2(9, 9)] {
+            if plan
+                .tile_at(&map, ground, pos)
+                .is_some_and(|tile| tile.id == 2)
+            {
+                plan.set_tile(ground, pos, None);
+            }
+        }
+        plan.set_tile(ground, ivec2(1, 0), Some(TileHandle::new("tiny", 1)));
+        plan.set_tile(ground, ivec2(1, 1), Some(TileHandle::new("tiny", 0)));
+        assert_eq!(plan.len(), 3);
+        assert_eq!(plan.tile_at(&map, ground, ivec2(1, 0)).unwrap().id, 1);
+        assert_eq!(plan.tile_at(&map, ground, ivec2(0, 1)), None);
+
+        let edits = map.commit(plan);
+        // (1, 1) already had tile 0.
+        assert_eq!(edits.len(), 2);
+        assert_eq!(map.tile_at(ground, ivec2(0, 1)), None);
+        assert_eq!(map.tile_at(ground, ivec2(1, 0)).unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_commit_objects() {
+        let mut map = tiny_map();
+        let objects = map.layer_by_name("objects").unwrap();
+        let mut plan = EditPlan::new();
+        plan.set_tile(objects, ivec2(1, 1), Some(TileHandle::new("tiny", 2)));
+        assert_eq!(plan.len(), 1);
+        // Nothing changed, nothing to record.
+        assert!(map.commit(plan).is_empty());
+        assert_eq!(map.tile_at(objects, ivec2(1, 1)), None);
+    }
+}
@@ -0,0 +1,459 @@
+use macroquad::input::{is_mouse_button_down, mouse_position, MouseButton};
+use macroquad::math::{ivec2, vec2, IVec2, Rect};
+
+use crate::fill::MatchPolicy;
+use crate::map::{Map, TileHandle};
+
+/// A tile on a layer, `None` for no tile.
+pub type EditTile = Option<TileHandle>;
+
+/// A single tile change, enough to undo or redo it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileEdit {
+    pub layer: usize,
+    pub pos: IVec2,
+    pub before: EditTile,
+    pub after: EditTile,
+}
+
+/// Undo journal of map edits. Edits are grouped into strokes, one per user action
+/// (e.g. a mouse drag), which are undone as a whole.
+#[derive(Clone, Debug, Default)]
+pub struct EditJournal {
+    done: Vec<Vec<TileEdit>>,
+    undone: Vec<Vec<TileEdit>>,
+    /// The stroke being recorded.
+    stroke: Option<Vec<TileEdit>>,
+}
+
+impl EditJournal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts grouping the following edits, finishing the previous stroke if any.
+    pub fn begin_stroke(&mut self) {
+        self.end_stroke();
+        self.stroke = Some(vec![]);
+    }
+
+    pub fn end_stroke(&mut self) {
+        if let Some(stroke) = self.stroke.take() {
+            if !stroke.is_empty() {
+                self.done.push(stroke);
+            }
+        }
+    }
+
+    /// Sets the tile and records the change. Outside of a stroke, it's a stroke of its own.
+    /// Like `Map::set_tile()`, edits outside of the map, and of layers without tiles,
+    /// are ignored, and not recorded.
+    pub fn set_tile(&mut self, map: &mut Map, layer: usize, pos: IVec2, tile: EditTile) {
+        if let Some(edit) = map.edit_tile(layer, pos, tile) {
+            self.record(vec![edit]);
+        }
+    }
+
+    /// Records edits already applied to the map, e.g. by `Map::draw_tile_line()`,
+    /// as a part of the current stroke, or as a stroke of their own.
+    pub fn record(&mut self, edits: Vec<TileEdit>) {
+        if edits.is_empty() {
+            return;
+        }
+        match &mut self.stroke {
+            Some(stroke) => stroke.extend(edits),
+            None => self.done.push(edits),
+        }
+        self.undone.clear();
+    }
+
+    /// Reverts the last stroke. Returns false if there's nothing to undo.
+    pub fn undo(&mut self, map: &mut Map) -> bool {
+        self.end_stroke();
+        let Some(stroke) = self.done.pop() else {
+            return false;
+        };
+        for edit in stroke.iter().rev() {
+            map.set_tile(edit.layer, edit.pos, edit.before.clone());
+        }
+        self.undone.push(stroke);
+        true
+    }
+
+    /// Re-applies the last undone stroke. Returns false if there's nothing to redo.
+    pub fn redo(&mut self, map: &mut Map) -> bool {
+        self.end_stroke();
+        let Some(stroke) = self.undone.pop() else {
+            return false;
+        };
+        for edit in &stroke {
+            map.set_tile(edit.layer, edit.pos, edit.after.clone());
+        }
+        self.done.push(stroke);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty() || matches!(&self.stroke, Some(stroke) if !stroke.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+}
+
+impl Map {
+    /// Same as `set_tile()`, returning the edit if it was applied and changed the tile,
+    /// for the edits returned by the editing helpers, e.g. `draw_tile_line()`.
+    pub(crate) fn edit_tile(
+        &mut self,
+        layer: usize,
+        pos: IVec2,
+        tile: EditTile,
+    ) -> Option<TileEdit> {
+        if !self.contains(pos) || !self.is_tile_layer(layer) {
+            return None;
+        }
+        let before = self.set_tile(layer, pos, tile.clone());
+        (before != tile).then_some(TileEdit {
+            layer,
+            pos,
+            before,
+            after: tile,
+        })
+    }
+}
+
+/// A rectangular pattern of tiles to paint with. A single tile is a 1x1 stamp.
+/// An empty stamp paints nothing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stamp {
+    size: IVec2,
+    /// Row-major, `size.x * size.y` tiles.
+    tiles: Vec<EditTile>,
+}
+
+impl Stamp {
+    /// A stamp of `size` from its `tiles`, row-major. `None` if the size is negative,
+    /// or doesn't match the number of tiles.
+    pub fn new(size: IVec2, tiles: Vec<EditTile>) -> Option<Self> {
+        let count = size.x.checked_mul(size.y)?;
+        (size.cmpge(IVec2::ZERO).all() && usize::try_from(count) == Ok(tiles.len()))
+            .then_some(Self { size, tiles })
+    }
+
+    pub fn single(tile: EditTile) -> Self {
+        Self {
+            size: ivec2(1, 1),
+            tiles: vec![tile],
+        }
+    }
+
+    pub fn size(&self) -> IVec2 {
+        self.size
+    }
+
+    /// Row-major.
+    pub fn tiles(&self) -> &[EditTile] {
+        &self.tiles
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// The tile to paint at `pos`, repeating the stamp from `origin`.
+    /// `None` if the stamp is empty.
+    pub fn tile_at(&self, origin: IVec2, pos: IVec2) -> Option<&EditTile> {
+        if self.is_empty() {
+            return None;
+        }
+        let offset = pos - origin;
+        let x = offset.x.rem_euclid(self.size.x);
+        let y = offset.y.rem_euclid(self.size.y);
+        self.tiles.get((y * self.size.x + x) as usize)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaintMode {
+    /// Paints a `brush_size` square under the cursor while dragging.
+    Brush,
+    /// Fills the rectangle between the press and the release cells.
+    RectFill,
+    /// Fills the contiguous area around the pressed cell, see `match_policy`.
+    FloodFill,
+}
+
+/// A paint tool for in-game level editors or debug terrain tweaks.
+/// Feed it with mouse input via `handle_mouse()`, or drive it with `press()`, `drag()`
+/// and `release()`. Every press-release is a stroke in the `EditJournal`.
+#[derive(Clone, Debug)]
+pub struct PaintTool {
+    pub layer: usize,
+    pub stamp: Stamp,
+    /// Side of the brush square, in tiles.
+    pub brush_size: u32,
+    pub mode: PaintMode,
+    /// What `PaintMode::FloodFill` fills.
+    pub match_policy: MatchPolicy,
+    /// Cell where the current stroke started.
+    stroke_start: Option<IVec2>,
+}
+
+impl PaintTool {
+    pub fn new(layer: usize, stamp: Stamp) -> Self {
+        Self {
+            layer,
+            stamp,
+            brush_size: 1,
+            mode: PaintMode::Brush,
+            match_policy: MatchPolicy::SameTile,
+            stroke_start: None,
+        }
+    }
+
+    pub fn is_painting(&self) -> bool {
+        self.stroke_start.is_some()
+    }
+
+    pub fn press(&mut self, map: &mut Map, journal: &mut EditJournal, cell: IVec2) {
+        journal.begin_stroke();
+        self.stroke_start = Some(cell);
+        if self.mode == PaintMode::FloodFill {
+            self.flood_fill(map, journal, cell);
+        }
+        self.drag(map, journal, cell);
+    }
+
+    pub fn drag(&mut self, map: &mut Map, journal: &mut EditJournal, cell: IVec2) {
+        let Some(start) = self.stroke_start else {
+            return;
+        };
+        if self.mode == PaintMode::Brush {
+            let size = self.brush_size.max(1) as i32;
+            let corner = cell - ivec2(size / 2, size / 2);
+            self.paint_rect(
+                map,
+                journal,
+                corner,
+                corner + ivec2(size - 1, size - 1),
+                start,
+            );
+        }
+    }
+
+    pub fn release(&mut self, map: &mut Map, journal: &mut EditJournal, cell: IVec2) {
+        let Some(start) = self.stroke_start.take() else {
+            return;
+        };
+        if self.mode == PaintMode::RectFill {
+            let corner = start.min(cell);
+            self.paint_rect(map, journal, corner, start.max(cell), corner);
+        }
+        journal.end_stroke();
+    }
+
+    /// Paints with the left mouse button, when the map is drawn from `source_px` into `dest`.
+    pub fn handle_mouse(
+        &mut self,
+        map: &mut Map,
+        journal: &mut EditJournal,
+        source_px: Rect,
+        dest: Rect,
+    ) {
+        let (x, y) = mouse_position();
+        let cell = map.pick_tile(vec2(x, y), source_px, dest);
+        match (is_mouse_button_down(MouseButton::Left), self.is_painting()) {
+            (true, false) => self.press(map, journal, cell),
+            (true, true) => self.drag(map, journal, cell),
+            (false, true) => self.release(map, journal, cell),
+            (false, false) => {}
+        }
+    }
+
+    fn flood_fill(&self, map: &mut Map, journal: &mut EditJournal, start: IVec2) {
+        let mut cells: Vec<IVec2> = map
+            .select_contiguous(self.layer, start, self.match_policy)
+            .cells
+            .into_iter()
+            .collect();
+        // Deterministic order for the journal.
+        cells.sort_by_key(|pos| (pos.y, pos.x));
+        for pos in cells {
+            if let Some(tile) = self.stamp.tile_at(start, pos) {
+                journal.set_tile(map, self.layer, pos, tile.clone());
+            }
+        }
+    }
+
+    fn paint_rect(
+        &self,
+        map: &mut Map,
+        journal: &mut EditJournal,
+        min: IVec2,
+        max: IVec2,
+        stamp_origin: IVec2,
+    ) {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let pos = ivec2(x, y);
+                if let Some(tile) = self.stamp.tile_at(stamp_origin, pos) {
+                    journal.set_tile(map, self.layer, pos, tile.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+
+    fn ids(map: &Map, layer: usize) -> Vec<Option<u32>> {
+        map.layer_tiles(layer)
+            .map(|(_, tile)| tile.map(|tile| tile.id))
+            .collect()
+    }
+
+    #[test]
+    fn test_journal() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let original = ids(&map, ground);
+        let mut journal = EditJournal::new();
+        assert!(!journal.can_undo() && !journal.undo(&mut map));
+
+        journal.begin_stroke();
+        journal.set_tile(&mut map, ground, ivec2(1, 1), None);
+        journal.set_tile(
+            &mut map,
+            ground,
+            ivec2(2, 1),
+            Some(TileHandle::new("tiny", 1)),
+        );
+        // Unchanged tiles, cells outside of the map and layers without tiles aren't recorded.
+        journal.set_tile(
+            &mut map,
+            ground,
+            ivec2(0, 0),
+            Some(TileHandle::new("tiny", 2)),
+        );
+        journal.set_tile(&mut map, ground, ivec2(9, 9), None);
+        let objects = map.layer_by_name("objects").unwrap();
+        journal.set_tile(
+            &mut map,
+            objects,
+            ivec2(1, 1),
+            Some(TileHandle::new("tiny", 1)),
+        );
+        journal.set_tile(&mut map, 7, ivec2(1, 1), Some(TileHandle::new("tiny", 1)));
+        journal.end_stroke();
+        assert_eq!(map.tile_at(objects, ivec2(1, 1)), None);
+        assert_eq!(map.tile_at(7, ivec2(1, 1)), None);
+        let edited = ids(&map, ground);
+        assert_eq!((edited[5], edited[6]), (None, Some(1)));
+
+        // A stroke of its own.
+        journal.set_tile(&mut map, ground, ivec2(3, 3), None);
+        assert!(journal.can_undo() && !journal.can_redo());
+
+        assert!(journal.undo(&mut map));
+        assert_eq!(ids(&map, ground), edited);
+        assert!(journal.undo(&mut map));
+        assert_eq!(ids(&map, ground), original);
+        assert!(!journal.undo(&mut map));
+
+        assert!(journal.redo(&mut map));
+        assert_eq!(ids(&map, ground), edited);
+        assert!(journal.can_redo());
+        // New edits drop what was undone.
+        journal.set_tile(&mut map, ground, ivec2(0, 3), None);
+        assert!(!journal.can_redo() && !journal.redo(&mut map));
+        assert!(journal.undo(&mut map) && journal.undo(&mut map));
+        assert_eq!(ids(&map, ground), original);
+    }
+
+    #[test]
+    fn test_paint_tool() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let original = ids(&map, ground);
+        let mut journal = EditJournal::new();
+        let stamp = Stamp::new(ivec2(2, 1), vec![Some(TileHandle::new("tiny", 0)), None]).unwrap();
+        let mut tool = PaintTool::new(ground, stamp);
+
+        // A brush stroke, the stamp repeating from the pressed cell.
+        tool.press(&mut map, &mut journal, ivec2(1, 0));
+        assert!(tool.is_painting());
+        tool.drag(&mut map, &mut journal, ivec2(2, 0));
+        tool.drag(&mut map, &mut journal, ivec2(3, 0));
+        tool.release(&mut map, &mut journal, ivec2(3, 0));
+        assert!(!tool.is_painting());
+        assert_eq!(ids(&map, ground)[..4], [Some(2), Some(0), None, Some(0)]);
+        assert!(journal.undo(&mut map));
+        assert_eq!(ids(&map, ground), original);
+
+        // Rectangles are painted on release, from their top-left corner.
+        tool.mode = PaintMode::RectFill;
+        tool.press(&mut map, &mut journal, ivec2(2, 3));
+        tool.drag(&mut map, &mut journal, ivec2(0, 2));
+        assert_eq!(ids(&map, ground), original);
+        tool.release(&mut map, &mut journal, ivec2(0, 2));
+        assert_eq!(
+            ids(&map, ground)[8..],
+            [
+                Some(0),
+                None,
+                Some(0),
+                Some(2),
+                Some(0),
+                None,
+                Some(0),
+                Some(2)
+            ]
+        );
+        assert!(journal.undo(&mut map));
+        assert_eq!(ids(&map, ground), original);
+
+        // The walls around the floor, in one stroke.
+        tool.mode = PaintMode::FloodFill;
+        tool.stamp = Stamp::single(Some(TileHandle::new("tiny", 1)));
+        tool.press(&mut map, &mut journal, ivec2(0, 0));
+        tool.release(&mut map, &mut journal, ivec2(0, 0));
+        let filled = ids(&map, ground);
+        assert_eq!(filled.iter().filter(|id| **id == Some(1)).count(), 13);
+        assert_eq!(filled[5], Some(0));
+        assert!(journal.undo(&mut map) && !journal.can_undo());
+        assert_eq!(ids(&map, ground), original);
+        assert!(journal.redo(&mut map));
+        assert_eq!(ids(&map, ground), filled);
+
+        // An empty stamp paints nothing.
+        tool.stamp = Stamp::new(ivec2(0, 3), vec![]).unwrap();
+        tool.mode = PaintMode::Brush;
+        tool.press(&mut map, &mut journal, ivec2(1, 1));
+        tool.release(&mut map, &mut journal, ivec2(1, 1));
+        assert_eq!(ids(&map, ground), filled);
+        assert!(journal.undo(&mut map));
+        assert_eq!(ids(&map, ground), original);
+    }
+
+    #[test]
+    fn test_stamp() {
+        let wall = Some(TileHandle::new("tiny", 2));
+        assert_eq!(Stamp::new(ivec2(2, 2), vec![wall.clone(); 3]), None);
+        assert_eq!(Stamp::new(ivec2(-1, -1), vec![wall.clone()]), None);
+        assert_eq!(Stamp::new(ivec2(i32::MAX, 2), vec![]), None);
+
+        let stamp = Stamp::new(ivec2(2, 1), vec![wall.clone(), None]).unwrap();
+        assert_eq!(stamp.size(), ivec2(2, 1));
+        assert_eq!(stamp.tile_at(ivec2(1, 1), ivec2(1, 5)), Some(&wall));
+        assert_eq!(stamp.tile_at(ivec2(1, 1), ivec2(0, 0)), Some(&None));
+
+        let empty = Stamp::new(ivec2(0, 0), vec![]).unwrap();
+        assert!(empty.is_empty() && empty.tiles().is_empty());
+        assert_eq!(empty.tile_at(ivec2(0, 0), ivec2(3, 3)), None);
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use macroquad::file::load_file;
+use macroquad::miniquad::fs::Error as FsError;
+use macroquad::texture::Image;
+use macroquad::Error as MqError;
+use tiled::{DefaultResourceCache, Error as TiledError, Loader};
+
+use crate::map::{file_error_to_tiled, LoadOptions, Map};
+use crate::orientation::map_hex_side_length;
+use crate::texture_cache::{normalize, TextureCache, TextureKey};
+use crate::tileset::TileSet;
+
+/// The path `Map::from_bytes()` gives the TMX, the others are relative to it.
+const MAP_PATH: &str = "map.tmx";
+/// Same for `TileSet::from_bytes()`.
+const TILESET_PATH: &str = "tileset.tsx";
+
+/// Serves files from memory to the tiled loader, e.g. assets embedded in the binary
+/// with `include_bytes!()`, by their paths. `.` and `..` in paths are resolved.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryReader<'a> {
+    files: HashMap<PathBuf, &'a [u8]>,
+}
+
+impl<'a> MemoryReader<'a> {
+    pub fn new(files: &[(&str, &'a [u8])]) -> Self {
+        let mut reader = Self::default();
+        for (path, bytes) in files {
+            reader.insert(path, bytes);
+        }
+        reader
+    }
+
+    pub fn insert(&mut self, path: impl AsRef<Path>, bytes: &'a [u8]) {
+        self.files.insert(normalize(path.as_ref()), bytes);
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&'a [u8]> {
+        self.files.get(&normalize(path.as_ref())).copied()
+    }
+
+    /// Serves `files`, by normalized paths.
+    fn from_files(files: &'a HashMap<PathBuf, Vec<u8>>) -> Self {
+        Self {
+            files: files
+                .iter()
+                .map(|(path, bytes)| (path.clone(), bytes.as_slice()))
+                .collect(),
+        }
+    }
+
+    /// The image of `tileset`, decoded and extruded by `padding`, see `TileSet::decode_image()`.
+    fn decode_image(&self, tileset: &tiled::Tileset, padding: u16) -> Result<Image, MqError> {
+        let source = &tileset
+            .image
+            .as_ref()
+            .expect("Only spritesheet-type tilesets are now supported")
+            .source;
+        let bytes = self.get(source).ok_or_else(|| MqError::FileError {
+            kind: FsError::IOError(not_found(source)),
+            path: source.to_string_lossy().into_owned(),
+        })?;
+        TileSet::decode_image_bytes(tileset, bytes, padding)
+    }
+}
+
+impl<'a> tiled::ResourceReader for MemoryReader<'a> {
+    type Resource = &'a [u8];
+    type Error = io::Error;
+
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error> {
+        self.get(path).ok_or_else(|| not_found(path))
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Not an embedded file: {:?}", path),
+    )
+}
+
+/// `path` relative to `base`, with forward slashes, for `load_file()`.
+fn base_path(base: &str, path: &Path) -> String {
+    let path = normalize(path).to_string_lossy().replace('\\', "/");
+    match base.trim_end_matches('/') {
+        "" => path,
+        base => format!("{}/{}", base, path),
+    }
+}
+
+/// A file used by a map, see `dependencies()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Dependency {
+    /// Relative to the file using it.
+    path: PathBuf,
+    /// The image of a tileset, which the tiled loader doesn't read.
+    image: bool,
+}
+
+/// The files a TMX, TSX or template uses, from its tags: external tilesets, templates,
+/// and the images of tilesets. Image layers and collections of images aren't drawn.
+fn dependencies(xml: &str) -> Vec<Dependency> {
+    let mut dependencies = vec![];
+    // Names of the open elements.
+    let mut open: Vec<&str> = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        // The end of the tag, `>` may be in quoted values.
+        let mut quote = None;
+        let Some(end) = rest.find(|c| {
+            match quote {
+                Some(q) if c == q => quote = None,
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None => return c == '>',
+                _ => {}
+            }
+            false
+        }) else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('/') {
+            open.pop();
+            continue;
+        }
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        let found = match (name, open.last()) {
+            ("tileset", _) => attribute(tag, "source").map(|path| (path, false)),
+            ("image", Some(&"tileset")) => attribute(tag, "source").map(|path| (path, true)),
+            ("object", _) => attribute(tag, "template").map(|path| (path, false)),
+            _ => None,
+        };
+        if let Some((path, image)) = found {
+            dependencies.push(Dependency {
+                path: PathBuf::from(path),
+                image,
+            });
+        }
+        if !tag.ends_with('/') {
+            open.push(name);
+        }
+    }
+    dependencies
+}
+
+/// The value of the attribute `name` of `tag`, unescaped.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    loop {
+        let start = rest.find(name)?;
+        let before = rest[..start].chars().next_back();
+        let after = rest[start + name.len()..].trim_start();
+        rest = &rest[start + name.len()..];
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        let value = &value[..value.find(quote)?];
+        return Some(
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+    }
+}
+
+impl Map {
+    /// Same as `from_bytes_with()`, with the default options.
+    pub async fn from_bytes(tmx: &[u8], files: &[(&str, &[u8])]) -> Result<Self, TiledError> {
+        Self::from_bytes_with(tmx, files, &LoadOptions::default()).await
+    }
+
+    /// Loads a map from memory, e.g. from assets embedded in the binary with
+    /// `include_bytes!()`: the TMX, and the `files` it uses by their paths relative to it,
+    /// its external tilesets and templates, and the images of the tilesets, e.g.
+    /// ("tilesets/forest.tsx", ...) and ("tilesets/forest.png", ...) for an image
+    /// "forest.png" of that tileset. Textures aren't streamed nor deferred.
+    /// Needs a macroquad window.
+    ///
+    /// Errors:
+    /// * If a file is missing, unless `LoadOptions::placeholder_missing_images` for images,
+    ///   or can't be parsed or decoded.
+    /// * On unsupported TMX features in strict mode, see `LoadOptions`.
+    pub async fn from_bytes_with(
+        tmx: &[u8],
+        files: &[(&str, &[u8])],
+        options: &LoadOptions,
+    ) -> Result<Self, TiledError> {
+        let mut reader = MemoryReader::new(files);
+        reader.insert(MAP_PATH, tmx);
+        Self::from_memory(reader, MAP_PATH, options).await
+    }
+
+    /// Loads the map at `map_path`, relative to `base`, and the files it uses, with
+    /// macroquad's `load_file()`: read from the filesystem on native targets, and fetched
+    /// relative to the page on the web, where there is none, e.g. `Map::load_from("assets",
+    /// "maps/town.tmx", &options)` works on both. Textures aren't streamed nor deferred.
+    ///
+    /// Errors: same as `from_bytes_with()`, and if a file can't be loaded, images included
+    /// unless `LoadOptions::placeholder_missing_images`.
+    pub async fn load_from(
+        base: &str,
+        map_path: &str,
+        options: &LoadOptions,
+    ) -> Result<Self, TiledError> {
+        // The tiled loader is blocking: fetch the files it will read, and the images of the
+        // tilesets, following the references of each file, then parse the map once.
+        let mut files: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut pending = vec![Dependency {
+            path: PathBuf::from(map_path),
+            image: false,
+        }];
+        while let Some(Dependency { path, image }) = pending.pop() {
+            let path = normalize(&path);
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            let bytes = match load_file(&base_path(base, &path)).await {
+                Ok(bytes) => bytes,
+                // Left to `new_async_map_decoded()`, which warns about it.
+                Err(_) if image && options.placeholder_missing_images => continue,
+                Err(e) => return Err(file_error_to_tiled(e)),
+            };
+            if !image {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                pending.extend(
+                    dependencies(&String::from_utf8_lossy(&bytes))
+                        .into_iter()
+                        .map(|dependency| Dependency {
+                            path: dir.join(dependency.path),
+                            ..dependency
+                        }),
+                );
+            }
+            files.insert(path, bytes);
+        }
+
+        Self::from_memory(MemoryReader::from_files(&files), map_path, options).await
+    }
+
+    /// Loads the map at `map_path` in `reader`, see `from_bytes_with()`.
+    async fn from_memory(
+        reader: MemoryReader<'_>,
+        map_path: &str,
+        options: &LoadOptions,
+    ) -> Result<Self, TiledError> {
+        let DecodedMap {
+            map,
+            hex_side_length,
+            images,
+        } = decode_memory(reader, map_path, options)?;
+        let options = LoadOptions {
+            stream_textures: false,
+            defer_textures: false,
+            ..options.clone()
+        };
+        let mut map =
+            Self::new_async_map_decoded(map, &options, images, &mut TextureCache::new()).await?;
+        if let Some(length) = hex_side_length {
+            map.hex_side_length = length;
+        }
+        Ok(map)
+    }
+}
+
+/// A map parsed from memory, before making the textures of its tilesets.
+struct DecodedMap {
+    map: tiled::Map,
+    hex_side_length: Option<u32>,
+    /// The images of the tilesets by name, one per texture, see `TextureKey`.
+    images: HashMap<String, Result<Image, MqError>>,
+}
+
+/// Parses the map at `map_path` in `reader`, and decodes the images of its tilesets.
+fn decode_memory(
+    reader: MemoryReader<'_>,
+    map_path: &str,
+    options: &LoadOptions,
+) -> Result<DecodedMap, TiledError> {
+    let tmx = reader.get(map_path).unwrap_or_default();
+    let mut loader = Loader::with_cache_and_reader(DefaultResourceCache::new(), reader);
+    let map = loader.load_tmx_map(map_path)?;
+    let hex_side_length = map_hex_side_length(&map, tmx)?;
+
+    let mut decoded = HashSet::new();
+    let images = map
+        .tilesets()
+        .iter()
+        // Once per image, the others share its texture.
+        .filter(|tileset| {
+            TextureKey::new(tileset, options.extrude_tiles).is_some_and(|key| decoded.insert(key))
+        })
+        .map(|tileset| {
+            let image = loader.reader().decode_image(tileset, options.extrude_tiles);
+            (tileset.name.clone(), image)
+        })
+        .collect();
+    Ok(DecodedMap {
+        map,
+        hex_side_length,
+        images,
+    })
+}
+
+/// Parses `tsx`, and decodes its `image`, see `TileSet::from_bytes()`.
+fn decode_tileset(tsx: &[u8], image: &[u8]) -> Result<(tiled::Tileset, Image), TiledError> {
+    let reader = MemoryReader::new(&[(TILESET_PATH, tsx)]);
+    let tileset = Loader::with_cache_and_reader(DefaultResourceCache::new(), reader)
+        .load_tsx_tileset(TILESET_PATH)?;
+    let image = TileSet::decode_image_bytes(&tileset, image, 0).map_err(file_error_to_tiled)?;
+    Ok((tileset, image))
+}
+
+impl TileSet {
+    /// Loads a tileset from memory, e.g. embedded in the binary with `include_bytes!()`:
+    /// the TSX, and the bytes of its image, whatever its path in the TSX.
+    /// Needs a macroquad window.
+    ///
+    /// Errors: if the TSX can't be parsed, or the image decoded.
+    pub fn from_bytes(tsx: &[u8], image: &[u8]) -> Result<Self, TiledError> {
+        let (tileset, decoded) = decode_tileset(tsx, image)?;
+        Ok(Self::from_decoded(tileset, &decoded, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TINY_PNG, TINY_TMX, TINY_TSX};
+
+    #[test]
+    fn test_paths() {
+        let reader = MemoryReader::new(&[("tilesets/forest.png", b"png")]);
+        assert_eq!(
+            reader.get("maps/../tilesets/./forest.png"),
+            Some(&b"png"[..])
+        );
+        assert_eq!(reader.get("forest.png"), None);
+
+        assert_eq!(
+            base_path("assets/", Path::new("maps/../town.tmx")),
+            "assets/town.tmx"
+        );
+        assert_eq!(base_path("", Path::new("town.tmx")), "town.tmx");
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="4" height="4">
+ <!-- <tileset firstgid="1" source="commented.tsx"/> -->
+ <tileset firstgid="1" source="../tilesets/forest.tsx"/>
+ <tileset firstgid="9" name="inline" tilewidth="16" tileheight="16">
+  <properties>
+   <property name="note" value="a > b"/>
+  </properties>
+  <image source="inline &amp; co.png" width="32" height="32"/>
+  <tile id="0">
+   <image source="collection.png"/>
+  </tile>
+ </tileset>
+ <imagelayer id="2" name="sky">
+  <image source="sky.png"/>
+ </imagelayer>
+ <objectgroup id="3" name="objects">
+  <object id="1" template='door.tx' x="8" y="8"/>
+  <object id="2" name="template" x="8" y="8"/>
+ </objectgroup>
+</map>"#;
+        let dependency = |path: &str, image| Dependency {
+            path: PathBuf::from(path),
+            image,
+        };
+        assert_eq!(
+            dependencies(tmx),
+            vec![
+                dependency("../tilesets/forest.tsx", false),
+                dependency("inline & co.png", true),
+                dependency("door.tx", false),
+            ]
+        );
+
+        let tsx = r#"<tileset version="1.10" name="forest"><image source="forest.png"/></tileset>"#;
+        assert_eq!(dependencies(tsx), vec![dependency("forest.png", true)]);
+        assert_eq!(
+            dependencies(crate::testing::TINY_TMX),
+            vec![dependency("tiny.tsx", false)]
+        );
+    }
+
+    #[test]
+    fn test_decode_from_bytes() {
+        // What `Map::from_bytes()` makes textures of, from a TMX in a subfolder.
+        let files = [
+            ("maps/tiny.tmx", TINY_TMX.as_bytes()),
+            ("maps/tiny.tsx", TINY_TSX.as_bytes()),
+            ("maps/tiny.png", TINY_PNG),
+        ];
+        let options = LoadOptions::default();
+        let decoded = decode_memory(MemoryReader::new(&files), "maps/tiny.tmx", &options).unwrap();
+        let expected = crate::testing::tiny_tiled_map();
+        assert_eq!((decoded.map.width, decoded.map.height), (4, 4));
+        let tile_id = |map: &tiled::Map, x, y| {
+            map.get_layer(0)
+                .and_then(|layer| layer.as_tile_layer())
+                .and_then(|layer| layer.get_tile(x, y))
+                .map(|tile| tile.id())
+        };
+        for (x, y) in [(0, 0), (1, 1), (2, 1), (2, 2)] {
+            assert_eq!(tile_id(&decoded.map, x, y), tile_id(&expected, x, y));
+        }
+        assert_eq!(decoded.hex_side_length, None);
+        let image = decoded.images["tiny"].as_ref().unwrap();
+        let png = Image::from_file_with_format(TINY_PNG, None).unwrap();
+        assert_eq!((image.width, image.height), (32, 32));
+        assert_eq!(image.bytes, png.bytes);
+
+        // Missing images are left to `new_async_map_decoded()`, others fail right away.
+        let decoded = decode_memory(MemoryReader::new(&files[..2]), "maps/tiny.tmx", &options);
+        assert!(decoded.unwrap().images["tiny"].is_err());
+        assert!(decode_memory(MemoryReader::new(&files[..1]), "maps/tiny.tmx", &options).is_err());
+
+        let (tileset, image) = decode_tileset(TINY_TSX.as_bytes(), TINY_PNG).unwrap();
+        assert_eq!(tileset.name, "tiny");
+        assert_eq!(tileset.tilecount, 4);
+        assert_eq!(image.bytes, png.bytes);
+        assert!(decode_tileset(TINY_TSX.as_bytes(), b"not a png").is_err());
+        assert!(decode_tileset(b"<tileset", TINY_PNG).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+#[cfg(feature = "render")]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+#[cfg(feature = "render")]
+use coarsetime::Instant;
+use macroquad::math::{IVec2, Vec2};
+use tiled::{LayerType, PropertyValue};
+
+#[cfg(feature = "render")]
+use crate::animation_controller::{AnimationController, AnimationTrigger};
+use crate::collision_shapes::Shape;
+use crate::map::{LoadWarning, Map, TileHandle};
+
+/// Class of the objects whose areas raise `MapEvent::ZoneEntered` and `ZoneLeft`,
+/// see `Map::update_zones()`.
+pub const TRIGGER_CLASS: &str = "trigger";
+
+/// Something that happened in a map, see `Map::drain_events()`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapEvent {
+    /// A cell was changed by `Map::set_tile()`.
+    TileEdited {
+        layer: usize,
+        pos: IVec2,
+        previous: Option<TileHandle>,
+        tile: Option<TileHandle>,
+    },
+    /// The state of a cell was changed by `Map::set_tile_state()` or `clear_tile_state()`.
+    TileStateChanged {
+        pos: IVec2,
+        previous: Option<String>,
+        state: Option<String>,
+    },
+    /// A runtime property was changed by `Map::set_runtime_property()`,
+    /// or removed by `clear_runtime_properties()`.
+    PropertyChanged {
+        layer: usize,
+        pos: IVec2,
+        name: String,
+        previous: Option<PropertyValue>,
+        value: Option<PropertyValue>,
+    },
+    /// `entity` moved into the area of the trigger object `object`, by its id.
+    ZoneEntered {
+        entity: u64,
+        object: u32,
+    },
+    ZoneLeft {
+        entity: u64,
+        object: u32,
+    },
+    /// Fired by the controller of `entity`, see `Map::add_controller()`.
+    #[cfg(feature = "render")]
+    Animation {
+        entity: u64,
+        trigger: AnimationTrigger,
+    },
+    /// Found while loading, or a streamed texture which failed, see `Map::warnings()`.
+    Warning(LoadWarning),
+}
+
+/// The event bus of a map.
+#[derive(Debug, Default)]
+pub(crate) struct MapEvents {
+    /// `None` until `Map::enable_events()`.
+    queue: Option<Vec<MapEvent>>,
+    /// The trigger objects each entity is in, by id.
+    zones: HashMap<u64, Vec<u32>>,
+    /// Set with `Map::add_controller()`, by entity, in id order for the triggers
+    /// to be queued in a stable order.
+    #[cfg(feature = "render")]
+    controllers: BTreeMap<u64, AnimationController>,
+}
+
+impl MapEvents {
+    pub(crate) fn push(&mut self, event: MapEvent) {
+        if let Some(queue) = &mut self.queue {
+            queue.push(event);
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    #[cfg(feature = "render")]
+    pub(crate) fn update_controllers(&mut self, now: Instant) {
+        for controller in self.controllers.values_mut() {
+            controller.update(now);
+        }
+    }
+
+    /// Queues the triggers fired by the controllers, or drops them if events are disabled.
+    #[cfg(feature = "render")]
+    fn drain_triggers(&mut self) {
+        for (&entity, controller) in &mut self.controllers {
+            for trigger in controller.drain_triggers() {
+                if let Some(queue) = &mut self.queue {
+                    queue.push(MapEvent::Animation { entity, trigger });
+                }
+            }
+        }
+    }
+}
+
+impl Map {
+    /// Starts queuing events for `drain_events()`, the warnings so far first.
+    /// Nothing is queued before, so that maps of games which don't drain them don't grow.
+    pub fn enable_events(&mut self) {
+        if self.events.queue.is_none() {
+            let warnings = self.warnings().iter().cloned().map(MapEvent::Warning);
+            self.events.queue = Some(warnings.collect());
+        }
+    }
+
+    pub fn events_enabled(&self) -> bool {
+        self.events.enabled()
+    }
+
+    /// Queues `event`, if events are enabled.
+    pub fn push_event(&mut self, event: MapEvent) {
+        self.events.push(event);
+    }
+
+    /// The events since the last call, in order: tile edits, trigger zones and warnings,
+    /// all in one place, then the triggers fired by the controllers, see `add_controller()`.
+    /// Call once per frame, after updating the map and the entities.
+    pub fn drain_events(&mut self) -> Vec<MapEvent> {
+        #[cfg(feature = "render")]
+        self.events.drain_triggers();
+        self.events
+            .queue
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Moves `entity` to `world_px`, queuing `MapEvent::ZoneLeft` for the trigger objects,
+    /// see `TRIGGER_CLASS`, it left, then `MapEvent::ZoneEntered` for those it entered.
+    pub fn update_zones(&mut self, entity: u64, world_px: Vec2) {
+        let inside = self.trigger_zones_at(world_px);
+        self.set_zones(entity, inside);
+    }
+
+    /// Takes `entity` out of all trigger zones, e.g. when it's removed from the map.
+    pub fn leave_zones(&mut self, entity: u64) {
+        self.set_zones(entity, vec![]);
+    }
+
+    /// The ids of the trigger objects whose area contains `world_px`, in id order.
+    pub fn trigger_zones_at(&self, world_px: Vec2) -> Vec<u32> {
+        let mut zones: Vec<_> = self
+            .map
+            .layers()
+            .filter_map(|layer| match layer.layer_type() {
+                LayerType::Objects(objects) => Some(objects),
+                _ => None,
+            })
+            .flat_map(|objects| objects.objects())
+            .filter(|object| object.user_type == TRIGGER_CLASS)
+            .filter(|object| Shape::from_object(object).is_some_and(|zone| zone.contains(world_px)))
+            .map(|object| object.id())
+            .collect();
+        zones.sort_unstable();
+        zones
+    }
+
+    fn set_zones(&mut self, entity: u64, inside: Vec<u32>) {
+        let before = self.events.zones.remove(&entity).unwrap_or_default();
+        for object in before.iter().filter(|object| !inside.contains(object)) {
+            self.events.push(MapEvent::ZoneLeft {
+                entity,
+                object: *object,
+            });
+        }
+        for object in inside.iter().filter(|object| !before.contains(object)) {
+            self.events.push(MapEvent::ZoneEntered {
+                entity,
+                object: *object,
+            });
+        }
+        if !inside.is_empty() {
+            self.events.zones.insert(entity, inside);
+        }
+    }
+
+    /// Hands the controller of `entity` over to the map: `update()` updates it, and
+    /// `drain_events()` queues its triggers as `MapEvent::Animation`. Replaces the previous
+    /// controller of `entity`, if any. See `controller_mut()` to add animations.
+    #[cfg(feature = "render")]
+    pub fn add_controller(&mut self, entity: u64, controller: AnimationController) {
+        self.events.controllers.insert(entity, controller);
+    }
+
+    #[cfg(feature = "render")]
+    pub fn controller(&self, entity: u64) -> Option<&AnimationController> {
+        self.events.controllers.get(&entity)
+    }
+
+    #[cfg(feature = "render")]
+    pub fn controller_mut(&mut self, entity: u64) -> Option<&mut AnimationController> {
+        self.events.controllers.get_mut(&entity)
+    }
+
+    /// The controllers added with `add_controller()`, by entity, e.g. to draw them.
+    #[cfg(feature = "render")]
+    pub fn controllers(&self) -> impl Iterator<Item = (u64, &AnimationController)> {
+        self.events
+            .controllers
+            .iter()
+            .map(|(entity, controller)| (*entity, controller))
+    }
+
+    /// Takes the controller of `entity` back, e.g. when it leaves the map. Its pending
+    /// triggers are queued first.
+    #[cfg(feature = "render")]
+    pub fn remove_controller(&mut self, entity: u64) -> Option<AnimationController> {
+        let mut controller = self.events.controllers.remove(&entity)?;
+        for trigger in controller.drain_triggers() {
+            self.events.push(MapEvent::Animation { entity, trigger });
+        }
+        Some(controller)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{tiny_map, triggers_map};
+    use macroquad::math::{ivec2, vec2};
+
+    #[test]
+    fn test_tile_events() {
+        let mut map = tiny_map();
+        let wall = TileHandle::new("tiny", 2);
+        map.set_tile(0, ivec2(1, 1), Some(wall.clone()));
+        assert!(map.drain_events().is_empty());
+
+        map.enable_events();
+        map.set_tile(0, ivec2(1, 1), None);
+        assert_eq!(
+            map.drain_events(),
+            vec![MapEvent::TileEdited {
+                layer: 0,
+                pos: ivec2(1, 1),
+                previous: Some(wall),
+                tile: None,
+            }]
+        );
+        assert!(map.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_state_and_property_events() {
+        let mut map = tiny_map();
+        map.enable_events();
+        map.set_tile_state(ivec2(1, 1), "on");
+        map.set_tile_state(ivec2(1, 1), "on");
+        map.clear_tile_state(ivec2(1, 1));
+        let on = Some("on".to_string());
+        assert_eq!(
+            map.drain_events(),
+            vec![
+                MapEvent::TileStateChanged {
+                    pos: ivec2(1, 1),
+                    previous: None,
+                    state: on.clone(),
+                },
+                MapEvent::TileStateChanged {
+                    pos: ivec2(1, 1),
+                    previous: on,
+                    state: None,
+                },
+            ]
+        );
+
+        let burned = Some(PropertyValue::BoolValue(true));
+        map.set_runtime_property(0, ivec2(1, 1), "burned", burned.clone());
+        map.set_runtime_property(0, ivec2(1, 1), "burned", burned.clone());
+        map.clear_runtime_properties();
+        let changed = |previous, value| MapEvent::PropertyChanged {
+            layer: 0,
+            pos: ivec2(1, 1),
+            name: "burned".to_string(),
+            previous,
+            value,
+        };
+        assert_eq!(
+            map.drain_events(),
+            vec![changed(None, burned.clone()), changed(burned, None)]
+        );
+    }
+
+    #[test]
+    fn test_zone_events() {
+        let mut map = triggers_map();
+        map.enable_events();
+        map.update_zones(7, vec2(4.0, 4.0));
+        assert!(map.drain_events().is_empty());
+
+        // The "door" covers the cell (1, 1), the "pit" the floor, the "sign" isn't a trigger.
+        assert_eq!(map.trigger_zones_at(vec2(24.0, 24.0)), vec![1, 2]);
+        assert_eq!(map.trigger_zones_at(vec2(40.0, 40.0)), vec![2]);
+        assert!(map.trigger_zones_at(vec2(17.0, 47.0)).is_empty());
+        map.update_zones(7, vec2(24.0, 24.0));
+        map.update_zones(7, vec2(25.0, 24.0));
+        let entered = |object| MapEvent::ZoneEntered { entity: 7, object };
+        let left = |object| MapEvent::ZoneLeft { entity: 7, object };
+        assert_eq!(map.drain_events(), vec![entered(1), entered(2)]);
+        map.update_zones(7, vec2(40.0, 40.0));
+        assert_eq!(map.drain_events(), vec![left(1)]);
+        map.leave_zones(7);
+        assert_eq!(map.drain_events(), vec![left(2)]);
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_controller_events() {
+        use crate::testing::{mock_frames1243, mock_template};
+        use coarsetime::Duration;
+
+        let mut template = mock_template(mock_frames1243(1..=4), 50);
+        let swing = PropertyValue::StringValue("sfx:swing".to_string());
+        template.triggers =
+            vec![AnimationTrigger::from_property("trigger_frame_1", &swing).unwrap()];
+
+        let mut map = tiny_map();
+        map.enable_events();
+        let start = Instant::now();
+        let mut controller = AnimationController::new();
+        controller.add_animation(start, &template, (0., 0.), (0., 0.));
+        map.add_controller(3, controller);
+        map.add_controller(5, AnimationController::new());
+
+        map.update(start + Duration::from_ticks(50));
+        assert!(map.drain_events().is_empty());
+        map.update(start + Duration::from_ticks(150));
+        let events = map.drain_events();
+        assert!(
+            matches!(&events[..], [MapEvent::Animation { entity: 3, trigger }] if trigger.payload == "swing")
+        );
+        assert!(map.drain_events().is_empty());
+        assert_eq!(
+            map.controllers()
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>(),
+            vec![3, 5]
+        );
+        assert!(map.remove_controller(3).is_some());
+        assert!(map.controller(3).is_none());
+    }
+}
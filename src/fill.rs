@@ -0,0 +1,159 @@
+use std::collections::{HashSet, VecDeque};
+
+use macroquad::math::IVec2;
+
+use crate::map::{Map, TileHandle, TileRef};
+
+/// Selections and fills on infinite maps stop after this many cells, so they terminate.
+/// Finite maps are bounded by their size.
+pub const MAX_FILL_CELLS: usize = 65536;
+
+/// A contiguous area of cells, see `Map::select_contiguous()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub cells: HashSet<IVec2>,
+    /// If the area was cut at `MAX_FILL_CELLS` and goes on beyond `cells`.
+    pub truncated: bool,
+}
+
+/// Which neighbours belong to the same area as the start cell.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchPolicy {
+    /// The same tile of the same tileset, or empty if the start cell is empty.
+    #[default]
+    SameTile,
+    /// The same Tiled class, aka type, e.g. all "water" tiles.
+    /// Tiles without a class fall back to `SameTile`.
+    SameClass,
+    /// Any tile if the start cell has one, otherwise empty cells.
+    Occupancy,
+}
+
+impl Map {
+    /// The 4-connected area of cells around `start` on `layer` matching it by `policy`,
+    /// like the magic wand of image editors. Empty if `start` is outside of the map.
+    /// On infinite maps, the area is cut at `MAX_FILL_CELLS`, see `Selection::truncated`.
+    pub fn select_contiguous(&self, layer: usize, start: IVec2, policy: MatchPolicy) -> Selection {
+        if !self.contains(start) {
+            return Selection::default();
+        }
+        let target = self.match_key(layer, start, policy);
+        let max_cells = self.map.infinite().then_some(MAX_FILL_CELLS);
+        contiguous(start, max_cells, |pos| {
+            self.contains(pos) && self.match_key(layer, pos, policy) == target
+        })
+    }
+
+    /// Replaces the area selected by `select_contiguous()` with `new_tile`, or erases it.
+    /// Returns the filled cells, which may be `truncated` on infinite maps.
+    ///
+    /// Panics:
+    /// * If `new_tile`'s tileset does not exist.
+    pub fn flood_fill(
+        &mut self,
+        layer: usize,
+        start: IVec2,
+        new_tile: Option<TileHandle>,
+        policy: MatchPolicy,
+    ) -> Selection {
+        let selection = self.select_contiguous(layer, start, policy);
+        for pos in &selection.cells {
+            self.set_tile(layer, *pos, new_tile.clone());
+        }
+        selection
+    }
+
+    fn match_key(&self, layer: usize, pos: IVec2, policy: MatchPolicy) -> MatchKey<'_> {
+        let Some(TileRef { tileset, id, .. }) = self.tile_ref_at(layer, pos) else {
+            return MatchKey::Empty;
+        };
+        match policy {
+            MatchPolicy::Occupancy => MatchKey::Occupied,
+            MatchPolicy::SameTile => MatchKey::Tile(tileset, id),
+            MatchPolicy::SameClass => {
+                let class = self
+                    .tile_data(tileset, id)
+                    .and_then(|tile| tile.user_type.clone());
+                match class {
+                    Some(class) => MatchKey::Class(class),
+                    None => MatchKey::Tile(tileset, id),
+                }
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum MatchKey<'a> {
+    Empty,
+    Occupied,
+    Tile(&'a str, u32),
+    Class(String),
+}
+
+/// Breadth-first search of the 4-connected cells around `start` for which `matches` is true.
+/// `start` is always included. Stops at `max_cells`, if any, marking the selection truncated.
+pub(crate) fn contiguous(
+    start: IVec2,
+    max_cells: Option<usize>,
+    matches: impl Fn(IVec2) -> bool,
+) -> Selection {
+    let mut cells = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(pos) = queue.pop_front() {
+        for next in [
+            pos + IVec2::X,
+            pos - IVec2::X,
+            pos + IVec2::Y,
+            pos - IVec2::Y,
+        ] {
+            if cells.contains(&next) || !matches(next) {
+                continue;
+            }
+            if max_cells.is_some_and(|max| cells.len() >= max) {
+                return Selection {
+                    cells,
+                    truncated: true,
+                };
+            }
+            cells.insert(next);
+            queue.push_back(next);
+        }
+    }
+    Selection {
+        cells,
+        truncated: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_contiguous() {
+        // A 5x5 grid with a wall at x == 2.
+        let inside = |pos: IVec2| pos.x >= 0 && pos.y >= 0 && pos.x < 5 && pos.y < 5;
+        let selection = contiguous(ivec2(0, 0), None, |pos| inside(pos) && pos.x != 2);
+        assert_eq!(selection.cells.len(), 10);
+        assert!(selection.cells.iter().all(|pos| pos.x < 2));
+        assert!(!selection.truncated);
+
+        // Areas exactly at the cap aren't truncated.
+        let selection = contiguous(ivec2(0, 0), Some(25), inside);
+        assert_eq!(selection.cells.len(), 25);
+        assert!(!selection.truncated);
+
+        // Unbounded areas are capped, and say so.
+        let selection = contiguous(ivec2(0, 0), Some(MAX_FILL_CELLS), |_| true);
+        assert_eq!(selection.cells.len(), MAX_FILL_CELLS);
+        assert!(selection.truncated);
+
+        // Without a cap, large areas are filled whole.
+        let big = |pos: IVec2| pos.x >= 0 && pos.y >= 0 && pos.x < 300 && pos.y < 300;
+        let selection = contiguous(ivec2(0, 0), None, big);
+        assert_eq!(selection.cells.len(), 300 * 300);
+        assert!(!selection.truncated);
+    }
+}
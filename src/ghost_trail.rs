@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use coarsetime::{Duration, Instant};
+use macroquad::color::Color;
+use macroquad::math::Rect;
+
+use crate::animation_controller::OutputFrame;
+use crate::tileset::TileSet;
+
+/// Faded copies of an entity at its past frames: motion trails, racing ghosts,
+/// or the last moves of a turn. Feed it `AnimationController::get_frame()` every frame.
+#[derive(Clone, Debug)]
+pub struct GhostTrail {
+    /// How long ghosts stay.
+    pub length: Duration,
+    /// Minimum time between ghosts.
+    pub interval: Duration,
+    /// Opacity of the newest ghost.
+    pub opacity: f32,
+    /// How fast ghosts fade with age: 1 is linear, higher fades the older ghosts faster.
+    pub falloff: f32,
+    /// Oldest first.
+    samples: VecDeque<(Instant, OutputFrame)>,
+}
+
+impl GhostTrail {
+    pub fn new(length: Duration, interval: Duration) -> Self {
+        Self {
+            length,
+            interval,
+            opacity: 0.5,
+            falloff: 1.0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Records `frame` as a ghost, unless the previous one is more recent than `interval`,
+    /// and forgets the ghosts older than `length`.
+    pub fn record(&mut self, now: Instant, frame: OutputFrame) {
+        self.forget_old(now);
+        let due = match self.samples.back() {
+            Some((time, _)) => now.duration_since(*time) >= self.interval,
+            None => true,
+        };
+        if due {
+            self.samples.push_back((now, frame));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The ghosts at `now`, oldest first, with their opacity.
+    pub fn ghosts(&self, now: Instant) -> impl Iterator<Item = (OutputFrame, f32)> + '_ {
+        let length = self.length.as_ticks().max(1) as f32;
+        self.samples.iter().filter_map(move |(time, frame)| {
+            let age = now.duration_since(*time).as_ticks() as f32 / length;
+            (age < 1.0).then(|| (*frame, self.opacity * (1.0 - age).powf(self.falloff)))
+        })
+    }
+
+    /// Draws the ghosts at `now` with `tileset`, tinted with `color` and faded.
+    /// `dest(position)` places a frame on the screen, same as the entity itself is placed.
+    pub fn draw(
+        &self,
+        tileset: &TileSet,
+        now: Instant,
+        color: Color,
+        dest: impl Fn((f32, f32)) -> Rect,
+    ) {
+        for (frame, opacity) in self.ghosts(now) {
+            let color = Color::new(color.r, color.g, color.b, color.a * opacity);
+            tileset.spr_color(frame.tile_id, dest(frame.position), color);
+        }
+    }
+
+    fn forget_old(&mut self, now: Instant) {
+        while let Some((time, _)) = self.samples.front() {
+            if now.duration_since(*time) < self.length {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(tile_id: u32) -> OutputFrame {
+        OutputFrame {
+            tile_id,
+            position: (tile_id as f32, 0.0),
+        }
+    }
+
+    #[test]
+    fn test_trail() {
+        let start = Instant::now();
+        let at = |ticks: u64| start + Duration::from_ticks(ticks);
+        let mut trail = GhostTrail::new(Duration::from_ticks(1000), Duration::from_ticks(100));
+        trail.opacity = 1.0;
+
+        trail.record(at(0), frame(1));
+        // Too soon.
+        trail.record(at(50), frame(2));
+        trail.record(at(500), frame(3));
+
+        let ghosts: Vec<_> = trail.ghosts(at(500)).collect();
+        assert_eq!(ghosts.len(), 2);
+        assert_eq!(ghosts[0].0.tile_id, 1);
+        assert!((ghosts[0].1 - 0.5).abs() < 0.01);
+        assert!((ghosts[1].1 - 1.0).abs() < 0.01);
+
+        trail.falloff = 2.0;
+        assert!((trail.ghosts(at(500)).next().unwrap().1 - 0.25).abs() < 0.01);
+
+        trail.record(at(1200), frame(4));
+        let ids: Vec<_> = trail
+            .ghosts(at(1200))
+            .map(|(frame, _)| frame.tile_id)
+            .collect();
+        assert_eq!(ids, vec![3, 4]);
+    }
+}
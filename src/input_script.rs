@@ -0,0 +1,428 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use macroquad::input::KeyCode;
+
+/// Keyboard state, read by game code through this trait rather than from macroquad,
+/// so that an `InputScript` can drive it instead of the player.
+pub trait KeyInput {
+    fn is_key_down(&self, key: KeyCode) -> bool;
+    /// Went down this frame.
+    fn is_key_pressed(&self, key: KeyCode) -> bool;
+    /// Went up this frame.
+    fn is_key_released(&self, key: KeyCode) -> bool;
+}
+
+/// The keyboard of the player, see `macroquad::input`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LiveInput;
+
+impl KeyInput for LiveInput {
+    fn is_key_down(&self, key: KeyCode) -> bool {
+        macroquad::input::is_key_down(key)
+    }
+
+    fn is_key_pressed(&self, key: KeyCode) -> bool {
+        macroquad::input::is_key_pressed(key)
+    }
+
+    fn is_key_released(&self, key: KeyCode) -> bool {
+        macroquad::input::is_key_released(key)
+    }
+}
+
+/// A key going down or up, `ms` after the start of the script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub ms: u64,
+    pub key: KeyCode,
+    pub down: bool,
+}
+
+/// Why `InputScript::from_text()` failed, with the 1-based line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptParseError {
+    /// Not "<ms> down|up <key>".
+    BadLine(usize),
+    /// Not the name of a `KeyCode`, e.g. "Left" or "Key1".
+    UnknownKey(usize, String),
+}
+
+impl fmt::Display for ScriptParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptParseError::BadLine(line) => {
+                write!(f, "Line {line}: expected \"<ms> down|up <key>\"")
+            }
+            ScriptParseError::UnknownKey(line, key) => write!(f, "Line {line}: no such key: {key}"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptParseError {}
+
+/// Timed key events, recorded from the player or written by hand, and replayed
+/// deterministically, for bug reproductions and smoke tests of real maps: replay at
+/// a fixed frame time, and the game reading its keys through `KeyInput` plays the same.
+/// Scripts are saved as text, a "<ms> down|up <key>" line per event, e.g. "120 down Left".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputScript {
+    /// Sorted by time.
+    events: Vec<InputEvent>,
+    /// The keys down after the last event, for `record()` to leave repeats out.
+    recorded_down: HashSet<KeyCode>,
+    /// Index of the next event to replay.
+    next: usize,
+    down: HashSet<KeyCode>,
+    pressed: HashSet<KeyCode>,
+    released: HashSet<KeyCode>,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_events(mut events: Vec<InputEvent>) -> Self {
+        events.sort_by_key(|event| event.ms);
+        let mut recorded_down = HashSet::new();
+        for event in &events {
+            if event.down {
+                recorded_down.insert(event.key);
+            } else {
+                recorded_down.remove(&event.key);
+            }
+        }
+        Self {
+            events,
+            recorded_down,
+            ..Default::default()
+        }
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    /// Records `key` going down or up at `ms`, later than the previous events.
+    /// Repeats of the current state of the key are left out.
+    pub fn record(&mut self, ms: u64, key: KeyCode, down: bool) {
+        let changed = if down {
+            self.recorded_down.insert(key)
+        } else {
+            self.recorded_down.remove(&key)
+        };
+        if changed {
+            let ms = ms.max(self.events.last().map_or(0, |event| event.ms));
+            self.events.push(InputEvent { ms, key, down });
+        }
+    }
+
+    /// Records the changes of `keys` on the player's keyboard, at `ms`. Call it every frame.
+    pub fn record_keys(&mut self, ms: u64, keys: &[KeyCode]) {
+        for key in keys {
+            self.record(ms, *key, macroquad::input::is_key_down(*key));
+        }
+    }
+
+    /// Replays the events up to `ms`, the time of the frame: the keys they press
+    /// and release are pressed and released in this frame only. Call it every frame.
+    pub fn advance(&mut self, ms: u64) {
+        self.pressed.clear();
+        self.released.clear();
+        while let Some(event) = self.events.get(self.next).filter(|event| event.ms <= ms) {
+            if event.down {
+                self.down.insert(event.key);
+                self.pressed.insert(event.key);
+            } else {
+                self.down.remove(&event.key);
+                self.released.insert(event.key);
+            }
+            self.next += 1;
+        }
+    }
+
+    /// Restarts the replay.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.down.clear();
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    /// If all the events were replayed.
+    pub fn is_done(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    pub fn to_text(&self) -> String {
+        self.events
+            .iter()
+            .map(|event| {
+                let action = if event.down { "down" } else { "up" };
+                format!("{} {} {:?}\n", event.ms, action, event.key)
+            })
+            .collect()
+    }
+
+    /// Parses `to_text()`. Empty lines and lines starting with '#' are skipped.
+    pub fn from_text(text: &str) -> Result<Self, ScriptParseError> {
+        let mut events = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some(ms), Some(action), Some(key), None) =
+                (words.next(), words.next(), words.next(), words.next())
+            else {
+                return Err(ScriptParseError::BadLine(i + 1));
+            };
+            let ms = ms.parse().map_err(|_| ScriptParseError::BadLine(i + 1))?;
+            let down = match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(ScriptParseError::BadLine(i + 1)),
+            };
+            let key = key_by_name(key)
+                .ok_or_else(|| ScriptParseError::UnknownKey(i + 1, key.to_string()))?;
+            events.push(InputEvent { ms, key, down });
+        }
+        Ok(Self::from_events(events))
+    }
+}
+
+impl KeyInput for InputScript {
+    fn is_key_down(&self, key: KeyCode) -> bool {
+        self.down.contains(&key)
+    }
+
+    fn is_key_pressed(&self, key: KeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    fn is_key_released(&self, key: KeyCode) -> bool {
+        self.released.contains(&key)
+    }
+}
+
+/// The key of a `KeyCode` variant name.
+fn key_by_name(name: &str) -> Option<KeyCode> {
+    ALL_KEYS
+        .iter()
+        .copied()
+        .find(|key| format!("{:?}", key) == name)
+}
+
+/// `KeyCode` can't be listed nor parsed.
+const ALL_KEYS: &[KeyCode] = {
+    use KeyCode::*;
+    &[
+        Space,
+        Apostrophe,
+        Comma,
+        Minus,
+        Period,
+        Slash,
+        Key0,
+        Key1,
+        Key2,
+        Key3,
+        Key4,
+        Key5,
+        Key6,
+        Key7,
+        Key8,
+        Key9,
+        Semicolon,
+        Equal,
+        A,
+        B,
+        C,
+        D,
+        E,
+        F,
+        G,
+        H,
+        I,
+        J,
+        K,
+        L,
+        M,
+        N,
+        O,
+        P,
+        Q,
+        R,
+        S,
+        T,
+        U,
+        V,
+        W,
+        X,
+        Y,
+        Z,
+        LeftBracket,
+        Backslash,
+        RightBracket,
+        GraveAccent,
+        World1,
+        World2,
+        Escape,
+        Enter,
+        Tab,
+        Backspace,
+        Insert,
+        Delete,
+        Right,
+        Left,
+        Down,
+        Up,
+        PageUp,
+        PageDown,
+        Home,
+        End,
+        CapsLock,
+        ScrollLock,
+        NumLock,
+        PrintScreen,
+        Pause,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+        F13,
+        F14,
+        F15,
+        F16,
+        F17,
+        F18,
+        F19,
+        F20,
+        F21,
+        F22,
+        F23,
+        F24,
+        F25,
+        Kp0,
+        Kp1,
+        Kp2,
+        Kp3,
+        Kp4,
+        Kp5,
+        Kp6,
+        Kp7,
+        Kp8,
+        Kp9,
+        KpDecimal,
+        KpDivide,
+        KpMultiply,
+        KpSubtract,
+        KpAdd,
+        KpEnter,
+        KpEqual,
+        LeftShift,
+        LeftControl,
+        LeftAlt,
+        LeftSuper,
+        RightShift,
+        RightControl,
+        RightAlt,
+        RightSuper,
+        Menu,
+        Unknown,
+    ]
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::PixelCamera;
+    use macroquad::math::vec2;
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut recorded = InputScript::new();
+        recorded.record(0, KeyCode::Right, true);
+        recorded.record(16, KeyCode::Right, true);
+        recorded.record(50, KeyCode::Right, false);
+        recorded.record(50, KeyCode::Space, true);
+        assert_eq!(recorded.events().len(), 3);
+
+        let text = recorded.to_text();
+        assert_eq!(text, "0 down Right\n50 up Right\n50 down Space\n");
+        let mut script = InputScript::from_text(&format!("# Walk right\n{}", text)).unwrap();
+        assert_eq!(script.events(), recorded.events());
+
+        // A camera following the keys, at a fixed 16 ms frame time.
+        let mut camera = PixelCamera::new(vec2(0., 0.), 1.0);
+        let mut jumps = 0;
+        for frame in 0..10 {
+            script.advance(frame * 16);
+            if script.is_key_down(KeyCode::Right) {
+                camera.position.x += 1.0;
+            }
+            if script.is_key_pressed(KeyCode::Space) {
+                jumps += 1;
+            }
+        }
+        // Down from frames 0 to 3, up at frame 4, 64 ms.
+        assert_eq!(camera.position.x, 4.0);
+        assert_eq!(jumps, 1);
+        assert!(script.is_done());
+        assert!(script.is_key_down(KeyCode::Space));
+        assert!(!script.is_key_pressed(KeyCode::Space));
+    }
+
+    #[test]
+    fn test_record_after_events() {
+        let mut script = InputScript::from_events(vec![
+            InputEvent {
+                ms: 20,
+                key: KeyCode::Left,
+                down: false,
+            },
+            InputEvent {
+                ms: 10,
+                key: KeyCode::Left,
+                down: true,
+            },
+            InputEvent {
+                ms: 10,
+                key: KeyCode::Up,
+                down: true,
+            },
+        ]);
+        // Up is still down, Left was released.
+        script.record(30, KeyCode::Up, true);
+        script.record(30, KeyCode::Left, false);
+        assert_eq!(script.events().len(), 3);
+        script.record(40, KeyCode::Left, true);
+        script.record(40, KeyCode::Up, false);
+        assert_eq!(
+            script.to_text(),
+            "10 down Left\n10 down Up\n20 up Left\n40 down Left\n40 up Up\n"
+        );
+    }
+
+    #[test]
+    fn test_from_text_errors() {
+        assert_eq!(
+            InputScript::from_text("0 down Left\n10 sideways Left"),
+            Err(ScriptParseError::BadLine(2))
+        );
+        assert_eq!(
+            InputScript::from_text("0 down Joystick"),
+            Err(ScriptParseError::UnknownKey(1, "Joystick".to_string()))
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use macroquad::math::IVec2;
+use macroquad::models::Mesh;
+use macroquad::texture::RenderTarget;
+
+use crate::layer_renderer::VisibleTile;
+
+/// String layer property choosing the layer's `LayerBackend`: "dynamic", "static" or "animated".
+pub const BACKEND_PROPERTY: &str = "backend";
+/// Bool layer property drawing the layer's tiles by their bottom, see `Map::layer_ysort()`.
+pub const YSORT_PROPERTY: &str = "ysort";
+
+/// How the tiles of a layer are drawn, see `Map::set_layer_backend()`.
+/// A map can mix backends, e.g. a static ground, an animated water layer and
+/// a dynamic layer of doors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LayerBackend {
+    /// Tiles are looked up and drawn one by one, every frame.
+    #[default]
+    Dynamic,
+    /// Tiles are baked into meshes per chunk and tileset, and drawn with a few draw calls.
+    /// Animated tiles are left out of the meshes and drawn one by one.
+    /// Tile states are applied at bake time; edits and state changes rebake their chunk.
+    /// Only orthogonal maps are baked, other maps, y-sorted layers and maps with tile offsets,
+    /// whose tiles may overlap those drawn after them, are drawn as animated ones.
+    Static,
+    /// The tiles of each chunk are looked up once, and drawn one by one with their current
+    /// animation frames and states. Edits invalidate their chunk.
+    Animated,
+}
+
+impl LayerBackend {
+    /// Parses a `BACKEND_PROPERTY` value.
+    pub fn from_property(value: &str) -> Option<Self> {
+        match value {
+            "dynamic" => Some(LayerBackend::Dynamic),
+            "static" => Some(LayerBackend::Static),
+            "animated" => Some(LayerBackend::Animated),
+            _ => None,
+        }
+    }
+}
+
+/// A tile cached by the animated backend.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CachedTile {
+    pub pos: IVec2,
+    pub tileset: String,
+    pub tile_id: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+}
+
+impl From<&VisibleTile<'_>> for CachedTile {
+    fn from(tile: &VisibleTile) -> Self {
+        Self {
+            pos: tile.pos,
+            tileset: tile.tileset.to_string(),
+            tile_id: tile.tile_id,
+            flip_h: tile.flip_h,
+            flip_v: tile.flip_v,
+            flip_d: tile.flip_d,
+        }
+    }
+}
+
+/// A chunk of a static layer.
+#[derive(Default)]
+pub(crate) struct BakedChunk {
+    /// In world pixels.
+    pub meshes: Vec<Mesh>,
+    /// Tiles with animations, drawn one by one.
+    pub animated: Vec<CachedTile>,
+}
+
+/// Chunks of static and animated layers, by (layer, chunk position), see `CHUNK_SIZE`.
+#[derive(Default)]
+pub(crate) struct LayerCache {
+    /// Static layers.
+    pub meshes: HashMap<(usize, IVec2), BakedChunk>,
+    /// Animated layers.
+    pub tiles: HashMap<(usize, IVec2), Vec<CachedTile>>,
+    /// Whole layers baked for far zooms, see `Map::set_layer_lod()`.
+    pub lods: HashMap<usize, RenderTarget>,
+}
+
+impl LayerCache {
+    pub fn invalidate_chunk(&mut self, layer: usize, chunk: IVec2) {
+        self.meshes.remove(&(layer, chunk));
+        self.tiles.remove(&(layer, chunk));
+        self.lods.remove(&layer);
+    }
+
+    pub fn invalidate_layer(&mut self, layer: usize) {
+        self.meshes.retain(|(l, _), _| *l != layer);
+        self.tiles.retain(|(l, _), _| *l != layer);
+        self.lods.remove(&layer);
+    }
+
+    /// Only static layers bake tile states.
+    pub fn invalidate_meshes_at(&mut self, chunk: IVec2) {
+        self.meshes.retain(|(_, c), _| *c != chunk);
+        self.lods.clear();
+    }
+}
+
+impl fmt::Debug for LayerCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayerCache")
+            .field("meshes", &self.meshes.len())
+            .field("tiles", &self.tiles.len())
+            .field("lods", &self.lods.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::{ivec2, vec2};
+
+    use super::*;
+    use crate::testing::stand_in_map;
+
+    #[test]
+    fn test_layer_backend_property() {
+        assert_eq!(
+            LayerBackend::from_property("static"),
+            Some(LayerBackend::Static)
+        );
+        assert_eq!(
+            LayerBackend::from_property("animated"),
+            Some(LayerBackend::Animated)
+        );
+        assert_eq!(
+            LayerBackend::from_property("dynamic"),
+            Some(LayerBackend::Dynamic)
+        );
+        assert_eq!(LayerBackend::from_property("Static"), None);
+
+        let mut map = stand_in_map(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="4" nextobjectid="1">
+ <tileset firstgid="1" source="tiny.tsx"/>
+ <layer id="1" name="ground" width="1" height="1">
+  <properties>
+   <property name="backend" value="static"/>
+  </properties>
+  <data encoding="csv">1</data>
+ </layer>
+ <layer id="2" name="water" width="1" height="1">
+  <properties>
+   <property name="backend" value="flowing"/>
+  </properties>
+  <data encoding="csv">1</data>
+ </layer>
+ <layer id="3" name="doors" width="1" height="1">
+  <data encoding="csv">1</data>
+ </layer>
+</map>"#,
+        );
+        assert_eq!(map.layer_backend(0), LayerBackend::Static);
+        // Unknown values fall back to the default.
+        assert_eq!(map.layer_backend(1), LayerBackend::Dynamic);
+        assert_eq!(map.layer_backend(2), LayerBackend::Dynamic);
+        map.set_layer_backend(0, LayerBackend::Animated);
+        assert_eq!(map.layer_backend(0), LayerBackend::Animated);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let tile = CachedTile::from(&VisibleTile {
+            pos: ivec2(1, 2),
+            tileset: "tiny",
+            tile_id: 3,
+            flip_h: true,
+            flip_v: false,
+            flip_d: true,
+            screen_pos: vec2(16., 32.),
+        });
+        assert_eq!(
+            tile,
+            CachedTile {
+                pos: ivec2(1, 2),
+                tileset: "tiny".to_string(),
+                tile_id: 3,
+                flip_h: true,
+                flip_v: false,
+                flip_d: true,
+            }
+        );
+
+        let filled = || {
+            let mut cache = LayerCache::default();
+            for key in [(0, ivec2(0, 0)), (0, ivec2(1, 0)), (1, ivec2(0, 0))] {
+                cache.meshes.insert(key, BakedChunk::default());
+                cache.tiles.insert(key, vec![tile.clone()]);
+            }
+            cache
+        };
+        let keys = |cache: &LayerCache| {
+            let mut meshes: Vec<_> = cache.meshes.keys().copied().collect();
+            let mut tiles: Vec<_> = cache.tiles.keys().copied().collect();
+            meshes.sort_by_key(|(layer, chunk)| (*layer, chunk.x, chunk.y));
+            tiles.sort_by_key(|(layer, chunk)| (*layer, chunk.x, chunk.y));
+            (meshes, tiles)
+        };
+
+        let mut cache = filled();
+        cache.invalidate_chunk(0, ivec2(1, 0));
+        let left = vec![(0, ivec2(0, 0)), (1, ivec2(0, 0))];
+        assert_eq!(keys(&cache), (left.clone(), left));
+
+        let mut cache = filled();
+        cache.invalidate_layer(0);
+        let left = vec![(1, ivec2(0, 0))];
+        assert_eq!(keys(&cache), (left.clone(), left));
+
+        // Tile states are only baked in meshes.
+        let mut cache = filled();
+        cache.invalidate_meshes_at(ivec2(0, 0));
+        assert_eq!(
+            keys(&cache),
+            (
+                vec![(0, ivec2(1, 0))],
+                vec![(0, ivec2(0, 0)), (0, ivec2(1, 0)), (1, ivec2(0, 0))]
+            )
+        );
+        assert_eq!(
+            format!("{:?}", cache),
+            "LayerCache { meshes: 1, tiles: 3, lods: 0 }"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+
+use macroquad::math::{ivec2, IVec2, UVec2};
+use tiled::{LayerType, TileLayer};
+
+use crate::map::{Map, TileHandle, TileRef};
+
+/// Cells grouped by rows, with their tiles: (y, [(x, tile)]).
+pub(crate) type Rows<'map> = Vec<(i32, Vec<(i32, Option<TileRef<'map>>)>)>;
+
+/// All tiles of a layer in one dense row-major array, for minimaps, statistics, exporters...
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerSnapshot {
+    /// The top-left cell, in world tiles. (0, 0) except on infinite maps.
+    pub origin: IVec2,
+    pub width: u32,
+    pub height: u32,
+    /// Row-major, `width * height` cells, with their flips.
+    pub tiles: Vec<Option<TileHandle>>,
+}
+
+impl LayerSnapshot {
+    /// The tile at `pos`, in world tiles. `None` outside of the snapshot too.
+    pub fn get(&self, pos: IVec2) -> Option<&TileHandle> {
+        let local = pos - self.origin;
+        if local.x < 0
+            || local.y < 0
+            || local.x as u32 >= self.width
+            || local.y as u32 >= self.height
+        {
+            return None;
+        }
+        self.tiles[local.y as usize * self.width as usize + local.x as usize].as_ref()
+    }
+}
+
+impl Map {
+    /// Every cell of `layer` which may have a tile, runtime edits included, row by row.
+    /// That's all the cells of finite maps, and the cells of chunks and edits on infinite ones.
+    pub fn layer_tiles(
+        &self,
+        layer: usize,
+    ) -> impl Iterator<Item = (IVec2, Option<TileRef<'_>>)> + '_ {
+        self.layer_rows(layer)
+            .into_iter()
+            .flat_map(|(y, cells)| cells.into_iter().map(move |(x, tile)| (ivec2(x, y), tile)))
+    }
+
+    /// Copies `layer` into a dense array, see `LayerSnapshot`.
+    /// On infinite maps, it covers the bounding box of the layer's chunks and edits.
+    pub fn layer_snapshot(&self, layer: usize) -> LayerSnapshot {
+        let rows = self.layer_rows(layer);
+        let (origin, size) = if !self.map.infinite() {
+            (
+                IVec2::ZERO,
+                ivec2(self.map.width as i32, self.map.height as i32),
+            )
+        } else if rows.is_empty() {
+            (IVec2::ZERO, IVec2::ZERO)
+        } else {
+            let first_x = |(_, xs): &(i32, Vec<_>)| xs.first().map(|(x, _)| *x);
+            let last_x = |(_, xs): &(i32, Vec<_>)| xs.last().map(|(x, _)| *x);
+            let min_x = rows.iter().filter_map(first_x).min().unwrap();
+            let max_x = rows.iter().filter_map(last_x).max().unwrap();
+            let (min_y, max_y) = (rows[0].0, rows[rows.len() - 1].0);
+            (
+                ivec2(min_x, min_y),
+                ivec2(max_x - min_x + 1, max_y - min_y + 1),
+            )
+        };
+
+        let mut tiles = vec![None; (size.x * size.y) as usize];
+        for (y, xs) in rows {
+            for (x, tile) in xs {
+                if let Some(tile) = tile {
+                    let local = ivec2(x, y) - origin;
+                    tiles[(local.y * size.x + local.x) as usize] = Some(tile.to_handle());
+                }
+            }
+        }
+
+        LayerSnapshot {
+            origin,
+            width: size.x as u32,
+            height: size.y as u32,
+            tiles,
+        }
+    }
+
+    /// Copies the `size` cells of `layer` from `min`, in world tiles, into a dense array,
+    /// e.g. the surroundings of an agent, to sample many times in a tick.
+    pub fn rect_snapshot(&self, layer: usize, min: IVec2, size: UVec2) -> LayerSnapshot {
+        let cells: Vec<IVec2> = (0..size.y as i32)
+            .flat_map(|y| (0..size.x as i32).map(move |x| min + ivec2(x, y)))
+            .collect();
+        let tiles = self
+            .get_tiles_bulk(layer, &cells)
+            .into_iter()
+            .map(|tile| tile.map(|tile| tile.to_handle()))
+            .collect();
+
+        LayerSnapshot {
+            origin: min,
+            width: size.x,
+            height: size.y,
+            tiles,
+        }
+    }
+
+    /// Folds every tile of every tile layer, runtime edits included, into accumulators
+    /// made by `init()`, one per row, then merges them in no particular order.
+    /// With the "rayon" feature, rows are folded in parallel, except on wasm.
+    /// `progress(done)` is called after every layer, `done` going from 0 to 1.
+    pub(crate) fn fold_tiles<T: Send>(
+        &self,
+        init: impl Fn() -> T + Sync + Send,
+        fold: impl Fn(&mut T, usize, IVec2, &str, u32) + Sync + Send,
+        merge: impl Fn(T, T) -> T + Sync + Send,
+        mut progress: impl FnMut(f32),
+    ) -> T {
+        let rows: Vec<(usize, Rows)> = (0..self.layer_count())
+            .map(|layer| (layer, self.layer_rows(layer)))
+            .collect();
+        let total = rows
+            .iter()
+            .map(|(_, rows)| rows.len())
+            .sum::<usize>()
+            .max(1);
+
+        let mut result = init();
+        let mut done = 0;
+        for (layer, rows) in rows {
+            done += rows.len();
+            let fold_row = |(y, xs): (i32, Vec<(i32, Option<TileRef>)>)| {
+                let mut acc = init();
+                for (x, tile) in xs {
+                    if let Some(tile) = tile {
+                        fold(&mut acc, layer, ivec2(x, y), tile.tileset, tile.id);
+                    }
+                }
+                acc
+            };
+
+            #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+            let layer_result = {
+                use rayon::prelude::*;
+                rows.into_par_iter().map(fold_row).reduce(&init, &merge)
+            };
+            #[cfg(not(all(feature = "rayon", not(target_arch = "wasm32"))))]
+            let layer_result = rows.into_iter().map(fold_row).fold(init(), &merge);
+
+            result = merge(result, layer_result);
+            progress(done as f32 / total as f32);
+        }
+        result
+    }
+
+    /// Cells of `layer` that may have tiles, grouped by rows, with their tiles after edits.
+    /// Reads the layer data row by row, or chunk by chunk, then lays the edits over it.
+    pub(crate) fn layer_rows(&self, layer: usize) -> Rows<'_> {
+        let tile_layer = match self.map.get_layer(layer).map(|layer| layer.layer_type()) {
+            Some(LayerType::Tiles(tile_layer)) => Some(tile_layer),
+            // Runtime layers.
+            None => None,
+            _ => return vec![],
+        };
+
+        if !self.map.infinite() {
+            let finite = match tile_layer {
+                Some(TileLayer::Finite(finite)) => Some(finite),
+                _ => None,
+            };
+            let mut rows: Rows = (0..self.map.height as i32)
+                .map(|y| {
+                    let xs = (0..self.map.width as i32)
+                        .map(|x| {
+                            let tile = finite.as_ref().and_then(|finite| finite.get_tile(x, y));
+                            (x, tile.map(TileRef::from))
+                        })
+                        .collect();
+                    (y, xs)
+                })
+                .collect();
+            for (pos, tile) in self.edited_cells(layer) {
+                let cell = rows
+                    .get_mut(pos.y as usize)
+                    .and_then(|(_, xs)| xs.get_mut(pos.x as usize));
+                if let Some((_, cell)) = cell {
+                    *cell = tile;
+                }
+            }
+            return rows;
+        }
+
+        let mut cells: BTreeMap<(i32, i32), Option<TileRef>> = BTreeMap::new();
+        if let Some(TileLayer::Infinite(infinite)) = tile_layer {
+            let (width, height) = (
+                tiled::ChunkData::WIDTH as i32,
+                tiled::ChunkData::HEIGHT as i32,
+            );
+            for ((chunk_x, chunk_y), chunk) in infinite.chunks() {
+                for y in 0..height {
+                    for x in 0..width {
+                        let tile = chunk.get_tile(x, y).map(TileRef::from);
+                        cells.insert((chunk_y * height + y, chunk_x * width + x), tile);
+                    }
+                }
+            }
+        }
+        for (pos, tile) in self.edited_cells(layer) {
+            cells.insert((pos.y, pos.x), tile);
+        }
+
+        let mut rows: Rows = vec![];
+        for ((y, x), tile) in cells {
+            match rows.last_mut() {
+                Some((row, xs)) if *row == y => xs.push((x, tile)),
+                _ => rows.push((y, vec![(x, tile)])),
+            }
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::LayerKind;
+    use crate::testing::{infinite_map, tiny_map};
+
+    #[test]
+    fn test_layer_tiles() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_tile(ground, ivec2(2, 2), None);
+        let mut flipped = TileHandle::new("tiny", 3);
+        flipped.flip_h = true;
+        map.set_tile(ground, ivec2(1, 2), Some(flipped.clone()));
+
+        let tiles: Vec<_> = map.layer_tiles(ground).collect();
+        assert_eq!(tiles.len(), 16);
+        // Row by row.
+        assert_eq!(tiles[1].0, ivec2(1, 0));
+        assert_eq!(tiles[4].0, ivec2(0, 1));
+        for (pos, tile) in &tiles {
+            assert_eq!(*tile, map.tile_ref_at(ground, *pos));
+        }
+        assert_eq!(tiles[9].1, Some(flipped.tile_ref()));
+        assert_eq!(tiles[10].1, None);
+
+        let objects = map.layer_by_name("objects").unwrap();
+        assert_eq!(map.layer_tiles(objects).count(), 0);
+
+        let decals = map.add_layer("decals", LayerKind::Tiles, 2);
+        map.set_tile(decals, ivec2(3, 0), Some(TileHandle::new("tiny", 1)));
+        let decals: Vec<_> = map.layer_tiles(decals).collect();
+        assert_eq!(decals.len(), 16);
+        assert_eq!(decals.iter().filter(|(_, tile)| tile.is_some()).count(), 1);
+        assert_eq!(decals[3].1.map(|tile| tile.id), Some(1));
+    }
+
+    #[test]
+    fn test_layer_tiles_infinite() {
+        let mut map = infinite_map();
+        map.set_tile(0, ivec2(-1, 1), None);
+        map.set_tile(0, ivec2(40, 5), Some(TileHandle::new("tiny", 3)));
+
+        let tiles: Vec<_> = map.layer_tiles(0).collect();
+        // Two chunks, and an edit out of them.
+        assert_eq!(tiles.len(), 2 * 16 * 16 + 1);
+        assert!(tiles.windows(2).all(|pair| {
+            let (a, b) = (pair[0].0, pair[1].0);
+            (a.y, a.x) < (b.y, b.x)
+        }));
+        for (pos, tile) in &tiles {
+            assert_eq!(*tile, map.tile_ref_at(0, *pos));
+        }
+        let placed: Vec<_> = tiles
+            .iter()
+            .filter_map(|(pos, tile)| Some((*pos, tile.as_ref()?.id)))
+            .collect();
+        assert_eq!(
+            placed,
+            vec![
+                (ivec2(-1, 0), 2),
+                (ivec2(40, 5), 3),
+                (ivec2(16, 16), 1),
+                (ivec2(19, 31), 2)
+            ]
+        );
+
+        let snapshot = map.layer_snapshot(0);
+        assert_eq!(snapshot.origin, ivec2(-16, 0));
+        assert_eq!((snapshot.width, snapshot.height), (57, 32));
+        let ids = |pos| snapshot.get(pos).map(|tile| tile.id);
+        assert_eq!(ids(ivec2(-1, 0)), Some(2));
+        assert_eq!(ids(ivec2(-1, 1)), None);
+        assert_eq!(ids(ivec2(40, 5)), Some(3));
+        assert_eq!(ids(ivec2(19, 31)), Some(2));
+        assert_eq!(
+            snapshot.get(ivec2(16, 16)),
+            Some(&TileHandle::new("tiny", 1))
+        );
+
+        let mut folded = map.fold_tiles(
+            Vec::new,
+            |acc, _, pos, _, id| acc.push((pos, id)),
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+            |_| {},
+        );
+        folded.sort_by_key(|(pos, _)| (pos.y, pos.x));
+        assert_eq!(folded, placed);
+    }
+}
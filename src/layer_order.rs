@@ -36,7 +36,7 @@ impl LayersOrder {
             })
             .collect();
 
-        indexes.sort_by(|a, b| a.y.cmp(&b.y));
+        indexes.sort_by_key(|layer| layer.y);
 
         Self { indexes }
     }
@@ -45,4 +45,59 @@ impl LayersOrder {
     pub fn order(&self) -> &Vec<LayerY> {
         &self.indexes
     }
+
+    /// Position of layer `index` in the drawing order.
+    pub fn position_of(&self, index: usize) -> Option<usize> {
+        self.indexes.iter().position(|layer| layer.index == index)
+    }
+
+    /// Inserts layer `index` at `position` in the drawing order, or last if it's past the end.
+    /// It takes the y-order of the layer it's drawn after.
+    pub fn insert(&mut self, index: usize, position: usize) {
+        let position = position.min(self.indexes.len());
+        let y = match position {
+            0 => self.indexes.first().map(|layer| layer.y).unwrap_or(-1),
+            _ => self.indexes[position - 1].y,
+        };
+        self.indexes.insert(position, LayerY { index, y });
+    }
+
+    /// Moves layer `index` to `position` in the drawing order.
+    ///
+    /// Panics:
+    /// * If there's no layer `index`.
+    pub fn move_layer(&mut self, index: usize, position: usize) {
+        let from = self
+            .position_of(index)
+            .unwrap_or_else(|| panic!("No such layer: {}", index));
+        self.indexes.remove(from);
+        self.insert(index, position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexes(order: &LayersOrder) -> Vec<usize> {
+        order.order().iter().map(|layer| layer.index).collect()
+    }
+
+    #[test]
+    fn test_insert_and_move() {
+        let mut order = LayersOrder {
+            indexes: vec![LayerY { index: 0, y: -1 }, LayerY { index: 1, y: 50 }],
+        };
+
+        order.insert(2, 1);
+        assert_eq!(indexes(&order), vec![0, 2, 1]);
+        assert_eq!(order.order()[1].y, -1);
+
+        order.insert(3, 100);
+        assert_eq!(indexes(&order), vec![0, 2, 1, 3]);
+
+        order.move_layer(0, 3);
+        assert_eq!(indexes(&order), vec![2, 1, 3, 0]);
+        assert_eq!(order.position_of(3), Some(2));
+    }
 }
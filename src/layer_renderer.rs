@@ -0,0 +1,145 @@
+use std::fmt;
+
+use macroquad::math::{IVec2, Rect, Vec2};
+
+use crate::map::Map;
+
+/// When a custom layer renderer runs, see `Map::set_layer_renderer()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerDrawMode {
+    /// Instead of the built-in tile drawing.
+    Replace,
+    /// After the built-in tile drawing, e.g. for an overlay.
+    After,
+}
+
+/// A tile about to be drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VisibleTile<'map> {
+    /// In world tiles.
+    pub pos: IVec2,
+    pub tileset: &'map str,
+    /// The placed tile, or its variant on "auto_variety" layers.
+    /// For animated tiles, see `Map::animated_tile_id()` for the current frame.
+    pub tile_id: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+    /// Top-left corner on the screen.
+    pub screen_pos: Vec2,
+}
+
+/// What a custom layer renderer gets: the visible tiles and the world to screen transform.
+#[derive(Clone, Debug)]
+pub struct LayerDraw<'map> {
+    pub layer: usize,
+    /// The drawn part of the layer, in world pixels, see `Map::layer_source_px()`.
+    pub source_px: Rect,
+    /// Where it's drawn on the screen.
+    pub dest: Rect,
+    /// Size of a tile on the screen.
+    pub tile_size: Vec2,
+    pub tiles: Vec<VisibleTile<'map>>,
+}
+
+impl LayerDraw<'_> {
+    /// Converts world pixels to screen pixels, see `map::world_px_to_screen()`.
+    pub fn to_screen(&self, world_px: Vec2) -> Vec2 {
+        crate::map::world_px_to_screen(world_px, self.source_px, self.dest)
+    }
+}
+
+pub type LayerRenderFn = dyn Fn(&Map, &LayerDraw) + Send + Sync;
+
+pub(crate) struct LayerRenderer {
+    pub mode: LayerDrawMode,
+    pub render: Box<LayerRenderFn>,
+}
+
+impl fmt::Debug for LayerRenderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayerRenderer")
+            .field("mode", &self.mode)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use macroquad::math::{ivec2, vec2};
+
+    use super::*;
+    use crate::draw_backend::DrawRecorder;
+    use crate::layer_backend::LayerBackend;
+    use crate::testing::tiny_map;
+
+    #[test]
+    fn test_layer_renderer() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        // Cached layers give their tiles to renderers too.
+        map.set_layer_backend(ground, LayerBackend::Static);
+        let dest = Rect::new(10., 20., 128., 128.);
+        // (mode, layer, number of tiles, the sixth tile, tile size, the cell (1, 1) on the screen).
+        let calls = Arc::new(Mutex::new(vec![]));
+        let draw = |map: &Map| {
+            let mut recorder = DrawRecorder::default();
+            map.draw_tiles_with(&mut recorder, ground, dest, None);
+            recorder.calls.len()
+        };
+
+        for mode in [LayerDrawMode::Replace, LayerDrawMode::After] {
+            let recorded = calls.clone();
+            map.set_layer_renderer("ground", mode, move |_, draw| {
+                recorded.lock().unwrap().push((
+                    mode,
+                    draw.layer,
+                    draw.tiles.len(),
+                    (
+                        draw.tiles[5].pos,
+                        draw.tiles[5].tile_id,
+                        draw.tiles[5].screen_pos,
+                    ),
+                    draw.tile_size,
+                    draw.to_screen(vec2(16., 16.)),
+                ));
+            });
+            let drawn = draw(&map);
+            assert_eq!(
+                drawn,
+                if mode == LayerDrawMode::Replace {
+                    0
+                } else {
+                    16
+                }
+            );
+        }
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        for (call, mode) in calls
+            .iter()
+            .zip([LayerDrawMode::Replace, LayerDrawMode::After])
+        {
+            let tile = (ivec2(1, 1), 0, vec2(42., 52.));
+            assert_eq!(
+                *call,
+                (mode, ground, 16, tile, vec2(32., 32.), vec2(42., 52.))
+            );
+        }
+
+        map.remove_layer_renderer("ground");
+        assert_eq!(draw(&map), 16);
+        assert_eq!(
+            format!(
+                "{:?}",
+                LayerRenderer {
+                    mode: LayerDrawMode::After,
+                    render: Box::new(|_, _| {}),
+                }
+            ),
+            "LayerRenderer { mode: After, .. }"
+        );
+    }
+}
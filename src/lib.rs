@@ -1,7 +1,106 @@
+//! Tiled maps for Macroquad. The core, loading and drawing maps, is always built, the rest
+//! is behind coarse features, all on by default: "render" (cameras, animated entities,
+//! worlds, atlases, save-game ids), "collision" (also shapes, materials and events), "pathfinding" (line of
+//! sight, areas of effect), "editor" (runtime editing and tools), "effects" (shadows,
+//! lighting, transitions, ambient life, day and night, reflections, masks) and "embedded"
+//! (maps loaded from memory). Games can opt out with `default-features = false` and list
+//! what they use.
+
+#[cfg(feature = "effects")]
+pub mod ambient;
+#[cfg(feature = "pathfinding")]
+pub mod aoe;
 pub mod animation;
+#[cfg(feature = "render")]
 pub mod animation_controller;
+#[cfg(feature = "render")]
+pub mod animation_world;
+#[cfg(feature = "render")]
+pub mod atlas;
+#[cfg(feature = "render")]
+pub mod camera;
+#[cfg(feature = "editor")]
+pub mod cellular;
+pub mod clock;
+#[cfg(feature = "collision")]
+pub mod collision;
+#[cfg(feature = "collision")]
+pub mod collision_shapes;
+#[cfg(feature = "render")]
+pub mod cutscene;
+#[cfg(feature = "effects")]
+pub mod day_night;
+#[cfg(feature = "editor")]
+pub mod debug;
+#[cfg(feature = "editor")]
+pub mod describe;
+pub mod draw_backend;
+#[cfg(feature = "editor")]
+pub mod edit_plan;
+#[cfg(feature = "editor")]
+pub mod editor;
+#[cfg(any(test, feature = "embedded"))]
+pub mod embedded;
+#[cfg(feature = "collision")]
+pub mod events;
+#[cfg(feature = "editor")]
+pub mod fill;
+#[cfg(feature = "effects")]
+pub mod ghost_trail;
+#[cfg(feature = "render")]
+pub mod input_script;
+pub mod layer_backend;
+pub mod layer_data;
 pub mod layer_order;
+pub mod layer_renderer;
+#[cfg(feature = "effects")]
+pub mod lighting;
 pub mod map;
-pub use map::{world_px_to_screen, Map};
+#[cfg(feature = "effects")]
+pub mod mask;
+#[cfg(feature = "collision")]
+pub mod material;
+pub use map::{camera_world_rect, screen_to_world_px, world_px_to_screen, Map};
+#[cfg(not(target_arch = "wasm32"))]
+mod offload;
+pub mod orientation;
+#[cfg(feature = "collision")]
+pub mod platform;
+pub mod prelude;
+pub mod properties;
+#[cfg(feature = "pathfinding")]
+pub mod raycast;
+#[cfg(feature = "effects")]
+pub mod reflection;
+#[cfg(feature = "render")]
+pub mod resolution;
+pub mod rng;
+#[cfg(feature = "effects")]
+pub mod shadow;
+#[cfg(any(feature = "pathfinding", feature = "editor"))]
+pub mod shapes;
+#[cfg(feature = "effects")]
+pub mod silhouette;
+#[cfg(feature = "render")]
+pub mod slippy;
+#[cfg(feature = "render")]
+pub mod stable_ids;
+#[cfg(feature = "editor")]
+pub mod terrain;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod texture_cache;
+pub mod texture_stream;
 pub mod tileset;
 pub use tileset::TileSet;
+#[cfg(feature = "effects")]
+pub mod transition;
+#[cfg(feature = "editor")]
+pub mod usage;
+pub mod variety;
+#[cfg(all(feature = "pathfinding", feature = "render"))]
+pub mod vision;
+#[cfg(any(feature = "render", feature = "collision"))]
+pub mod walls;
+#[cfg(feature = "render")]
+pub mod world;
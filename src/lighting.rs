@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use macroquad::color::Color;
+use macroquad::math::{ivec2, IVec2, Rect, Vec2};
+use macroquad::models::draw_mesh;
+
+use crate::map::Map;
+use crate::tileset::Flips;
+
+/// (tile id, dest, the light of its corners, flips), see `TileSet::batch_meshes()`.
+type LitSprite = (u32, Rect, [Color; 4], Flips);
+
+/// Light per cell, e.g. the ambient darkness of a cave and the torches on its walls,
+/// multiplying the colors of the tiles drawn by `Map::draw_tiles_lit()`. Each corner of
+/// a tile gets the average of the cells sharing it, blended across the tile, so that
+/// falloffs look smooth rather than blocky. Cells outside of the grid get `ambient`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightGrid {
+    size: IVec2,
+    ambient: Color,
+    cells: Vec<Color>,
+}
+
+impl LightGrid {
+    /// A grid of `size` cells, all lit by `ambient`.
+    pub fn new(size: IVec2, ambient: Color) -> Self {
+        let size = size.max(IVec2::ZERO);
+        Self {
+            size,
+            ambient,
+            cells: vec![ambient; (size.x * size.y) as usize],
+        }
+    }
+
+    /// A grid covering the cells of a finite `map`.
+    pub fn for_map(map: &Map, ambient: Color) -> Self {
+        Self::new(ivec2(map.map.width as i32, map.map.height as i32), ambient)
+    }
+
+    pub fn size(&self) -> IVec2 {
+        self.size
+    }
+
+    pub fn ambient(&self) -> Color {
+        self.ambient
+    }
+
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        let inside = cell.cmpge(IVec2::ZERO).all() && cell.cmplt(self.size).all();
+        inside.then(|| (cell.y * self.size.x + cell.x) as usize)
+    }
+
+    pub fn get(&self, cell: IVec2) -> Color {
+        self.index(cell)
+            .map_or(self.ambient, |index| self.cells[index])
+    }
+
+    /// Ignored outside of the grid.
+    pub fn set(&mut self, cell: IVec2, color: Color) {
+        if let Some(index) = self.index(cell) {
+            self.cells[index] = color;
+        }
+    }
+
+    /// Back to `ambient` everywhere, e.g. before adding this frame's lights.
+    pub fn clear(&mut self) {
+        self.cells.fill(self.ambient);
+    }
+
+    /// Adds `color` to the cells around `center`, in world tiles, fading linearly
+    /// to nothing at `radius` tiles. Lights add up, up to full brightness.
+    pub fn add_light(&mut self, center: Vec2, radius: f32, color: Color) {
+        if radius <= 0.0 {
+            return;
+        }
+        let min = (center - radius).floor().as_ivec2().max(IVec2::ZERO);
+        let max = (center + radius)
+            .ceil()
+            .as_ivec2()
+            .min(self.size - IVec2::ONE);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let cell = ivec2(x, y);
+                let distance = (cell.as_vec2() + 0.5).distance(center);
+                let strength = 1.0 - distance / radius;
+                if strength <= 0.0 {
+                    continue;
+                }
+                let lit = self.get(cell);
+                let add = |base: f32, light: f32| (base + light * strength * color.a).min(1.0);
+                self.set(
+                    cell,
+                    Color::new(
+                        add(lit.r, color.r),
+                        add(lit.g, color.g),
+                        add(lit.b, color.b),
+                        lit.a,
+                    ),
+                );
+            }
+        }
+    }
+
+    /// The light at a corner of the grid, in world tiles: (0, 0) is the top-left corner
+    /// of cell (0, 0). The average of the four cells sharing it.
+    pub fn vertex(&self, vertex: IVec2) -> Color {
+        let cells = [ivec2(-1, -1), ivec2(0, -1), ivec2(-1, 0), ivec2(0, 0)]
+            .map(|offset| self.get(vertex + offset));
+        let average = |channel: fn(&Color) -> f32| cells.iter().map(channel).sum::<f32>() / 4.0;
+        Color::new(
+            average(|c| c.r),
+            average(|c| c.g),
+            average(|c| c.b),
+            average(|c| c.a),
+        )
+    }
+
+    /// The lights of the corners of `cell`: top-left, top-right, bottom-right and
+    /// bottom-left, see `TileSet::spr_batch_corners()`.
+    pub fn corners(&self, cell: IVec2) -> [Color; 4] {
+        [ivec2(0, 0), ivec2(1, 0), ivec2(1, 1), ivec2(0, 1)]
+            .map(|corner| self.vertex(cell + corner))
+    }
+}
+
+impl Map {
+    /// Same as `draw_tiles()`, with the tiles lit by `light`, blended from corner to corner.
+    /// Tiles are batched into meshes with vertex colors every call, nothing is cached.
+    /// Meant for orthogonal maps; night variants are drawn as `Map::set_day_night_blend()`
+    /// says, layer materials are ignored.
+    pub fn draw_tiles_lit(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: impl Into<Option<Rect>>,
+        light: &LightGrid,
+    ) {
+        for (tileset, sprites) in self.lit_sprites(layer, dest, source_px.into(), light) {
+            for mesh in self.tilesets[tileset].batch_meshes(sprites.into_iter()) {
+                draw_mesh(&mesh);
+            }
+        }
+    }
+
+    /// The sprites `draw_tiles_lit()` batches, by tileset: each tile, then its night
+    /// variant fading in over it, if any.
+    fn lit_sprites(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: Option<Rect>,
+        light: &LightGrid,
+    ) -> HashMap<&str, Vec<LitSprite>> {
+        let mut by_tileset: HashMap<&str, Vec<_>> = HashMap::new();
+        if !self.is_layer_visible(layer) {
+            return by_tileset;
+        }
+        let Some(draw) = self.layer_draw(layer, dest, source_px) else {
+            return by_tileset;
+        };
+        let tint = self.layer_tint(layer);
+        let scale = draw.tile_size / self.tile_size_px();
+
+        for tile in &draw.tiles {
+            let tile_id = self.state_tile_id(tile.tileset, tile.tile_id, tile.pos);
+            let (tile_id, night) = self.day_night_tile(tile.tileset, tile_id);
+            let tile_id = self.animated_tile_id(tile.tileset, tile_id);
+            let offset = self.tilesets[tile.tileset].tile_offset() * scale;
+            let dest = Rect::new(
+                tile.screen_pos.x + offset.x,
+                tile.screen_pos.y + offset.y,
+                draw.tile_size.x,
+                draw.tile_size.y,
+            );
+            let corners = light.corners(tile.pos).map(|light| multiply(light, tint));
+            let flips = (tile.flip_h, tile.flip_v, tile.flip_d);
+            let sprites = by_tileset.entry(tile.tileset).or_default();
+            sprites.push((tile_id, dest, corners, flips));
+            if let Some((night_id, alpha)) = night {
+                let night_id = self.animated_tile_id(tile.tileset, night_id);
+                let corners = corners.map(|corner| Color {
+                    a: corner.a * alpha,
+                    ..corner
+                });
+                sprites.push((night_id, dest, corners, flips));
+            }
+        }
+        by_tileset
+    }
+}
+
+fn multiply(a: Color, b: Color) -> Color {
+    Color::new(a.r * b.r, a.g * b.g, a.b * b.b, a.a * b.a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day_night::DayNightBlend;
+    use crate::testing::tiny_map;
+    use macroquad::color::{BLACK, WHITE};
+    use macroquad::math::vec2;
+
+    #[test]
+    fn test_vertex_light() {
+        let mut light = LightGrid::new(ivec2(3, 3), BLACK);
+        light.set(ivec2(1, 1), WHITE);
+
+        // Corners of the lit cell average it with its dark neighbours.
+        assert_eq!(
+            light.corners(ivec2(1, 1)),
+            [Color::new(0.25, 0.25, 0.25, 1.0); 4]
+        );
+        // Far corners see no light.
+        assert_eq!(light.vertex(ivec2(0, 0)), BLACK);
+        assert_eq!(light.vertex(ivec2(3, 3)), BLACK);
+        // Outside of the grid is ambient.
+        assert_eq!(light.get(ivec2(-1, 5)), BLACK);
+    }
+
+    #[test]
+    fn test_add_light() {
+        let mut light = LightGrid::new(ivec2(5, 1), BLACK);
+        light.add_light(vec2(0.5, 0.5), 2.0, WHITE);
+        assert_eq!(light.get(ivec2(0, 0)).r, 1.0);
+        assert_eq!(light.get(ivec2(1, 0)).r, 0.5);
+        assert_eq!(light.get(ivec2(2, 0)).r, 0.0);
+
+        light.add_light(vec2(0.5, 0.5), 2.0, WHITE);
+        assert_eq!(light.get(ivec2(1, 0)).r, 1.0);
+        light.clear();
+        assert_eq!(light.get(ivec2(0, 0)), BLACK);
+    }
+
+    #[test]
+    fn test_lit_night_variants() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let light = LightGrid::for_map(&map, WHITE);
+        let dest = Rect::new(0., 0., 64., 64.);
+        // Tile 3, at (2, 1), turns into tile 1 at night.
+        let at = |map: &Map| {
+            map.lit_sprites(ground, dest, None, &light)["tiny"]
+                .iter()
+                .filter(|(_, dest, _, _)| dest.point() == vec2(32., 16.))
+                .map(|(tile_id, _, corners, _)| (*tile_id, corners[0].a))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(at(&map), vec![(3, 1.0)]);
+        map.set_night(0.6);
+        assert_eq!(at(&map), vec![(1, 1.0)]);
+
+        map.set_day_night_blend(DayNightBlend::CrossFade);
+        assert_eq!(at(&map), vec![(3, 1.0), (1, 0.6)]);
+        map.set_night(1.0);
+        assert_eq!(at(&map), vec![(1, 1.0)]);
+    }
+}
@@ -1,17 +1,295 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::f32::consts::PI;
+use std::fmt;
+use std::io::Cursor;
 use std::ops::Deref;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+#[cfg(feature = "effects")]
+use std::sync::PoisonError;
 
-use macroquad::math::{ivec2, vec2, IVec2, Rect, Vec2};
-use macroquad::texture::DrawTextureParams;
+use coarsetime::{Duration, Instant};
+use macroquad::camera::{pop_camera_state, push_camera_state, set_camera, Camera, Camera2D};
+use macroquad::color::{Color, WHITE};
+use macroquad::material::Material;
+use macroquad::math::{ivec2, vec2, vec3, IVec2, Mat4, Rect, Vec2};
+use macroquad::models::draw_mesh;
+#[cfg(feature = "effects")]
+use macroquad::models::Mesh;
+use macroquad::shapes::draw_rectangle;
+use macroquad::texture::{
+    draw_texture_ex, render_target, DrawTextureParams, FilterMode, Image, RenderTarget,
+};
+use macroquad::window::{clear_background, get_internal_gl, next_frame};
 use macroquad::Error as MqError;
 
 use tiled::Error as TiledError;
-use tiled::{LayerType, Loader};
+use tiled::{
+    ChunkData, DefaultResourceCache, LayerTile, LayerType, Loader, Orientation, Properties,
+    PropertyValue, ResourceReader, TileLayer,
+};
 
+use crate::clock::MapClock;
+#[cfg(feature = "effects")]
+use crate::day_night::DayNightBlend;
+use crate::draw_backend::{DrawBackend, DrawParams, MacroquadBackend};
+#[cfg(feature = "collision")]
+use crate::events::{MapEvent, MapEvents};
+use crate::layer_backend::{
+    BakedChunk, CachedTile, LayerBackend, LayerCache, BACKEND_PROPERTY, YSORT_PROPERTY,
+};
 use crate::layer_order::LayersOrder;
+use crate::layer_renderer::{LayerDraw, LayerDrawMode, LayerRenderer, VisibleTile};
+#[cfg(feature = "effects")]
+use crate::mask::{clip_to_rect, Mask};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::offload::offload;
+use crate::orientation::map_hex_side_length;
+use crate::properties::{to_mq_color, PropertiesExt};
+#[cfg(feature = "effects")]
+use crate::reflection::{mirror_rect, Reflection};
+use crate::rng::MapRng;
+use crate::texture_cache::{TextureCache, TextureKey};
+#[cfg(feature = "effects")]
+use crate::tileset::vertex;
 use crate::tileset::TileSet;
+use crate::variety::AUTO_VARIETY_PROPERTY;
+
+/// Size of a chunk for dirty tracking, in tiles. Same as Tiled's chunks in infinite maps.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Kinds of layers that can be created at runtime with `Map::add_layer()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LayerKind {
+    Tiles,
+}
+
+/// A layer added at runtime, e.g. for decals or highlights. It has no tiles in the Tiled map,
+/// all of them are runtime edits.
+#[derive(Clone, Debug)]
+pub struct RuntimeLayer {
+    pub name: String,
+    pub kind: LayerKind,
+}
+
+/// A tile placed on a map: its tileset, tile id and flips. See `TileHandle` for an owned one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TileRef<'map> {
+    pub tileset: &'map str,
+    pub id: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+}
+
+impl TileRef<'_> {
+    pub fn to_handle(&self) -> TileHandle {
+        TileHandle {
+            tileset: self.tileset.to_string(),
+            id: self.id,
+            flip_h: self.flip_h,
+            flip_v: self.flip_v,
+            flip_d: self.flip_d,
+        }
+    }
+}
+
+impl<'map> From<LayerTile<'map>> for TileRef<'map> {
+    fn from(tile: LayerTile<'map>) -> Self {
+        Self {
+            tileset: tile.get_tileset().name.as_str(),
+            id: tile.id(),
+            flip_h: tile.flip_h,
+            flip_v: tile.flip_v,
+            flip_d: tile.flip_d,
+        }
+    }
+}
+
+/// A tile: its tileset, tile id in the tileset, and flips, like in Tiled.
+/// Placed with `Map::set_tile()`, returned by `Map::tile_at()`, drawn with `Map::spr_tile()`,
+/// and serializable with the "serde" feature, e.g. for save games.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileHandle {
+    pub tileset: String,
+    pub id: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flip_h: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flip_v: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flip_d: bool,
+}
+
+impl TileHandle {
+    /// An unflipped tile.
+    pub fn new(tileset: &str, id: u32) -> Self {
+        Self {
+            tileset: tileset.to_string(),
+            id,
+            flip_h: false,
+            flip_v: false,
+            flip_d: false,
+        }
+    }
+
+    /// The same tile, flipped horizontally, vertically and/or diagonally.
+    pub fn flipped(self, flip_h: bool, flip_v: bool, flip_d: bool) -> Self {
+        Self {
+            flip_h,
+            flip_v,
+            flip_d,
+            ..self
+        }
+    }
+
+    pub fn tile_ref(&self) -> TileRef<'_> {
+        TileRef {
+            tileset: &self.tileset,
+            id: self.id,
+            flip_h: self.flip_h,
+            flip_v: self.flip_v,
+            flip_d: self.flip_d,
+        }
+    }
+}
+
+/// Runtime edits of a layer: cell -> tile, or `None` for an erased tile.
+type LayerEdits = HashMap<IVec2, Option<TileHandle>>;
+
+/// The tile at `pos`: the edit if any, else the tile of `layer`.
+fn cell_tile_ref<'map>(
+    edits: Option<&'map LayerEdits>,
+    layer: Option<&TileLayer<'map>>,
+    pos: IVec2,
+) -> Option<TileRef<'map>> {
+    if let Some(edit) = edits.and_then(|edits| edits.get(&pos)) {
+        return edit.as_ref().map(TileHandle::tile_ref);
+    }
+    layer?.get_tile(pos.x, pos.y).map(TileRef::from)
+}
+
+/// A tile drawn by `Map::draw_tiles_rows()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawnTile {
+    /// The bottom of the row of the tile, in world pixels, as passed to `after_row()`.
+    pub row: f32,
+    /// In screen pixels.
+    pub dest: Rect,
+}
+
+/// A tile layer ready to draw, see `Map::layer_setup()`.
+struct LayerSetup<'map> {
+    index: usize,
+    /// `None` for runtime layers, which only have edits.
+    layer: Option<TileLayer<'map>>,
+    edits: Option<&'map LayerEdits>,
+    auto_variety: bool,
+    ysort: bool,
+    tint: Color,
+    /// The drawn part of the layer, see `Map::layer_source_px()`.
+    source: Rect,
+    dest: Rect,
+    /// Screen pixels per world pixel.
+    scale: Vec2,
+    /// Visible cells, inclusive.
+    min: IVec2,
+    max: IVec2,
+}
+
+/// Where `Map::draw_budgeted()` ran out of time: the chunks it didn't cache.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DrawResume {
+    pub layer: usize,
+    pub chunks: Vec<IVec2>,
+}
+
+/// How far `Map::prewarm()` got, in chunks of the static and animated layers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrewarmProgress {
+    /// Cached, by this call or before.
+    pub done: usize,
+    pub total: usize,
+    /// Cached by this call.
+    pub baked: usize,
+}
+
+impl PrewarmProgress {
+    pub fn is_done(&self) -> bool {
+        self.done == self.total
+    }
+
+    /// From 0 to 1, e.g. for a progress bar. 1 if there's nothing to cache.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+/// How to load maps, see `Map::new_async_with()`.
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Fail on TMX features this crate doesn't support, e.g. to fail fast in CI.
+    /// Otherwise, such maps load with `Map::warnings()` and degraded rendering.
+    pub strict: bool,
+    /// Draw tilesets with low-res placeholders of their images while the full-res images
+    /// load in the background, e.g. on the web, see `TileSet::new_streaming()`.
+    /// The swap happens in `Map::update()`.
+    pub stream_textures: bool,
+    /// Return right away, with tilesets drawn with checkerboards, see
+    /// `TileSet::new_deferred()`, while their images load in the background, e.g. so that
+    /// large maps don't block the loading screen. The swap happens in `Map::update()`.
+    /// Takes precedence over `stream_textures`.
+    pub defer_textures: bool,
+    /// Pixels of extruded edges around each tile, against lines between tiles at
+    /// non-integer zooms, see `TileSet::new_async_extruded()`. Ignored when streaming
+    /// or deferring.
+    pub extrude_tiles: u16,
+    /// Draw tilesets whose image fails to load with a checkerboard, see
+    /// `TileSet::new_missing()`, and warn, rather than fail. The rest of the level
+    /// stays playable when one asset path is wrong.
+    pub placeholder_missing_images: bool,
+}
+
+/// A TMX feature this crate doesn't support, found while loading a map.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadWarning {
+    /// The map is drawn as an orthogonal one.
+    UnsupportedOrientation(Orientation),
+    /// Image collection tilesets are not loaded, their tiles are not drawn.
+    ImageCollectionTileset(String),
+    /// The full-res image of a streamed tileset failed to load, its placeholder is kept:
+    /// (tileset, error).
+    TextureStreamFailed(String, String),
+    /// The image of a tileset failed to load, it's drawn with a checkerboard:
+    /// (tileset, error). See `LoadOptions::placeholder_missing_images`.
+    MissingImage(String, String),
+}
+
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadWarning::UnsupportedOrientation(orientation) => {
+                write!(f, "Unsupported map orientation: {}", orientation)
+            }
+            LoadWarning::ImageCollectionTileset(name) => {
+                write!(f, "Image collection tilesets are not supported: {}", name)
+            }
+            LoadWarning::TextureStreamFailed(name, error) => {
+                write!(f, "Couldn't stream the texture of {}: {}", name, error)
+            }
+            LoadWarning::MissingImage(name, error) => {
+                write!(f, "Couldn't load the image of {}: {}", name, error)
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Map {
@@ -19,183 +297,2144 @@ pub struct Map {
     pub tilesets: HashMap<String, TileSet>,
     pub layer_order: LayersOrder,
     pub map: tiled::Map,
+
+    /// Runtime edits on top of `map`, which is immutable, by layer.
+    edits: HashMap<usize, LayerEdits>,
+    /// Set with `set_runtime_property()`, by (layer, cell).
+    runtime_properties: HashMap<(usize, IVec2), Properties>,
+    /// Chunks changed since the last `take_dirty_chunks()`: (layer, chunk position).
+    dirty_chunks: HashSet<(usize, IVec2)>,
+    /// Layers added with `add_layer()`, indexed after the layers of `map`.
+    runtime_layers: Vec<RuntimeLayer>,
+    warnings: Vec<LoadWarning>,
+    /// See `drain_events()`.
+    #[cfg(feature = "collision")]
+    pub(crate) events: MapEvents,
+    /// Tilesets skipped by a permissive load.
+    skipped_tilesets: HashSet<String>,
+    /// Custom renderers by layer name.
+    layer_renderers: HashMap<String, LayerRenderer>,
+    /// Backends set with `set_layer_backend()`, overriding the layer property.
+    layer_backends: HashMap<usize, LayerBackend>,
+    /// Set with `set_layer_ysort()`, overriding the layer property.
+    layer_ysorts: HashMap<usize, bool>,
+    /// Set with `set_layer_visible()`, overriding the visibility set in Tiled.
+    layer_visibility: HashMap<usize, bool>,
+    /// Set with `set_layer_material()`.
+    layer_materials: HashMap<usize, Material>,
+    /// Set with `set_layer_lod()`.
+    layer_lods: HashMap<usize, f32>,
+    /// See `set_pixel_snap()`.
+    pixel_snap: bool,
+    /// See `set_night()`.
+    #[cfg(feature = "effects")]
+    night: f32,
+    /// See `rng()`.
+    rng: MapRng,
+    #[cfg(feature = "effects")]
+    day_night_blend: DayNightBlend,
+    /// Chunks of static and animated layers. Locked while drawing them.
+    layer_cache: Mutex<LayerCache>,
+    /// Kept between `draw_masked()` calls, with its size.
+    #[cfg(feature = "effects")]
+    mask_target: Mutex<Option<((u32, u32), RenderTarget)>>,
+    /// Set with `set_layer_reflection()`.
+    #[cfg(feature = "effects")]
+    layer_reflections: HashMap<usize, Reflection>,
+    /// Kept between `draw_reflections()` calls, with its size.
+    #[cfg(feature = "effects")]
+    reflection_target: Mutex<Option<((u32, u32), RenderTarget)>>,
+    /// Time of animated tiles.
+    pub clock: MapClock,
+    /// States of cells set with `set_tile_state()`.
+    tile_states: HashMap<IVec2, String>,
+    /// Length of the straight edges of hexes on hexagonal maps, in pixels. Read from the
+    /// TMX when loading, which fails without it. `tiled::Map` lacks it: other maps take it
+    /// from the "hexsidelength" map property, or need it set here.
+    pub hex_side_length: u32,
 }
 
-impl Map {
-    pub async fn new_async(map_path: &Path) -> Result<Self, TiledError> {
-        let map = Loader::new().load_tmx_map(map_path)?;
-        Self::new_async_map(map).await
-    }
+impl Map {
+    pub async fn new_async(map_path: &Path) -> Result<Self, TiledError> {
+        Self::new_async_with(map_path, &LoadOptions::default()).await
+    }
+
+    /// Loads the map at `map_path` and its tileset images. On native targets, the TMX is
+    /// parsed and the images are decoded on a thread of their own, so that the window stays
+    /// responsive. To draw a loading screen meanwhile, load in a coroutine, e.g.
+    /// `let loading = start_coroutine(async move { Map::new_async(&path).await });`
+    /// and draw frames until `loading.is_done()`.
+    ///
+    /// Errors:
+    /// * If the TMX or an image can't be read or parsed.
+    /// * On unsupported TMX features in strict mode, see `LoadOptions`.
+    pub async fn new_async_with(
+        map_path: &Path,
+        options: &LoadOptions,
+    ) -> Result<Self, TiledError> {
+        Self::new_async_cached(map_path, options, &mut TextureCache::new()).await
+    }
+
+    /// Same as `new_async_with()`, taking the textures of tileset images from `cache`,
+    /// and adding those loaded: tilesets using the same image file, in this map and those
+    /// loaded with the same cache, share its texture. Streamed textures aren't shared.
+    pub async fn new_async_cached(
+        map_path: &Path,
+        options: &LoadOptions,
+        cache: &mut TextureCache,
+    ) -> Result<Self, TiledError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let (map, images, hex_side_length) = {
+            let path = map_path.to_path_buf();
+            let (map, hex_side_length) = offload(move || load_tmx_map(&path)).await?;
+
+            let mut images = HashMap::new();
+            let mut decoded = HashSet::new();
+            let stream = options.stream_textures || options.defer_textures;
+            // Streamed and deferred tilesets load their placeholders first instead.
+            for tileset in map.tilesets().iter().filter(|_| !stream) {
+                let Some(key) = TextureKey::new(tileset, options.extrude_tiles) else {
+                    continue;
+                };
+                // Once per image.
+                if !cache.contains(&key) && decoded.insert(key) {
+                    let image = TileSet::decode_image(tileset, options.extrude_tiles).await;
+                    images.insert(tileset.name.clone(), image);
+                }
+            }
+            (map, images, hex_side_length)
+        };
+        #[cfg(target_arch = "wasm32")]
+        let (map, images, hex_side_length) = {
+            let (map, hex_side_length) = load_tmx_map(map_path)?;
+            (map, HashMap::new(), hex_side_length)
+        };
+
+        let mut map = Self::new_async_map_decoded(map, options, images, cache).await?;
+        if let Some(length) = hex_side_length {
+            map.hex_side_length = length;
+        }
+        Ok(map)
+    }
+
+    pub async fn new_async_map(map: tiled::Map) -> Result<Self, TiledError> {
+        Self::new_async_map_with(map, &LoadOptions::default()).await
+    }
+
+    /// Errors:
+    /// * On unsupported TMX features in strict mode, see `LoadOptions`.
+    pub async fn new_async_map_with(
+        map: tiled::Map,
+        options: &LoadOptions,
+    ) -> Result<Self, TiledError> {
+        Self::new_async_map_decoded(map, options, HashMap::new(), &mut TextureCache::new()).await
+    }
+
+    /// Same as `new_async_map_with()`, with the images of some tilesets already decoded
+    /// and extruded, by tileset name, see `TileSet::decode_image()`, and the textures
+    /// of others in `cache`.
+    pub(crate) async fn new_async_map_decoded(
+        map: tiled::Map,
+        options: &LoadOptions,
+        mut images: HashMap<String, Result<Image, MqError>>,
+        cache: &mut TextureCache,
+    ) -> Result<Self, TiledError> {
+        let mut warnings = vec![];
+        let mut warn = |warning: LoadWarning| {
+            if options.strict {
+                return Err(TiledError::MalformedAttributes(format!(
+                    "Strict mode: {}",
+                    warning
+                )));
+            }
+            warnings.push(warning);
+            Ok(())
+        };
+
+        if !matches!(
+            map.orientation,
+            Orientation::Orthogonal | Orientation::Isometric | Orientation::Hexagonal
+        ) {
+            warn(LoadWarning::UnsupportedOrientation(map.orientation))?;
+        }
+
+        let mut tilesets = HashMap::new();
+        let mut skipped_tilesets = HashSet::new();
+
+        for tileset in map.tilesets().iter() {
+            if tileset.image.is_none() {
+                warn(LoadWarning::ImageCollectionTileset(tileset.name.clone()))?;
+                skipped_tilesets.insert(tileset.name.clone());
+                continue;
+            }
+
+            // FIXME: Probably better to save a reference than clone(), but
+            // then Map/Tileset will be sprawling with lifetimes. Try it later.
+            let key = TextureKey::new(tileset, options.extrude_tiles);
+            let shared = key
+                .as_ref()
+                .filter(|_| !options.stream_textures)
+                .and_then(|key| cache.get(key));
+            let mqts = match (images.remove(&tileset.name), shared) {
+                (Some(image), _) => image.map(|image| {
+                    TileSet::from_decoded(tileset.deref().clone(), &image, options.extrude_tiles)
+                }),
+                (None, Some(texture)) => Ok(TileSet::from_texture(
+                    tileset.deref().clone(),
+                    texture,
+                    options.extrude_tiles,
+                )),
+                (None, None) if options.defer_textures => {
+                    Ok(TileSet::new_deferred(tileset.deref().clone()))
+                }
+                (None, None) if options.stream_textures => {
+                    TileSet::new_streaming(tileset.deref().clone()).await
+                }
+                (None, None) => {
+                    TileSet::new_async_extruded(tileset.deref().clone(), options.extrude_tiles)
+                        .await
+                }
+            };
+            let mqts = match mqts {
+                Ok(mqts) => mqts,
+                Err(e) if options.placeholder_missing_images => {
+                    warn(LoadWarning::MissingImage(
+                        tileset.name.clone(),
+                        e.to_string(),
+                    ))?;
+                    TileSet::new_missing(tileset.deref().clone())
+                }
+                Err(e) => return Err(file_error_to_tiled(e)),
+            };
+            if let (Some(key), Some(texture)) = (key, mqts.shareable_texture()) {
+                cache.insert(key, texture);
+            }
+            tilesets.insert(tileset.name.clone(), mqts);
+        }
+
+        Ok(Self::from_parts(map, tilesets, skipped_tilesets, warnings))
+    }
+
+    /// A map with already loaded tilesets.
+    pub(crate) fn from_parts(
+        map: tiled::Map,
+        tilesets: HashMap<String, TileSet>,
+        skipped_tilesets: HashSet<String>,
+        warnings: Vec<LoadWarning>,
+    ) -> Self {
+        let layer_order = LayersOrder::new(map.layers());
+        let hex_side_length = map.properties.get_int("hexsidelength").unwrap_or(0).max(0) as u32;
+
+        Self {
+            tilesets,
+            layer_order,
+            map,
+            edits: HashMap::new(),
+            runtime_properties: HashMap::new(),
+            dirty_chunks: HashSet::new(),
+            runtime_layers: vec![],
+            warnings,
+            #[cfg(feature = "collision")]
+            events: MapEvents::default(),
+            skipped_tilesets,
+            layer_renderers: HashMap::new(),
+            layer_backends: HashMap::new(),
+            layer_ysorts: HashMap::new(),
+            layer_visibility: HashMap::new(),
+            layer_materials: HashMap::new(),
+            layer_lods: HashMap::new(),
+            pixel_snap: false,
+            #[cfg(feature = "effects")]
+            night: 0.0,
+            rng: MapRng::default(),
+            #[cfg(feature = "effects")]
+            day_night_blend: DayNightBlend::default(),
+            layer_cache: Mutex::default(),
+            #[cfg(feature = "effects")]
+            mask_target: Mutex::default(),
+            #[cfg(feature = "effects")]
+            layer_reflections: HashMap::new(),
+            #[cfg(feature = "effects")]
+            reflection_target: Mutex::default(),
+            clock: MapClock::new(),
+            hex_side_length,
+            tile_states: HashMap::new(),
+        }
+    }
+
+    /// Call once per frame, before drawing, to animate tiles, update the controllers
+    /// added with `add_controller()` and swap in streamed textures.
+    pub fn update(&mut self, now: Instant) {
+        self.clock.tick(now);
+        #[cfg(all(feature = "render", feature = "collision"))]
+        self.events.update_controllers(now);
+
+        let mut swapped = false;
+        for (name, tileset) in self.tilesets.iter_mut() {
+            match tileset.poll_stream() {
+                Some(Ok(())) => swapped = true,
+                Some(Err(e)) => {
+                    let warning = LoadWarning::TextureStreamFailed(name.clone(), e.to_string());
+                    #[cfg(feature = "collision")]
+                    self.events.push(MapEvent::Warning(warning.clone()));
+                    self.warnings.push(warning);
+                }
+                None => {}
+            }
+        }
+        if swapped {
+            // Baked meshes hold the placeholders.
+            self.textures_changed();
+        }
+    }
+
+    /// Drops what was baked with the previous textures of the tilesets.
+    pub(crate) fn textures_changed(&mut self) {
+        self.cache().meshes.clear();
+        self.cache().lods.clear();
+    }
+
+    /// Whether some tilesets are still drawn with placeholders, see
+    /// `LoadOptions::stream_textures` and `LoadOptions::defer_textures`.
+    pub fn is_streaming(&self) -> bool {
+        self.tilesets.values().any(TileSet::is_placeholder)
+    }
+
+    /// Freezes animated tiles, e.g. for a pause menu.
+    pub fn pause_animations(&mut self) {
+        self.clock.pause();
+    }
+
+    pub fn resume_animations(&mut self) {
+        self.clock.resume();
+    }
+
+    /// 1.0 is normal speed, see `MapClock::set_speed()`.
+    pub fn set_animation_speed(&mut self, speed: f32) {
+        self.clock.set_speed(speed);
+    }
+
+    /// Advances animated tiles by `dt`, even when paused, e.g. for frame-by-frame debugging.
+    pub fn step(&mut self, dt: Duration) {
+        self.clock.step(dt);
+    }
+
+    /// Switches the tiles at `pos`, on all layers, to their `state` variant: a tile with
+    /// an int property "anim_<state>", e.g. "anim_on", is drawn as the tile it references,
+    /// which is animated if it has an animation. Lamps, machines, traps...
+    /// Tiles without such a property are not affected.
+    pub fn set_tile_state(&mut self, pos: IVec2, state: &str) {
+        let previous = self.tile_states.insert(pos, state.to_string());
+        #[cfg(feature = "collision")]
+        if previous.as_deref() != Some(state) {
+            self.events.push(MapEvent::TileStateChanged {
+                pos,
+                previous,
+                state: Some(state.to_string()),
+            });
+        }
+        #[cfg(not(feature = "collision"))]
+        let _ = previous;
+        self.cache().invalidate_meshes_at(chunk_of(pos));
+    }
+
+    /// Goes back to drawing the placed tiles at `pos`.
+    pub fn clear_tile_state(&mut self, pos: IVec2) {
+        if let Some(previous) = self.tile_states.remove(&pos) {
+            #[cfg(feature = "collision")]
+            self.events.push(MapEvent::TileStateChanged {
+                pos,
+                previous: Some(previous),
+                state: None,
+            });
+            #[cfg(not(feature = "collision"))]
+            let _ = previous;
+            self.cache().invalidate_meshes_at(chunk_of(pos));
+        }
+    }
+
+    pub fn tile_state(&self, pos: IVec2) -> Option<&str> {
+        self.tile_states.get(&pos).map(String::as_str)
+    }
+
+    /// The tile drawn for `tile_id` at `pos` in its current state, see `set_tile_state()`.
+    pub fn state_tile_id(&self, tileset: &str, tile_id: u32, pos: IVec2) -> u32 {
+        let Some(state) = self.tile_states.get(&pos) else {
+            return tile_id;
+        };
+        self.tile_data(tileset, tile_id)
+            .and_then(|tile| tile.properties.get_int(&format!("anim_{}", state)))
+            .map(|id| id as u32)
+            .unwrap_or(tile_id)
+    }
+
+    /// The tile shown for `tile_id` now, which differs for animated tiles.
+    pub fn animated_tile_id(&self, tileset: &str, tile_id: u32) -> u32 {
+        self.tilesets
+            .get(tileset)
+            .and_then(|tileset| tileset.animations.get(&tile_id))
+            .map(|animation| animation.frame_at(self.clock.elapsed()))
+            .unwrap_or(tile_id)
+    }
+
+    /// Registers `render` for the layers named `layer_name`, to be called by `draw_tiles()`
+    /// instead of or after drawing their tiles, depending on `mode`.
+    /// It's an escape hatch for special layers, e.g. water with a custom shader.
+    /// Replaces the previous renderer of the layer, if any.
+    pub fn set_layer_renderer(
+        &mut self,
+        layer_name: &str,
+        mode: LayerDrawMode,
+        render: impl Fn(&Map, &LayerDraw) + Send + Sync + 'static,
+    ) {
+        self.layer_renderers.insert(
+            layer_name.to_string(),
+            LayerRenderer {
+                mode,
+                render: Box::new(render),
+            },
+        );
+    }
+
+    pub fn remove_layer_renderer(&mut self, layer_name: &str) {
+        self.layer_renderers.remove(layer_name);
+    }
+
+    /// The offset of `layer` set in Tiled, in world pixels. Zero for runtime layers.
+    pub fn layer_offset(&self, layer: usize) -> Vec2 {
+        self.map
+            .get_layer(layer)
+            .map(|layer| vec2(layer.offset_x, layer.offset_y))
+            .unwrap_or(Vec2::ZERO)
+    }
+
+    /// The parallax factors of `layer` set in Tiled: 1.0 scrolls with the map, less is farther
+    /// away, 0.0 stays put. (1, 1) for runtime layers.
+    pub fn layer_parallax(&self, layer: usize) -> Vec2 {
+        self.map
+            .get_layer(layer)
+            .map(|layer| vec2(layer.parallax_x, layer.parallax_y))
+            .unwrap_or(Vec2::ONE)
+    }
+
+    /// The part of `layer` seen through the map's `source_px`, both in world pixels,
+    /// after the layer's parallax and offset. `draw_tiles()` draws that part, so use it with
+    /// `world_px_to_screen()` to place things on the layer.
+    pub fn layer_source_px(&self, layer: usize, source_px: Rect) -> Rect {
+        // Like in Tiled, the parallax origin is the top-left corner of the map.
+        let center = source_px.center() * self.layer_parallax(layer);
+        // Moving the view against the layer offset moves the layer along it.
+        let top_left = center - source_px.size() / 2.0 - self.layer_offset(layer);
+        Rect::new(top_left.x, top_left.y, source_px.w, source_px.h)
+    }
+
+    /// The tint color of `layer` set in Tiled, multiplying the colors of its tiles.
+    /// White for none and for runtime layers.
+    pub fn layer_tint(&self, layer: usize) -> Color {
+        self.map
+            .get_layer(layer)
+            .and_then(|layer| layer.tint_color)
+            .map(to_mq_color)
+            .unwrap_or(WHITE)
+    }
+
+    /// Sets how `layer` is drawn, overriding its "backend" property, see `LayerBackend`.
+    pub fn set_layer_backend(&mut self, layer: usize, backend: LayerBackend) {
+        self.layer_backends.insert(layer, backend);
+        self.cache().invalidate_layer(layer);
+    }
+
+    /// The backend set with `set_layer_backend()`, or else by the "backend" layer property.
+    pub fn layer_backend(&self, layer: usize) -> LayerBackend {
+        if let Some(backend) = self.layer_backends.get(&layer) {
+            return *backend;
+        }
+        self.map
+            .get_layer(layer)
+            .and_then(|layer| {
+                let value = layer.properties.get_string(BACKEND_PROPERTY)?;
+                LayerBackend::from_property(value)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Shows or hides `layer`, overriding its visibility set in Tiled, e.g. to hide roofs
+    /// when entering a house. `draw_tiles()` draws hidden layers anyway, on demand.
+    pub fn set_layer_visible(&mut self, layer: usize, visible: bool) {
+        self.layer_visibility.insert(layer, visible);
+    }
+
+    /// Whether `layer` is drawn by `draw_with_camera()`, see `set_layer_visible()`.
+    /// Runtime layers are visible unless hidden.
+    pub fn is_layer_visible(&self, layer: usize) -> bool {
+        if let Some(visible) = self.layer_visibility.get(&layer) {
+            return *visible;
+        }
+        self.map
+            .get_layer(layer)
+            .map(|layer| layer.visible)
+            .unwrap_or(true)
+    }
+
+    /// The layers drawn by `draw_with_camera()`, in drawing order.
+    pub fn visible_layers(&self) -> Vec<usize> {
+        self.layer_order
+            .order()
+            .iter()
+            .map(|layer| layer.index)
+            .filter(|layer| self.is_layer_visible(*layer))
+            .collect()
+    }
+
+    /// Rounds the screen edges of the tiles drawn one by one to whole pixels, so that
+    /// adjacent tiles stay flush instead of seaming and shimmering at non-integer zooms.
+    /// Tiles may be a pixel larger or smaller than others. Static layers drawn as meshes
+    /// share their vertices, so don't seam, and aren't snapped. Off by default.
+    pub fn set_pixel_snap(&mut self, snap: bool) {
+        self.pixel_snap = snap;
+    }
+
+    pub fn pixel_snap(&self) -> bool {
+        self.pixel_snap
+    }
+
+    /// How dark it is, from 0 at day to 1 at night, e.g. from `night_from_hour()`: tiles with
+    /// a night variant, see `NIGHT_TILE_PROPERTY`, change to it as set by
+    /// `set_day_night_blend()`, e.g. windows lighting up. Games darkening the screen
+    /// at night can use the same value.
+    #[cfg(feature = "effects")]
+    pub fn set_night(&mut self, night: f32) {
+        let night = night.clamp(0.0, 1.0);
+        if night != self.night {
+            self.night = night;
+            // Baked with the previous variants.
+            self.cache().lods.clear();
+        }
+    }
+
+    #[cfg(feature = "effects")]
+    pub fn night(&self) -> f32 {
+        self.night
+    }
+
+    #[cfg(feature = "effects")]
+    pub fn set_day_night_blend(&mut self, blend: DayNightBlend) {
+        self.day_night_blend = blend;
+        self.cache().lods.clear();
+    }
+
+    #[cfg(feature = "effects")]
+    pub fn day_night_blend(&self) -> DayNightBlend {
+        self.day_night_blend
+    }
+
+    /// Randomness of the map: the variants of "auto_variety" layers are picked under
+    /// its seed, and games can draw from it for what should replay the same, e.g.
+    /// procedural generation. Save it with the game, see `MapRng`.
+    pub fn rng(&self) -> &MapRng {
+        &self.rng
+    }
+
+    pub fn rng_mut(&mut self) -> &mut MapRng {
+        &mut self.rng
+    }
+
+    /// Replaces the randomness of the map, e.g. with a saved one, or `MapRng::new(seed)`
+    /// for a new game. A new seed picks new variants.
+    pub fn set_rng(&mut self, rng: MapRng) {
+        if rng.seed() != self.rng.seed() {
+            // Baked with the previous variants.
+            let cache = self.cache();
+            cache.meshes.clear();
+            cache.tiles.clear();
+            cache.lods.clear();
+        }
+        self.rng = rng;
+    }
+
+    /// Draws the tiles of `layer` with `material`, e.g. a water distortion or CRT shader,
+    /// or as the rest of the map if `None`. The map doesn't change the material: set its
+    /// uniforms before drawing. After the layer, drawing goes back to the material set
+    /// with `draw_backend::use_material()`, the default one unless set.
+    /// Custom layer renderers draw with their own materials, see `set_layer_renderer()`.
+    pub fn set_layer_material(&mut self, layer: usize, material: Option<Material>) {
+        match material {
+            Some(material) => self.layer_materials.insert(layer, material),
+            None => self.layer_materials.remove(&layer),
+        };
+    }
+
+    pub fn layer_material(&self, layer: usize) -> Option<&Material> {
+        self.layer_materials.get(&layer)
+    }
+
+    /// Below `zoom`, in screen pixels per world pixel, draws `layer` from a texture of the
+    /// whole layer baked at that zoom, in a single draw call however many tiles are in view,
+    /// e.g. 0.25 for a world map view; or always tile by tile if `None`. The texture is
+    /// baked when first needed, and again after edits. Animated tiles are frozen in it,
+    /// and custom renderers and materials don't apply to it. Finite maps drawn with
+    /// macroquad only, see `DrawBackend::draws_meshes()`.
+    pub fn set_layer_lod(&mut self, layer: usize, zoom: Option<f32>) {
+        match zoom {
+            Some(zoom) => self.layer_lods.insert(layer, zoom),
+            None => self.layer_lods.remove(&layer),
+        };
+        self.cache().lods.remove(&layer);
+    }
+
+    pub fn layer_lod(&self, layer: usize) -> Option<f32> {
+        self.layer_lods.get(&layer).copied()
+    }
+
+    /// Makes the reflective tiles of `layer` reflect what's above them, or stop if `None`,
+    /// see `draw_reflections()`.
+    #[cfg(feature = "effects")]
+    pub fn set_layer_reflection(&mut self, layer: usize, reflection: Option<Reflection>) {
+        match reflection {
+            Some(reflection) => self.layer_reflections.insert(layer, reflection),
+            None => self.layer_reflections.remove(&layer),
+        };
+    }
+
+    #[cfg(feature = "effects")]
+    pub fn layer_reflection(&self, layer: usize) -> Option<&Reflection> {
+        self.layer_reflections.get(&layer)
+    }
+
+    /// Sets whether `layer` is y-sorted, overriding its "ysort" property, see `layer_ysort()`.
+    pub fn set_layer_ysort(&mut self, layer: usize, ysort: bool) {
+        self.layer_ysorts.insert(layer, ysort);
+        self.cache().invalidate_layer(layer);
+    }
+
+    /// Whether the tiles of `layer` keep their size, standing on the bottom of their
+    /// cells, and are drawn by their bottom, then left, so that tall tiles like trees
+    /// overlap the rows behind them, whatever the map render order. Set with
+    /// `set_layer_ysort()`, or else by the "ysort" bool layer property.
+    /// Static y-sorted layers are drawn as animated ones, see `LayerBackend`.
+    pub fn layer_ysort(&self, layer: usize) -> bool {
+        if let Some(ysort) = self.layer_ysorts.get(&layer) {
+            return *ysort;
+        }
+        self.map
+            .get_layer(layer)
+            .and_then(|layer| layer.properties.get_bool(YSORT_PROPERTY))
+            .unwrap_or(false)
+    }
+
+    fn cache(&mut self) -> &mut LayerCache {
+        self.layer_cache
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Unsupported features found by a permissive load, see `LoadOptions`,
+    /// and streamed textures that failed to load.
+    pub fn warnings(&self) -> &[LoadWarning] {
+        &self.warnings
+    }
+
+    /// Tile data of a loaded tileset: properties, class, animation, etc.
+    pub fn tile_data(&self, tileset: &str, tile_id: u32) -> Option<tiled::Tile<'_>> {
+        self.tilesets.get(tileset)?.tileset.get_tile(tile_id)
+    }
+
+    /// The tiles of Tiled class `class` in all the tilesets, by tileset name, then id,
+    /// see `TileSet::tiles_by_class()`.
+    pub fn tiles_by_class(&self, class: &str) -> Vec<TileHandle> {
+        let mut names: Vec<_> = self.tilesets.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .flat_map(|name| {
+                self.tilesets[name]
+                    .tiles_by_class(class)
+                    .into_iter()
+                    .map(|tile_id| TileHandle::new(name, tile_id))
+            })
+            .collect()
+    }
+
+    /// The Tiled class of the tile at `pos` of `layer`, if it has one.
+    pub fn tile_class_at(&self, layer: usize, pos: IVec2) -> Option<String> {
+        let tile = self.tile_ref_at(layer, pos)?;
+        self.tile_data(tile.tileset, tile.id)?.user_type.clone()
+    }
+
+    /// Number of layers, runtime ones included. Layer indexes are `0..layer_count()`.
+    pub fn layer_count(&self) -> usize {
+        self.map.layers().len() + self.runtime_layers.len()
+    }
+
+    pub fn layer_name(&self, layer: usize) -> Option<String> {
+        match self.map.get_layer(layer) {
+            Some(layer) => Some(layer.name.clone()),
+            None => self.runtime_layer(layer).map(|layer| layer.name.clone()),
+        }
+    }
+
+    /// If `layer` has tiles: a tile layer of the Tiled map, or a runtime one.
+    pub fn is_tile_layer(&self, layer: usize) -> bool {
+        match self.map.get_layer(layer) {
+            Some(layer) => layer.as_tile_layer().is_some(),
+            None => self
+                .runtime_layer(layer)
+                .is_some_and(|layer| layer.kind == LayerKind::Tiles),
+        }
+    }
+
+    /// Index of the first layer named `name`.
+    pub fn layer_by_name(&self, name: &str) -> Option<usize> {
+        (0..self.layer_count()).find(|layer| self.layer_name(*layer).as_deref() == Some(name))
+    }
+
+    /// The layer added at runtime with index `layer`, `None` for Tiled layers.
+    pub fn runtime_layer(&self, layer: usize) -> Option<&RuntimeLayer> {
+        self.runtime_layers
+            .get(layer.checked_sub(self.map.layers().len())?)
+    }
+
+    /// Adds an empty layer at `position` in the drawing order, see `layer_order`.
+    /// Returns its index, which stays valid when layers are moved.
+    pub fn add_layer(&mut self, name: &str, kind: LayerKind, position: usize) -> usize {
+        let index = self.layer_count();
+        self.runtime_layers.push(RuntimeLayer {
+            name: name.to_string(),
+            kind,
+        });
+        self.layer_order.insert(index, position);
+        index
+    }
+
+    /// Moves `layer` to `new_position` in the drawing order. Layer indexes don't change.
+    ///
+    /// Panics:
+    /// * If `layer` does not exist.
+    pub fn move_layer(&mut self, layer: usize, new_position: usize) {
+        self.layer_order.move_layer(layer, new_position);
+    }
+
+    /// The tile at `pos` on `layer`, after runtime edits.
+    pub fn tile_at(&self, layer: usize, pos: IVec2) -> Option<TileHandle> {
+        self.tile_ref_at(layer, pos).map(|tile| tile.to_handle())
+    }
+
+    /// Overrides the property `name` of the tile at `pos` of `layer`, or removes the override
+    /// if `None`, e.g. to mark it burned. Overrides belong to the cell: they are kept when its
+    /// tile changes. See `tile_property_at()`, and `for_tiles_matching()` to set many.
+    pub fn set_runtime_property(
+        &mut self,
+        layer: usize,
+        pos: IVec2,
+        name: &str,
+        value: Option<PropertyValue>,
+    ) {
+        let previous = match value.clone() {
+            Some(value) => self
+                .runtime_properties
+                .entry((layer, pos))
+                .or_default()
+                .insert(name.to_string(), value),
+            None => match self.runtime_properties.entry((layer, pos)) {
+                Entry::Occupied(mut cell) => {
+                    let previous = cell.get_mut().remove(name);
+                    if cell.get().is_empty() {
+                        cell.remove();
+                    }
+                    previous
+                }
+                Entry::Vacant(_) => None,
+            },
+        };
+        #[cfg(feature = "collision")]
+        if previous != value {
+            self.events.push(MapEvent::PropertyChanged {
+                layer,
+                pos,
+                name: name.to_string(),
+                previous,
+                value,
+            });
+        }
+        #[cfg(not(feature = "collision"))]
+        let _ = previous;
+    }
+
+    /// The overrides of the cell `pos` of `layer`, see `set_runtime_property()`.
+    pub fn runtime_properties(&self, layer: usize, pos: IVec2) -> Option<&Properties> {
+        self.runtime_properties.get(&(layer, pos))
+    }
+
+    /// Removes all the overrides set with `set_runtime_property()`.
+    pub fn clear_runtime_properties(&mut self) {
+        #[cfg(not(feature = "collision"))]
+        self.runtime_properties.clear();
+        #[cfg(feature = "collision")]
+        for ((layer, pos), properties) in self.runtime_properties.drain() {
+            for (name, previous) in properties {
+                self.events.push(MapEvent::PropertyChanged {
+                    layer,
+                    pos,
+                    name,
+                    previous: Some(previous),
+                    value: None,
+                });
+            }
+        }
+    }
+
+    /// Same as `tile_at()`, without allocating.
+    pub fn tile_ref_at(&self, layer: usize, pos: IVec2) -> Option<TileRef<'_>> {
+        let tile_layer = self
+            .map
+            .get_layer(layer)
+            .and_then(|layer| layer.as_tile_layer());
+        cell_tile_ref(self.edits.get(&layer), tile_layer.as_ref(), pos)
+    }
+
+    /// Same as `tile_ref_at()` for many cells, looking the layer and its edits up once,
+    /// e.g. for AI sampling dozens of cells per agent per tick. See also `rect_snapshot()`.
+    pub fn get_tiles_bulk(&self, layer: usize, cells: &[IVec2]) -> Vec<Option<TileRef<'_>>> {
+        let edits = self.edits.get(&layer);
+        let tile_layer = self
+            .map
+            .get_layer(layer)
+            .and_then(|layer| layer.as_tile_layer());
+        cells
+            .iter()
+            .map(|pos| cell_tile_ref(edits, tile_layer.as_ref(), *pos))
+            .collect()
+    }
+
+    /// Places `tile` at `pos` on `layer`, or erases it if `None`.
+    /// Returns the previous tile. Edits outside of the map, and of layers without tiles,
+    /// see `is_tile_layer()`, are ignored.
+    ///
+    /// Panics:
+    /// * If the tileset of `tile` does not exist.
+    pub fn set_tile(
+        &mut self,
+        layer: usize,
+        pos: IVec2,
+        tile: Option<TileHandle>,
+    ) -> Option<TileHandle> {
+        if let Some(tile) = &tile {
+            self.get_tileset(&tile.tileset);
+        }
+        if !self.contains(pos) || !self.is_tile_layer(layer) {
+            return None;
+        }
+        let previous = self.tile_at(layer, pos);
+
+        #[cfg(feature = "collision")]
+        if self.events_enabled() {
+            self.events.push(MapEvent::TileEdited {
+                layer,
+                pos,
+                previous: previous.clone(),
+                tile: tile.clone(),
+            });
+        }
+        self.edits.entry(layer).or_default().insert(pos, tile);
+        self.dirty_chunks.insert((layer, chunk_of(pos)));
+        self.cache().invalidate_chunk(layer, chunk_of(pos));
+        previous
+    }
+
+    /// Cells of `layer` changed at runtime with their tiles, `None` for erased ones.
+    pub(crate) fn edited_cells(
+        &self,
+        layer: usize,
+    ) -> impl Iterator<Item = (IVec2, Option<TileRef<'_>>)> + '_ {
+        self.edits.get(&layer).into_iter().flat_map(|edits| {
+            edits
+                .iter()
+                .map(|(pos, tile)| (*pos, tile.as_ref().map(TileHandle::tile_ref)))
+        })
+    }
+
+    /// If `pos` is inside of the map. Infinite maps contain everything.
+    pub fn contains(&self, pos: IVec2) -> bool {
+        self.map.infinite()
+            || (pos.x >= 0
+                && pos.y >= 0
+                && (pos.x as u32) < self.map.width
+                && (pos.y as u32) < self.map.height)
+    }
+
+    /// Returns the chunks, (layer, chunk position), edited since the last call,
+    /// for caches to invalidate. See `CHUNK_SIZE`.
+    pub fn take_dirty_chunks(&mut self) -> HashSet<(usize, IVec2)> {
+        std::mem::take(&mut self.dirty_chunks)
+    }
+
+    /// Fills `dest` with the map's background color, if it has one.
+    pub fn draw_background(&self, dest: Rect) {
+        if let Some(color) = self.map.background_color {
+            draw_rectangle(dest.x, dest.y, dest.w, dest.h, to_mq_color(color));
+        }
+    }
+
+    pub fn background_color(&self) -> Option<Color> {
+        self.map.background_color.map(to_mq_color)
+    }
+
+    /// Map-level custom property, e.g. "music".
+    pub fn property_string(&self, name: &str) -> Option<&str> {
+        self.map.properties.get_string(name)
+    }
+
+    pub fn property_bool(&self, name: &str) -> Option<bool> {
+        self.map.properties.get_bool(name)
+    }
+
+    pub fn property_int(&self, name: &str) -> Option<i32> {
+        self.map.properties.get_int(name)
+    }
+
+    /// Map-level custom property, e.g. "ambient_light".
+    pub fn property_float(&self, name: &str) -> Option<f32> {
+        self.map.properties.get_float(name)
+    }
+
+    pub fn property_color(&self, name: &str) -> Option<Color> {
+        self.map.properties.get_color(name)
+    }
+
+    pub(crate) fn get_tileset(&self, tileset: &str) -> &TileSet {
+        self.tilesets.get(tileset).unwrap_or_else(|| {
+            panic!(
+                "No such tileset: {}, tilesets available: {:?}",
+                tileset,
+                self.tilesets.keys()
+            )
+        })
+    }
+
+    pub fn spr(&self, tileset: &str, sprite: u32, dest: Rect) {
+        self.spr_color(tileset, sprite, dest, WHITE);
+    }
+
+    pub fn spr_color(&self, tileset: &str, sprite: u32, dest: Rect, color: Color) {
+        let tileset = self.get_tileset(tileset);
+        tileset.spr_color(sprite, dest, color);
+    }
+
+    /// Draws `tile` with its flips into `dest`, in screen pixels.
+    pub fn spr_tile(&self, tile: &TileHandle, dest: Rect) {
+        self.spr_tile_color(tile, dest, WHITE);
+    }
+
+    pub fn spr_tile_color(&self, tile: &TileHandle, dest: Rect, color: Color) {
+        let flips = (tile.flip_h, tile.flip_v, tile.flip_d);
+        for mesh in self
+            .get_tileset(&tile.tileset)
+            .batch_meshes(std::iter::once((tile.id, dest, [color; 4], flips)))
+        {
+            draw_mesh(&mesh);
+        }
+    }
+
+    pub fn spr_ex(&self, tileset: &TileSet, params: DrawTextureParams, dest: Vec2) {
+        self.spr_ex_color(tileset, params, dest, WHITE);
+    }
+
+    pub fn spr_ex_color(
+        &self,
+        tileset: &TileSet,
+        params: DrawTextureParams,
+        dest: Vec2,
+        color: Color,
+    ) {
+        tileset.spr_ex_color(params, dest, color);
+    }
+
+    /// Draws sprites from several tilesets, `(tileset, sprite, dest)`, grouped by tileset
+    /// to minimize texture switches. See `TileSet::spr_batch()`.
+    /// Sprites of different tilesets may be drawn out of order.
+    pub fn spr_batch(&self, sprites: &[(&str, u32, Rect)]) {
+        let mut by_tileset: HashMap<&str, Vec<(u32, Rect)>> = HashMap::new();
+        for (tileset, sprite, dest) in sprites {
+            by_tileset
+                .entry(tileset)
+                .or_default()
+                .push((*sprite, *dest));
+        }
+
+        for (tileset, sprites) in by_tileset {
+            self.get_tileset(tileset).spr_batch(&sprites);
+        }
+    }
+
+    /// Same as `spr_batch()`, with a tint color per sprite.
+    pub fn spr_batch_color(&self, sprites: &[(&str, u32, Rect, Color)]) {
+        let mut by_tileset: HashMap<&str, Vec<(u32, Rect, Color)>> = HashMap::new();
+        for (tileset, sprite, dest, color) in sprites {
+            by_tileset
+                .entry(tileset)
+                .or_default()
+                .push((*sprite, *dest, *color));
+        }
+
+        for (tileset, sprites) in by_tileset {
+            self.get_tileset(tileset).spr_batch_color(&sprites);
+        }
+    }
+
+    // pub fn contains_layer(&self, layer: &str) -> bool {
+    //     self.map.layers.contains_key(layer)
+    // }
+
+    // FIXME: Introduce different (new?)types for world pixel, world tile, screen pixel and maybe
+    // screen tile types.
+    /// Arguments:
+    /// * `layer`: the Layer to draw.
+    /// * `source`: the source Rect inside the entire Map, in world pixels. `None` for the entire layer.
+    /// * `dest`: the Rect to draw into.
+    /// * `callback(pos: Vec2) -> bool`: draw if callback return `true`.
+    ///
+    /// The layer is moved by its parallax and offset, and tinted,
+    /// see `layer_source_px()` and `layer_tint()`.
+    /// Calls the layer's custom renderer, if any, see `set_layer_renderer()`.
+    /// Large layers draw much faster as static ones, in a few draw calls, see `LayerBackend`.
+    ///
+    /// Panics:
+    /// * If `source` is `None` on infinite map;
+    /// * If `layer` does not exist.
+    pub fn draw_tiles_callback<F>(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: impl Into<Option<Rect>>,
+        callback: Option<F>,
+    ) where
+        F: Fn(IVec2) -> bool,
+    {
+        self.draw_layer(
+            &mut MacroquadBackend,
+            layer,
+            dest,
+            source_px.into(),
+            callback,
+        );
+    }
+
+    /// Same as `draw_tiles()`, drawing through `target` instead of macroquad,
+    /// e.g. a `DrawRecorder` in tests. Custom layer renderers still draw with macroquad.
+    pub fn draw_tiles_with(
+        &self,
+        target: &mut dyn DrawBackend,
+        layer: usize,
+        dest: Rect,
+        source_px: impl Into<Option<Rect>>,
+    ) {
+        let no_callback: Option<fn(IVec2) -> bool> = None;
+        self.draw_layer(target, layer, dest, source_px.into(), no_callback);
+    }
+
+    fn draw_layer<F>(
+        &self,
+        target: &mut dyn DrawBackend,
+        layer: usize,
+        dest: Rect,
+        source_px: Option<Rect>,
+        callback: Option<F>,
+    ) where
+        F: Fn(IVec2) -> bool,
+    {
+        let Some(setup) = self.layer_setup(layer, dest, source_px) else {
+            return;
+        };
+
+        let renderer = self
+            .layer_name(layer)
+            .and_then(|name| self.layer_renderers.get(&name));
+        if callback.is_none() && renderer.is_none() && self.draw_lod(target, &setup) {
+            return;
+        }
+
+        let material = self.layer_material(layer);
+        if material.is_some() {
+            target.use_material(material);
+        }
+
+        // Cached chunks can't filter cells, nor give the tiles to custom renderers.
+        let backend = self.layer_backend(layer);
+        if backend != LayerBackend::Dynamic && callback.is_none() && renderer.is_none() {
+            self.draw_cached(target, &setup, backend, None, &[]);
+            if material.is_some() {
+                target.use_material(None);
+            }
+            return;
+        }
+
+        let tiles = self.visible_tiles(&setup, callback.as_ref());
+
+        if !matches!(renderer, Some(renderer) if renderer.mode == LayerDrawMode::Replace) {
+            for tile in &tiles {
+                self.draw_visible_tile(target, tile, &setup);
+            }
+        }
+        if material.is_some() {
+            target.use_material(None);
+        }
+
+        if let Some(renderer) = renderer {
+            let draw = LayerDraw {
+                layer,
+                source_px: setup.source,
+                dest: setup.dest,
+                tile_size: self.tile_size_px() * setup.scale,
+                tiles,
+            };
+            (renderer.render)(self, &draw);
+        }
+    }
+
+    /// The tiles of the visible cells for which `callback(cell)`, if any, in drawing order.
+    fn visible_tiles<'map, F>(
+        &'map self,
+        setup: &LayerSetup<'map>,
+        callback: Option<&F>,
+    ) -> Vec<VisibleTile<'map>>
+    where
+        F: Fn(IVec2) -> bool,
+    {
+        // todo: support map.renderorder
+
+        let mut tiles = vec![];
+        for cell in self.visible_cells(setup.layer.as_ref(), setup.edits, setup.min, setup.max) {
+            if let Some(cb) = callback {
+                if !cb(cell) {
+                    continue;
+                }
+            }
+
+            if let Some(mut tile) = self.cell_tile(setup, cell) {
+                tile.screen_pos =
+                    world_px_to_screen(self.tile_to_world_px(cell), setup.source, setup.dest);
+                tiles.push(tile);
+            }
+        }
+
+        self.sort_tiles(&mut tiles, setup.ysort);
+        tiles
+    }
+
+    /// Draws the layer from its LOD texture if zoomed out below `layer_lod()`,
+    /// baking it if needed. Returns whether it did.
+    fn draw_lod(&self, target: &dyn DrawBackend, setup: &LayerSetup) -> bool {
+        let Some(zoom) = self.layer_lod(setup.index) else {
+            return false;
+        };
+        if !target.draws_meshes() || self.map.infinite() || setup.scale.min_element() >= zoom {
+            return false;
+        }
+
+        let lock = || {
+            self.layer_cache
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        };
+        let cached = lock().lods.get(&setup.index).cloned();
+        let lod = match cached {
+            Some(lod) => lod,
+            None => {
+                // Not locked while baking, which draws the layer.
+                let lod = self.bake_lod(setup.index, zoom);
+                lock().lods.insert(setup.index, lod.clone());
+                lod
+            }
+        };
+
+        let size = self.size_px();
+        let Some(visible) = setup.source.intersect(Rect::new(0., 0., size.x, size.y)) else {
+            return true;
+        };
+        let texture_size = lod.texture.size();
+        let bake_scale = texture_size / size;
+        // Render targets are stored bottom up.
+        let region = Rect::new(
+            visible.x * bake_scale.x,
+            texture_size.y - visible.bottom() * bake_scale.y,
+            visible.w * bake_scale.x,
+            visible.h * bake_scale.y,
+        );
+        let at = world_px_to_screen(visible.point(), setup.source, setup.dest);
+        let params = DrawTextureParams {
+            dest_size: Some(visible.size() * setup.scale),
+            source: Some(region),
+            flip_y: true,
+            ..Default::default()
+        };
+        draw_texture_ex(&lod.texture, at.x, at.y, WHITE, params);
+        true
+    }
+
+    /// The whole layer at `zoom`, whatever its offset and parallax, see `set_layer_lod()`.
+    fn bake_lod(&self, layer: usize, zoom: f32) -> RenderTarget {
+        let size = self.size_px();
+        let world = Rect::new(0., 0., size.x, size.y);
+        let texture_size = (size * zoom).ceil().max(Vec2::ONE);
+        let target = render_target(texture_size.x as u32, texture_size.y as u32);
+        // Smooth rather than shimmering when shrunk further.
+        target.texture.set_filter(FilterMode::Linear);
+        let dest = Rect::new(0., 0., texture_size.x, texture_size.y);
+        let Some(mut setup) = self.layer_setup(layer, dest, Some(world)) else {
+            return target;
+        };
+        setup.source = world;
+        setup.scale = dest.size() / world.size();
+        (setup.min, setup.max) = self.visible_tile_range(world);
+
+        let mut camera = Camera2D::from_display_rect(dest);
+        camera.render_target = Some(target.clone());
+        push_camera_state();
+        set_camera(&camera);
+        clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+        let no_callback: Option<&fn(IVec2) -> bool> = None;
+        for tile in &self.visible_tiles(&setup, no_callback) {
+            self.draw_visible_tile(&mut MacroquadBackend, tile, &setup);
+        }
+        pop_camera_state();
+        target
+    }
+
+    /// Same as `draw_tiles()`, for weak hardware: spreads the work of showing a large area
+    /// for the first time over several frames. Draws the cached chunks of the layer,
+    /// and caches the missing ones only until `budget` is spent, at least one per call.
+    /// Returns the chunks left out, pass them back next frame to cache them first.
+    /// Dynamic layers are cached as animated ones here, see `LayerBackend`.
+    pub fn draw_budgeted(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: Rect,
+        budget: Duration,
+        resume: Option<DrawResume>,
+    ) -> Option<DrawResume> {
+        let setup = self.layer_setup(layer, dest, Some(source_px))?;
+        let backend = match self.layer_backend(layer) {
+            LayerBackend::Dynamic => LayerBackend::Animated,
+            backend => backend,
+        };
+        let first = resume
+            .filter(|resume| resume.layer == layer)
+            .map(|resume| resume.chunks)
+            .unwrap_or_default();
+        let deadline = Instant::now() + budget;
+
+        let material = self.layer_material(layer);
+        if material.is_some() {
+            MacroquadBackend.use_material(material);
+        }
+        let chunks = self.draw_cached(
+            &mut MacroquadBackend,
+            &setup,
+            backend,
+            Some(deadline),
+            &first,
+        );
+        if material.is_some() {
+            MacroquadBackend.use_material(None);
+        }
+        (!chunks.is_empty()).then_some(DrawResume { layer, chunks })
+    }
+
+    /// Caches the chunks of static and animated layers in `region_px`, in world pixels,
+    /// or in the whole map if `None`, without drawing, e.g. behind a loading screen or
+    /// a menu, so that they don't hitch when first seen. Stops once `budget` is spent,
+    /// after at least one chunk: call it every frame until the progress `is_done()`.
+    ///
+    /// Panics:
+    /// * If `region_px` is `None` on an infinite map.
+    pub fn prewarm(&self, region_px: Option<Rect>, budget: Duration) -> PrewarmProgress {
+        let deadline = Instant::now() + budget;
+        self.prewarm_until(region_px, |_| Instant::now() >= deadline)
+    }
+
+    /// Same as `prewarm()`, to the end, as a coroutine caching `chunks_per_frame` chunks
+    /// then waiting for the next frame, e.g.
+    /// `start_coroutine(async move { map.prewarm_async(None, 8).await; map })`.
+    /// It counts chunks, not time, so it behaves the same on the web.
+    pub async fn prewarm_async(&self, region_px: Option<Rect>, chunks_per_frame: usize) {
+        let chunks_per_frame = chunks_per_frame.max(1);
+        while !self
+            .prewarm_until(region_px, |baked| baked >= chunks_per_frame)
+            .is_done()
+        {
+            next_frame().await;
+        }
+    }
+
+    /// Caches chunks, see `prewarm()`, until `stop(chunks baked so far)`, checked before
+    /// each chunk after the first one.
+    fn prewarm_until(
+        &self,
+        region_px: Option<Rect>,
+        mut stop: impl FnMut(usize) -> bool,
+    ) -> PrewarmProgress {
+        let mut progress = PrewarmProgress::default();
+        let mut cache = self
+            .layer_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        for layer in 0..self.layer_count() {
+            let backend = self.layer_backend(layer);
+            if backend == LayerBackend::Dynamic {
+                continue;
+            }
+            let region = region_px.unwrap_or_else(|| {
+                let size = self.size_px();
+                Rect::new(0., 0., size.x, size.y)
+            });
+            let Some(setup) = self.layer_setup(layer, region, Some(region)) else {
+                continue;
+            };
+            let meshes = backend == LayerBackend::Static
+                && self.map.orientation == Orientation::Orthogonal
+                && !setup.ysort;
+
+            let (min_chunk, max_chunk) = (chunk_of(setup.min), chunk_of(setup.max));
+            for y in min_chunk.y..=max_chunk.y {
+                for x in min_chunk.x..=max_chunk.x {
+                    let key = (layer, ivec2(x, y));
+                    progress.total += 1;
+                    let cached = if meshes {
+                        cache.meshes.contains_key(&key)
+                    } else {
+                        cache.tiles.contains_key(&key)
+                    };
+                    if !cached {
+                        if progress.baked > 0 && stop(progress.baked) {
+                            continue;
+                        }
+                        let tiles = self.chunk_tiles(&setup, key.1);
+                        if meshes {
+                            cache
+                                .meshes
+                                .insert(key, self.bake_tiles(&tiles, setup.tint));
+                        } else {
+                            cache
+                                .tiles
+                                .insert(key, tiles.iter().map(CachedTile::from).collect());
+                        }
+                        progress.baked += 1;
+                    }
+                    progress.done += 1;
+                }
+            }
+        }
+        progress
+    }
+
+    /// Everything drawing `layer` needs. `None` if it's not a tile layer.
+    fn layer_setup(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: Option<Rect>,
+    ) -> Option<LayerSetup<'_>> {
+        assert!(self.layer_count() > layer, "No such layer: {}", layer);
+        assert!(
+            !self.map.infinite() || source_px.is_some(),
+            "On infinite maps, you must specify a `source` rect"
+        );
+
+        let source = source_px.unwrap_or_else(|| {
+            let size = self.size_px();
+            Rect::new(0., 0., size.x, size.y)
+        });
+        let source = self.layer_source_px(layer, source);
+
+        // Runtime layers have no Tiled layer, only edits.
+        let tile_layer = match self.map.get_layer(layer).map(|layer| layer.layer_type()) {
+            Some(LayerType::Tiles(layer)) => Some(layer),
+            None => None,
+            _ => return None,
+            // TODO: Implement
+            // LayerType::ObjectLayer(_) => {}
+            // LayerType::ImageLayer(_) => {}
+            // LayerType::GroupLayer(_) => {}
+        };
+
+        let auto_variety = self
+            .map
+            .get_layer(layer)
+            .and_then(|layer| layer.properties.get_bool(AUTO_VARIETY_PROPERTY))
+            .unwrap_or(false);
+        let (min, max) = self.visible_tile_range(source);
+
+        Some(LayerSetup {
+            index: layer,
+            layer: tile_layer,
+            edits: self.edits.get(&layer),
+            auto_variety,
+            ysort: self.layer_ysort(layer),
+            tint: self.layer_tint(layer),
+            source,
+            dest,
+            scale: dest.size() / source.size(),
+            min,
+            max,
+        })
+    }
+
+    /// Draws the visible chunks of a static or animated layer from the cache, caching
+    /// the missing ones, `first` first. Past the `deadline`, missing chunks are skipped
+    /// and returned instead, once at least one was cached.
+    fn draw_cached(
+        &self,
+        target: &mut dyn DrawBackend,
+        setup: &LayerSetup,
+        backend: LayerBackend,
+        deadline: Option<Instant>,
+        first: &[IVec2],
+    ) -> Vec<IVec2> {
+        let mut cache = self
+            .layer_cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let (min_chunk, max_chunk) = (chunk_of(setup.min), chunk_of(setup.max));
+        let mut chunks: Vec<IVec2> = first
+            .iter()
+            .copied()
+            .filter(|chunk| chunk.cmpge(min_chunk).all() && chunk.cmple(max_chunk).all())
+            .collect();
+        for y in min_chunk.y..=max_chunk.y {
+            for x in min_chunk.x..=max_chunk.x {
+                if !first.contains(&ivec2(x, y)) {
+                    chunks.push(ivec2(x, y));
+                }
+            }
+        }
+
+        let mut cached = 0;
+        let mut skipped = vec![];
+        let mut out_of_time = || {
+            cached += 1;
+            cached > 1 && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        };
 
-    pub async fn new_async_map(map: tiled::Map) -> Result<Self, TiledError> {
-        let mut tilesets = HashMap::new();
+        if self.draws_baked(target, setup, backend) {
+            let translation = setup.dest.point() - setup.source.point() * setup.scale;
+            let gl = unsafe { get_internal_gl() }.quad_gl;
+            gl.push_model_matrix(
+                Mat4::from_translation(vec3(translation.x, translation.y, 0.0))
+                    * Mat4::from_scale(vec3(setup.scale.x, setup.scale.y, 1.0)),
+            );
+            let mut animated = vec![];
+            for chunk in chunks {
+                let baked = match cache.meshes.entry((setup.index, chunk)) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(_) if out_of_time() => {
+                        skipped.push(chunk);
+                        continue;
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(self.bake_tiles(&self.chunk_tiles(setup, chunk), setup.tint))
+                    }
+                };
+                for mesh in baked.meshes.iter() {
+                    draw_mesh(mesh);
+                }
+                animated.extend(
+                    baked
+                        .animated
+                        .iter()
+                        .filter_map(|tile| self.cached_tile(tile, setup)),
+                );
+            }
+            let gl = unsafe { get_internal_gl() }.quad_gl;
+            gl.pop_model_matrix();
+            drop(cache);
+            // Row by row across the chunks, like the dynamic backend.
+            animated.sort_by_key(|tile| (tile.pos.y, tile.pos.x));
+            for tile in &animated {
+                self.draw_visible_tile(target, tile, setup);
+            }
+            return skipped;
+        }
 
-        for tileset in map.tilesets().iter() {
-            // FIXME: Probably better to save a reference than clone(), but
-            // then Map/Tileset will be sprawling with lifetimes. Try it later.
-            let mqts = TileSet::new_async(tileset.deref().clone())
-                .await
-                .map_err(file_error_to_tiled)?;
-            tilesets.insert(tileset.name.clone(), mqts);
+        let mut tiles = vec![];
+        for chunk in chunks {
+            let chunk_tiles = match cache.tiles.entry((setup.index, chunk)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(_) if out_of_time() => {
+                    skipped.push(chunk);
+                    continue;
+                }
+                Entry::Vacant(entry) => entry.insert(
+                    self.chunk_tiles(setup, chunk)
+                        .iter()
+                        .map(CachedTile::from)
+                        .collect(),
+                ),
+            };
+            tiles.extend(
+                chunk_tiles
+                    .iter()
+                    .filter_map(|tile| self.cached_tile(tile, setup)),
+            );
         }
+        drop(cache);
+        self.sort_tiles(&mut tiles, setup.ysort);
+        for tile in &tiles {
+            self.draw_visible_tile(target, tile, setup);
+        }
+        skipped
+    }
 
-        let layer_order = LayersOrder::new(map.layers());
+    /// Whether `draw_cached()` draws baked meshes, then the animated tiles over them:
+    /// the same as drawing them all in order only while no tile can overlap another,
+    /// i.e. orthogonal tiles stretched to their cells, without y-sorting nor tile offsets.
+    fn draws_baked(
+        &self,
+        target: &dyn DrawBackend,
+        setup: &LayerSetup,
+        backend: LayerBackend,
+    ) -> bool {
+        backend == LayerBackend::Static
+            && self.map.orientation == Orientation::Orthogonal
+            && !setup.ysort
+            && target.draws_meshes()
+            && self
+                .tilesets
+                .values()
+                .all(|tileset| tileset.tile_offset() == Vec2::ZERO)
+    }
 
-        Ok(Self {
-            tilesets,
-            layer_order,
-            map,
+    /// The tiles of `chunk` of the layer.
+    fn chunk_tiles<'map>(
+        &'map self,
+        setup: &LayerSetup<'map>,
+        chunk: IVec2,
+    ) -> Vec<VisibleTile<'map>> {
+        let chunk_min = chunk * CHUNK_SIZE;
+        let chunk_max = chunk_min + IVec2::splat(CHUNK_SIZE - 1);
+        self.visible_cells(setup.layer.as_ref(), setup.edits, chunk_min, chunk_max)
+            .into_iter()
+            .filter_map(|cell| self.cell_tile(setup, cell))
+            .collect()
+    }
+
+    /// A cached tile, borrowing the tileset name from the map: the cache is locked only
+    /// while drawing from it.
+    fn cached_tile(&self, tile: &CachedTile, setup: &LayerSetup) -> Option<VisibleTile<'_>> {
+        let (tileset, _) = self.tilesets.get_key_value(&tile.tileset)?;
+        Some(VisibleTile {
+            pos: tile.pos,
+            tileset,
+            tile_id: tile.tile_id,
+            flip_h: tile.flip_h,
+            flip_v: tile.flip_v,
+            flip_d: tile.flip_d,
+            screen_pos: world_px_to_screen(
+                self.tile_to_world_px(tile.pos),
+                setup.source,
+                setup.dest,
+            ),
         })
     }
 
-    fn get_tileset(&self, tileset: &str) -> &TileSet {
-        self.tilesets.get(tileset).unwrap_or_else(|| {
-            panic!(
-                "No such tileset: {}, tilesets available: {:?}",
-                tileset,
-                self.tilesets.keys()
-            )
+    /// The tile drawn at `cell` of the layer, after edits. `screen_pos` is left to the caller.
+    fn cell_tile<'map>(
+        &'map self,
+        setup: &LayerSetup<'map>,
+        cell: IVec2,
+    ) -> Option<VisibleTile<'map>> {
+        let (tileset, tile_id, flip_h, flip_v, flip_d) =
+            match setup.edits.and_then(|edits| edits.get(&cell)) {
+                Some(edit) => edit.as_ref().map(|tile| {
+                    let flips = (tile.flip_h, tile.flip_v, tile.flip_d);
+                    (tile.tileset.as_str(), tile.id, flips.0, flips.1, flips.2)
+                }),
+                None => setup
+                    .layer
+                    .as_ref()
+                    .and_then(|layer| layer.get_tile(cell.x, cell.y))
+                    .map(|tile| {
+                        let tileset = tile.get_tileset().name.as_str();
+                        (tileset, tile.id(), tile.flip_h, tile.flip_v, tile.flip_d)
+                    }),
+            }?;
+
+        if self.skipped_tilesets.contains(tileset) {
+            return None;
+        }
+        let tile_id = if setup.auto_variety {
+            self.tilesets[tileset]
+                .variants
+                .pick_with(tile_id, cell, &self.rng)
+        } else {
+            tile_id
+        };
+        Some(VisibleTile {
+            pos: cell,
+            tileset,
+            tile_id,
+            flip_h,
+            flip_v,
+            flip_d,
+            screen_pos: Vec2::ZERO,
         })
     }
 
-    pub fn spr(&self, tileset: &str, sprite: u32, dest: Rect) {
-        let tileset = self.get_tileset(tileset);
-        tileset.spr(sprite, dest);
+    /// The visible tiles of `layer`, in drawing order, for the draw paths of other modules,
+    /// e.g. `Map::draw_tiles_lit()`. `None` if it's not a tile layer.
+    #[cfg(feature = "effects")]
+    pub(crate) fn layer_draw(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: Option<Rect>,
+    ) -> Option<LayerDraw<'_>> {
+        let setup = self.layer_setup(layer, dest, source_px)?;
+        let no_callback: Option<&fn(IVec2) -> bool> = None;
+        Some(LayerDraw {
+            layer,
+            source_px: setup.source,
+            dest: setup.dest,
+            tile_size: self.tile_size_px() * setup.scale,
+            tiles: self.visible_tiles(&setup, no_callback),
+        })
     }
 
-    pub fn spr_ex(&self, tileset: &TileSet, params: DrawTextureParams, dest: Vec2) {
-        tileset.spr_ex(params, dest);
+    /// Back to front on non-orthogonal maps: tiles may stick out of their cells upwards.
+    fn sort_tiles(&self, tiles: &mut [VisibleTile], ysort: bool) {
+        if ysort || self.map.orientation != Orientation::Orthogonal {
+            tiles.sort_by(|a, b| {
+                let (a, b) = (self.tile_to_world_px(a.pos), self.tile_to_world_px(b.pos));
+                a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+            });
+        }
     }
 
-    // pub fn contains_layer(&self, layer: &str) -> bool {
-    //     self.map.layers.contains_key(layer)
-    // }
+    /// Meshes of orthogonal `tiles` in world pixels, by tileset, with their current states.
+    /// Animated tiles, and those with night variants, are set aside.
+    fn bake_tiles(&self, tiles: &[VisibleTile], tint: Color) -> BakedChunk {
+        let mut by_tileset: HashMap<&str, Vec<_>> = HashMap::new();
+        let mut animated = vec![];
+        let tile_size = self.tile_size_px();
+        for tile in tiles {
+            let tile_id = self.state_tile_id(tile.tileset, tile.tile_id, tile.pos);
+            #[cfg(feature = "effects")]
+            let night = self.night_tile_id(tile.tileset, tile_id).is_some();
+            #[cfg(not(feature = "effects"))]
+            let night = false;
+            if self.tilesets[tile.tileset]
+                .animations
+                .contains_key(&tile_id)
+                || night
+            {
+                animated.push(CachedTile::from(tile));
+                continue;
+            }
+            let offset = self.tilesets[tile.tileset].tile_offset();
+            let dest = Rect::new(
+                tile.pos.x as f32 * tile_size.x + offset.x,
+                tile.pos.y as f32 * tile_size.y + offset.y,
+                tile_size.x,
+                tile_size.y,
+            );
+            by_tileset.entry(tile.tileset).or_default().push((
+                tile_id,
+                dest,
+                [tint; 4],
+                (tile.flip_h, tile.flip_v, tile.flip_d),
+            ));
+        }
+        let meshes = by_tileset
+            .into_iter()
+            .flat_map(|(tileset, sprites)| self.tilesets[tileset].batch_meshes(sprites.into_iter()))
+            .collect();
+        BakedChunk { meshes, animated }
+    }
 
-    // FIXME: Introduce different (new?)types for world pixel, world tile, screen pixel and maybe
-    // screen tile types.
-    /// Arguments:
-    /// * `layer`: the Layer to draw.
-    /// * `source`: the source Rect inside the entire Map, in world pixels. `None` for the entire layer.
-    /// * `dest`: the Rect to draw into.
-    /// * `callback(pos: Vec2) -> bool`: draw if callback return `true`.
-    ///
-    /// Panics:
-    /// * If `source` is `None` on infinite map;
-    /// * If `layer` does not exist.
-    pub fn draw_tiles_callback<F>(
+    /// Cells of `layer` within `min..=max`, in tiles, row by row.
+    /// Finite maps clamp the range to the map, infinite maps only visit the cells of
+    /// the layer's chunks and the edited cells, which may be negative.
+    fn visible_cells(
+        &self,
+        layer: Option<&TileLayer>,
+        edits: Option<&LayerEdits>,
+        min: IVec2,
+        max: IVec2,
+    ) -> Vec<IVec2> {
+        if !self.map.infinite() {
+            let min = min.max(IVec2::ZERO);
+            let max = max.min(ivec2(self.map.width as i32, self.map.height as i32) - IVec2::ONE);
+            return (min.y..=max.y)
+                .flat_map(|y| (min.x..=max.x).map(move |x| ivec2(x, y)))
+                .collect();
+        }
+
+        let chunk_size = ivec2(ChunkData::WIDTH as i32, ChunkData::HEIGHT as i32);
+        let mut cells = vec![];
+        if let Some(TileLayer::Infinite(layer)) = layer {
+            for ((x, y), _) in layer.chunks() {
+                let chunk_min = ivec2(x, y) * chunk_size;
+                let from = chunk_min.max(min);
+                let to = (chunk_min + chunk_size - IVec2::ONE).min(max);
+                for y in from.y..=to.y {
+                    cells.extend((from.x..=to.x).map(|x| ivec2(x, y)));
+                }
+            }
+        }
+        if let Some(edits) = edits {
+            let has_chunk = |cell: IVec2| {
+                let (x, y) = ChunkData::tile_to_chunk_pos(cell.x, cell.y);
+                matches!(layer, Some(TileLayer::Infinite(layer)) if layer.get_chunk(x, y).is_some())
+            };
+            cells.extend(edits.keys().filter(|cell| {
+                cell.cmpge(min).all() && cell.cmple(max).all() && !has_chunk(**cell)
+            }));
+        }
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells
+    }
+
+    /// `scale`: screen pixels per world pixel.
+    fn draw_visible_tile(
+        &self,
+        target: &mut dyn DrawBackend,
+        tile: &VisibleTile,
+        setup: &LayerSetup,
+    ) -> Rect {
+        // TODO (performance): Move out of loop, or cache tilesets.
+        let mq_tile_set = self
+            .tilesets
+            .get(tile.tileset)
+            .unwrap_or_else(|| panic!("Tileset {} not found", tile.tileset));
+        let tile_id = self.state_tile_id(tile.tileset, tile.tile_id, tile.pos);
+        #[cfg(feature = "effects")]
+        let (tile_id, night) = self.day_night_tile(tile.tileset, tile_id);
+        #[cfg(not(feature = "effects"))]
+        let night = None::<(u32, f32)>;
+        let tile_id = self.animated_tile_id(tile.tileset, tile_id);
+        let spr_rect = mq_tile_set.sprite_rect(tile_id); //  - tileset.first_gid
+
+        // 90: 101, 180: 110, 270: 011 - HVD
+        let (h, v, r) = match (tile.flip_h, tile.flip_v, tile.flip_d) {
+            (h, v, false) => (h, v, 0.0),
+            (true, false, true) => (false, false, PI / 2.0),
+            // (true, true, false) => (false, false, PI), - covered by above
+            (false, true, true) => (false, false, PI * 3.0 / 2.0),
+            // tiled didn't produce other combinations for me, so
+
+            // not sure about these two.
+            (true, true, true) => (false, false, PI / 2.0),
+            (false, false, true) => (true, true, 0.0),
+        };
+
+        // Orthogonal tiles are stretched to the cell, isometric and hexagonal ones, and those
+        // of y-sorted layers, keep their size and stand on the bottom of the cell, like in Tiled.
+        let cell_size = self.tile_size_px() * setup.scale;
+        let keeps_size = setup.ysort
+            || matches!(
+                self.map.orientation,
+                Orientation::Isometric | Orientation::Hexagonal
+            );
+        let (spr_size, screen_pos) = if keeps_size {
+            let spr_size = spr_rect.size() * setup.scale;
+            let offset = vec2(0.0, cell_size.y - spr_size.y);
+            (spr_size, tile.screen_pos + offset)
+        } else {
+            (cell_size, tile.screen_pos)
+        };
+        let screen_pos = screen_pos + mq_tile_set.tile_offset() * setup.scale;
+
+        let params = DrawParams {
+            color: setup.tint,
+            rotation: r,
+            flip_x: h,
+            flip_y: v,
+        };
+        let mut dest = Rect::new(screen_pos.x, screen_pos.y, spr_size.x, spr_size.y);
+        if self.pixel_snap {
+            // Edges, not sizes, so that the right edge of a tile is the left one of the next.
+            let min = dest.point().round();
+            let max = (dest.point() + dest.size()).round();
+            dest = Rect::new(min.x, min.y, max.x - min.x, max.y - min.y);
+        }
+        target.draw_texture(mq_tile_set, spr_rect, dest, params);
+        if let Some((night_id, alpha)) = night {
+            let night_id = self.animated_tile_id(tile.tileset, night_id);
+            let params = DrawParams {
+                color: Color {
+                    a: setup.tint.a * alpha,
+                    ..setup.tint
+                },
+                ..params
+            };
+            target.draw_texture(mq_tile_set, mq_tile_set.sprite_rect(night_id), dest, params);
+        }
+        dest
+    }
+
+    /// Draws `layer` into `dest`. `source_px` is in world pixels, see `draw_tiles_callback()`.
+    /// Animated tiles show their frame at the map clock, advanced by `update()`.
+    pub fn draw_tiles(&self, layer: usize, dest: Rect, source_px: impl Into<Option<Rect>>) {
+        let no_callback: Option<fn(IVec2) -> bool> = None;
+        self.draw_tiles_callback(layer, dest, source_px, no_callback)
+    }
+
+    /// Same as `draw_tiles()`, spelling out that `source_px` is in world pixels.
+    pub fn draw_tiles_px(&self, layer: usize, dest: Rect, source_px: impl Into<Option<Rect>>) {
+        self.draw_tiles(layer, dest, source_px)
+    }
+
+    /// Same as `draw_tiles()`, with `source_tiles` in world tiles.
+    pub fn draw_tiles_tiles(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_tiles: impl Into<Option<Rect>>,
+    ) {
+        let source_px = source_tiles.into().map(|rect| self.tiles_to_px(rect));
+        self.draw_tiles(layer, dest, source_px)
+    }
+
+    /// Draws all visible layers in drawing order, in world pixels, through `camera`:
+    /// macroquad does the world to screen transform, rotation and custom projections
+    /// included. Only the part seen by the camera is drawn, see `camera_world_rect()`.
+    /// The previous camera is restored after.
+    pub fn draw_with_camera(&self, camera: &Camera2D) {
+        let source = camera_world_rect(camera);
+        push_camera_state();
+        set_camera(camera);
+        for layer in self.layer_order.order().iter().map(|layer| layer.index) {
+            if self.is_layer_visible(layer) {
+                self.draw_tiles(layer, source, source);
+                #[cfg(feature = "effects")]
+                self.draw_reflections(layer, source, source, |_, _| {});
+            }
+        }
+        pop_camera_state();
+    }
+
+    /// Draws `layer` like `draw_tiles()`, but only inside `mask`, in screen pixels like
+    /// `dest`, e.g. for spotlights, dream sequences or spell area previews.
+    /// The layer is drawn into a render target the size of `dest`, kept for the next calls,
+    /// which then fills the mask.
+    #[cfg(feature = "effects")]
+    pub fn draw_masked(
         &self,
         layer: usize,
         dest: Rect,
         source_px: impl Into<Option<Rect>>,
-        callback: Option<F>,
-    ) where
-        F: Fn(IVec2) -> bool,
-    {
-        assert!(self.map.layers().len() > layer, "No such layer: {}", layer);
+        mask: &Mask,
+    ) {
+        let size = (dest.w.ceil().max(1.0) as u32, dest.h.ceil().max(1.0) as u32);
+        let target = cached_render_target(&self.mask_target, size);
+        let target_rect = Rect::new(dest.x, dest.y, size.0 as f32, size.1 as f32);
 
-        let source = source_px.into();
-        assert!(
-            !self.map.infinite() || source.is_some(),
-            "On infinite maps, you must specify a `source` rect"
-        );
+        let mut camera = Camera2D::from_display_rect(target_rect);
+        camera.render_target = Some(target.clone());
+        push_camera_state();
+        set_camera(&camera);
+        clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+        self.draw_tiles(layer, dest, source_px);
+        pop_camera_state();
 
-        let source = source.unwrap_or_else(|| {
-            Rect::new(
-                0.,
-                0.,
-                (self.map.width * self.map.tile_width) as f32,
-                (self.map.height * self.map.tile_height) as f32,
+        // Render targets are sampled bottom up.
+        let uv = |p: Vec2| {
+            vec2(
+                (p.x - target_rect.x) / target_rect.w,
+                1.0 - (p.y - target_rect.y) / target_rect.h,
             )
+        };
+        let (mut vertices, mut indices) = (vec![], vec![]);
+        for triangle in mask.triangles() {
+            let polygon = clip_to_rect(&triangle, dest);
+            if polygon.len() < 3 {
+                continue;
+            }
+            let base = vertices.len() as u16;
+            for point in &polygon {
+                let uv = uv(*point);
+                vertices.push(vertex(point.x, point.y, uv.x, uv.y, WHITE));
+            }
+            for i in 1..polygon.len() as u16 - 1 {
+                indices.extend([base, base + i, base + i + 1]);
+            }
+        }
+        draw_mesh(&Mesh {
+            vertices,
+            indices,
+            texture: Some(target.texture),
         });
+    }
 
-        let layer = match self.map.get_layer(layer) {
-            Some(layer) => layer,
-            None => return,
+    /// Draws the reflections set with `set_layer_reflection()` into the reflective tiles
+    /// of `layer`, see `REFLECTIVE_PROPERTY`, mirrored about the top of each column of them,
+    /// e.g. trees upside down in a pond. `entities(dest, source)` draws the sprites
+    /// to reflect, as `draw_tiles()` does tiles. `draw_with_camera()` draws them without
+    /// entities. Orthogonal maps only. The reflected layers are drawn into a render target,
+    /// kept for the next calls, which is then drawn flipped into the tiles.
+    #[cfg(feature = "effects")]
+    pub fn draw_reflections(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: impl Into<Option<Rect>>,
+        mut entities: impl FnMut(Rect, Rect),
+    ) {
+        let Some(reflection) = self.layer_reflections.get(&layer) else {
+            return;
         };
-
-        let layer = match layer.layer_type() {
-            LayerType::Tiles(layer) => layer,
-            _ => return,
-            // TODO: Implement
-            // LayerType::ObjectLayer(_) => {}
-            // LayerType::ImageLayer(_) => {}
-            // LayerType::GroupLayer(_) => {}
+        let source = source_px.into().unwrap_or_else(|| {
+            let size = self.size_px();
+            Rect::new(0., 0., size.x, size.y)
+        });
+        let (min, max) = self.visible_tile_range(source);
+        let tile_size = self.tile_size_px();
+        let cells: Vec<(Rect, Rect)> = self
+            .reflective_cells(layer, min, max)
+            .into_iter()
+            .map(|(cell, axis)| {
+                let world = Rect::new(
+                    cell.x as f32 * tile_size.x,
+                    cell.y as f32 * tile_size.y,
+                    tile_size.x,
+                    tile_size.y,
+                );
+                (world, mirror_rect(world, axis as f32 * tile_size.y))
+            })
+            .collect();
+        let Some(mirrored) = cells
+            .iter()
+            .map(|(_, mirrored)| *mirrored)
+            .reduce(|a, b| a.combine_with(b))
+        else {
+            return;
         };
 
-        let world_tile_size = vec2(self.map.tile_width as f32, self.map.tile_height as f32);
-        let spr_size = world_tile_size * dest.size() / source.size();
-
-        let source_tiles = Rect::new(
-            (source.x as i32 / self.map.tile_width as i32) as f32,
-            (source.y as i32 / self.map.tile_height as i32) as f32,
-            (source.w as i32 / self.map.tile_width as i32) as f32,
-            (source.h as i32 / self.map.tile_height as i32) as f32,
-        );
-
-        // todo: support map.renderorder
+        let scale = dest.size() / source.size();
+        let size = (mirrored.size() * scale).ceil().max(Vec2::ONE);
+        let target = cached_render_target(&self.reflection_target, (size.x as u32, size.y as u32));
+        let target_rect = Rect::new(0., 0., size.x, size.y);
+        let mut camera = Camera2D::from_display_rect(target_rect);
+        camera.render_target = Some(target.clone());
+        push_camera_state();
+        set_camera(&camera);
+        clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+        for reflected in &reflection.layers {
+            if self.is_layer_visible(*reflected) {
+                self.draw_tiles(*reflected, target_rect, mirrored);
+            }
+        }
+        entities(target_rect, mirrored);
+        pop_camera_state();
 
-        for y in (source_tiles.y as i32 - 1)..=(source_tiles.y as i32 + source_tiles.h as i32) + 1 {
-            for x in
-                (source_tiles.x as i32 - 1)..=(source_tiles.x as i32 + source_tiles.w as i32) + 1
-            {
-                // maybe use layer. instead of map
-                if x < 0 || x as u32 >= self.map.width || y < 0 || y as u32 >= self.map.height {
-                    continue;
+        // Render targets are sampled bottom up.
+        let uv = |x: f32, y: f32| {
+            vec2(
+                (x - mirrored.x) / mirrored.w,
+                1.0 - (y - mirrored.y) / mirrored.h,
+            )
+        };
+        // Meshes have u16 indices.
+        for batch in cells.chunks(u16::MAX as usize / 4) {
+            let (mut vertices, mut indices) = (vec![], vec![]);
+            for (world, mirrored) in batch {
+                let at = world_px_to_screen(world.point(), source, dest);
+                let cell = Rect::new(at.x, at.y, world.w * scale.x, world.h * scale.y);
+                // The top of the cell shows the bottom of the mirrored rect.
+                let corners = [
+                    (cell.left(), cell.top(), mirrored.left(), mirrored.bottom()),
+                    (
+                        cell.right(),
+                        cell.top(),
+                        mirrored.right(),
+                        mirrored.bottom(),
+                    ),
+                    (
+                        cell.right(),
+                        cell.bottom(),
+                        mirrored.right(),
+                        mirrored.top(),
+                    ),
+                    (cell.left(), cell.bottom(), mirrored.left(), mirrored.top()),
+                ];
+                let base = vertices.len() as u16;
+                for (x, y, world_x, world_y) in corners {
+                    let uv = uv(world_x, world_y);
+                    vertices.push(vertex(x, y, uv.x, uv.y, reflection.tint));
                 }
+                indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+            draw_mesh(&Mesh {
+                vertices,
+                indices,
+                texture: Some(target.texture.clone()),
+            });
+        }
+    }
 
-                if let Some(cb) = callback.as_ref() {
-                    if !cb(ivec2(x, y)) {
-                        continue;
-                    }
-                }
+    /// Same as `draw_tiles()`, calling `after_row(row_bottom)` after drawing each row of
+    /// visible cells, with the bottom of the row in world pixels. Draw the sprites whose
+    /// feet are in that row from it, so that they appear behind the walls of the rows below.
+    /// Wall tiles, see `WallPart`, are drawn with the row of their base, see `wall_base()`.
+    /// On isometric and hexagonal maps, rows are the cells of the same height on the screen.
+    /// Tiles are drawn one by one, whatever the layer backend, see `LayerBackend::Dynamic`.
+    /// Returns the drawn tiles, to find the sprites they hide, see `Silhouettes`.
+    pub fn draw_tiles_rows<F>(
+        &self,
+        layer: usize,
+        dest: Rect,
+        source_px: impl Into<Option<Rect>>,
+        after_row: F,
+    ) -> Vec<DrawnTile>
+    where
+        F: FnMut(f32),
+    {
+        self.draw_tiles_rows_with(&mut MacroquadBackend, layer, dest, source_px, after_row)
+    }
 
-                let pos =
-                    world_px_to_screen(vec2(x as f32, y as f32) * world_tile_size, source, dest);
-
-                if let Some(tile) = layer.get_tile(x, y) {
-                    let tileset = tile.get_tileset();
-
-                    // TODO (performance): Move out of loop, or cache tilesets.
-                    let mq_tile_set = self
-                        .tilesets
-                        .get(&tileset.name)
-                        .unwrap_or_else(|| panic!("Tileset {} not found", tileset.name));
-                    let spr_rect = mq_tile_set.sprite_rect(tile.id()); //  - tileset.first_gid
-
-                    // 90: 101, 180: 110, 270: 011 - HVD
-                    let (h, v, r) = match (tile.flip_h, tile.flip_v, tile.flip_d) {
-                        (h, v, false) => (h, v, 0.0),
-                        (true, false, true) => (false, false, PI / 2.0),
-                        // (true, true, false) => (false, false, PI), - covered by above
-                        (false, true, true) => (false, false, PI * 3.0 / 2.0),
-                        // tiled didn't produce other combinations for me, so
-
-                        // not sure about these two.
-                        (true, true, true) => (false, false, PI / 2.0),
-                        (false, false, true) => (true, true, 0.0),
-                    };
+    /// Same as `draw_tiles_rows()`, drawing the tiles through `target` instead of macroquad,
+    /// like `draw_tiles_with()`.
+    pub fn draw_tiles_rows_with<F>(
+        &self,
+        target: &mut dyn DrawBackend,
+        layer: usize,
+        dest: Rect,
+        source_px: impl Into<Option<Rect>>,
+        mut after_row: F,
+    ) -> Vec<DrawnTile>
+    where
+        F: FnMut(f32),
+    {
+        let Some(setup) = self.layer_setup(layer, dest, source_px.into()) else {
+            return vec![];
+        };
+        let row_bottom = |cell: IVec2| self.tile_to_world_px(cell).y + self.tile_size_px().y;
 
-                    let params = DrawTextureParams {
-                        dest_size: Some(spr_size),
-                        source: Some(spr_rect),
-                        rotation: r,
-                        flip_x: h,
-                        flip_y: v,
-                        pivot: None,
-                    };
+        let cells = self.visible_cells(setup.layer.as_ref(), setup.edits, setup.min, setup.max);
+        let mut tiles: Vec<_> = cells
+            .iter()
+            .filter_map(|cell| {
+                let mut tile = self.cell_tile(&setup, *cell)?;
+                tile.screen_pos =
+                    world_px_to_screen(self.tile_to_world_px(*cell), setup.source, setup.dest);
+                Some(tile)
+            })
+            .collect();
+        self.sort_tiles(&mut tiles, setup.ysort);
+        // Walls are drawn with the row they stand on, their tops hiding the sprites behind.
+        let mut tiles: Vec<_> = tiles
+            .into_iter()
+            .map(|tile| {
+                #[cfg(any(feature = "render", feature = "collision"))]
+                let base = self.wall_base(layer, tile.pos).unwrap_or(tile.pos.y);
+                #[cfg(not(any(feature = "render", feature = "collision")))]
+                let base = tile.pos.y;
+                (row_bottom(ivec2(tile.pos.x, base)), tile)
+            })
+            .collect();
+        tiles.sort_by(|(a, _), (b, _)| a.total_cmp(b));
 
-                    self.spr_ex(mq_tile_set, params, pos);
-                }
+        // Empty rows too, sprites may stand there, and those of walls standing out of view.
+        let mut rows: Vec<f32> = cells.into_iter().map(row_bottom).collect();
+        rows.extend(tiles.iter().map(|(row, _)| *row));
+        rows.sort_by(f32::total_cmp);
+        rows.dedup();
+
+        // The sprites drawn by `after_row()` keep the default material.
+        let material = self.layer_material(layer);
+        let mut drawn = Vec::with_capacity(tiles.len());
+        let mut tiles = tiles.iter().peekable();
+        for row in rows {
+            if material.is_some() {
+                target.use_material(material);
             }
+            while let Some((_, tile)) = tiles.next_if(|(bottom, _)| *bottom <= row) {
+                let dest = self.draw_visible_tile(target, tile, &setup);
+                drawn.push(DrawnTile { row, dest });
+            }
+            if material.is_some() {
+                target.use_material(None);
+            }
+            after_row(row);
         }
+        drawn
     }
 
-    pub fn draw_tiles(&self, layer: usize, dest: Rect, source_px: impl Into<Option<Rect>>) {
-        let no_callback: Option<fn(IVec2) -> bool> = None;
-        self.draw_tiles_callback(layer, dest, source_px, no_callback)
+    /// If anything of `rect` can be seen through `source_px`, both in world pixels.
+    /// The viewport is expanded by a tile each side, same as `draw_tiles()` does,
+    /// so that entities partially outside of their tile don't pop in late.
+    pub fn is_world_rect_visible(&self, rect: Rect, source_px: Rect) -> bool {
+        let tile_size = vec2(self.map.tile_width as f32, self.map.tile_height as f32);
+        let viewport = Rect::new(
+            source_px.x - tile_size.x,
+            source_px.y - tile_size.y,
+            source_px.w + tile_size.x * 2.0,
+            source_px.h + tile_size.y * 2.0,
+        );
+        viewport.overlaps(&rect)
+    }
+
+    /// Converts a Rect in world tiles into world pixels.
+    pub fn tiles_to_px(&self, tiles: Rect) -> Rect {
+        let tile_size = vec2(self.map.tile_width as f32, self.map.tile_height as f32);
+        Rect::new(
+            tiles.x * tile_size.x,
+            tiles.y * tile_size.y,
+            tiles.w * tile_size.x,
+            tiles.h * tile_size.y,
+        )
     }
 }
 
+/// The render target in `cache` if it has this `size`, otherwise a new one, cached.
+#[cfg(feature = "effects")]
+fn cached_render_target(
+    cache: &Mutex<Option<((u32, u32), RenderTarget)>>,
+    size: (u32, u32),
+) -> RenderTarget {
+    let mut cached = cache.lock().unwrap_or_else(PoisonError::into_inner);
+    match &*cached {
+        Some((cached_size, target)) if *cached_size == size => target.clone(),
+        _ => {
+            let target = render_target(size.0, size.1);
+            target.texture.set_filter(FilterMode::Nearest);
+            *cached = Some((size, target.clone()));
+            target
+        }
+    }
+}
+
+/// The chunk containing the tile `pos`, see `CHUNK_SIZE`.
+#[inline]
+pub fn chunk_of(pos: IVec2) -> IVec2 {
+    ivec2(pos.x.div_euclid(CHUNK_SIZE), pos.y.div_euclid(CHUNK_SIZE))
+}
+
+/// The world rect seen by `camera`, in world pixels: the bounds of its view when rotated.
+pub fn camera_world_rect(camera: &Camera2D) -> Rect {
+    let inverse = camera.matrix().inverse();
+    let corners = [vec2(-1., -1.), vec2(1., -1.), vec2(-1., 1.), vec2(1., 1.)].map(|corner| {
+        inverse
+            .transform_point3(vec3(corner.x, corner.y, 0.))
+            .truncate()
+    });
+    let min = corners
+        .iter()
+        .fold(Vec2::splat(f32::MAX), |min, corner| min.min(*corner));
+    let max = corners
+        .iter()
+        .fold(Vec2::splat(f32::MIN), |max, corner| max.max(*corner));
+    Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+}
+
+/// Translate screen pixel coordinates into world pixels, the inverse of `world_px_to_screen()`.
+#[inline]
+pub fn screen_to_world_px(screen: Vec2, source_px: Rect, dest: Rect) -> Vec2 {
+    (screen - dest.point()) / dest.size() * source_px.size() + source_px.point()
+}
+
 /// Translate world pixel coordinates into screen pixels.
 /// `world_px`: position in world pixels
 /// `source`: source rectangle in world pixels
@@ -205,7 +2444,40 @@ pub fn world_px_to_screen(world_px: Vec2, source_px: Rect, dest: Rect) -> Vec2 {
     (world_px - source_px.point()) / source_px.size() * dest.size() + dest.point()
 }
 
-fn file_error_to_tiled(e: MqError) -> tiled::Error {
+/// Loads the TMX at `map_path`, and the hex side length of hexagonal maps from the bytes
+/// tiled parsed, see `Map::hex_side_length`.
+fn load_tmx_map(map_path: &Path) -> Result<(tiled::Map, Option<u32>), TiledError> {
+    let reader = TmxKeepingReader {
+        map_path: map_path.to_path_buf(),
+        tmx: vec![],
+    };
+    let mut loader = Loader::with_cache_and_reader(DefaultResourceCache::new(), reader);
+    let map = loader.load_tmx_map(map_path)?;
+    let hex_side_length = map_hex_side_length(&map, &loader.reader().tmx)?;
+    Ok((map, hex_side_length))
+}
+
+/// Reads files from the filesystem, like tiled does, keeping the bytes of the TMX
+/// for what `tiled::Map` leaves out.
+struct TmxKeepingReader {
+    map_path: PathBuf,
+    tmx: Vec<u8>,
+}
+
+impl ResourceReader for TmxKeepingReader {
+    type Resource = Cursor<Vec<u8>>;
+    type Error = std::io::Error;
+
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error> {
+        let bytes = std::fs::read(path)?;
+        if path == self.map_path {
+            self.tmx.clone_from(&bytes);
+        }
+        Ok(Cursor::new(bytes))
+    }
+}
+
+pub(crate) fn file_error_to_tiled(e: MqError) -> tiled::Error {
     match e {
         MqError::FontError(message) => TiledError::MalformedAttributes(message.to_string()),
         MqError::FileError { kind, path } => TiledError::ResourceLoadingError {
@@ -217,3 +2489,339 @@ fn file_error_to_tiled(e: MqError) -> tiled::Error {
         MqError::UnknownError(e) => TiledError::MalformedAttributes(e.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::draw_backend::DrawRecorder;
+    use crate::testing::{stand_in_map, tiny_map};
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_prewarm() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        assert!(map.prewarm(None, Duration::from_ticks(0)).is_done());
+
+        map.set_layer_backend(ground, LayerBackend::Animated);
+        // At least a chunk per call, whatever the budget.
+        let progress = map.prewarm(None, Duration::from_ticks(0));
+        assert_eq!((progress.done, progress.baked), (1, 1));
+        assert!(progress.total > 1);
+
+        let mut calls = 1;
+        while !map.prewarm(None, Duration::from_ticks(0)).is_done() {
+            calls += 1;
+        }
+        assert_eq!(calls, progress.total - 1);
+        let progress = map.prewarm(None, Duration::from_ticks(0));
+        assert_eq!(progress.baked, 0);
+        assert_eq!(progress.fraction(), 1.0);
+    }
+
+    #[test]
+    fn test_ysort() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        assert!(!map.layer_ysort(ground));
+        map.set_layer_ysort(ground, true);
+        assert!(map.layer_ysort(ground));
+
+        let mut recorder = DrawRecorder::default();
+        map.draw_tiles_with(&mut recorder, ground, Rect::new(0., 0., 64., 64.), None);
+        assert_eq!(recorder.calls.len(), 16);
+        let bottoms: Vec<_> = recorder
+            .calls
+            .iter()
+            .map(|call| call.dest.bottom())
+            .collect();
+        assert!(bottoms.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn test_ysort_overlapping() {
+        // Tiles twice as tall as the cells, two chunks wide: the tree at (0, 1) overlaps
+        // (0, 0), and comes after (16, 0) although it's in the first chunk.
+        let map_tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="17" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tall" tilewidth="16" tileheight="32" tilecount="2" columns="2">
+  <image source="tall.png" width="32" height="32"/>
+ </tileset>
+ <layer id="1" name="trees" width="17" height="2">
+  <data encoding="csv">
+1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,1,
+2,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0
+</data>
+ </layer>
+</map>"#;
+        let mut map = stand_in_map(map_tmx);
+        let trees = map.layer_by_name("trees").unwrap();
+        map.set_layer_ysort(trees, true);
+
+        for backend in [
+            LayerBackend::Dynamic,
+            LayerBackend::Animated,
+            LayerBackend::Static,
+        ] {
+            map.set_layer_backend(trees, backend);
+            let mut recorder = DrawRecorder::default();
+            map.draw_tiles_with(&mut recorder, trees, Rect::new(0., 0., 272., 32.), None);
+            let drawn: Vec<_> = recorder
+                .calls
+                .iter()
+                .map(|call| (call.dest, call.region.x))
+                .collect();
+            assert_eq!(
+                drawn,
+                [
+                    (Rect::new(0., -16., 16., 32.), 0.),
+                    (Rect::new(256., -16., 16., 32.), 0.),
+                    (Rect::new(0., 0., 16., 32.), 16.),
+                ],
+                "{backend:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_draw_animated_tiles() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        // The animated tile at (1, 1), the sixth drawn.
+        let frame_region = |map: &Map| {
+            let mut recorder = DrawRecorder::default();
+            map.draw_tiles_with(&mut recorder, ground, Rect::new(0., 0., 64., 64.), None);
+            recorder.calls[5].region
+        };
+
+        for backend in [
+            LayerBackend::Dynamic,
+            LayerBackend::Animated,
+            LayerBackend::Static,
+        ] {
+            map.set_layer_backend(ground, backend);
+            map.clock = MapClock::new();
+            map.clock.step(Duration::from_millis(50));
+            assert_eq!(frame_region(&map), Rect::new(0., 0., 16., 16.));
+            map.clock.step(Duration::from_millis(100));
+            assert_eq!(frame_region(&map), Rect::new(16., 0., 16., 16.));
+        }
+    }
+
+    #[test]
+    fn test_tile_offset() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let tileset = &mut map.tilesets.get_mut("tiny").unwrap().tileset;
+        (tileset.offset_x, tileset.offset_y) = (2, -4);
+
+        let mut recorder = DrawRecorder::default();
+        // Twice the size.
+        map.draw_tiles_with(&mut recorder, ground, Rect::new(0., 0., 128., 128.), None);
+        assert_eq!(recorder.calls[0].dest, Rect::new(4., -8., 32., 32.));
+    }
+
+    #[test]
+    fn test_pixel_snap() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_pixel_snap(true);
+
+        let mut recorder = DrawRecorder::default();
+        // 1.3 screen pixels per world pixel.
+        map.draw_tiles_with(&mut recorder, ground, Rect::new(0.5, 0., 83.2, 83.2), None);
+        let row: Vec<_> = recorder.calls[..4].iter().map(|call| call.dest).collect();
+        for pair in row.windows(2) {
+            assert_eq!(pair[0].right(), pair[1].left());
+        }
+        assert!(row
+            .iter()
+            .all(|dest| dest.x.fract() == 0.0 && dest.w.fract() == 0.0));
+    }
+
+    #[test]
+    fn test_visible_layers() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        assert!(map.visible_layers().contains(&ground));
+        map.set_layer_visible(ground, false);
+        assert!(!map.visible_layers().contains(&ground));
+    }
+
+    #[test]
+    fn test_layer_lod() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_layer_lod(ground, Some(0.5));
+        assert_eq!(map.layer_lod(ground), Some(0.5));
+
+        // Backends without meshes still draw tile by tile below the threshold.
+        let mut recorder = DrawRecorder::default();
+        map.draw_tiles_with(&mut recorder, ground, Rect::new(0., 0., 16., 16.), None);
+        assert_eq!(recorder.calls.len(), 16);
+
+        map.set_layer_lod(ground, None);
+        assert_eq!(map.layer_lod(ground), None);
+    }
+
+    #[test]
+    fn test_get_tiles_bulk() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_tile(ground, ivec2(2, 2), None);
+
+        let cells = [ivec2(0, 0), ivec2(1, 1), ivec2(2, 2), ivec2(9, 9)];
+        let ids: Vec<_> = map
+            .get_tiles_bulk(ground, &cells)
+            .into_iter()
+            .map(|tile| tile.map(|tile| tile.id))
+            .collect();
+        assert_eq!(ids, vec![Some(2), Some(0), None, None]);
+
+        let snapshot = map.rect_snapshot(ground, ivec2(1, 1), macroquad::math::uvec2(4, 2));
+        assert_eq!((snapshot.width, snapshot.height), (4, 2));
+        assert_eq!(snapshot.get(ivec2(1, 1)).map(|tile| tile.id), Some(0));
+        assert_eq!(snapshot.get(ivec2(2, 1)).map(|tile| tile.id), Some(3));
+        assert_eq!(snapshot.get(ivec2(2, 2)), None);
+        assert_eq!(snapshot.get(ivec2(4, 1)), None);
+        assert_eq!(snapshot.get(ivec2(0, 0)), None);
+    }
+
+    #[test]
+    fn test_draws_baked() {
+        /// Draws meshes, like `MacroquadBackend`, without a window.
+        struct Meshes;
+
+        impl DrawBackend for Meshes {
+            fn draw_texture(&mut self, _: &TileSet, _: Rect, _: Rect, _: DrawParams) {}
+
+            fn draws_meshes(&self) -> bool {
+                true
+            }
+        }
+
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let dest = Rect::new(0., 0., 64., 64.);
+        let baked = |map: &Map, target: &dyn DrawBackend| {
+            let setup = map.layer_setup(ground, dest, None).unwrap();
+            map.draws_baked(target, &setup, map.layer_backend(ground))
+        };
+        assert!(!baked(&map, &Meshes));
+        map.set_layer_backend(ground, LayerBackend::Static);
+        assert!(baked(&map, &Meshes));
+        assert!(!baked(&map, &DrawRecorder::default()));
+
+        // Tiles overlapping their neighbours are drawn in order with the animated ones.
+        map.set_layer_ysort(ground, true);
+        assert!(!baked(&map, &Meshes));
+        map.set_layer_ysort(ground, false);
+        map.tilesets.get_mut("tiny").unwrap().tileset.offset_y = -4;
+        assert!(!baked(&map, &Meshes));
+    }
+
+    #[test]
+    fn test_draw_tiles_rows() {
+        /// Logs the bottoms of the tiles drawn, alongside the rows.
+        struct Log<'a>(&'a RefCell<Vec<String>>);
+
+        impl DrawBackend for Log<'_> {
+            fn draw_texture(&mut self, _: &TileSet, _: Rect, dest: Rect, _: DrawParams) {
+                self.0.borrow_mut().push(format!("tile {}", dest.bottom()));
+            }
+        }
+
+        let map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let log = RefCell::new(vec![]);
+        let drawn = map.draw_tiles_rows_with(
+            &mut Log(&log),
+            ground,
+            Rect::new(0., 0., 64., 64.),
+            None,
+            |row| log.borrow_mut().push(format!("row {}", row)),
+        );
+        // Each row, top to bottom, after its tiles.
+        let expected: Vec<String> = [16, 32, 48, 64]
+            .into_iter()
+            .flat_map(|row| {
+                let tiles = std::iter::repeat_n(format!("tile {}", row), 4);
+                tiles.chain([format!("row {}", row)])
+            })
+            .collect();
+        assert_eq!(log.into_inner(), expected);
+        assert_eq!(drawn.len(), 16);
+        assert!(drawn.iter().all(|tile| tile.row == tile.dest.bottom()));
+
+        let mut recorder = DrawRecorder::default();
+        map.draw_tiles_rows_with(
+            &mut recorder,
+            ground,
+            Rect::new(0., 0., 64., 64.),
+            None,
+            |_| {},
+        );
+        assert_eq!(recorder.calls.len(), 16);
+        assert!(recorder.materials.is_empty());
+    }
+
+    #[test]
+    fn test_tile_handle() {
+        let tile = TileHandle::new("tiny", 3);
+        assert!(!tile.flip_h && !tile.flip_v && !tile.flip_d);
+        let flipped = tile.clone().flipped(true, false, true);
+        assert_eq!(
+            (flipped.flip_h, flipped.flip_v, flipped.flip_d),
+            (true, false, true)
+        );
+        assert_eq!(flipped.tile_ref().to_handle(), flipped);
+        assert_ne!(flipped, tile);
+
+        // Placed and read back with its flips, in snapshots too.
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_tile(ground, ivec2(1, 2), Some(flipped.clone()));
+        assert_eq!(map.tile_at(ground, ivec2(1, 2)), Some(flipped.clone()));
+        assert_eq!(map.tile_at(ground, ivec2(2, 1)), Some(tile.clone()));
+        let snapshot = map.layer_snapshot(ground);
+        assert_eq!(snapshot.get(ivec2(1, 2)), Some(&flipped));
+        let snapshot = map.rect_snapshot(ground, ivec2(1, 2), macroquad::math::uvec2(1, 1));
+        assert_eq!(snapshot.tiles, vec![Some(flipped)]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_tile_handle_json() {
+        let tile = TileHandle::new("tiny", 3).flipped(false, true, false);
+        let json = serde_json::to_string(&tile).unwrap();
+        assert_eq!(serde_json::from_str::<TileHandle>(&json).unwrap(), tile);
+        // Flips default to none.
+        assert_eq!(
+            serde_json::from_str::<TileHandle>(r#"{"tileset":"tiny","id":3}"#).unwrap(),
+            TileHandle::new("tiny", 3)
+        );
+    }
+
+    #[test]
+    fn test_load_hex_side_length() {
+        let path = std::env::temp_dir().join(format!("hex-{}.tmx", std::process::id()));
+        let tmx = |orientation: &str, side: &str| {
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="{orientation}" width="4" height="4" tilewidth="28" tileheight="32" {side} staggeraxis="y" staggerindex="odd">
+</map>"#
+            )
+        };
+        let load = |tmx: String| {
+            std::fs::write(&path, tmx).unwrap();
+            load_tmx_map(&path).map(|(_, hex_side_length)| hex_side_length)
+        };
+
+        let hex_side_length = load(tmx("hexagonal", r#"hexsidelength="16""#));
+        assert_eq!(hex_side_length.unwrap(), Some(16));
+        assert_eq!(load(tmx("orthogonal", "")).unwrap(), None);
+        let error = load(tmx("hexagonal", "")).unwrap_err();
+        assert!(matches!(error, TiledError::MalformedAttributes(_)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use std::f32::consts::TAU;
+
+use macroquad::math::{vec2, Rect, Vec2};
+
+/// Segments of a circle mask.
+const CIRCLE_SEGMENTS: usize = 48;
+
+/// Where `Map::draw_masked()` draws, in screen pixels, like `dest`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mask {
+    /// A simple polygon, convex or not, in either winding.
+    Polygon(Vec<Vec2>),
+    Circle {
+        center: Vec2,
+        radius: f32,
+    },
+}
+
+impl Mask {
+    /// The outline of the mask, circles as many-sided polygons.
+    pub fn outline(&self) -> Vec<Vec2> {
+        match self {
+            Mask::Polygon(points) => points.clone(),
+            Mask::Circle { center, radius } => (0..CIRCLE_SEGMENTS)
+                .map(|i| {
+                    let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+                    *center + vec2(angle.cos(), angle.sin()) * *radius
+                })
+                .collect(),
+        }
+    }
+
+    /// The mask as triangles. Self-intersecting polygons are only partly covered.
+    pub fn triangles(&self) -> Vec<[Vec2; 3]> {
+        triangulate(&self.outline())
+    }
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Ear clipping: cuts off convex corners which contain no other vertex, one by one.
+fn triangulate(points: &[Vec2]) -> Vec<[Vec2; 3]> {
+    if points.len() < 3 {
+        return vec![];
+    }
+    let mut indices: Vec<usize> = (0..points.len()).collect();
+    let area: f32 = (0..points.len())
+        .map(|i| cross(points[i], points[(i + 1) % points.len()]))
+        .sum();
+    // Convex corners turn the same way as the polygon.
+    if area < 0.0 {
+        indices.reverse();
+    }
+
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while indices.len() > 3 {
+        let n = indices.len();
+        let corner = |i: usize| {
+            (
+                points[indices[(i + n - 1) % n]],
+                points[indices[i]],
+                points[indices[(i + 1) % n]],
+            )
+        };
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = corner(i);
+            cross(b - a, c - b) > 0.0
+                && !indices.iter().any(|&j| {
+                    let p = points[j];
+                    p != a && p != b && p != c && in_triangle(p, a, b, c)
+                })
+        });
+        let Some(ear) = ear else {
+            // Self-intersecting or degenerate.
+            break;
+        };
+        let (a, b, c) = corner(ear);
+        triangles.push([a, b, c]);
+        indices.remove(ear);
+    }
+    if indices.len() == 3 {
+        triangles.push([points[indices[0]], points[indices[1]], points[indices[2]]]);
+    }
+    triangles
+}
+
+/// For counter-clockwise triangles, edges included.
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    cross(b - a, p - a) >= 0.0 && cross(c - b, p - b) >= 0.0 && cross(a - c, p - c) >= 0.0
+}
+
+/// The part of the convex `polygon` inside `rect`, Sutherland-Hodgman.
+pub(crate) fn clip_to_rect(polygon: &[Vec2], rect: Rect) -> Vec<Vec2> {
+    let mut result = polygon.to_vec();
+    // (along x, bound, inner side)
+    let edges = [
+        (true, rect.left(), 1.0),
+        (true, rect.right(), -1.0),
+        (false, rect.top(), 1.0),
+        (false, rect.bottom(), -1.0),
+    ];
+    for (along_x, bound, side) in edges {
+        let axis = |p: Vec2| if along_x { p.x } else { p.y };
+        let inside = |p: Vec2| (axis(p) - bound) * side >= 0.0;
+        let input = std::mem::take(&mut result);
+        for (i, &current) in input.iter().enumerate() {
+            let previous = input[(i + input.len() - 1) % input.len()];
+            if inside(current) != inside(previous) {
+                let t = (bound - axis(previous)) / (axis(current) - axis(previous));
+                result.push(previous.lerp(current, t));
+            }
+            if inside(current) {
+                result.push(current);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(triangles: &[[Vec2; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|[a, b, c]| cross(*b - *a, *c - *a).abs() / 2.0)
+            .sum()
+    }
+
+    #[test]
+    fn test_triangulate() {
+        // An L, clockwise on screen.
+        let l = Mask::Polygon(vec![
+            vec2(0., 0.),
+            vec2(1., 0.),
+            vec2(1., 2.),
+            vec2(2., 2.),
+            vec2(2., 3.),
+            vec2(0., 3.),
+        ]);
+        let triangles = l.triangles();
+        assert_eq!(triangles.len(), 4);
+        assert!((area(&triangles) - 4.0).abs() < 1e-5);
+        // Same the other way around.
+        let Mask::Polygon(mut points) = l else {
+            unreachable!()
+        };
+        points.reverse();
+        assert!((area(&Mask::Polygon(points).triangles()) - 4.0).abs() < 1e-5);
+
+        let circle = Mask::Circle {
+            center: vec2(5., 5.),
+            radius: 2.,
+        };
+        assert!((area(&circle.triangles()) - 4.0 * std::f32::consts::PI).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_clip_to_rect() {
+        let triangle = [vec2(0., 0.), vec2(4., 0.), vec2(0., 4.)];
+        let clipped = clip_to_rect(&triangle, Rect::new(1., -1., 10., 2.));
+        // The part of the triangle with x >= 1 and y <= 1: a trapezoid.
+        assert_eq!(clipped.len(), 4);
+        assert!(clipped.iter().all(|p| p.x >= 1.0 && p.y <= 1.0));
+        assert!(clip_to_rect(&triangle, Rect::new(5., 5., 1., 1.)).is_empty());
+    }
+}
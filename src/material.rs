@@ -0,0 +1,75 @@
+use std::str::FromStr;
+
+use macroquad::math::IVec2;
+
+use crate::map::Map;
+use crate::properties::PropertiesExt;
+use crate::tileset::dominant_wang_color;
+
+/// String tile property naming the surface material of a tile, e.g. "grass" or "stone".
+pub const MATERIAL_PROPERTY: &str = "material";
+
+impl Map {
+    /// The material of the surface at `pos`, in world tiles, for footstep sounds,
+    /// movement particles and such: of the topmost tile there, its "material" property,
+    /// or else the name of its dominant Wang color, or else the "material" of its tileset,
+    /// e.g. "sand" for a whole beach tileset, parsed into the game's own type, e.g.:
+    /// `enum Surface { Grass, Stone, Water }` implementing `FromStr`.
+    /// Tiles without a material, or with one `M` doesn't parse, are seen through.
+    pub fn surface_material<M: FromStr>(&self, pos: IVec2) -> Option<M> {
+        (0..self.layer_count()).rev().find_map(|layer| {
+            let tile = self.tile_ref_at(layer, pos)?;
+            let tile_data = self.tile_data(tile.tileset, tile.id);
+            if let Some(material) = tile_data
+                .as_ref()
+                .and_then(|data| data.properties.get_string(MATERIAL_PROPERTY))
+            {
+                return material.parse().ok();
+            }
+
+            let tileset = self.tilesets.get(tile.tileset)?;
+            let wang_color = tileset.tileset.wang_sets.iter().find_map(|wang_set| {
+                let wang_tile = wang_set.wang_tiles.get(&tile.id)?;
+                let color = dominant_wang_color(wang_tile.wang_id.0)?;
+                wang_set
+                    .wang_colors
+                    .get(color as usize - 1)?
+                    .name
+                    .parse()
+                    .ok()
+            });
+            wang_color.or_else(|| tileset.property_string(MATERIAL_PROPERTY)?.parse().ok())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_surface_material() {
+        let mut map = tiny_map();
+        let wall = map.surface_material::<String>(ivec2(0, 0));
+        assert_eq!(wall.as_deref(), Some("stone"));
+        assert_eq!(map.surface_material::<String>(ivec2(1, 1)), None);
+
+        // The tileset's material is the default of its tiles.
+        let tileset = map.tilesets.get_mut("tiny").unwrap();
+        tileset.tileset.properties.insert(
+            MATERIAL_PROPERTY.to_string(),
+            tiled::PropertyValue::StringValue("dirt".to_string()),
+        );
+        assert_eq!(tileset.property_string(MATERIAL_PROPERTY), Some("dirt"));
+        assert_eq!(
+            map.surface_material::<String>(ivec2(1, 1)).as_deref(),
+            Some("dirt")
+        );
+        assert_eq!(
+            map.surface_material::<String>(ivec2(0, 0)).as_deref(),
+            Some("stone")
+        );
+    }
+}
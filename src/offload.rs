@@ -0,0 +1,69 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::task::{Context, Poll};
+
+/// The result of `work` run on a thread of its own, as a future for macroquad's executor,
+/// which polls it once per frame: the window keeps drawing meanwhile, e.g. a loading screen
+/// in a coroutine. Native targets only, wasm has no threads.
+pub(crate) fn offload<T, F>(work: F) -> Offloaded<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        // Fails only if the future was dropped, then nobody waits for the result.
+        let _ = sender.send(work());
+    });
+    Offloaded { receiver }
+}
+
+/// See `offload()`.
+pub(crate) struct Offloaded<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Future for Offloaded<T> {
+    type Output = T;
+
+    /// Panics: if `work` did.
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<T> {
+        match self.receiver.try_recv() {
+            Ok(result) => Poll::Ready(result),
+            Err(TryRecvError::Empty) => {
+                // Other executors than macroquad's need a wake-up to poll again.
+                context.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(TryRecvError::Disconnected) => panic!("The offloaded work panicked"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::noop_waker;
+
+    #[test]
+    fn test_offload() {
+        let noop_waker = noop_waker();
+        let (go, wait) = channel::<()>();
+        let mut future = offload(move || {
+            wait.recv().unwrap();
+            42
+        });
+        let mut context = Context::from_waker(&noop_waker);
+        assert_eq!(Pin::new(&mut future).poll(&mut context), Poll::Pending);
+
+        go.send(()).unwrap();
+        loop {
+            if let Poll::Ready(result) = Pin::new(&mut future).poll(&mut context) {
+                assert_eq!(result, 42);
+                break;
+            }
+            std::thread::yield_now();
+        }
+    }
+}
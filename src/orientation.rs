@@ -0,0 +1,432 @@
+use macroquad::math::{ivec2, vec2, IVec2, Rect, Vec2};
+use tiled::{Orientation, StaggerAxis, StaggerIndex};
+
+use crate::map::Map;
+
+/// Coordinate math for map orientations.
+/// Orthogonal, isometric (diamond) and hexagonal maps are supported,
+/// staggered ones are treated as orthogonal.
+impl Map {
+    /// Size of the map grid cell in world pixels.
+    pub fn tile_size_px(&self) -> Vec2 {
+        vec2(self.map.tile_width as f32, self.map.tile_height as f32)
+    }
+
+    /// Size of the whole map in world pixels.
+    pub fn size_px(&self) -> Vec2 {
+        let (width, height) = (self.map.width as f32, self.map.height as f32);
+        let tile = self.tile_size_px();
+        match self.map.orientation {
+            Orientation::Isometric => (width + height) * tile / 2.0,
+            Orientation::Hexagonal => self.hex().size_px(self.map.width, self.map.height),
+            _ => vec2(width, height) * tile,
+        }
+    }
+
+    /// Top-left corner of the bounding box of the cell `pos`, in world pixels.
+    /// On isometric maps, that's the box around the diamond.
+    pub fn tile_to_world_px(&self, pos: IVec2) -> Vec2 {
+        let tile = self.tile_size_px();
+        match self.map.orientation {
+            Orientation::Isometric => iso_tile_to_px(pos, tile, self.map.height),
+            Orientation::Hexagonal => self.hex().tile_to_px(pos),
+            _ => pos.as_vec2() * tile,
+        }
+    }
+
+    /// The cell containing `world_px`, the inverse of `tile_to_world_px()`.
+    /// The cell may be outside of the map.
+    pub fn world_px_to_tile(&self, world_px: Vec2) -> IVec2 {
+        let tile = self.tile_size_px();
+        match self.map.orientation {
+            Orientation::Isometric => iso_px_to_tile(world_px, tile, self.map.height),
+            Orientation::Hexagonal => self.hex().px_to_tile(world_px),
+            _ => (world_px / tile).floor().as_ivec2(),
+        }
+    }
+
+    /// Corners of the cell `pos`, in world pixels, clockwise from the left:
+    /// a rectangle, a diamond on isometric maps, or a hexagon.
+    pub fn cell_outline(&self, pos: IVec2) -> Vec<Vec2> {
+        let top_left = self.tile_to_world_px(pos);
+        let tile = self.tile_size_px();
+        let corners = match self.map.orientation {
+            Orientation::Isometric => vec![
+                vec2(0., tile.y / 2.),
+                vec2(tile.x / 2., 0.),
+                vec2(tile.x, tile.y / 2.),
+                vec2(tile.x / 2., tile.y),
+            ],
+            Orientation::Hexagonal => self.hex().outline(),
+            _ => vec![vec2(0., tile.y), vec2(0., 0.), vec2(tile.x, 0.), tile],
+        };
+        corners
+            .into_iter()
+            .map(|corner| top_left + corner)
+            .collect()
+    }
+
+    /// Cells to draw for `source_px`, (min, max) inclusive, with a margin for tiles
+    /// sticking out of their cells. Not clamped to the map.
+    pub(crate) fn visible_tile_range(&self, source_px: Rect) -> (IVec2, IVec2) {
+        match self.map.orientation {
+            Orientation::Isometric => {
+                let tile = self.tile_size_px();
+                // Tiles taller than the grid reach up into the view from below it.
+                let overhang = (self.max_tileset_tile_height() - tile.y).max(0.0);
+                let rect = Rect::new(
+                    source_px.x - tile.x,
+                    source_px.y - tile.y,
+                    source_px.w + tile.x * 2.0,
+                    source_px.h + tile.y * 2.0 + overhang,
+                );
+                let corners = [
+                    rect.point(),
+                    vec2(rect.right(), rect.y),
+                    vec2(rect.x, rect.bottom()),
+                    vec2(rect.right(), rect.bottom()),
+                ]
+                .map(|corner| self.world_px_to_tile(corner));
+                let min = corners.iter().fold(corners[0], |min, cell| min.min(*cell));
+                let max = corners.iter().fold(corners[0], |max, cell| max.max(*cell));
+                (min, max)
+            }
+            Orientation::Hexagonal => {
+                let hex = self.hex();
+                let step = hex.cell_step();
+                let min = (source_px.point() / step).floor().as_ivec2() - IVec2::ONE;
+                let max = ((source_px.point() + source_px.size()) / step)
+                    .ceil()
+                    .as_ivec2()
+                    + IVec2::ONE;
+                // Tiles taller than the grid reach up into the view from below it.
+                let overhang =
+                    (self.max_tileset_tile_height() - self.map.tile_height as f32).max(0.0);
+                (min, max + ivec2(0, (overhang / step.y).ceil() as i32))
+            }
+            _ => {
+                // Floored, for the negative cells of infinite maps.
+                let tile = self.tile_size_px();
+                let min = (source_px.point() / tile).floor().as_ivec2();
+                let max = ((source_px.point() + source_px.size()) / tile)
+                    .ceil()
+                    .as_ivec2();
+                (min - IVec2::ONE, max + IVec2::ONE)
+            }
+        }
+    }
+
+    fn max_tileset_tile_height(&self) -> f32 {
+        self.tilesets
+            .values()
+            .map(|tileset| tileset.tileset.tile_height as f32)
+            .fold(0.0, f32::max)
+    }
+
+    fn hex(&self) -> Hex {
+        Hex::new(
+            self.map.tile_width,
+            self.map.tile_height,
+            self.hex_side_length,
+            self.map.stagger_axis == StaggerAxis::X,
+            self.map.stagger_index == StaggerIndex::Even,
+        )
+    }
+}
+
+/// Hexagonal grid parameters, same as Tiled's `HexagonalRenderer` uses.
+/// Cells are columns or rows of hexes, every other one shifted by half a cell
+/// along the stagger axis.
+struct Hex {
+    tile_width: i32,
+    tile_height: i32,
+    side_length_x: i32,
+    side_length_y: i32,
+    side_offset_x: i32,
+    side_offset_y: i32,
+    column_width: i32,
+    row_height: i32,
+    stagger_x: bool,
+    stagger_even: bool,
+}
+
+impl Hex {
+    fn new(
+        tile_width: u32,
+        tile_height: u32,
+        side_length: u32,
+        stagger_x: bool,
+        stagger_even: bool,
+    ) -> Self {
+        // Tiled rounds tile sizes down to even numbers.
+        let tile_width = tile_width as i32 & !1;
+        let tile_height = tile_height as i32 & !1;
+        let side_length_x = if stagger_x { side_length as i32 } else { 0 };
+        let side_length_y = if stagger_x { 0 } else { side_length as i32 };
+        let side_offset_x = (tile_width - side_length_x) / 2;
+        let side_offset_y = (tile_height - side_length_y) / 2;
+        Self {
+            tile_width,
+            tile_height,
+            side_length_x,
+            side_length_y,
+            side_offset_x,
+            side_offset_y,
+            column_width: side_offset_x + side_length_x,
+            row_height: side_offset_y + side_length_y,
+            stagger_x,
+            stagger_even,
+        }
+    }
+
+    fn is_staggered(&self, index: i32) -> bool {
+        ((index & 1) == 1) != self.stagger_even
+    }
+
+    /// Distance between neighbouring cells along the axes, ignoring the stagger.
+    fn cell_step(&self) -> Vec2 {
+        if self.stagger_x {
+            vec2(
+                self.column_width as f32,
+                (self.tile_height + self.side_length_y) as f32,
+            )
+        } else {
+            vec2(
+                (self.tile_width + self.side_length_x) as f32,
+                self.row_height as f32,
+            )
+        }
+    }
+
+    fn size_px(&self, width: u32, height: u32) -> Vec2 {
+        let (width, height) = (width as i32, height as i32);
+        let size = if self.stagger_x {
+            let stagger = if width > 1 { self.row_height } else { 0 };
+            ivec2(
+                width * self.column_width + self.side_offset_x,
+                height * (self.tile_height + self.side_length_y) + stagger,
+            )
+        } else {
+            let stagger = if height > 1 { self.column_width } else { 0 };
+            ivec2(
+                width * (self.tile_width + self.side_length_x) + stagger,
+                height * self.row_height + self.side_offset_y,
+            )
+        };
+        size.as_vec2()
+    }
+
+    /// Corners of the hex from the top-left of its bounding box, like Tiled's
+    /// `HexagonalRenderer::tileToScreenPolygon()`.
+    fn outline(&self) -> Vec<Vec2> {
+        let (w, h) = (self.tile_width, self.tile_height);
+        let (x, y) = (self.side_offset_x, self.side_offset_y);
+        let mut corners: Vec<_> = [
+            ivec2(0, h - y),
+            ivec2(0, y),
+            ivec2(x, 0),
+            ivec2(w - x, 0),
+            ivec2(w, y),
+            ivec2(w, h - y),
+            ivec2(w - x, h),
+            ivec2(x, h),
+        ]
+        .map(|corner| corner.as_vec2())
+        .to_vec();
+        // Pointy hexes have a single corner at the top and bottom.
+        corners.dedup();
+        corners
+    }
+
+    /// Top-left of the bounding box of the hex.
+    fn tile_to_px(&self, pos: IVec2) -> Vec2 {
+        let px = if self.stagger_x {
+            let stagger = if self.is_staggered(pos.x) {
+                self.row_height
+            } else {
+                0
+            };
+            ivec2(
+                pos.x * self.column_width,
+                pos.y * (self.tile_height + self.side_length_y) + stagger,
+            )
+        } else {
+            let stagger = if self.is_staggered(pos.y) {
+                self.column_width
+            } else {
+                0
+            };
+            ivec2(
+                pos.x * (self.tile_width + self.side_length_x) + stagger,
+                pos.y * self.row_height,
+            )
+        };
+        px.as_vec2()
+    }
+
+    /// Tiled's `HexagonalRenderer::screenToTileCoords()`: finds the nearest hex center
+    /// around a grid-aligned reference cell.
+    fn px_to_tile(&self, world_px: Vec2) -> IVec2 {
+        let mut px = world_px;
+        if self.stagger_x {
+            px.x -= (if self.stagger_even {
+                self.tile_width
+            } else {
+                self.side_offset_x
+            }) as f32;
+        } else {
+            px.y -= (if self.stagger_even {
+                self.tile_height
+            } else {
+                self.side_offset_y
+            }) as f32;
+        }
+
+        let square = vec2(self.column_width as f32, self.row_height as f32) * 2.0;
+        let mut reference = (px / square).floor().as_ivec2();
+        let relative = px - reference.as_vec2() * square;
+
+        let stagger_index = if self.stagger_x {
+            &mut reference.x
+        } else {
+            &mut reference.y
+        };
+        *stagger_index *= 2;
+        if self.stagger_even {
+            *stagger_index += 1;
+        }
+
+        let (column_width, row_height) = (self.column_width as f32, self.row_height as f32);
+        let (centers, offsets) = if self.stagger_x {
+            let left = (self.side_length_x / 2) as f32;
+            let center_x = left + column_width;
+            let center_y = (self.tile_height / 2) as f32;
+            (
+                [
+                    vec2(left, center_y),
+                    vec2(center_x, center_y - row_height),
+                    vec2(center_x, center_y + row_height),
+                    vec2(center_x + column_width, center_y),
+                ],
+                [ivec2(0, 0), ivec2(1, -1), ivec2(1, 0), ivec2(2, 0)],
+            )
+        } else {
+            let top = (self.side_length_y / 2) as f32;
+            let center_x = (self.tile_width / 2) as f32;
+            let center_y = top + row_height;
+            (
+                [
+                    vec2(center_x, top),
+                    vec2(center_x - column_width, center_y),
+                    vec2(center_x + column_width, center_y),
+                    vec2(center_x, center_y + row_height),
+                ],
+                [ivec2(0, 0), ivec2(-1, 1), ivec2(0, 1), ivec2(0, 2)],
+            )
+        };
+
+        let nearest = (0..4)
+            .min_by(|a, b| {
+                let a = centers[*a].distance_squared(relative);
+                let b = centers[*b].distance_squared(relative);
+                a.total_cmp(&b)
+            })
+            .unwrap_or(0);
+        reference + offsets[nearest]
+    }
+}
+
+/// The hex side length of `map`, from `tmx`, the bytes tiled parsed it from:
+/// None unless it's hexagonal. Errors if a hexagonal map lacks it.
+pub(crate) fn map_hex_side_length(
+    map: &tiled::Map,
+    tmx: &[u8],
+) -> Result<Option<u32>, tiled::Error> {
+    if map.orientation != Orientation::Hexagonal {
+        return Ok(None);
+    }
+    hex_side_length_from_tmx(&String::from_utf8_lossy(tmx))
+        .map(Some)
+        .ok_or_else(|| {
+            tiled::Error::MalformedAttributes("Hexagonal map without hexsidelength".to_string())
+        })
+}
+
+/// Tiled stores `hexsidelength` in the `<map>` tag, but `tiled::Map` doesn't expose it.
+fn hex_side_length_from_tmx(tmx: &str) -> Option<u32> {
+    let map_tag = &tmx[tmx.find("<map ")?..];
+    let map_tag = &map_tag[..map_tag.find('>')?];
+    let value = &map_tag[map_tag.find("hexsidelength=\"")? + "hexsidelength=\"".len()..];
+    value[..value.find('"')?].parse().ok()
+}
+
+/// Top-left of the bounding box of an isometric cell. Tiled puts the top corner of the cell
+/// (0, 0) at `map_height` half-tiles from the left, so the whole map has positive coordinates.
+fn iso_tile_to_px(pos: IVec2, tile: Vec2, map_height: u32) -> Vec2 {
+    vec2(
+        (pos.x - pos.y + map_height as i32 - 1) as f32 * tile.x / 2.0,
+        (pos.x + pos.y) as f32 * tile.y / 2.0,
+    )
+}
+
+fn iso_px_to_tile(world_px: Vec2, tile: Vec2, map_height: u32) -> IVec2 {
+    let x = (world_px.x - map_height as f32 * tile.x / 2.0) / tile.x;
+    let y = world_px.y / tile.y;
+    ivec2((y + x).floor() as i32, (y - x).floor() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_roundtrip() {
+        // Pointy-top hexes in rows, and flat-top ones in columns.
+        for (stagger_x, stagger_even) in
+            [(false, false), (false, true), (true, false), (true, true)]
+        {
+            let hex = Hex::new(28, 32, 16, stagger_x, stagger_even);
+            for pos in [
+                ivec2(0, 0),
+                ivec2(1, 0),
+                ivec2(0, 1),
+                ivec2(5, 7),
+                ivec2(-3, -2),
+            ] {
+                let center = hex.tile_to_px(pos) + vec2(14., 16.);
+                assert_eq!(hex.px_to_tile(center), pos, "{stagger_x} {stagger_even}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_side_length_from_tmx() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="hexagonal" width="4" height="4" tilewidth="28" tileheight="32" hexsidelength="16" staggeraxis="y" staggerindex="odd">
+ <tileset firstgid="1" source="hex.tsx" hexsidelength="3"/>"#;
+        assert_eq!(hex_side_length_from_tmx(tmx), Some(16));
+        assert_eq!(hex_side_length_from_tmx("<map width=\"4\">"), None);
+    }
+
+    #[test]
+    fn test_hex_outline() {
+        // Pointy-top: a corner at the top and at the bottom.
+        let outline = Hex::new(28, 32, 16, false, false).outline();
+        assert_eq!(outline.len(), 6);
+        assert!(outline.contains(&vec2(14., 0.)));
+        assert!(outline.contains(&vec2(0., 24.)));
+    }
+
+    #[test]
+    fn test_iso_roundtrip() {
+        let tile = vec2(64., 32.);
+        assert_eq!(iso_tile_to_px(ivec2(0, 0), tile, 10), vec2(288., 0.));
+        assert_eq!(iso_tile_to_px(ivec2(0, 9), tile, 10), vec2(0., 144.));
+
+        for pos in [ivec2(0, 0), ivec2(3, 7), ivec2(9, 0), ivec2(-2, 4)] {
+            // The center of the diamond.
+            let center = iso_tile_to_px(pos, tile, 10) + tile / 2.0;
+            assert_eq!(iso_px_to_tile(center, tile, 10), pos);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use macroquad::math::{Rect, Vec2};
+
+/// How far above or below the top of a platform feet land on it, in world pixels.
+pub const LANDING_TOLERANCE: f32 = 2.0;
+
+/// Entities riding moving platforms, e.g. lifts and rafts: riders move along with
+/// the platform they stand on until they leave it. Platforms and riders are keys of
+/// the game's choosing, e.g. object ids. Every frame, move the platforms, then `carry()`
+/// the riders, before using their positions for logic and drawing.
+#[derive(Clone, Debug)]
+pub struct Platforms<P, R> {
+    /// In world pixels.
+    platforms: HashMap<P, Rect>,
+    /// The platform of each rider, and where it was at the rider's last `carry()`.
+    riders: HashMap<R, (P, Vec2)>,
+}
+
+impl<P, R> Default for Platforms<P, R> {
+    fn default() -> Self {
+        Self {
+            platforms: HashMap::new(),
+            riders: HashMap::new(),
+        }
+    }
+}
+
+impl<P: Eq + Hash + Clone, R: Eq + Hash> Platforms<P, R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets where `platform` is now, e.g. where its path puts it this frame.
+    pub fn move_platform(&mut self, platform: P, rect: Rect) {
+        self.platforms.insert(platform, rect);
+    }
+
+    /// Removes `platform`, its riders are dropped off.
+    pub fn remove_platform(&mut self, platform: &P) {
+        self.platforms.remove(platform);
+        self.riders.retain(|_, (ridden, _)| ridden != platform);
+    }
+
+    pub fn platform(&self, platform: &P) -> Option<Rect> {
+        self.platforms.get(platform).copied()
+    }
+
+    /// Puts `rider` on `platform`, from where it is now. Returns false for unknown platforms.
+    pub fn attach(&mut self, rider: R, platform: P) -> bool {
+        let Some(rect) = self.platforms.get(&platform) else {
+            return false;
+        };
+        self.riders.insert(rider, (platform, rect.point()));
+        true
+    }
+
+    pub fn detach(&mut self, rider: &R) {
+        self.riders.remove(rider);
+    }
+
+    pub fn platform_of(&self, rider: &R) -> Option<&P> {
+        self.riders.get(rider).map(|(platform, _)| platform)
+    }
+
+    /// Attaches `rider` to the platform whose top its `feet` touch, see `LANDING_TOLERANCE`,
+    /// staying on its current one if still touching it, or detaches it if none.
+    /// Of overlapping platforms, it lands on the highest, then the lowest key, the same
+    /// one whatever the order they were added in.
+    /// Call it after `carry()`, when the rider has moved on its own. Returns the platform.
+    pub fn land(&mut self, rider: R, feet: Vec2) -> Option<P>
+    where
+        P: Ord,
+    {
+        let touches = |rect: &Rect| {
+            (feet.y - rect.y).abs() <= LANDING_TOLERANCE
+                && feet.x >= rect.x
+                && feet.x < rect.right()
+        };
+        if let Some((platform, _)) = self.riders.get(&rider) {
+            if self.platforms.get(platform).is_some_and(touches) {
+                return Some(platform.clone());
+            }
+        }
+        let landed = self
+            .platforms
+            .iter()
+            .filter(|(_, rect)| touches(rect))
+            .min_by(|(a, a_rect), (b, b_rect)| a_rect.y.total_cmp(&b_rect.y).then_with(|| a.cmp(b)))
+            .map(|(platform, rect)| (platform.clone(), rect.point()));
+        match landed {
+            Some((platform, at)) => {
+                self.riders.insert(rider, (platform.clone(), at));
+                Some(platform)
+            }
+            None => {
+                self.riders.remove(&rider);
+                None
+            }
+        }
+    }
+
+    /// Moves `position`, of `rider`, by how much its platform moved since the last call,
+    /// or since it was attached. Returns the move, zero for riders on no platform.
+    pub fn carry(&mut self, rider: &R, position: &mut Vec2) -> Vec2 {
+        let Some((platform, last)) = self.riders.get_mut(rider) else {
+            return Vec2::ZERO;
+        };
+        let Some(rect) = self.platforms.get(platform) else {
+            return Vec2::ZERO;
+        };
+        let delta = rect.point() - *last;
+        *last = rect.point();
+        *position += delta;
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::vec2;
+
+    #[test]
+    fn test_ride() {
+        let mut platforms = Platforms::new();
+        platforms.move_platform("lift", Rect::new(0., 100., 32., 8.));
+        let mut player = vec2(16., 100.);
+        assert_eq!(platforms.land("player", player), Some("lift"));
+
+        platforms.move_platform("lift", Rect::new(4., 90., 32., 8.));
+        assert_eq!(platforms.carry(&"player", &mut player), vec2(4., -10.));
+        assert_eq!(player, vec2(20., 90.));
+        // Carried once per move.
+        assert_eq!(platforms.carry(&"player", &mut player), Vec2::ZERO);
+
+        // Walked off.
+        player.x = 40.;
+        assert_eq!(platforms.land("player", player), None);
+        platforms.move_platform("lift", Rect::new(4., 80., 32., 8.));
+        assert_eq!(platforms.carry(&"player", &mut player), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_land_overlapping() {
+        for order in [["raft", "lift", "crate"], ["crate", "lift", "raft"]] {
+            let mut platforms = Platforms::new();
+            for platform in order {
+                let y = if platform == "crate" { 101. } else { 100. };
+                platforms.move_platform(platform, Rect::new(0., y, 32., 8.));
+            }
+            // Both tops are at 100: the lowest key.
+            assert_eq!(platforms.land("player", vec2(16., 101.)), Some("lift"));
+            platforms.detach(&"player");
+            platforms.move_platform("raft", Rect::new(0., 99., 32., 8.));
+            // The highest top.
+            assert_eq!(platforms.land("player", vec2(16., 101.)), Some("raft"));
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! The commonly used types, to import at once:
+//! `use macroquad_tiled_redux::prelude::*;`
+
+pub use tiled::Error as TiledError;
+
+#[cfg(feature = "render")]
+pub use crate::animation_controller::{AnimationController, AnimationRegistry, Facing};
+#[cfg(feature = "render")]
+pub use crate::camera::PixelCamera;
+pub use crate::clock::MapClock;
+#[cfg(feature = "collision")]
+pub use crate::collision::{CollisionGrid, GridDecodeError};
+pub use crate::draw_backend::{DrawBackend, DrawParams};
+pub use crate::layer_backend::LayerBackend;
+pub use crate::layer_renderer::{LayerDraw, LayerDrawMode, VisibleTile};
+pub use crate::map::{
+    camera_world_rect, screen_to_world_px, world_px_to_screen, LoadOptions, LoadWarning, Map,
+    TileHandle, TileRef,
+};
+pub use crate::properties::{PropertiesExt, ResolvedTile};
+pub use crate::tileset::TileSet;
+#[cfg(feature = "render")]
+pub use crate::world::World;
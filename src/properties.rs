@@ -0,0 +1,284 @@
+use macroquad::color::Color;
+use macroquad::math::IVec2;
+use tiled::{Properties, PropertyValue};
+
+use crate::map::{Map, TileRef};
+use crate::tileset::TileSet;
+
+/// Typed getters for Tiled custom properties, instead of matching `PropertyValue` every time.
+/// All of them return `None` if the property is missing or has another type.
+pub trait PropertiesExt {
+    fn get_bool(&self, name: &str) -> Option<bool>;
+    fn get_int(&self, name: &str) -> Option<i32>;
+    /// Int properties are accepted too, Tiled users often don't bother typing "1.0".
+    fn get_float(&self, name: &str) -> Option<f32>;
+    /// String or file properties.
+    fn get_string(&self, name: &str) -> Option<&str>;
+    fn get_color(&self, name: &str) -> Option<Color>;
+}
+
+impl PropertiesExt for Properties {
+    fn get_bool(&self, name: &str) -> Option<bool> {
+        bool_value(self.get(name)?)
+    }
+
+    fn get_int(&self, name: &str) -> Option<i32> {
+        int_value(self.get(name)?)
+    }
+
+    fn get_float(&self, name: &str) -> Option<f32> {
+        float_value(self.get(name)?)
+    }
+
+    fn get_string(&self, name: &str) -> Option<&str> {
+        string_value(self.get(name)?)
+    }
+
+    fn get_color(&self, name: &str) -> Option<Color> {
+        color_value(self.get(name)?)
+    }
+}
+
+// The conversions of `PropertiesExt`, for single values, e.g. `TileSet::tile_property()`.
+
+pub(crate) fn bool_value(value: &PropertyValue) -> Option<bool> {
+    match value {
+        PropertyValue::BoolValue(value) => Some(*value),
+        _ => None,
+    }
+}
+
+pub(crate) fn int_value(value: &PropertyValue) -> Option<i32> {
+    match value {
+        PropertyValue::IntValue(value) => Some(*value),
+        _ => None,
+    }
+}
+
+pub(crate) fn float_value(value: &PropertyValue) -> Option<f32> {
+    match value {
+        PropertyValue::FloatValue(value) => Some(*value),
+        PropertyValue::IntValue(value) => Some(*value as f32),
+        _ => None,
+    }
+}
+
+pub(crate) fn string_value(value: &PropertyValue) -> Option<&str> {
+    match value {
+        PropertyValue::StringValue(value) | PropertyValue::FileValue(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub(crate) fn color_value(value: &PropertyValue) -> Option<Color> {
+    match value {
+        PropertyValue::ColorValue(value) => Some(to_mq_color(*value)),
+        _ => None,
+    }
+}
+
+/// A tile placed on a map with its tileset and properties, see `Map::get_tile()`.
+#[derive(Clone, Copy, Debug)]
+pub struct ResolvedTile<'map> {
+    /// Its tileset name, tile id and flips.
+    pub tile: TileRef<'map>,
+    pub tileset: &'map TileSet,
+    /// The runtime overrides of its cell, see `Map::set_runtime_property()`.
+    pub overrides: Option<&'map Properties>,
+}
+
+impl ResolvedTile<'_> {
+    pub fn id(&self) -> u32 {
+        self.tile.id
+    }
+
+    /// The property `name`: its runtime override if any, else the tile's, else its
+    /// tileset's. Same as `Map::tile_property_at()`.
+    pub fn property(&self, name: &str) -> Option<PropertyValue> {
+        match self.overrides.and_then(|overrides| overrides.get(name)) {
+            Some(value) => Some(value.clone()),
+            None => self.tileset.tile_property(self.tile.id, name),
+        }
+    }
+
+    /// All the properties `property()` resolves, merged. Allocates, prefer `property()`
+    /// for a few of them.
+    pub fn properties(&self) -> Properties {
+        let properties = self.tileset.tile_properties(self.tile.id);
+        match self.overrides {
+            Some(overrides) => inherit_properties(&properties, overrides),
+            None => properties,
+        }
+    }
+
+    /// Tiled class, aka type.
+    pub fn class(&self) -> Option<String> {
+        self.tileset
+            .tileset
+            .get_tile(self.tile.id)?
+            .user_type
+            .clone()
+    }
+}
+
+impl Map {
+    /// The tile at `pos` of `layer`, with its tileset and properties, instead of
+    /// digging through the layers of `map`. Runtime edits included, `None` if empty.
+    pub fn get_tile(&self, layer: usize, pos: IVec2) -> Option<ResolvedTile<'_>> {
+        let tile = self.tile_ref_at(layer, pos)?;
+        Some(ResolvedTile {
+            tile,
+            tileset: self.tilesets.get(tile.tileset)?,
+            overrides: self.runtime_properties(layer, pos),
+        })
+    }
+
+    /// The property `name` of the tile at `pos` of `layer`: its runtime override if any,
+    /// see `set_runtime_property()`, else the tile's, else its tileset's.
+    /// `None` for empty cells. See `ResolvedTile::property()`.
+    pub fn tile_property_at(&self, layer: usize, pos: IVec2, name: &str) -> Option<PropertyValue> {
+        self.get_tile(layer, pos)?.property(name)
+    }
+
+    /// Same as `tile_property_at()`, for bool properties.
+    pub fn tile_bool_at(&self, layer: usize, pos: IVec2, name: &str) -> Option<bool> {
+        bool_value(&self.tile_property_at(layer, pos, name)?)
+    }
+
+    /// Same as `tile_property_at()`, for float or int properties.
+    pub fn tile_float_at(&self, layer: usize, pos: IVec2, name: &str) -> Option<f32> {
+        float_value(&self.tile_property_at(layer, pos, name)?)
+    }
+
+    /// Selects the tiles of all the tile layers, runtime edits included, for which
+    /// `selector(map, layer, pos, tile)`, to override their properties at once, e.g.
+    /// to mark all the "grass" tiles burned. With the "rayon" feature, rows are scanned
+    /// in parallel.
+    pub fn for_tiles_matching(
+        &mut self,
+        selector: impl Fn(&Map, usize, IVec2, &TileRef) -> bool + Sync + Send,
+    ) -> TileSelection<'_> {
+        let map = &*self;
+        let mut cells = map.fold_tiles(
+            Vec::new,
+            |cells, layer, pos, _, _| {
+                if let Some(tile) = map.tile_ref_at(layer, pos) {
+                    if selector(map, layer, pos, &tile) {
+                        cells.push((layer, pos));
+                    }
+                }
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+            |_| {},
+        );
+        // Merged in no particular order.
+        cells.sort_by_key(|(layer, pos)| (*layer, pos.y, pos.x));
+        TileSelection { map: self, cells }
+    }
+}
+
+/// Tiles selected by `Map::for_tiles_matching()`.
+pub struct TileSelection<'map> {
+    map: &'map mut Map,
+    cells: Vec<(usize, IVec2)>,
+}
+
+impl TileSelection<'_> {
+    /// The selected (layer, cell) pairs, by layer, then row.
+    pub fn cells(&self) -> &[(usize, IVec2)] {
+        &self.cells
+    }
+
+    /// Overrides `name` on the selected tiles, see `Map::set_runtime_property()`.
+    pub fn set_runtime_property(&mut self, name: &str, value: PropertyValue) -> &mut Self {
+        for (layer, pos) in &self.cells {
+            self.map
+                .set_runtime_property(*layer, *pos, name, Some(value.clone()));
+        }
+        self
+    }
+
+    /// Removes the override of `name` from the selected tiles.
+    pub fn remove_runtime_property(&mut self, name: &str) -> &mut Self {
+        for (layer, pos) in &self.cells {
+            self.map.set_runtime_property(*layer, *pos, name, None);
+        }
+        self
+    }
+}
+
+/// `own` properties over `defaults`, e.g. a tile's over its tileset's.
+pub fn inherit_properties(defaults: &Properties, own: &Properties) -> Properties {
+    let mut properties = defaults.clone();
+    properties.extend(
+        own.iter()
+            .map(|(name, value)| (name.clone(), value.clone())),
+    );
+    properties
+}
+
+/// Converts a Tiled color into a Macroquad one.
+pub fn to_mq_color(color: tiled::Color) -> Color {
+    Color::from_rgba(color.red, color.green, color.blue, color.alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+    use macroquad::math::ivec2;
+
+    #[cfg(feature = "collision")]
+    #[test]
+    fn test_for_tiles_matching() {
+        use crate::collision::SOLID_PROPERTY;
+
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let mut selection = map.for_tiles_matching(|_, _, _, tile| tile.id == 3);
+        assert_eq!(
+            selection.cells(),
+            &[(ground, ivec2(2, 1)), (ground, ivec2(1, 2))]
+        );
+        selection.set_runtime_property(SOLID_PROPERTY, PropertyValue::BoolValue(true));
+
+        assert_eq!(
+            map.tile_bool_at(ground, ivec2(2, 1), SOLID_PROPERTY),
+            Some(true)
+        );
+        let grid = map.collision_grid();
+        assert!(grid.is_solid(ivec2(1, 2)));
+        assert!(!grid.is_solid(ivec2(1, 1)));
+
+        map.for_tiles_matching(|_, _, _, tile| tile.id == 3)
+            .remove_runtime_property(SOLID_PROPERTY);
+        assert!(map.runtime_properties(ground, ivec2(2, 1)).is_none());
+        assert!(!map.collision_grid().is_solid(ivec2(1, 2)));
+    }
+
+    #[test]
+    fn test_get_tile() {
+        let mut map = tiny_map();
+        let wall = map.get_tile(0, ivec2(0, 0)).unwrap();
+        assert_eq!((wall.id(), wall.tile.tileset), (2, "tiny"));
+        assert_eq!(wall.tileset.tileset.name, "tiny");
+        assert_eq!(wall.class().as_deref(), Some("wall"));
+        assert_eq!(
+            wall.property("material"),
+            Some(PropertyValue::StringValue("stone".to_string()))
+        );
+        assert_eq!(wall.properties().get_string("material"), Some("stone"));
+        assert!(map.get_tile(0, ivec2(9, 9)).is_none());
+
+        let soft = Some(PropertyValue::BoolValue(false));
+        map.set_runtime_property(0, ivec2(0, 0), "solid", soft.clone());
+        let wall = map.get_tile(0, ivec2(0, 0)).unwrap();
+        assert_eq!(wall.property("solid"), soft);
+        assert_eq!(wall.properties().get_bool("solid"), Some(false));
+        assert_eq!(wall.properties().get_string("material"), Some("stone"));
+        assert_eq!(map.tile_property_at(0, ivec2(0, 0), "solid"), soft);
+    }
+}
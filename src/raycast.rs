@@ -0,0 +1,100 @@
+use macroquad::math::{ivec2, vec2, IVec2, Vec2};
+
+/// Where a ray stopped, see `raycast()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// The blocking cell, `None` if the ray ran its whole length.
+    pub cell: Option<IVec2>,
+    /// Where the ray stopped, in world tiles.
+    pub point: Vec2,
+    /// From the origin, in tiles.
+    pub distance: f32,
+}
+
+/// Casts a ray from `origin` along `direction`, both in world tiles (cell (x, y) spans
+/// x..x+1, y..y+1), through every cell it crosses, until `blocks(cell)` or `max_distance`.
+/// `blocks` is called for each crossed cell in order, the starting one included,
+/// which never blocks, so it can also collect the cells the ray sees.
+pub fn raycast(
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+    mut blocks: impl FnMut(IVec2) -> bool,
+) -> RayHit {
+    let mut cell = origin.floor().as_ivec2();
+    blocks(cell);
+
+    let direction = direction.normalize_or_zero();
+    if direction == Vec2::ZERO {
+        return RayHit {
+            cell: None,
+            point: origin,
+            distance: 0.0,
+        };
+    }
+
+    // Amanatides & Woo: distance along the ray to the next vertical and horizontal cell edge.
+    let step = ivec2(direction.x.signum() as i32, direction.y.signum() as i32);
+    let t_delta = vec2(1.0 / direction.x.abs(), 1.0 / direction.y.abs());
+    let edge = |pos: f32, cell: i32, dir: f32| {
+        if dir > 0.0 {
+            (cell as f32 + 1.0 - pos) / dir
+        } else if dir < 0.0 {
+            (pos - cell as f32) / -dir
+        } else {
+            f32::INFINITY
+        }
+    };
+    let mut t_max = vec2(
+        edge(origin.x, cell.x, direction.x),
+        edge(origin.y, cell.y, direction.y),
+    );
+
+    loop {
+        let t = t_max.x.min(t_max.y);
+        if t > max_distance {
+            return RayHit {
+                cell: None,
+                point: origin + direction * max_distance,
+                distance: max_distance,
+            };
+        }
+        if t_max.x < t_max.y {
+            cell.x += step.x;
+            t_max.x += t_delta.x;
+        } else {
+            cell.y += step.y;
+            t_max.y += t_delta.y;
+        }
+        if blocks(cell) {
+            return RayHit {
+                cell: Some(cell),
+                point: origin + direction * t,
+                distance: t,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raycast() {
+        let wall = |cell: IVec2| cell.x == 3;
+        let hit = raycast(vec2(0.5, 0.5), vec2(1.0, 0.0), 10.0, wall);
+        assert_eq!(hit.cell, Some(ivec2(3, 0)));
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+
+        let mut seen = vec![];
+        let hit = raycast(vec2(0.5, 0.5), vec2(1.0, 1.0), 2.0, |cell| {
+            seen.push(cell);
+            false
+        });
+        assert_eq!(hit.cell, None);
+        assert!((hit.point - vec2(0.5 + 2.0_f32.sqrt(), 0.5 + 2.0_f32.sqrt())).length() < 1e-5);
+        assert_eq!(seen[0], ivec2(0, 0));
+        assert_eq!(*seen.last().unwrap(), ivec2(1, 1));
+    }
+}
@@ -0,0 +1,99 @@
+use macroquad::color::Color;
+use macroquad::math::{ivec2, IVec2, Rect};
+
+use crate::map::Map;
+
+/// Bool tile property, or tileset property for all of its tiles, marking the tiles
+/// which show reflections, e.g. water, see `Map::set_layer_reflection()`.
+pub const REFLECTIVE_PROPERTY: &str = "reflective";
+
+/// What the reflective tiles of a layer show, see `Map::set_layer_reflection()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reflection {
+    /// Layers drawn upside down into the reflective tiles, e.g. the walls and the trees.
+    pub layers: Vec<usize>,
+    /// Attenuates the reflection, e.g. a translucent blue.
+    pub tint: Color,
+}
+
+/// The world rect reflected into `cell`, mirrored about the horizontal line at `axis`,
+/// all in world pixels.
+pub fn mirror_rect(cell: Rect, axis: f32) -> Rect {
+    Rect::new(cell.x, 2.0 * axis - cell.bottom(), cell.w, cell.h)
+}
+
+impl Map {
+    /// If the tile at `pos` of `layer` is reflective, see `REFLECTIVE_PROPERTY`
+    /// and `tile_property_at()`.
+    pub fn is_reflective(&self, layer: usize, pos: IVec2) -> bool {
+        self.tile_bool_at(layer, pos, REFLECTIVE_PROPERTY)
+            .unwrap_or(false)
+    }
+
+    /// The reflective cells of `layer` from `min` to `max`, in tiles, with the row
+    /// they mirror about: the top of their column of reflective cells.
+    pub(crate) fn reflective_cells(
+        &self,
+        layer: usize,
+        min: IVec2,
+        max: IVec2,
+    ) -> Vec<(IVec2, i32)> {
+        let mut cells = vec![];
+        for x in min.x..=max.x {
+            let mut top = None;
+            for y in min.y..=max.y {
+                if !self.is_reflective(layer, ivec2(x, y)) {
+                    top = None;
+                    continue;
+                }
+                let axis = *top.get_or_insert_with(|| {
+                    let mut row = y;
+                    while self.is_reflective(layer, ivec2(x, row - 1)) {
+                        row -= 1;
+                    }
+                    row
+                });
+                cells.push((ivec2(x, y), axis));
+            }
+        }
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+
+    #[test]
+    fn test_reflective_cells() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        assert!(!map.is_reflective(ground, ivec2(1, 1)));
+
+        // The whole tileset is water, but for a gap at (1, 1).
+        let tileset = map.tilesets.get_mut("tiny").unwrap();
+        tileset.tileset.properties.insert(
+            REFLECTIVE_PROPERTY.to_string(),
+            tiled::PropertyValue::BoolValue(true),
+        );
+        map.set_tile(ground, ivec2(1, 1), None);
+        let cells = map.reflective_cells(ground, ivec2(0, 1), ivec2(1, 3));
+        assert_eq!(
+            cells,
+            vec![
+                (ivec2(0, 1), 0),
+                (ivec2(0, 2), 0),
+                (ivec2(0, 3), 0),
+                (ivec2(1, 2), 2),
+                (ivec2(1, 3), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mirror_rect() {
+        let cell = Rect::new(16., 48., 16., 16.);
+        assert_eq!(mirror_rect(cell, 32.), Rect::new(16., 0., 16., 16.));
+    }
+}
@@ -0,0 +1,54 @@
+use macroquad::math::{vec2, Rect, Vec2};
+use macroquad::miniquad::window::dpi_scale;
+use macroquad::window::{screen_height, screen_width};
+
+/// The largest integer zoom at which `virtual_size` fits into `screen_size`, at least 1.
+/// E.g. a 640x360 game gets zoom 3 on a 1920x1080 screen, and 2 on 1600x900.
+pub fn integer_zoom(virtual_size: Vec2, screen_size: Vec2) -> f32 {
+    let fit = screen_size / virtual_size;
+    fit.x.min(fit.y).floor().max(1.0)
+}
+
+/// The rect of `virtual_size * zoom`, centered in `screen_size`, with black bars
+/// (letterboxing or pillarboxing) left around it. The corner is on a whole pixel.
+/// Use it as `dest` for `Map::draw_tiles()`.
+pub fn letterbox(virtual_size: Vec2, screen_size: Vec2, zoom: f32) -> Rect {
+    let size = virtual_size * zoom;
+    let corner = ((screen_size - size) / 2.0).floor();
+    Rect::new(corner.x, corner.y, size.x, size.y)
+}
+
+/// Zoom and letterboxed `dest` for `virtual_size` on the current screen.
+/// The zoom is integer in physical pixels, so it stays crisp on high-DPI screens too,
+/// while the returned values are in Macroquad's logical pixels.
+pub fn fit_screen(virtual_size: Vec2) -> (f32, Rect) {
+    let dpi = dpi_scale();
+    let physical_screen = vec2(screen_width(), screen_height()) * dpi;
+    let zoom = integer_zoom(virtual_size, physical_screen) / dpi;
+    let dest = letterbox(virtual_size, physical_screen / dpi, zoom);
+    (zoom, dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_zoom() {
+        let virtual_size = vec2(640., 360.);
+        assert_eq!(3.0, integer_zoom(virtual_size, vec2(1920., 1080.)));
+        assert_eq!(2.0, integer_zoom(virtual_size, vec2(1600., 900.)));
+        // Limited by height.
+        assert_eq!(2.0, integer_zoom(virtual_size, vec2(2560., 1000.)));
+        assert_eq!(1.0, integer_zoom(virtual_size, vec2(320., 200.)));
+    }
+
+    #[test]
+    fn test_letterbox() {
+        let dest = letterbox(vec2(640., 360.), vec2(1600., 900.), 2.0);
+        assert_eq!(Rect::new(160., 90., 1280., 720.), dest);
+
+        let dest = letterbox(vec2(640., 360.), vec2(1601., 901.), 2.0);
+        assert_eq!(Rect::new(160., 90., 1280., 720.), dest);
+    }
+}
@@ -0,0 +1,142 @@
+use std::ops::Range;
+
+use macroquad::math::IVec2;
+
+use crate::variety::hash_pos;
+
+/// Seedable, serializable random numbers for what depends on a map: the variants of
+/// "auto_variety" layers, idle animations, procedural generation, see `Map::rng()`.
+/// Saving it, e.g. with serde behind the "serde" feature, and restoring it with
+/// `Map::set_rng()` reproduces the same visuals and the same draws, e.g. for replays.
+///
+/// Draws, `next_u32()` and such, advance the state. Cell hashes, `cell_hash()`,
+/// only depend on the seed: they are the same whatever was drawn before.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapRng {
+    seed: u64,
+    state: u64,
+}
+
+impl Default for MapRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl MapRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// SplitMix64.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix64(self.state)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// In [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// In `range`, `range.start` if it's empty.
+    pub fn range(&mut self, range: Range<i32>) -> i32 {
+        let span = range.end.saturating_sub(range.start).max(0) as u64;
+        if span == 0 {
+            return range.start;
+        }
+        range.start + (self.next_u64() % span) as i32
+    }
+
+    /// True with probability `p`, 0 to 1.
+    pub fn chance(&mut self, p: f32) -> bool {
+        self.next_f32() < p
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.range(0..items.len() as i32) as usize)
+    }
+
+    /// Another generator, seeded from this one's seed and `salt`, e.g. one per system
+    /// or per map of a `World`, so that their draws don't depend on each other's.
+    pub fn derive(&self, salt: u64) -> Self {
+        Self::new(mix64(self.seed ^ mix64(salt.wrapping_add(1))))
+    }
+
+    /// A hash of `pos` under the seed, random but always the same for the cell,
+    /// e.g. to pick a variant. `salt` tells apart the picks of the same cell.
+    /// All the 64 bits of the seed count: distinct seeds give distinct hashes.
+    pub fn cell_hash(&self, pos: IVec2, salt: u32) -> u32 {
+        // The unseeded hash, for the picks made before seeds.
+        if self.seed == 0 && salt == 0 {
+            return hash_pos(pos);
+        }
+        let key = mix64(self.seed ^ mix64(salt as u64 + 1));
+        (mix64(key ^ hash_pos(pos) as u64) >> 32) as u32
+    }
+}
+
+fn mix64(x: u64) -> u64 {
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_map_rng() {
+        let mut rng = MapRng::new(42);
+        let draws: Vec<_> = (0..8).map(|_| rng.range(0..10)).collect();
+        assert!(draws.iter().all(|draw| (0..10).contains(draw)));
+
+        // Restored, it draws the same.
+        let mut restored = MapRng::new(42);
+        let again: Vec<_> = (0..8).map(|_| restored.range(0..10)).collect();
+        assert_eq!(draws, again);
+        assert_eq!(rng, restored);
+
+        // Cell hashes don't depend on the draws.
+        assert_eq!(
+            rng.cell_hash(ivec2(3, 4), 0),
+            MapRng::new(42).cell_hash(ivec2(3, 4), 0)
+        );
+        assert_ne!(rng.cell_hash(ivec2(3, 4), 0), rng.cell_hash(ivec2(3, 4), 1));
+        assert_eq!(
+            MapRng::default().cell_hash(ivec2(3, 4), 0),
+            hash_pos(ivec2(3, 4))
+        );
+        assert_ne!(rng.derive(0).seed(), rng.derive(1).seed());
+
+        // Seeds differing only in their high bits, or folding to the same 32 bits,
+        // don't collide.
+        let pos = ivec2(3, 4);
+        assert_ne!(
+            MapRng::new(1).cell_hash(pos, 0),
+            MapRng::new(1 << 32).cell_hash(pos, 0)
+        );
+        assert_ne!(
+            MapRng::new(0).cell_hash(pos, 0),
+            MapRng::new(0x0000_0001_0000_0001).cell_hash(pos, 0)
+        );
+        // Only the default generator falls back to the unseeded hash.
+        assert_ne!(MapRng::new(1).cell_hash(pos, u32::MAX), hash_pos(pos));
+        assert_eq!(rng.range(5..5), 5);
+    }
+}
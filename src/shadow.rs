@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+
+use macroquad::color::{Color, WHITE};
+use macroquad::math::{ivec2, IVec2, Rect, Vec2};
+use macroquad::texture::{draw_texture_ex, DrawTextureParams, FilterMode, Image, Texture2D};
+
+use crate::map::{world_px_to_screen, Map, TileRef, CHUNK_SIZE};
+use crate::properties::PropertiesExt;
+use crate::tileset::dominant_wang_color;
+
+/// Class of the tiles casting a drop shadow, see `ShadowLayer`. Wang colors cast one
+/// with a true bool property of the same name, e.g. a "wall" color.
+pub const SHADOW_CLASS: &str = "casts_shadow";
+
+/// Texels of the shadow textures per tile side: shadows are baked coarse,
+/// and smoothed by linear filtering when drawn.
+pub const SHADOW_TEXELS_PER_TILE: i32 = 8;
+
+/// If `tile` casts a shadow: of class `SHADOW_CLASS`, or of a Wang color which does.
+pub fn casts_shadow(map: &Map, tile: &TileRef) -> bool {
+    if map
+        .tile_data(tile.tileset, tile.id)
+        .is_some_and(|data| data.user_type.as_deref() == Some(SHADOW_CLASS))
+    {
+        return true;
+    }
+    let Some(tileset) = map.tilesets.get(tile.tileset) else {
+        return false;
+    };
+    tileset.tileset.wang_sets.iter().any(|wang_set| {
+        wang_set
+            .wang_tiles
+            .get(&tile.id)
+            .and_then(|wang_tile| dominant_wang_color(wang_tile.wang_id.0))
+            .and_then(|color| wang_set.wang_colors.get(color as usize - 1))
+            .is_some_and(|color| color.properties.get_bool(SHADOW_CLASS) == Some(true))
+    })
+}
+
+/// Soft drop shadows of the walls of a layer, see `casts_shadow()`: their silhouettes,
+/// offset and blurred, baked into a texture per chunk when first drawn. Draw it between
+/// the floor and the walls. Orthogonal maps only.
+pub struct ShadowLayer {
+    layer: usize,
+    offset: Vec2,
+    softness: f32,
+    color: Color,
+    /// `None` for chunks without shadows.
+    textures: HashMap<IVec2, Option<Texture2D>>,
+}
+
+impl ShadowLayer {
+    /// Shadows of the walls of `layer`, falling `offset` away from them, in world pixels,
+    /// e.g. (4, 6) for light from the top left, their edges blurred over `softness` pixels.
+    pub fn new(layer: usize, offset: Vec2, softness: f32, color: Color) -> Self {
+        Self {
+            layer,
+            offset,
+            softness: softness.max(0.0),
+            color,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Drops the shadows of the edited chunks and of those their walls shadow,
+    /// e.g. of `Map::take_dirty_chunks()`, to bake them again.
+    pub fn invalidate(&mut self, map: &Map, dirty: &HashSet<(usize, IVec2)>) {
+        let reach = (self.reach(map) + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        for (_, chunk) in dirty.iter().filter(|(layer, _)| *layer == self.layer) {
+            for y in -reach..=reach {
+                for x in -reach..=reach {
+                    self.textures.remove(&(*chunk + ivec2(x, y)));
+                }
+            }
+        }
+    }
+
+    /// Drops all the shadows.
+    pub fn clear(&mut self) {
+        self.textures.clear();
+    }
+
+    /// How far walls shadow, in tiles.
+    fn reach(&self, map: &Map) -> i32 {
+        let reach = (self.offset.abs() + Vec2::splat(self.softness)) / map.tile_size_px();
+        reach.ceil().max_element() as i32
+    }
+
+    /// The shadows over `chunk`, `CHUNK_SIZE * SHADOW_TEXELS_PER_TILE` texels wide,
+    /// top-down, or `None` if there are none.
+    pub fn bake_chunk(&self, map: &Map, chunk: IVec2) -> Option<Image> {
+        let texels = SHADOW_TEXELS_PER_TILE;
+        let tile_size = map.tile_size_px();
+        let offset = (self.offset / tile_size * texels as f32).round().as_ivec2();
+        let blur = (Vec2::splat(self.softness) / tile_size * texels as f32)
+            .round()
+            .as_ivec2();
+
+        // The chunk and the walls around it which can shadow it.
+        let reach = self.reach(map);
+        let margin = reach * texels;
+        let size = (CHUNK_SIZE + reach * 2) * texels;
+        let mut mask = vec![0.0; (size * size) as usize];
+        let min = chunk * CHUNK_SIZE - IVec2::splat(reach);
+        let mut any = false;
+        for y in 0..CHUNK_SIZE + reach * 2 {
+            for x in 0..CHUNK_SIZE + reach * 2 {
+                let wall = map
+                    .tile_ref_at(self.layer, min + ivec2(x, y))
+                    .is_some_and(|tile| casts_shadow(map, &tile));
+                if !wall {
+                    continue;
+                }
+                any = true;
+                let from = ivec2(x, y) * texels + offset;
+                for ty in from.y.max(0)..(from.y + texels).min(size) {
+                    for tx in from.x.max(0)..(from.x + texels).min(size) {
+                        mask[(ty * size + tx) as usize] = 1.0;
+                    }
+                }
+            }
+        }
+        if !any {
+            return None;
+        }
+        box_blur(&mut mask, size as usize, blur.x as usize, true);
+        box_blur(&mut mask, size as usize, blur.y as usize, false);
+
+        let side = CHUNK_SIZE * texels;
+        let mut image =
+            Image::gen_image_color(side as u16, side as u16, Color::new(0., 0., 0., 0.));
+        let mut any = false;
+        for y in 0..side {
+            for x in 0..side {
+                let alpha = mask[((y + margin) * size + x + margin) as usize] * self.color.a;
+                if alpha > 0.0 {
+                    any = true;
+                    image.set_pixel(
+                        x as u32,
+                        y as u32,
+                        Color {
+                            a: alpha,
+                            ..self.color
+                        },
+                    );
+                }
+            }
+        }
+        any.then_some(image)
+    }
+
+    /// Draws the shadows in `source`, in world pixels, to `dest` on the screen,
+    /// baking the missing chunks. Needs a macroquad window.
+    pub fn draw(&mut self, map: &Map, source: Rect, dest: Rect) {
+        let chunk_px = map.tile_size_px() * CHUNK_SIZE as f32;
+        let min = (source.point() / chunk_px).floor().as_ivec2();
+        let max = ((source.point() + source.size()) / chunk_px)
+            .ceil()
+            .as_ivec2();
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let chunk = ivec2(x, y);
+                if !map.contains(chunk * CHUNK_SIZE) {
+                    continue;
+                }
+                if !self.textures.contains_key(&chunk) {
+                    let texture = self.bake_chunk(map, chunk).map(|image| {
+                        let texture = Texture2D::from_image(&image);
+                        texture.set_filter(FilterMode::Linear);
+                        texture
+                    });
+                    self.textures.insert(chunk, texture);
+                }
+                let Some(texture) = &self.textures[&chunk] else {
+                    continue;
+                };
+                let at = world_px_to_screen(chunk.as_vec2() * chunk_px, source, dest);
+                let params = DrawTextureParams {
+                    dest_size: Some(chunk_px / source.size() * dest.size()),
+                    ..Default::default()
+                };
+                draw_texture_ex(texture, at.x, at.y, WHITE, params);
+            }
+        }
+    }
+}
+
+/// Averages each value of the `size` by `size` `mask` with the `radius` values
+/// on each side of it along an axis, values past the edges being 0.
+fn box_blur(mask: &mut [f32], size: usize, radius: usize, horizontal: bool) {
+    if radius == 0 {
+        return;
+    }
+    let index = |line: usize, i: usize| {
+        if horizontal {
+            line * size + i
+        } else {
+            i * size + line
+        }
+    };
+    let mut sums = vec![0.0; size + 1];
+    for line in 0..size {
+        for i in 0..size {
+            sums[i + 1] = sums[i] + mask[index(line, i)];
+        }
+        for i in 0..size {
+            let (from, to) = (i.saturating_sub(radius), (i + radius + 1).min(size));
+            mask[index(line, i)] = (sums[to] - sums[from]) / (radius * 2 + 1) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tiny_map;
+    use macroquad::color::BLACK;
+    use macroquad::math::vec2;
+
+    #[test]
+    fn test_bake_chunk() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        // Tile 3, at (2, 1) and (1, 2), casts shadows. 2 px per texel.
+        let sharp = ShadowLayer::new(ground, vec2(4., 4.), 0., BLACK);
+        let image = sharp.bake_chunk(&map, ivec2(0, 0)).unwrap();
+        assert_eq!(image.width as i32, CHUNK_SIZE * SHADOW_TEXELS_PER_TILE);
+        assert_eq!(image.get_pixel(20, 12).a, 1.0);
+        assert_eq!(image.get_pixel(25, 17).a, 1.0);
+        assert_eq!(image.get_pixel(17, 12).a, 0.0);
+        assert_eq!(image.get_pixel(1, 1).a, 0.0);
+        assert!(sharp.bake_chunk(&map, ivec2(1, 0)).is_none());
+
+        let soft = ShadowLayer::new(ground, vec2(4., 4.), 4., BLACK);
+        let edge = soft
+            .bake_chunk(&map, ivec2(0, 0))
+            .unwrap()
+            .get_pixel(18, 12)
+            .a;
+        assert!(edge > 0.0 && edge < 1.0);
+
+        map.set_tile(ground, ivec2(2, 1), None);
+        map.set_tile(ground, ivec2(1, 2), None);
+        assert!(sharp.bake_chunk(&map, ivec2(0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_box_blur() {
+        let mut mask = vec![0.0; 25];
+        mask[12] = 9.0;
+        box_blur(&mut mask, 5, 1, true);
+        box_blur(&mut mask, 5, 1, false);
+        assert_eq!(mask[6], 1.0);
+        assert_eq!(mask[18], 1.0);
+        assert_eq!(mask[0], 0.0);
+        assert_eq!(mask.iter().sum::<f32>(), 9.0);
+    }
+}
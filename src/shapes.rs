@@ -0,0 +1,189 @@
+use macroquad::math::{ivec2, IVec2};
+
+#[cfg(feature = "editor")]
+use macroquad::math::Rect;
+
+#[cfg(feature = "editor")]
+use crate::editor::TileEdit;
+#[cfg(feature = "editor")]
+use crate::map::{Map, TileHandle};
+
+/// Map-edit helpers for placing roads, walls, rivers, etc. programmatically.
+/// They return the changed cells, pass them to `EditJournal::record()` to make them undoable.
+#[cfg(feature = "editor")]
+impl Map {
+    /// Places `tile` on a Bresenham line from `a` to `b`, both ends included.
+    pub fn draw_tile_line(
+        &mut self,
+        layer: usize,
+        a: IVec2,
+        b: IVec2,
+        tile: Option<TileHandle>,
+    ) -> Vec<TileEdit> {
+        self.set_tiles(layer, line_cells(a, b), tile)
+    }
+
+    /// Places `tile` on `rect`, in world tiles, or on its outline if not `filled`.
+    pub fn draw_tile_rect(
+        &mut self,
+        layer: usize,
+        rect: Rect,
+        tile: Option<TileHandle>,
+        filled: bool,
+    ) -> Vec<TileEdit> {
+        let min = ivec2(rect.x.floor() as i32, rect.y.floor() as i32);
+        let max = ivec2(rect.right().ceil() as i32, rect.bottom().ceil() as i32) - IVec2::ONE;
+        self.set_tiles(layer, rect_cells(min, max, filled), tile)
+    }
+
+    /// Places `tile` on an ellipse around `center` with `radius` in tiles,
+    /// or on its outline if not `filled`. A zero radius gives a line or a single tile.
+    pub fn draw_tile_ellipse(
+        &mut self,
+        layer: usize,
+        center: IVec2,
+        radius: IVec2,
+        tile: Option<TileHandle>,
+        filled: bool,
+    ) -> Vec<TileEdit> {
+        self.set_tiles(layer, ellipse_cells(center, radius, filled), tile)
+    }
+
+    fn set_tiles(
+        &mut self,
+        layer: usize,
+        cells: Vec<IVec2>,
+        tile: Option<TileHandle>,
+    ) -> Vec<TileEdit> {
+        cells
+            .into_iter()
+            .filter_map(|pos| self.edit_tile(layer, pos, tile.clone()))
+            .collect()
+    }
+}
+
+/// Cells of a Bresenham line from `a` to `b`, both ends included.
+pub fn line_cells(a: IVec2, b: IVec2) -> Vec<IVec2> {
+    let delta = (b - a).abs();
+    let step = (b - a).signum();
+    let mut error = delta.x - delta.y;
+    let mut pos = a;
+    let mut cells = vec![pos];
+    while pos != b {
+        let error2 = error * 2;
+        if error2 > -delta.y {
+            error -= delta.y;
+            pos.x += step.x;
+        }
+        if error2 < delta.x {
+            error += delta.x;
+            pos.y += step.y;
+        }
+        cells.push(pos);
+    }
+    cells
+}
+
+/// Cells of the rect from `min` to `max`, both included, or of its outline.
+pub fn rect_cells(min: IVec2, max: IVec2, filled: bool) -> Vec<IVec2> {
+    let mut cells = vec![];
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            if filled || x == min.x || x == max.x || y == min.y || y == max.y {
+                cells.push(ivec2(x, y));
+            }
+        }
+    }
+    cells
+}
+
+/// Cells of the ellipse around `center`, or of its 4-connected outline.
+pub fn ellipse_cells(center: IVec2, radius: IVec2, filled: bool) -> Vec<IVec2> {
+    let radius = radius.abs();
+    // Half a tile more, so the extreme cells are round and zero radii work.
+    let (rx, ry) = (radius.x as f32 + 0.5, radius.y as f32 + 0.5);
+    let inside = |offset: IVec2| {
+        let (x, y) = (offset.x as f32 / rx, offset.y as f32 / ry);
+        x * x + y * y <= 1.0
+    };
+
+    let mut cells = vec![];
+    for y in -radius.y..=radius.y {
+        for x in -radius.x..=radius.x {
+            let offset = ivec2(x, y);
+            if !inside(offset) {
+                continue;
+            }
+            let edge = [IVec2::X, -IVec2::X, IVec2::Y, -IVec2::Y]
+                .iter()
+                .any(|side| !inside(offset + *side));
+            if filled || edge {
+                cells.push(center + offset);
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_cells() {
+        assert_eq!(line_cells(ivec2(1, 1), ivec2(1, 1)), vec![ivec2(1, 1)]);
+        assert_eq!(
+            line_cells(ivec2(0, 0), ivec2(4, 2)),
+            vec![
+                ivec2(0, 0),
+                ivec2(1, 0),
+                ivec2(2, 1),
+                ivec2(3, 1),
+                ivec2(4, 2)
+            ]
+        );
+        // Same cells backwards.
+        let mut back = line_cells(ivec2(4, 2), ivec2(0, 0));
+        back.reverse();
+        assert_eq!(back.len(), 5);
+        assert_eq!(back[0], ivec2(0, 0));
+    }
+
+    #[test]
+    fn test_shape_cells() {
+        assert_eq!(rect_cells(ivec2(0, 0), ivec2(2, 2), true).len(), 9);
+        assert_eq!(rect_cells(ivec2(0, 0), ivec2(2, 2), false).len(), 8);
+
+        assert_eq!(
+            ellipse_cells(ivec2(5, 5), ivec2(0, 0), false),
+            vec![ivec2(5, 5)]
+        );
+        let filled = ellipse_cells(ivec2(0, 0), ivec2(3, 2), true);
+        let outline = ellipse_cells(ivec2(0, 0), ivec2(3, 2), false);
+        assert!(outline.len() < filled.len());
+        assert!(outline.iter().all(|cell| filled.contains(cell)));
+        for cell in [ivec2(3, 0), ivec2(-3, 0), ivec2(0, 2), ivec2(0, -2)] {
+            assert!(outline.contains(&cell));
+        }
+    }
+
+    #[cfg(feature = "editor")]
+    #[test]
+    fn test_draw_tile_line() {
+        let mut map = crate::testing::tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let wall = Some(TileHandle::new("tiny", 2));
+        // Only the changed cells in the map.
+        let edits = map.draw_tile_line(ground, ivec2(0, 1), ivec2(5, 1), wall.clone());
+        let cells: Vec<_> = edits.iter().map(|edit| edit.pos).collect();
+        assert_eq!(cells, [ivec2(1, 1), ivec2(2, 1)]);
+        assert_eq!(edits[1].before, Some(TileHandle::new("tiny", 3)));
+        assert_eq!(map.tile_at(ground, ivec2(2, 1)), wall);
+
+        // Layers without tiles are left as they are.
+        let objects = map.layer_by_name("objects").unwrap();
+        assert!(map
+            .draw_tile_rect(objects, Rect::new(0., 0., 4., 4.), wall, true)
+            .is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+use macroquad::color::Color;
+use macroquad::material::{gl_use_default_material, gl_use_material, load_material, Material};
+use macroquad::math::Rect;
+use macroquad::miniquad::{
+    BlendFactor, BlendState, BlendValue, Equation, PipelineParams, ShaderSource,
+};
+use macroquad::prelude::MaterialParams;
+use macroquad::texture::{draw_texture_ex, DrawTextureParams, Texture2D};
+
+use crate::map::DrawnTile;
+
+const VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}"#;
+
+/// The shape of the texture, in the color of the vertices.
+const FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = vec4(color.rgb, color.a * texture2D(Texture, uv).a);
+}"#;
+
+/// Solid color silhouettes of the sprites hidden by the tiles of the rows in front of them,
+/// e.g. the player behind a wall or under a roof, so that they are never lost.
+/// Push the sprites drawn in `Map::draw_tiles_rows()`, then draw the silhouettes with
+/// the tiles it returns: only the hidden parts of the sprites are drawn, over the tiles.
+pub struct Silhouettes {
+    pub color: Color,
+    material: Material,
+    sprites: Vec<Sprite>,
+}
+
+struct Sprite {
+    row: f32,
+    texture: Texture2D,
+    dest: Rect,
+    source: Option<Rect>,
+    flip_x: bool,
+}
+
+impl Silhouettes {
+    /// Silhouettes of `color`, e.g. a translucent one. Needs a macroquad window.
+    ///
+    /// Panics: if the shader doesn't compile.
+    pub fn new(color: Color) -> Self {
+        let pipeline_params = PipelineParams {
+            color_blend: Some(BlendState::new(
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            ..Default::default()
+        };
+        let material = load_material(
+            ShaderSource::Glsl {
+                vertex: VERTEX,
+                fragment: FRAGMENT,
+            },
+            MaterialParams {
+                pipeline_params,
+                ..Default::default()
+            },
+        )
+        .unwrap_or_else(|err| panic!("Silhouette shader: {}", err));
+        Self {
+            color,
+            material,
+            sprites: vec![],
+        }
+    }
+
+    /// Adds a sprite drawn by `after_row(row)` from `source`, the whole texture if `None`,
+    /// to `dest` in screen pixels.
+    pub fn push(
+        &mut self,
+        row: f32,
+        texture: &Texture2D,
+        dest: Rect,
+        source: Option<Rect>,
+        flip_x: bool,
+    ) {
+        self.sprites.push(Sprite {
+            row,
+            texture: texture.clone(),
+            dest,
+            source,
+            flip_x,
+        });
+    }
+
+    /// Draws the hidden parts of the pushed sprites, and forgets them.
+    pub fn draw(&mut self, tiles: &[DrawnTile]) {
+        gl_use_material(&self.material);
+        for sprite in self.sprites.drain(..) {
+            let source = sprite.source.unwrap_or_else(|| {
+                Rect::new(0., 0., sprite.texture.width(), sprite.texture.height())
+            });
+            for part in hidden_parts(sprite.row, sprite.dest, tiles) {
+                // The same part of the source, mirrored if the sprite is.
+                let mut x = (part.x - sprite.dest.x) / sprite.dest.w;
+                let w = part.w / sprite.dest.w;
+                if sprite.flip_x {
+                    x = 1.0 - x - w;
+                }
+                let y = (part.y - sprite.dest.y) / sprite.dest.h;
+                let h = part.h / sprite.dest.h;
+                let params = DrawTextureParams {
+                    dest_size: Some(part.size()),
+                    source: Some(Rect::new(
+                        source.x + x * source.w,
+                        source.y + y * source.h,
+                        w * source.w,
+                        h * source.h,
+                    )),
+                    flip_x: sprite.flip_x,
+                    ..Default::default()
+                };
+                draw_texture_ex(&sprite.texture, part.x, part.y, self.color, params);
+            }
+        }
+        gl_use_default_material();
+    }
+}
+
+/// The parts of `dest`, a sprite drawn after `row`, covered by the tiles of later rows.
+fn hidden_parts(row: f32, dest: Rect, tiles: &[DrawnTile]) -> Vec<Rect> {
+    tiles
+        .iter()
+        .filter(|tile| tile.row > row)
+        .filter_map(|tile| tile.dest.intersect(dest))
+        .filter(|part| part.w > 0.0 && part.h > 0.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden_parts() {
+        let tiles = [
+            // Behind the sprite.
+            DrawnTile {
+                row: 16.,
+                dest: Rect::new(0., 0., 16., 16.),
+            },
+            // In front of it, hiding its feet.
+            DrawnTile {
+                row: 48.,
+                dest: Rect::new(0., 24., 16., 16.),
+            },
+        ];
+        let sprite = Rect::new(4., 4., 8., 24.);
+        assert_eq!(
+            hidden_parts(32., sprite, &tiles),
+            vec![Rect::new(4., 24., 8., 4.)]
+        );
+        assert!(hidden_parts(48., sprite, &tiles).is_empty());
+    }
+}
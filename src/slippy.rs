@@ -0,0 +1,257 @@
+use std::io;
+use std::path::Path;
+
+use macroquad::camera::{pop_camera_state, push_camera_state, set_camera, Camera2D};
+use macroquad::color::Color;
+use macroquad::math::{Rect, Vec2};
+use macroquad::texture::{render_target, FilterMode, Image, RenderTarget};
+use macroquad::window::{clear_background, get_internal_gl, next_frame};
+
+use crate::map::Map;
+use crate::texture_stream::generate_placeholder;
+
+/// Width and height of the exported tiles, in pixels, like web maps.
+pub const SLIPPY_TILE_SIZE: u32 = 256;
+
+/// The deepest zoom level of the pyramid of a map of `size_px`, see `Map::export_slippy()`.
+/// At zoom 0, a single tile shows the whole map; at the max zoom, a pixel is a world pixel.
+pub fn slippy_max_zoom(size_px: Vec2) -> u8 {
+    let tiles = (size_px.max_element() / SLIPPY_TILE_SIZE as f32).max(1.0);
+    tiles.log2().ceil() as u8
+}
+
+/// The world pixels shown by the tile `x`, `y` at `zoom`.
+pub fn slippy_tile_rect(max_zoom: u8, zoom: u8, x: i32, y: i32) -> Rect {
+    let span = tile_span(max_zoom, zoom);
+    Rect::new(x as f32 * span, y as f32 * span, span, span)
+}
+
+/// The tile showing `world_px` at `zoom`.
+pub fn slippy_tile_at(max_zoom: u8, zoom: u8, world_px: Vec2) -> (i32, i32) {
+    let tile = (world_px / tile_span(max_zoom, zoom)).floor();
+    (tile.x as i32, tile.y as i32)
+}
+
+/// World pixels per side of a tile at `zoom`.
+fn tile_span(max_zoom: u8, zoom: u8) -> f32 {
+    SLIPPY_TILE_SIZE as f32 * (1u64 << max_zoom.saturating_sub(zoom)) as f32
+}
+
+impl Map {
+    /// Renders the visible layers into a pyramid of `SLIPPY_TILE_SIZE` PNG tiles,
+    /// `dir/{z}/{x}/{y}.png`, for browser map viewers during development, e.g. Leaflet
+    /// with `CRS.Simple`, where `map.unproject([x, y], maxZoom)` is the world pixel x, y.
+    /// Tiles past the map are left out. `progress(done)` goes from 0 to 1.
+    /// Returns the max zoom, see `slippy_max_zoom()`. Needs a macroquad window.
+    ///
+    /// Errors: on infinite maps, and if the directories can't be created.
+    ///
+    /// Panics: if a PNG can't be written.
+    pub fn export_slippy(&self, dir: &Path, mut progress: impl FnMut(f32)) -> io::Result<u8> {
+        if self.map.infinite() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Infinite maps have no size to export",
+            ));
+        }
+        let mut export = SlippyExport {
+            map: self,
+            dir,
+            max_zoom: slippy_max_zoom(self.size_px()),
+            target: render_target(SLIPPY_TILE_SIZE, SLIPPY_TILE_SIZE),
+            done: 0,
+            total: 0,
+        };
+        export.target.texture.set_filter(FilterMode::Nearest);
+        let tiles = (self.size_px() / SLIPPY_TILE_SIZE as f32).ceil();
+        export.total = (tiles.x * tiles.y) as usize;
+
+        export.tile(0, 0, 0, &mut progress)?;
+        Ok(export.max_zoom)
+    }
+
+    /// Renders `layers`, in this order, inside `region`, in world pixels, into an image
+    /// `scale` times its size, e.g. for screenshots of the overworld or thumbnails of save
+    /// slots. Hidden layers are drawn too; pass `visible_layers()` for what the player sees.
+    /// Needs a macroquad window.
+    pub fn render_to_image(&self, layers: &[usize], region: Rect, scale: f32) -> Image {
+        let size = (region.size() * scale).ceil().max(Vec2::ONE);
+        let target = render_target(size.x as u32, size.y as u32);
+        target.texture.set_filter(FilterMode::Nearest);
+        self.render_layers(&target, layers.iter().copied(), region)
+    }
+
+    /// Same as `render_to_image()`, as a coroutine drawing `layers_per_frame` layers
+    /// then waiting for the next frame, e.g. for the minimap of a large map, a layer a frame:
+    /// `start_coroutine(async move { map.render_to_image_async(&layers, r, 0.25, 1).await })`.
+    pub async fn render_to_image_async(
+        &self,
+        layers: &[usize],
+        region: Rect,
+        scale: f32,
+        layers_per_frame: usize,
+    ) -> Image {
+        let size = (region.size() * scale).ceil().max(Vec2::ONE);
+        let target = render_target(size.x as u32, size.y as u32);
+        target.texture.set_filter(FilterMode::Nearest);
+        self.draw_layers(&target, [].into_iter(), region, true);
+        for batch in layers.chunks(layers_per_frame.max(1)) {
+            self.draw_layers(&target, batch.iter().copied(), region, false);
+            next_frame().await;
+        }
+        read_target(&target)
+    }
+
+    /// `layers` inside `rect`, in world pixels, stretched over `target`, as a top-down image.
+    fn render_layers(
+        &self,
+        target: &RenderTarget,
+        layers: impl Iterator<Item = usize>,
+        rect: Rect,
+    ) -> Image {
+        self.draw_layers(target, layers, rect, true);
+        read_target(target)
+    }
+
+    /// Draws `layers` inside `rect` over `target`, cleared first if `clear`.
+    fn draw_layers(
+        &self,
+        target: &RenderTarget,
+        layers: impl Iterator<Item = usize>,
+        rect: Rect,
+        clear: bool,
+    ) {
+        let mut camera = Camera2D::from_display_rect(rect);
+        camera.render_target = Some(target.clone());
+        push_camera_state();
+        set_camera(&camera);
+        if clear {
+            clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+        }
+        for layer in layers {
+            self.draw_tiles(layer, rect, rect);
+        }
+        pop_camera_state();
+    }
+}
+
+/// What was drawn over `target`, as a top-down image.
+fn read_target(target: &RenderTarget) -> Image {
+    // Draw before reading.
+    unsafe { get_internal_gl() }.flush();
+    // Render targets are read bottom up.
+    flip_rows(&target.texture.get_texture_data())
+}
+
+struct SlippyExport<'a> {
+    map: &'a Map,
+    dir: &'a Path,
+    max_zoom: u8,
+    target: RenderTarget,
+    /// Tiles of the max zoom.
+    done: usize,
+    total: usize,
+}
+
+impl SlippyExport<'_> {
+    /// Depth first, so that only a branch of the pyramid is in memory.
+    /// `None` past the map.
+    fn tile(
+        &mut self,
+        zoom: u8,
+        x: i32,
+        y: i32,
+        progress: &mut impl FnMut(f32),
+    ) -> io::Result<Option<Image>> {
+        let rect = slippy_tile_rect(self.max_zoom, zoom, x, y);
+        let size = self.map.size_px();
+        if rect.x >= size.x || rect.y >= size.y {
+            return Ok(None);
+        }
+
+        let image = if zoom == self.max_zoom {
+            let layers = self.map.visible_layers().into_iter();
+            let image = self.map.render_layers(&self.target, layers, rect);
+            self.done += 1;
+            progress(self.done as f32 / self.total.max(1) as f32);
+            image
+        } else {
+            let mut children = Image::gen_image_color(
+                SLIPPY_TILE_SIZE as u16 * 2,
+                SLIPPY_TILE_SIZE as u16 * 2,
+                Color::new(0.0, 0.0, 0.0, 0.0),
+            );
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                if let Some(child) = self.tile(zoom + 1, x * 2 + dx, y * 2 + dy, progress)? {
+                    blit(
+                        &mut children,
+                        &child,
+                        dx as u32 * SLIPPY_TILE_SIZE,
+                        dy as u32 * SLIPPY_TILE_SIZE,
+                    );
+                }
+            }
+            generate_placeholder(&children, 2)
+        };
+
+        let dir = self.dir.join(zoom.to_string()).join(x.to_string());
+        std::fs::create_dir_all(&dir)?;
+        // `export_png()` takes bottom up images.
+        flip_rows(&image).export_png(&dir.join(format!("{}.png", y)).to_string_lossy());
+        Ok(Some(image))
+    }
+}
+
+fn flip_rows(image: &Image) -> Image {
+    let row = image.width as usize * 4;
+    Image {
+        bytes: image.bytes.chunks(row).rev().flatten().copied().collect(),
+        width: image.width,
+        height: image.height,
+    }
+}
+
+/// Copies `source` into `dest` at `x`, `y`, which must fit.
+fn blit(dest: &mut Image, source: &Image, x: u32, y: u32) {
+    let (dest_row, source_row) = (dest.width as usize * 4, source.width as usize * 4);
+    for (row, bytes) in source.bytes.chunks(source_row).enumerate() {
+        let start = (y as usize + row) * dest_row + x as usize * 4;
+        dest.bytes[start..start + source_row].copy_from_slice(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::vec2;
+
+    #[test]
+    fn test_slippy_coordinates() {
+        assert_eq!(slippy_max_zoom(vec2(100., 50.)), 0);
+        assert_eq!(slippy_max_zoom(vec2(1000., 300.)), 2);
+
+        assert_eq!(
+            slippy_tile_rect(2, 2, 1, 3),
+            Rect::new(256., 768., 256., 256.)
+        );
+        assert_eq!(
+            slippy_tile_rect(2, 0, 0, 0),
+            Rect::new(0., 0., 1024., 1024.)
+        );
+        assert_eq!(slippy_tile_at(2, 1, vec2(600., 100.)), (1, 0));
+        assert_eq!(slippy_tile_at(2, 2, vec2(-1., 0.)), (-1, 0));
+    }
+
+    #[test]
+    fn test_blit_and_flip() {
+        let red = Color::new(1.0, 0.0, 0.0, 1.0);
+        let mut dest = Image::gen_image_color(4, 4, Color::new(0.0, 0.0, 0.0, 0.0));
+        blit(&mut dest, &Image::gen_image_color(2, 2, red), 2, 0);
+        assert_eq!(dest.get_pixel(3, 1), red);
+        assert_eq!(dest.get_pixel(1, 1).a, 0.0);
+
+        let flipped = flip_rows(&dest);
+        assert_eq!(flipped.get_pixel(3, 3), red);
+        assert_eq!(flipped.get_pixel(3, 1).a, 0.0);
+    }
+}
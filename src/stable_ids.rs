@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use crate::map::Map;
+
+/// A layer as recorded in save data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayerEntry {
+    /// Tiled's layer id, which survives reordering. `None` for runtime layers.
+    pub id: Option<u32>,
+    pub name: String,
+    pub index: usize,
+}
+
+/// An object as recorded in save data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectEntry {
+    /// Tiled's object id.
+    pub id: u32,
+    pub name: String,
+    /// Name of the object layer.
+    pub layer: String,
+}
+
+/// Stable identities of a map's layers and objects, to store in save games next to
+/// layer indexes and object ids, and to check them against the shipped map on load,
+/// see `Map::check_manifest()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapManifest {
+    pub layers: Vec<LayerEntry>,
+    pub objects: Vec<ObjectEntry>,
+}
+
+/// How references from save data map to the current map, see `Map::check_manifest()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RemapReport {
+    /// Saved layer index -> current layer index, for layers found.
+    pub layers: HashMap<usize, usize>,
+    /// Saved object id -> current object id, for objects found.
+    pub objects: HashMap<u32, u32>,
+    /// Saved layers found under another name or index.
+    pub moved_layers: Vec<(LayerEntry, LayerEntry)>,
+    /// Saved objects found under another id, name or layer.
+    pub moved_objects: Vec<(ObjectEntry, ObjectEntry)>,
+    pub missing_layers: Vec<LayerEntry>,
+    pub missing_objects: Vec<ObjectEntry>,
+}
+
+impl RemapReport {
+    /// If every saved reference points to the same thing as before.
+    pub fn is_clean(&self) -> bool {
+        self.moved_layers.is_empty()
+            && self.moved_objects.is_empty()
+            && self.missing_layers.is_empty()
+            && self.missing_objects.is_empty()
+    }
+}
+
+impl MapManifest {
+    /// Matches the saved `self` against `current`: layers by id, else by name,
+    /// objects by id if the name still matches, else by layer and name when unique.
+    pub fn remap(&self, current: &MapManifest) -> RemapReport {
+        let mut report = RemapReport::default();
+
+        for saved in &self.layers {
+            let found = current
+                .layers
+                .iter()
+                .find(|layer| saved.id.is_some() && layer.id == saved.id)
+                .or_else(|| current.layers.iter().find(|layer| layer.name == saved.name));
+            match found {
+                Some(layer) => {
+                    report.layers.insert(saved.index, layer.index);
+                    if layer != saved {
+                        report.moved_layers.push((saved.clone(), layer.clone()));
+                    }
+                }
+                None => report.missing_layers.push(saved.clone()),
+            }
+        }
+
+        for saved in &self.objects {
+            let found = current
+                .objects
+                .iter()
+                .find(|object| object.id == saved.id && object.name == saved.name)
+                .or_else(|| {
+                    let mut same_name = current
+                        .objects
+                        .iter()
+                        .filter(|object| object.layer == saved.layer && object.name == saved.name);
+                    match (same_name.next(), same_name.next()) {
+                        (Some(object), None) => Some(object),
+                        _ => None,
+                    }
+                });
+            match found {
+                Some(object) => {
+                    report.objects.insert(saved.id, object.id);
+                    if object != saved {
+                        report.moved_objects.push((saved.clone(), object.clone()));
+                    }
+                }
+                None => report.missing_objects.push(saved.clone()),
+            }
+        }
+        report
+    }
+}
+
+impl Map {
+    /// The stable identities of the layers and objects, to store in save data.
+    pub fn manifest(&self) -> MapManifest {
+        let mut manifest = MapManifest::default();
+        for index in 0..self.layer_count() {
+            let layer = self.map.get_layer(index);
+            manifest.layers.push(LayerEntry {
+                id: layer.as_ref().map(|layer| layer.id()),
+                name: self.layer_name(index).unwrap_or_default(),
+                index,
+            });
+            let Some(objects) = layer.and_then(|layer| layer.as_object_layer()) else {
+                continue;
+            };
+            for object in objects.objects() {
+                manifest.objects.push(ObjectEntry {
+                    id: object.id(),
+                    name: object.name.clone(),
+                    layer: layer.map(|layer| layer.name.clone()).unwrap_or_default(),
+                });
+            }
+        }
+        manifest
+    }
+
+    /// Checks a manifest stored in save data against this map, e.g. after a level update,
+    /// and tells how to remap the saved layer indexes and object ids.
+    pub fn check_manifest(&self, saved: &MapManifest) -> RemapReport {
+        saved.remap(&self.manifest())
+    }
+
+    /// The index of the layer with Tiled's layer id `id`.
+    pub fn layer_by_id(&self, id: u32) -> Option<usize> {
+        self.map.layers().position(|layer| layer.id() == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(id: u32, name: &str, index: usize) -> LayerEntry {
+        LayerEntry {
+            id: Some(id),
+            name: name.to_string(),
+            index,
+        }
+    }
+
+    fn object(id: u32, name: &str) -> ObjectEntry {
+        ObjectEntry {
+            id,
+            name: name.to_string(),
+            layer: "objects".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_remap() {
+        let saved = MapManifest {
+            layers: vec![
+                layer(1, "ground", 0),
+                layer(2, "walls", 1),
+                layer(3, "old", 2),
+            ],
+            objects: vec![object(10, "chest"), object(11, "door"), object(12, "npc")],
+        };
+        let current = MapManifest {
+            layers: vec![layer(2, "walls", 0), layer(1, "ground", 1)],
+            objects: vec![object(10, "chest"), object(20, "door")],
+        };
+        let report = saved.remap(&current);
+
+        assert_eq!(report.layers, HashMap::from([(0, 1), (1, 0)]));
+        assert_eq!(report.missing_layers, vec![layer(3, "old", 2)]);
+        assert_eq!(report.moved_layers.len(), 2);
+        assert_eq!(report.objects, HashMap::from([(10, 10), (11, 20)]));
+        assert_eq!(report.missing_objects, vec![object(12, "npc")]);
+        assert!(!report.is_clean());
+        assert!(current.remap(&current).is_clean());
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::{BTreeSet, HashMap};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use macroquad::math::{ivec2, vec2, IVec2};
+
+use crate::editor::TileEdit;
+use crate::map::{Map, TileHandle};
+use crate::variety::{pick_weighted, tile_probability};
+
+/// Corners of a tile in a Wang id: top-left, top-right, bottom-right, bottom-left.
+const CORNERS: [usize; 4] = [7, 1, 3, 5];
+/// Offsets of the cell corners: top-left, top-right, bottom-right, bottom-left.
+const CORNER_OFFSETS: [IVec2; 4] = [ivec2(0, 0), ivec2(1, 0), ivec2(1, 1), ivec2(0, 1)];
+/// Offsets of the other cells sharing each position of a Wang id: the top edge,
+/// the top-right corner, the right edge, and so on clockwise.
+const WANG_NEIGHBORS: [&[IVec2]; 8] = [
+    &[ivec2(0, -1)],
+    &[ivec2(0, -1), ivec2(1, -1), ivec2(1, 0)],
+    &[ivec2(1, 0)],
+    &[ivec2(1, 0), ivec2(1, 1), ivec2(0, 1)],
+    &[ivec2(0, 1)],
+    &[ivec2(0, 1), ivec2(-1, 1), ivec2(-1, 0)],
+    &[ivec2(-1, 0)],
+    &[ivec2(-1, 0), ivec2(-1, -1), ivec2(0, -1)],
+];
+
+/// Paints terrains of a Wang set at runtime, like Tiled's terrain brush, e.g. for digging
+/// or flooding in gameplay. Painting sets the terrain of tile corners, then picks
+/// the tiles matching their corners, so the edges around the painted area stay correct.
+/// Corner and mixed Wang sets are supported, tiles are matched by their corners.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TerrainBrush {
+    pub tileset: String,
+    /// Index of the Wang set in the tileset.
+    pub wang_set: usize,
+}
+
+impl TerrainBrush {
+    pub fn new(tileset: &str, wang_set: usize) -> Self {
+        Self {
+            tileset: tileset.to_string(),
+            wang_set,
+        }
+    }
+
+    /// Paints `wang_color`, 1-based like in Tiled, on the corners within `radius` tiles
+    /// of the center of `pos`, and re-resolves the tiles around them.
+    /// A zero radius paints the cell `pos`. Returns the changed cells,
+    /// pass them to `EditJournal::record()` to make them undoable.
+    ///
+    /// Panics:
+    /// * If the tileset or the Wang set does not exist.
+    pub fn paint(
+        &self,
+        map: &mut Map,
+        layer: usize,
+        pos: IVec2,
+        wang_color: u8,
+        radius: u32,
+    ) -> Vec<TileEdit> {
+        let wang_tiles = self.wang_ids(map);
+
+        let reach = radius as i32 + 1;
+        let center = pos.as_vec2() + vec2(0.5, 0.5);
+        let painted: Vec<IVec2> = (pos.y - reach..=pos.y + reach + 1)
+            .flat_map(|y| (pos.x - reach..=pos.x + reach + 1).map(move |x| ivec2(x, y)))
+            .filter(|corner| corner.as_vec2().distance(center) <= radius as f32 + FRAC_1_SQRT_2)
+            .collect();
+
+        // Cells touching the painted corners, in order for the edits.
+        let cells: BTreeSet<(i32, i32)> = painted
+            .iter()
+            .flat_map(|corner| CORNER_OFFSETS.map(|offset| *corner - offset))
+            .filter(|cell| map.contains(*cell))
+            .map(|cell| (cell.y, cell.x))
+            .collect();
+
+        // Corners of the touched cells as they are now, then painted.
+        let mut corners: HashMap<IVec2, u8> = HashMap::new();
+        for &(y, x) in &cells {
+            let cell = ivec2(x, y);
+            for (i, offset) in CORNER_OFFSETS.iter().enumerate() {
+                let color = self.corner_color(map, layer, cell, i);
+                let corner = corners.entry(cell + *offset).or_default();
+                if *corner == 0 {
+                    *corner = color;
+                }
+            }
+        }
+        for corner in painted {
+            corners.insert(corner, wang_color);
+        }
+
+        let mut edits = vec![];
+        for (y, x) in cells {
+            let cell = ivec2(x, y);
+            // Edges are left unset: only the corners are matched.
+            let mut wanted = [0; 8];
+            for (i, offset) in CORNER_OFFSETS.iter().enumerate() {
+                wanted[CORNERS[i]] = corners[&(cell + *offset)];
+            }
+            if wanted != [0; 8] {
+                self.apply(map, layer, cell, &wang_tiles, wanted, &mut edits);
+            }
+        }
+        edits
+    }
+
+    /// Picks the tiles of `cells` from the terrain `color(cell)` of every cell,
+    /// 1-based like in Tiled, e.g. from the game's own grid of rock and dug tunnels.
+    /// Cells claim their corners and edges: where cells of different colors meet,
+    /// the highest color wins, so that transitions are drawn in the cells of the lower
+    /// colors. Order the colors of the Wang set from background to foreground.
+    /// This differs from Tiled on purpose: Tiled paints corners and edges, so there the
+    /// last color painted wins, but games keep a color per cell, without any history.
+    /// Cells of color 0 are left alone. Any kind of Wang set is supported.
+    /// After changing colors, pass the changed cells and their 8 neighbors.
+    /// Returns the changed cells, see `paint()`.
+    ///
+    /// Panics:
+    /// * If the tileset or the Wang set does not exist.
+    pub fn autotile(
+        &self,
+        map: &mut Map,
+        layer: usize,
+        cells: impl IntoIterator<Item = IVec2>,
+        color: impl Fn(IVec2) -> u8,
+    ) -> Vec<TileEdit> {
+        let wang_tiles = self.wang_ids(map);
+        let mut edits = vec![];
+        for cell in cells {
+            if color(cell) == 0 || !map.contains(cell) {
+                continue;
+            }
+            let wanted = wang_id_of(cell, &color);
+            self.apply(map, layer, cell, &wang_tiles, wanted, &mut edits);
+        }
+        edits
+    }
+
+    /// Sets the tile of `tiles` best matching `wanted` at `cell`, see `best_wang_id()`,
+    /// and records the edit if it was applied and changed the tile.
+    fn apply(
+        &self,
+        map: &mut Map,
+        layer: usize,
+        cell: IVec2,
+        tiles: &[(u32, [u8; 8], f32)],
+        wanted: [u8; 8],
+        edits: &mut Vec<TileEdit>,
+    ) {
+        let Some(id) = best_wang_id(tiles, wanted, roll(map, cell)) else {
+            return;
+        };
+        let tile = Some(TileHandle::new(&self.tileset, id));
+        edits.extend(map.edit_tile(layer, cell, tile));
+    }
+
+    /// Tiles of the Wang set, with their Wang ids and probability, sorted by id.
+    fn wang_ids(&self, map: &Map) -> Vec<(u32, [u8; 8], f32)> {
+        let tileset = &map.get_tileset(&self.tileset).tileset;
+        let wang_set = tileset
+            .wang_sets
+            .get(self.wang_set)
+            .unwrap_or_else(|| panic!("No Wang set {} in {}", self.wang_set, self.tileset));
+        let mut tiles: Vec<_> = wang_set
+            .wang_tiles
+            .iter()
+            .map(|(id, tile)| (*id, tile.wang_id.0, tile_probability(tileset, *id)))
+            .collect();
+        tiles.sort_by_key(|(id, _, _)| *id);
+        tiles
+    }
+
+    /// The color of the `corner`-th corner of the tile at `cell`, as drawn, flips included,
+    /// 0 if it's not a tile of the Wang set.
+    fn corner_color(&self, map: &Map, layer: usize, cell: IVec2, corner: usize) -> u8 {
+        let Some(tile) = map.tile_ref_at(layer, cell) else {
+            return 0;
+        };
+        if tile.tileset != self.tileset {
+            return 0;
+        }
+        map.get_tileset(&self.tileset)
+            .tileset
+            .wang_sets
+            .get(self.wang_set)
+            .and_then(|wang_set| wang_set.wang_tiles.get(&tile.id))
+            .map(|wang_tile| {
+                let wang_id =
+                    flip_wang_id(wang_tile.wang_id.0, tile.flip_h, tile.flip_v, tile.flip_d);
+                wang_id[CORNERS[corner]]
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// `wang_id` of a tile drawn flipped like in Tiled: diagonally, i.e. transposed,
+/// then horizontally, then vertically.
+fn flip_wang_id(wang_id: [u8; 8], flip_h: bool, flip_v: bool, flip_d: bool) -> [u8; 8] {
+    // The offset of each position from the center of the tile, see `WANG_NEIGHBORS`.
+    let offsets = WANG_NEIGHBORS.map(|neighbors| neighbors[neighbors.len() / 2]);
+    let mut flipped = [0; 8];
+    for (position, mut offset) in offsets.into_iter().enumerate() {
+        if flip_d {
+            offset = ivec2(offset.y, offset.x);
+        }
+        if flip_h {
+            offset.x = -offset.x;
+        }
+        if flip_v {
+            offset.y = -offset.y;
+        }
+        if let Some(to) = offsets.iter().position(|other| *other == offset) {
+            flipped[to] = wang_id[position];
+        }
+    }
+    flipped
+}
+
+/// Where ties between matching tiles fall at `cell`, in `0..1`: random, but always
+/// the same for the cell under the seed of the map, see `Map::rng()`.
+fn roll(map: &Map, cell: IVec2) -> f32 {
+    map.rng().cell_hash(cell, 0) as f32 / u32::MAX as f32
+}
+
+/// The tile with the highest score, among `tiles`: (id, score, probability). Ties are
+/// drawn at `roll` weighted by their probability, like Tiled, or the first one if all
+/// of them have a probability of 0.
+fn pick_best(tiles: impl IntoIterator<Item = (u32, usize, f32)>, roll: f32) -> Option<u32> {
+    let mut best = 0;
+    let mut ties = vec![];
+    for (id, score, probability) in tiles {
+        if score > best || ties.is_empty() {
+            best = score;
+            ties.clear();
+        }
+        if score == best {
+            ties.push((id, probability));
+        }
+    }
+    pick_weighted(&ties, roll).or(ties.first().map(|(id, _)| *id))
+}
+
+/// The Wang id wanted at `cell` by `TerrainBrush::autotile()`: each position gets
+/// the highest color among the cells sharing it.
+fn wang_id_of(cell: IVec2, color: impl Fn(IVec2) -> u8) -> [u8; 8] {
+    let own = color(cell);
+    WANG_NEIGHBORS.map(|neighbors| {
+        neighbors
+            .iter()
+            .map(|offset| color(cell + *offset))
+            .fold(own, u8::max)
+    })
+}
+
+/// The tile matching the most of `wang_id`, see `pick_best()` for ties. Positions a tile
+/// leaves unset, 0, are those its kind of Wang set doesn't use, and are ignored, and so
+/// are those unset in `wang_id`, e.g. the edges when painting corners.
+fn best_wang_id(tiles: &[(u32, [u8; 8], f32)], wang_id: [u8; 8], roll: f32) -> Option<u32> {
+    let score = |tile_id: &[u8; 8]| {
+        tile_id
+            .iter()
+            .zip(wang_id)
+            .filter(|(tile, wanted)| **tile != 0 && **tile == *wanted)
+            .count()
+    };
+    let scored = tiles
+        .iter()
+        .map(|(id, tile_id, probability)| (*id, score(tile_id), *probability));
+    pick_best(scored, roll)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::MapRng;
+    use crate::testing::terrain_map;
+
+    #[test]
+    fn test_best_wang_id_corners() {
+        let tiles = [
+            (0, [0, 1, 0, 1, 0, 1, 0, 1], 1.0),
+            (1, [0, 2, 0, 2, 0, 1, 0, 1], 1.0),
+            (2, [0, 2, 0, 2, 0, 2, 0, 2], 1.0),
+        ];
+        assert_eq!(best_wang_id(&tiles, [0, 2, 0, 2, 0, 1, 0, 1], 0.5), Some(1));
+        assert_eq!(best_wang_id(&tiles, [0, 2, 0, 2, 0, 2, 0, 0], 0.5), Some(2));
+        // No exact match: the closest one.
+        assert_eq!(best_wang_id(&tiles, [0, 1, 0, 1, 0, 1, 0, 2], 0.5), Some(0));
+        assert_eq!(best_wang_id(&[], [0, 1, 0, 1, 0, 1, 0, 1], 0.5), None);
+    }
+
+    #[test]
+    fn test_pick_best() {
+        let tiles = [(0, 3, 1.0), (1, 4, 1.0), (2, 4, 3.0), (3, 4, 0.0)];
+        // Ties weighted 1:3.
+        assert_eq!(pick_best(tiles, 0.2), Some(1));
+        assert_eq!(pick_best(tiles, 0.3), Some(2));
+        assert_eq!(pick_best(tiles, 0.99), Some(2));
+        assert_eq!(pick_best([(5, 1, 0.0), (6, 1, 0.0)], 0.5), Some(5));
+        assert_eq!(pick_best([], 0.5), None);
+    }
+
+    #[test]
+    fn test_paint_picks_weighted_variants() {
+        let mut map = terrain_map();
+        let brush = TerrainBrush::new("terrain", 0);
+        let mut counts = [0; 8];
+        for seed in 0..200 {
+            map.set_rng(MapRng::new(seed));
+            // Dirt, then grass back.
+            for color in [2, 1] {
+                for edit in brush.paint(&mut map, 0, ivec2(1, 1), color, 1) {
+                    counts[edit.after.unwrap().id as usize] += 1;
+                }
+            }
+        }
+        // Plain dirt is tile 3 or 5, evenly.
+        assert!(counts[3] > 0 && counts[5] > 0);
+        assert!(counts[3] < counts[5] * 2 && counts[5] < counts[3] * 2);
+        // Plain grass is tile 0 or 1, 1:3, never 2.
+        assert_eq!(counts[2], 0);
+        assert!(counts[1] > counts[0] * 2);
+    }
+
+    #[test]
+    fn test_flip_wang_id() {
+        // Dirt on the top edge and corners.
+        let top = [2, 2, 1, 1, 1, 1, 1, 2];
+        assert_eq!(flip_wang_id(top, false, false, false), top);
+        assert_eq!(
+            flip_wang_id(top, false, true, false),
+            [1, 1, 1, 2, 2, 2, 1, 1]
+        );
+        assert_eq!(flip_wang_id(top, true, false, false), top);
+        // Transposed: on the left, then the right once flipped horizontally.
+        assert_eq!(
+            flip_wang_id(top, false, false, true),
+            [1, 1, 1, 1, 1, 2, 2, 2]
+        );
+        assert_eq!(
+            flip_wang_id(top, true, false, true),
+            [1, 2, 2, 2, 1, 1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn test_paint_map() {
+        let mut map = terrain_map();
+        let brush = TerrainBrush::new("terrain", 0);
+        // Grass above, dirt below: tile 4 upside down.
+        let flipped = TileHandle::new("terrain", 4).flipped(false, true, false);
+        map.set_tile(0, ivec2(1, 2), Some(flipped.clone()));
+        assert_eq!(brush.corner_color(&map, 0, ivec2(1, 2), 0), 1);
+        assert_eq!(brush.corner_color(&map, 0, ivec2(1, 2), 3), 2);
+
+        let edits = brush.paint(&mut map, 0, ivec2(2, 2), 2, 0);
+        let id = |x, y| map.tile_at(0, ivec2(x, y)).unwrap().id;
+        assert!([3, 5].contains(&id(2, 2)));
+        // Below the flipped tile, its dirt corner and the painted one: dirt on top.
+        assert_eq!(id(1, 3), 4);
+        // Out of reach.
+        assert!([0, 1, 2].contains(&id(0, 0)));
+        for edit in &edits {
+            assert_ne!(edit.before, edit.after);
+            assert_eq!(map.tile_at(0, edit.pos), edit.after);
+        }
+        assert!(edits.iter().all(|edit| edit.pos.cmpge(ivec2(1, 1)).all()));
+        // Painting it again changes nothing.
+        assert!(brush.paint(&mut map, 0, ivec2(2, 2), 2, 0).is_empty());
+
+        // Nor painting a layer without tiles, here past the only layer.
+        assert!(!map.is_tile_layer(1));
+        assert!(brush.paint(&mut map, 1, ivec2(2, 2), 1, 1).is_empty());
+        assert!(brush
+            .autotile(&mut map, 1, [ivec2(0, 0), ivec2(1, 0)], |_| 2)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_autotile_wang_ids() {
+        // A tunnel, 2, dug through rock, 1, along the row 0.
+        let color = |cell: IVec2| if cell.y == 0 { 2 } else { 1 };
+        assert_eq!(wang_id_of(ivec2(5, 0), color), [2; 8]);
+        // The rock below gets the transition on its top side.
+        assert_eq!(wang_id_of(ivec2(5, 1), color), [2, 2, 1, 1, 1, 1, 1, 2]);
+
+        // A corner set: edges are unused.
+        let tiles = [
+            (0, [0, 1, 0, 1, 0, 1, 0, 1], 1.0),
+            (1, [0, 2, 0, 1, 0, 1, 0, 2], 1.0),
+            (2, [0, 2, 0, 2, 0, 2, 0, 2], 1.0),
+        ];
+        assert_eq!(best_wang_id(&tiles, [2, 2, 1, 1, 1, 1, 1, 2], 0.5), Some(1));
+        assert_eq!(best_wang_id(&tiles, [2; 8], 0.5), Some(2));
+    }
+
+    #[test]
+    fn test_autotile_map() {
+        let mut map = terrain_map();
+        let brush = TerrainBrush::new("terrain", 0);
+        // Dirt along the top row, grass below, and a cell left alone.
+        let color = |cell: IVec2| match cell {
+            IVec2 { x: 3, y: 3 } => 0,
+            IVec2 { y: 0, .. } => 2,
+            _ => 1,
+        };
+        let cells: Vec<_> = (0..4)
+            .flat_map(|y| (0..4).map(move |x| ivec2(x, y)))
+            .collect();
+        let edits = brush.autotile(&mut map, 0, cells.clone(), color);
+
+        let id = |x, y| map.tile_at(0, ivec2(x, y)).unwrap().id;
+        for x in 0..4 {
+            assert!([3, 5].contains(&id(x, 0)));
+            // The transition is drawn in the grass, the lower color.
+            assert_eq!(id(x, 1), 4);
+        }
+        assert!([0, 1].contains(&id(0, 3)));
+        assert_eq!(id(3, 3), 1);
+        // Only changed cells are returned, and a second pass changes nothing.
+        assert!(edits.iter().all(|edit| edit.before != edit.after));
+        assert!(edits.iter().all(|edit| edit.pos != ivec2(3, 3)));
+        assert!(brush.autotile(&mut map, 0, cells, color).is_empty());
+    }
+}
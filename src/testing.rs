@@ -0,0 +1,307 @@
+//! Helpers for deterministic tests of maps and animations, without crafting assets:
+//! a tiny bundled map, mock animation templates, and clock-driven assertions.
+//! Enabled by the "testing" feature, the animation helpers by the "render" feature too.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+#[cfg(feature = "render")]
+use std::ops::RangeInclusive;
+use std::task::Waker;
+
+use coarsetime::Duration;
+#[cfg(feature = "render")]
+use coarsetime::Instant;
+use macroquad::miniquad::{RawId, TextureId};
+use macroquad::texture::Texture2D;
+
+#[cfg(feature = "render")]
+use crate::animation_controller::{AnimationController, AnimationFrame, AnimationTemplate};
+use crate::clock::MapClock;
+use crate::embedded::MemoryReader;
+use crate::map::Map;
+use crate::tileset::{load_animations, TileSet};
+
+/// A 4x4 orthogonal map of 16x16 tiles: walls around a 2x2 floor, with an animated tile
+/// at (1, 1), and an "objects" layer with a "spawn" point object at (24, 24) px.
+pub const TINY_TMX: &str = include_str!("../assets/testing/tiny.tmx");
+/// A 4x4 map of `TINY_TSX` tiles with a "zones" layer: a "door" rect object of class
+/// "trigger" over the cell (1, 1), a "pit" ellipse object of class "trigger" over the
+/// 2x2 floor, and a "sign" point object at (40, 40) px.
+pub const TRIGGERS_TMX: &str = include_str!("../assets/testing/triggers.tmx");
+/// The tileset of `TINY_TMX`, "tiny": tile 0 is animated (0, 1, 100 ms each),
+/// tile 2 is of class "wall", with a bool property "solid" and a "material" "stone",
+/// and collision shapes: a rect over its bottom half and a triangle in its top half,
+/// tile 3 is of class "casts_shadow", with a "night_tile" 1.
+pub const TINY_TSX: &str = include_str!("../assets/testing/tiny.tsx");
+/// A 4x4 map of `TERRAIN_TSX` tiles, all plain grass: tile 1.
+pub const TERRAIN_TMX: &str = include_str!("../assets/testing/terrain.tmx");
+/// "terrain": a corner Wang set "ground" of grass, 1, and dirt, 2. Tiles 0, 1 and 2
+/// are plain grass, of probability 1, 3 and 0, the latter "cracked", tiles 3 and 5
+/// plain dirt, tile 4 has dirt on top and grass below. Tiles 6 and 7 are of class
+/// "floor", of probability 1 and 2.
+pub const TERRAIN_TSX: &str = include_str!("../assets/testing/terrain.tsx");
+/// An infinite map of `TINY_TSX` tiles, with two chunks: from (-16, 0), with tile 2 at
+/// (-1, 0) and tile 3 at (-1, 1), and from (16, 16), with tile 1 at (16, 16) and tile 2
+/// at (19, 31).
+pub const INFINITE_TMX: &str = include_str!("../assets/testing/infinite.tmx");
+/// The image of `TINY_TSX`, a tile per color.
+pub const TINY_PNG: &[u8] = include_bytes!("../assets/testing/tiny.png");
+
+/// `TINY_TMX`, as loaded by tiled.
+pub fn tiny_tiled_map() -> tiled::Map {
+    load_tiled_map(TINY_TMX)
+}
+
+fn load_tiled_map(tmx: &str) -> tiled::Map {
+    let reader = MemoryReader::new(&[
+        ("map.tmx", tmx.as_bytes()),
+        ("tiny.tsx", TINY_TSX.as_bytes()),
+        ("terrain.tsx", TERRAIN_TSX.as_bytes()),
+    ]);
+    tiled::Loader::with_cache_and_reader(tiled::DefaultResourceCache::new(), reader)
+        .load_tmx_map("map.tmx")
+        .expect("The bundled test maps are valid")
+}
+
+/// `TINY_TMX` with stand-in textures: everything but drawing works,
+/// and no macroquad window is needed.
+pub fn tiny_map() -> Map {
+    map_with(tiny_tiled_map(), stand_in_texture)
+}
+
+/// `TRIGGERS_TMX` with stand-in textures, like `tiny_map()`.
+pub fn triggers_map() -> Map {
+    map_with(load_tiled_map(TRIGGERS_TMX), stand_in_texture)
+}
+
+/// `TERRAIN_TMX` with stand-in textures, like `tiny_map()`.
+pub fn terrain_map() -> Map {
+    map_with(load_tiled_map(TERRAIN_TMX), stand_in_texture)
+}
+
+/// `INFINITE_TMX` with stand-in textures, like `tiny_map()`.
+pub fn infinite_map() -> Map {
+    map_with(load_tiled_map(INFINITE_TMX), stand_in_texture)
+}
+
+/// A map of `tmx`, next to `TINY_TSX` as "tiny.tsx" and `TERRAIN_TSX` as "terrain.tsx",
+/// with stand-in textures like `tiny_map()`, e.g. for a test's own layout of tilesets.
+pub fn stand_in_map(tmx: &str) -> Map {
+    map_with(load_tiled_map(tmx), stand_in_texture)
+}
+
+fn stand_in_texture() -> Texture2D {
+    Texture2D::from_miniquad_texture(TextureId::from_raw_id(RawId::OpenGl(0)))
+}
+
+/// `TINY_TMX` with `TINY_PNG` textures, for drawing. Needs a macroquad window.
+pub fn tiny_map_drawable() -> Map {
+    map_with(tiny_tiled_map(), || {
+        Texture2D::from_file_with_format(TINY_PNG, None)
+    })
+}
+
+fn map_with(map: tiled::Map, texture: impl Fn() -> Texture2D) -> Map {
+    let tilesets: HashMap<String, TileSet> = map
+        .tilesets()
+        .iter()
+        .map(|tileset| {
+            let tileset = tileset.deref().clone();
+            let animations = load_animations(&tileset);
+            (
+                tileset.name.clone(),
+                TileSet::new(tileset, texture(), animations),
+            )
+        })
+        .collect();
+    Map::from_parts(map, tilesets, HashSet::new(), vec![])
+}
+
+/// A waker doing nothing, to poll futures by hand, e.g. those of `Map::new_async()`.
+pub fn noop_waker() -> Waker {
+    crate::texture_stream::noop_waker()
+}
+
+/// Sets the map clock to `elapsed`, and checks the tile shown for `tile_id`.
+#[track_caller]
+pub fn assert_animated_tile_at(
+    map: &mut Map,
+    tileset: &str,
+    tile_id: u32,
+    elapsed: Duration,
+    expected: u32,
+) {
+    map.clock = MapClock::new();
+    map.clock.step(elapsed);
+    let actual = map.animated_tile_id(tileset, tile_id);
+    assert_eq!(
+        actual,
+        expected,
+        "At {} ms, tile {} shows {}, expected {}",
+        elapsed.as_millis(),
+        tile_id,
+        actual,
+        expected
+    );
+}
+
+#[cfg(feature = "render")]
+pub fn mock_template(frames: Vec<AnimationFrame>, max_compression: u32) -> AnimationTemplate {
+    let mut template = AnimationTemplate::new_frames("dummy".to_string(), 1, frames);
+    template.max_compression = max_compression;
+    template
+}
+
+#[cfg(feature = "render")]
+/// Frames of 100, 200, 400 and 300 ticks, repeating: 1000 ticks per 4 frames.
+pub fn mock_frames1243(ids: RangeInclusive<u32>) -> Vec<AnimationFrame> {
+    let mut result = vec![];
+    let durations = [100, 200, 400, 300];
+    for (index, tile_id) in ids.enumerate() {
+        result.push(AnimationFrame {
+            tile_id,
+            duration: Duration::from_ticks(durations[index % durations.len()]),
+        });
+    }
+    result
+}
+
+#[track_caller]
+pub fn assert_pos_almost_eq(expected: (f32, f32), actual: (f32, f32), delta: f32, what: &str) {
+    if (expected.0 - actual.0).abs() > delta || (expected.1 - actual.1).abs() > delta {
+        panic!(
+            "Expected {}: {:?} and actual: {:?} are different",
+            what, expected, actual
+        );
+    }
+}
+
+#[cfg(feature = "render")]
+/// Drives an `AnimationController` through time given in ticks since the start.
+pub struct AnimationTest {
+    pub controller: AnimationController,
+    pub start_time: Instant,
+    pub now: Instant,
+}
+
+#[cfg(feature = "render")]
+impl Default for AnimationTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "render")]
+impl AnimationTest {
+    pub fn new() -> Self {
+        Self {
+            controller: AnimationController::new(),
+            start_time: Instant::now(),
+            now: Instant::recent(),
+        }
+    }
+
+    /// Moves to `now_in_ticks` and updates the controller.
+    pub fn advance_to(&mut self, now_in_ticks: u64) {
+        self.now = self.start_time + Duration::from_ticks(now_in_ticks);
+        self.controller.update(self.now);
+    }
+
+    #[track_caller]
+    pub fn assert_frame_at(&mut self, now_in_ticks: u64, tile_id: u32, expected_pos: (f32, f32)) {
+        self.advance_to(now_in_ticks);
+
+        let frame_now = self.controller.get_frame(self.now).expect("Frame expected");
+
+        assert_eq!(
+            frame_now.tile_id, tile_id,
+            "tiles_id differ: expected {}, got {}",
+            tile_id, frame_now.tile_id
+        );
+        assert_pos_almost_eq(expected_pos, frame_now.position, 1.1, "position");
+    }
+
+    /// Same as `assert_frame_at()`, but tolerates the frame and position
+    /// being reached within 5 ticks, for timer imprecision.
+    #[track_caller]
+    pub fn assert_in_interval(
+        &mut self,
+        now_in_ticks: u64,
+        expected_tile_id: u32,
+        expected_pos: (f32, f32),
+    ) {
+        let start = now_in_ticks.saturating_sub(5);
+
+        //if expected tile_id is present in time interval now_in_ticks +- 5 ms
+        let mut tile_id = false;
+        let mut got_tile_id = None;
+        for i in start..(start + 11) {
+            self.advance_to(i);
+            let Some(frame_now) = self.controller.get_frame(self.now) else {
+                continue;
+            };
+            if frame_now.tile_id == expected_tile_id {
+                tile_id = true;
+                break;
+            }
+            if i == now_in_ticks {
+                got_tile_id = Some(frame_now.tile_id);
+            }
+        }
+        assert!(
+            tile_id,
+            "At {} tile_id is not {}, it is {:?}",
+            now_in_ticks, expected_tile_id, got_tile_id
+        );
+
+        //if real position == expected position +- 1
+        let mut pos = false;
+        let mut got_frame_pos = (0., 0.);
+        for i in start..(start + 11) {
+            self.advance_to(i);
+            let Some(frame_now) = self.controller.get_frame(self.now) else {
+                continue;
+            };
+            let frame_pos = frame_now.position;
+            if (expected_pos.0 - frame_pos.0).abs() <= 1.
+                && (expected_pos.1 - frame_pos.1).abs() <= 1.
+            {
+                pos = true;
+                break;
+            }
+            if i == now_in_ticks {
+                got_frame_pos = frame_pos;
+            }
+        }
+        assert!(
+            pos,
+            "At {} position is not {:?}, it is {:?}",
+            now_in_ticks, expected_pos, got_frame_pos
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_empty_at(&mut self, now_in_ticks: u64) {
+        self.advance_to(now_in_ticks);
+        assert!(self.controller.get_frame(self.now).is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_tiny_map() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        let wall = map.tile_at(ground, ivec2(0, 0)).unwrap();
+        assert_eq!((wall.tileset.as_str(), wall.id), ("tiny", 2));
+        assert_eq!(map.tile_at(ground, ivec2(1, 1)).unwrap().id, 0);
+
+        assert_animated_tile_at(&mut map, "tiny", 0, Duration::from_millis(50), 0);
+        assert_animated_tile_at(&mut map, "tiny", 0, Duration::from_millis(150), 1);
+        assert_animated_tile_at(&mut map, "tiny", 0, Duration::from_millis(250), 0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use macroquad::texture::Texture2D;
+
+/// Textures of tileset images by image path, shared by the tilesets which use the same
+/// image, in a map or across maps, instead of loading it once per tileset.
+/// See `Map::new_async_cached()`. Cloning shares the textures.
+#[derive(Clone, Debug, Default)]
+pub struct TextureCache {
+    textures: HashMap<TextureKey, Texture2D>,
+}
+
+/// The image of a tileset, and how it's extruded, see `TileSet::new_async_extruded()`:
+/// tilesets cutting the same image differently get different extruded textures.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TextureKey {
+    path: PathBuf,
+    padding: u16,
+    /// (tile width, tile height, spacing, margin, columns), when extruded.
+    layout: Option<(u32, u32, u32, u32, u32)>,
+}
+
+impl TextureKey {
+    /// None for image collection tilesets.
+    pub(crate) fn new(tileset: &tiled::Tileset, padding: u16) -> Option<Self> {
+        let image = tileset.image.as_ref()?;
+        let layout = (padding > 0).then_some((
+            tileset.tile_width,
+            tileset.tile_height,
+            tileset.spacing,
+            tileset.margin,
+            tileset.columns,
+        ));
+        Some(Self {
+            path: normalize(&image.source),
+            padding,
+            layout,
+        })
+    }
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of distinct textures.
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Forgets the textures, they are freed once no tileset uses them.
+    pub fn clear(&mut self) {
+        self.textures.clear();
+    }
+
+    pub(crate) fn get(&self, key: &TextureKey) -> Option<Texture2D> {
+        self.textures.get(key).cloned()
+    }
+
+    pub(crate) fn insert(&mut self, key: TextureKey, texture: Texture2D) {
+        self.textures.insert(key, texture);
+    }
+
+    pub(crate) fn contains(&self, key: &TextureKey) -> bool {
+        self.textures.contains_key(key)
+    }
+}
+
+/// `path` without `.`, and with `..` applied to the previous components.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::tiny_map;
+
+    #[test]
+    fn test_texture_key() {
+        let map = tiny_map();
+        let mut tileset = map.tilesets["tiny"].tileset.clone();
+        let key = TextureKey::new(&tileset, 0);
+        assert!(key.is_some());
+
+        // Same image through another path, cut differently.
+        let image = tileset.image.as_mut().unwrap();
+        image.source = Path::new("maps/..").join(&image.source);
+        tileset.spacing += 1;
+        assert_eq!(TextureKey::new(&tileset, 0), key);
+        assert_ne!(
+            TextureKey::new(&tileset, 1),
+            TextureKey::new(&map.tilesets["tiny"].tileset, 1)
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("./a/../b/c.tsx")), Path::new("b/c.tsx"));
+        assert_eq!(normalize(Path::new("../a.png")), Path::new("../a.png"));
+    }
+}
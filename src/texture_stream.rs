@@ -0,0 +1,161 @@
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Wake, Waker};
+
+use macroquad::color::Color;
+use macroquad::texture::Image;
+use macroquad::Error as MqError;
+
+use crate::tileset::TileSet;
+
+/// Low-res copies of tileset images are looked up next to them, with this suffix:
+/// "atlas.png" -> "atlas.placeholder.png". See `placeholder_path()`.
+pub const PLACEHOLDER_SUFFIX: &str = "placeholder";
+
+/// Where the placeholder of a tileset image is looked up, see `LoadOptions::stream_textures`.
+pub fn placeholder_path(image: &Path) -> PathBuf {
+    let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match image.extension() {
+        Some(extension) => format!(
+            "{}.{}.{}",
+            stem,
+            PLACEHOLDER_SUFFIX,
+            extension.to_string_lossy()
+        ),
+        None => format!("{}.{}", stem, PLACEHOLDER_SUFFIX),
+    };
+    image.with_file_name(name)
+}
+
+/// Shrinks `image` by `factor`, averaging each `factor` x `factor` block of pixels,
+/// e.g. to make placeholders in a build script, then save them with `Image::export_png()`.
+/// Keep tile sizes multiples of `factor`, so that tiles don't bleed into each other.
+pub fn generate_placeholder(image: &Image, factor: u16) -> Image {
+    let factor = factor.max(1);
+    let width = image.width.div_ceil(factor);
+    let height = image.height.div_ceil(factor);
+    let mut placeholder = Image::gen_image_color(width, height, Color::new(0.0, 0.0, 0.0, 0.0));
+
+    for y in 0..height as u32 {
+        for x in 0..width as u32 {
+            let mut sum = [0.0; 4];
+            let mut count = 0.0;
+            for sy in y * factor as u32..((y + 1) * factor as u32).min(image.height as u32) {
+                for sx in x * factor as u32..((x + 1) * factor as u32).min(image.width as u32) {
+                    let pixel = image.get_pixel(sx, sy);
+                    // Premultiplied, so that transparent pixels don't darken the edges.
+                    sum[0] += pixel.r * pixel.a;
+                    sum[1] += pixel.g * pixel.a;
+                    sum[2] += pixel.b * pixel.a;
+                    sum[3] += pixel.a;
+                    count += 1.0;
+                }
+            }
+            let color = if sum[3] > 0.0 {
+                Color::new(
+                    sum[0] / sum[3],
+                    sum[1] / sum[3],
+                    sum[2] / sum[3],
+                    sum[3] / count,
+                )
+            } else {
+                Color::new(0.0, 0.0, 0.0, 0.0)
+            };
+            placeholder.set_pixel(x, y, color);
+        }
+    }
+    placeholder
+}
+
+type Loading = Pin<Box<dyn Future<Output = Result<Image, MqError>> + Send>>;
+
+/// A full-res image loading in the background, polled by `Map::update()`: read with
+/// `load_file()`, and decoded on a thread of its own on native targets,
+/// see `TileSet::decode_image()`.
+pub(crate) struct TextureStream {
+    /// Behind a mutex only for tilesets to stay `Sync`, it's polled through `&mut self`.
+    loading: Mutex<Loading>,
+}
+
+impl fmt::Debug for TextureStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextureStream").finish_non_exhaustive()
+    }
+}
+
+impl TextureStream {
+    /// Starts loading the image of `tileset`.
+    pub fn start(tileset: tiled::Tileset) -> Self {
+        Self::from_future(async move { TileSet::decode_image(&tileset, 0).await })
+    }
+
+    pub fn from_future(
+        loading: impl Future<Output = Result<Image, MqError>> + Send + 'static,
+    ) -> Self {
+        Self {
+            loading: Mutex::new(Box::pin(loading)),
+        }
+    }
+
+    /// The image, or the error, once loaded. Call it once per frame, like macroquad
+    /// polls its coroutines.
+    pub fn poll(&mut self) -> Option<Result<Image, MqError>> {
+        let loading = self
+            .loading
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        match loading
+            .as_mut()
+            .poll(&mut Context::from_waker(&noop_waker()))
+        {
+            Poll::Ready(result) => Some(result),
+            Poll::Pending => None,
+        }
+    }
+}
+
+/// A waker doing nothing: streams are polled every frame anyway.
+pub(crate) fn noop_waker() -> Waker {
+    struct Noop;
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+    Waker::from(Arc::new(Noop))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholder_path() {
+        assert_eq!(
+            placeholder_path(Path::new("assets/atlas.png")),
+            Path::new("assets/atlas.placeholder.png")
+        );
+        assert_eq!(
+            placeholder_path(Path::new("atlas")),
+            Path::new("atlas.placeholder")
+        );
+    }
+
+    #[test]
+    fn test_generate_placeholder() {
+        let mut image = Image::gen_image_color(3, 2, Color::new(0.0, 0.0, 0.0, 0.0));
+        image.set_pixel(0, 0, Color::new(1.0, 0.0, 0.0, 1.0));
+        image.set_pixel(2, 1, Color::new(0.0, 1.0, 0.0, 1.0));
+
+        let placeholder = generate_placeholder(&image, 2);
+        assert_eq!((placeholder.width, placeholder.height), (2, 1));
+        // A quarter covered, but not darkened by the transparent pixels.
+        let red = placeholder.get_pixel(0, 0);
+        assert!(red.r > 0.99 && red.g < 0.01);
+        assert!((red.a - 0.25).abs() < 0.01);
+        // The edge block only averages the pixels in the image.
+        let green = placeholder.get_pixel(1, 0);
+        assert!(green.g > 0.99 && (green.a - 0.5).abs() < 0.01);
+    }
+}
@@ -2,22 +2,143 @@ use coarsetime::{Duration, Instant};
 use std::collections::HashMap;
 use std::ops::Add;
 
-use macroquad::color::WHITE;
-use macroquad::math::{vec2, Rect, Vec2};
-use macroquad::texture::{draw_texture_ex, load_texture, DrawTextureParams, FilterMode, Texture2D};
+use macroquad::color::{Color, WHITE};
+use macroquad::file::load_file;
+use macroquad::math::{vec2, vec3, Rect, Vec2};
+use macroquad::models::{draw_mesh, Mesh, Vertex};
+use macroquad::texture::{
+    draw_texture_ex, load_image, load_texture, DrawTextureParams, FilterMode, Image, Texture2D,
+};
 use macroquad::Error as MqError;
-use tiled::{PropertyValue, TileId};
+use tiled::{Properties, PropertyValue, TileId};
 
 use crate::animation::{AnimatedSpriteState, AnimatedTile, Animation, AnimationFrame};
+use crate::draw_backend::DrawParams;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::offload::offload;
+use crate::properties::{
+    bool_value, color_value, float_value, inherit_properties, int_value, string_value,
+    PropertiesExt,
+};
+#[cfg(feature = "render")]
+use crate::texture_cache::TextureKey;
+use crate::texture_stream::{placeholder_path, TextureStream};
+use crate::variety::VariantGroups;
+#[cfg(any(feature = "render", feature = "collision"))]
+use crate::walls::{wall_parts, WallPart};
+
+/// Repacks the tiles of `image`, laid out as `tileset` says, in the same columns, with
+/// `padding` pixels around each tile, copies of its edge pixels: sampling past the edge of
+/// a tile then picks its own color rather than the next tile's. Margins and spacing
+/// are dropped. See `TileSet::new_async_extruded()`.
+pub fn extrude_tiles(image: &Image, tileset: &tiled::Tileset, padding: u16) -> Image {
+    let (tile_width, tile_height) = (tileset.tile_width as i32, tileset.tile_height as i32);
+    let padding = padding as i32;
+    let columns = tileset.columns.max(1);
+    let rows = tileset.tilecount.div_ceil(columns);
+    let (cell_width, cell_height) = (tile_width + padding * 2, tile_height + padding * 2);
+    let mut extruded = Image::gen_image_color(
+        (columns as i32 * cell_width) as u16,
+        (rows as i32 * cell_height) as u16,
+        Color::new(0.0, 0.0, 0.0, 0.0),
+    );
+
+    for tile in 0..tileset.tilecount {
+        let (column, row) = extruded_cell(tileset, tile);
+        let source_x =
+            column as i32 * (tile_width + tileset.spacing as i32) + tileset.margin as i32;
+        let source_y = row as i32 * (tile_height + tileset.spacing as i32) + tileset.margin as i32;
+        for y in 0..cell_height {
+            for x in 0..cell_width {
+                let from_x = source_x + (x - padding).clamp(0, tile_width - 1);
+                let from_y = source_y + (y - padding).clamp(0, tile_height - 1);
+                if from_x >= image.width as i32 || from_y >= image.height as i32 {
+                    continue;
+                }
+                let from = (from_y as usize * image.width as usize + from_x as usize) * 4;
+                let to_x = column as i32 * cell_width + x;
+                let to_y = row as i32 * cell_height + y;
+                let to = (to_y as usize * extruded.width as usize + to_x as usize) * 4;
+                extruded.bytes[to..to + 4].copy_from_slice(&image.bytes[from..from + 4]);
+            }
+        }
+    }
+    extruded
+}
+
+/// (column, row) of `tile` in the image of `tileset`.
+fn extruded_cell(tileset: &tiled::Tileset, tile: u32) -> (u32, u32) {
+    let columns = tileset.columns.max(1);
+    (tile % columns, tile / columns)
+}
+
+/// Sprites per mesh in `TileSet::spr_batch()`. Macroquad clamps a draw call to 5000 indices,
+/// and a sprite takes 6.
+const BATCH_SPRITES: usize = 800;
+
+/// (flip_h, flip_v, flip_d) of a placed tile.
+pub(crate) type Flips = (bool, bool, bool);
+const NO_FLIPS: Flips = (false, false, false);
+
+#[inline]
+pub(crate) fn vertex(x: f32, y: f32, u: f32, v: f32, color: Color) -> Vertex {
+    Vertex {
+        position: vec3(x, y, 0.),
+        uv: vec2(u, v),
+        color,
+    }
+}
+
+/// A magenta and black checkerboard the size of the image of `tileset`,
+/// with squares of half a tile.
+fn checkerboard(tileset: &tiled::Tileset) -> Image {
+    let (width, height) = match &tileset.image {
+        Some(image) => (image.width.max(1) as u16, image.height.max(1) as u16),
+        None => (tileset.tile_width as u16, tileset.tile_height as u16),
+    };
+    let square = (
+        (tileset.tile_width / 2).max(1) as usize,
+        (tileset.tile_height / 2).max(1) as usize,
+    );
+    let bytes = (0..height as usize)
+        .flat_map(|y| (0..width as usize).map(move |x| (x, y)))
+        .flat_map(|(x, y)| match (x / square.0 + y / square.1) % 2 {
+            0 => [255, 0, 255, 255],
+            _ => [0, 0, 0, 255],
+        })
+        .collect();
+    Image {
+        bytes,
+        width,
+        height,
+    }
+}
 
 #[derive(Debug)]
 pub struct TileSet {
     texture: Texture2D,
+    /// Size of the full-res image, which sprite rects are in.
+    /// `texture` is smaller while it's a placeholder.
+    image_size: Vec2,
+    /// The full-res texture loading, while `texture` is a placeholder.
+    stream: Option<TextureStream>,
+    /// Extruded pixels around each tile in `texture`, see `new_async_extruded()`.
+    padding: u16,
+    /// (where the image is, size) of `texture` when it's an atlas, see `Map::build_atlas()`.
+    atlas: Option<(Vec2, Vec2)>,
+    /// `texture` stands in for the image: the checkerboard of `new_missing()`, or
+    /// a placeholder until its full-res image is in. Never shared, see `TextureCache`.
+    stand_in: bool,
     pub tileset: tiled::Tileset,
 
     // todo: hide behind get_animation?
     /// Animations: map tile_id -> AnimatedSprite
     pub animations: HashMap<u32, AnimatedTile>,
+    /// Interchangeable tiles, for "auto_variety" layers.
+    pub variants: VariantGroups,
+    /// See `wall_part()`.
+    #[cfg(any(feature = "render", feature = "collision"))]
+    pub(crate) wall_parts: HashMap<u32, WallPart>,
 }
 
 impl TileSet {
@@ -29,15 +150,27 @@ impl TileSet {
         texture: Texture2D,
         animations: HashMap<u32, AnimatedTile>,
     ) -> Self {
+        let image_size = match &tileset.image {
+            Some(image) => vec2(image.width as f32, image.height as f32),
+            None => texture.size(),
+        };
         Self {
             texture,
+            image_size,
+            stream: None,
+            padding: 0,
+            atlas: None,
+            stand_in: false,
+            variants: VariantGroups::new(&tileset),
+            #[cfg(any(feature = "render", feature = "collision"))]
+            wall_parts: wall_parts(&tileset),
             tileset,
             animations,
         }
     }
 
-    /// Future: loading Tileset can be wrapped into another async Future that
-    /// loads it in another thread. Then the entire function could be Macroquad-async.
+    /// Decodes the image on the calling thread, `Map::new_async_with()` decodes
+    /// the images of a map on a thread of their own.
     pub async fn new_async(tileset: tiled::Tileset) -> Result<Self, MqError> {
         let image_source = &tileset
             .image
@@ -45,46 +178,255 @@ impl TileSet {
             .expect("Only spritesheet-type tilesets are now supported")
             .source;
 
-        let texture: Texture2D = load_texture(image_source.to_str().unwrap())
-            .await
-            .unwrap_or_else(|e| panic!("Couldn't load the texture: {:?}: {}", image_source, e));
+        let texture: Texture2D = load_texture(&image_source.to_string_lossy()).await?;
 
         // For a pixel-perfect rendering.
         // https://gamedev.stackexchange.com/questions/22712/how-can-i-draw-crisp-per-pixel-images-with-opengl-es-on-android
         texture.set_filter(FilterMode::Nearest);
 
-        let mut animations = HashMap::new();
-
-        for (tile_id, tile) in tileset.tiles() {
-            if let Some(tiled_animation) = &tile.animation {
-                let frames: Vec<AnimationFrame> =
-                    tiled_animation.iter().map(AnimationFrame::from).collect();
-
-                // two passes, sure, but I expect them all not to exceed 10-20 frames.
-                let total_duration = frames
-                    .iter()
-                    .fold(Duration::from_ticks(0), |sum, val| sum.add(val.duration));
-
-                let animation = AnimatedTile::new(
-                    tile_id,
-                    Animation {
-                        frames,
-                        duration: total_duration,
-                    },
-                );
-                animations.insert(tile_id, animation);
-            }
+        let animations = load_animations(&tileset);
+        Ok(Self::new(tileset, texture, animations))
+    }
+
+    /// Same as `new_async()`, but repacks the image with `padding` pixels around each tile,
+    /// copies of its edge pixels, so that adjacent tiles don't bleed into each other's
+    /// edges at non-integer zooms or with linear filtering. See `extrude_tiles()`.
+    pub async fn new_async_extruded(
+        tileset: tiled::Tileset,
+        padding: u16,
+    ) -> Result<Self, MqError> {
+        if padding == 0 {
+            return Self::new_async(tileset).await;
+        }
+        let image_source = &tileset
+            .image
+            .as_ref()
+            .expect("Only spritesheet-type tilesets are now supported")
+            .source;
+        let image = load_image(&image_source.to_string_lossy()).await?;
+        let extruded = extrude_tiles(&image, &tileset, padding);
+        Ok(Self::from_decoded(tileset, &extruded, padding))
+    }
+
+    /// Reads the image of `tileset` with macroquad's `load_file()`, which also reads
+    /// Android assets and fetches on the web, and decodes it, extruded by `padding`,
+    /// see `extrude_tiles()`. On native targets, the decoding runs on a thread of its own.
+    pub(crate) async fn decode_image(
+        tileset: &tiled::Tileset,
+        padding: u16,
+    ) -> Result<Image, MqError> {
+        let image_source = &tileset
+            .image
+            .as_ref()
+            .expect("Only spritesheet-type tilesets are now supported")
+            .source;
+        let bytes = load_file(&image_source.to_string_lossy()).await?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let tileset = tileset.clone();
+            offload(move || Self::decode_image_bytes(&tileset, &bytes, padding)).await
         }
+        #[cfg(target_arch = "wasm32")]
+        Self::decode_image_bytes(tileset, &bytes, padding)
+    }
 
-        Ok(Self::new(tileset, texture, animations))
+    /// Same as `decode_image()`, with the bytes of the image file already read.
+    pub(crate) fn decode_image_bytes(
+        tileset: &tiled::Tileset,
+        bytes: &[u8],
+        padding: u16,
+    ) -> Result<Image, MqError> {
+        let image = Image::from_file_with_format(bytes, None)?;
+        Ok(match padding {
+            0 => image,
+            _ => extrude_tiles(&image, tileset, padding),
+        })
+    }
+
+    /// A tileset of an image already decoded and extruded by `padding`, see `decode_image()`.
+    pub(crate) fn from_decoded(tileset: tiled::Tileset, image: &Image, padding: u16) -> Self {
+        let texture = Texture2D::from_image(image);
+        texture.set_filter(FilterMode::Nearest);
+        Self::from_texture(tileset, texture, padding)
+    }
+
+    /// A tileset drawing from the texture of an image extruded by `padding`, e.g. one
+    /// shared with another tileset, see `TextureCache`.
+    pub(crate) fn from_texture(tileset: tiled::Tileset, texture: Texture2D, padding: u16) -> Self {
+        let animations = load_animations(&tileset);
+        let mut tileset = Self::new(tileset, texture, animations);
+        tileset.image_size = tileset.texture.size();
+        tileset.padding = padding;
+        tileset
+    }
+
+    /// The texture, to share it, see `TextureCache`. None while it's a placeholder,
+    /// a checkerboard for a missing image, or an atlas.
+    pub(crate) fn shareable_texture(&self) -> Option<Texture2D> {
+        (!self.stand_in && !self.is_in_atlas()).then(|| self.texture.clone())
+    }
+
+    /// Same as `new_async()`, but loads the low-res placeholder of the image first,
+    /// see `texture_stream::placeholder_path()`, and the full-res image in the background.
+    /// Without a placeholder, loads the full-res image right away.
+    pub async fn new_streaming(tileset: tiled::Tileset) -> Result<Self, MqError> {
+        let image_source = &tileset
+            .image
+            .as_ref()
+            .expect("Only spritesheet-type tilesets are now supported")
+            .source;
+        let Ok(placeholder) = load_texture(&placeholder_path(image_source).to_string_lossy()).await
+        else {
+            return Self::new_async(tileset).await;
+        };
+        // Blurry rather than blocky, until the full-res texture is in.
+        placeholder.set_filter(FilterMode::Linear);
+
+        let stream = TextureStream::start(tileset.clone());
+        let animations = load_animations(&tileset);
+        let mut tileset = Self::new(tileset, placeholder, animations);
+        tileset.stream = Some(stream);
+        tileset.stand_in = true;
+        Ok(tileset)
+    }
+
+    /// A tileset drawn with a checkerboard, see `new_missing()`, until its image is loaded
+    /// in the background, decoded on a thread of its own on native targets: returns right
+    /// away. The swap happens in `Map::update()`, the checkerboard is kept if the image
+    /// fails to load.
+    pub fn new_deferred(tileset: tiled::Tileset) -> Self {
+        assert!(
+            tileset.image.is_some(),
+            "Only spritesheet-type tilesets are now supported"
+        );
+        let stream = TextureStream::start(tileset.clone());
+        let mut tileset = Self::new_missing(tileset);
+        tileset.stream = Some(stream);
+        tileset
+    }
+
+    /// A tileset drawn with a magenta and black checkerboard, a checker per half tile,
+    /// for when its image is missing, see `LoadOptions::placeholder_missing_images`.
+    pub fn new_missing(tileset: tiled::Tileset) -> Self {
+        let texture = Texture2D::from_image(&checkerboard(&tileset));
+        texture.set_filter(FilterMode::Nearest);
+        Self::new_stand_in(tileset, texture)
+    }
+
+    /// A tileset drawn with `texture` instead of its image.
+    fn new_stand_in(tileset: tiled::Tileset, texture: Texture2D) -> Self {
+        let animations = load_animations(&tileset);
+        let mut tileset = Self::new(tileset, texture, animations);
+        tileset.stand_in = true;
+        tileset
+    }
+
+    /// Whether the texture is still a placeholder, see `new_streaming()` and `new_deferred()`.
+    pub fn is_placeholder(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Replaces the texture, e.g. with one streamed by other means.
+    /// Its size may differ from the image's, sprites are scaled to it.
+    pub fn set_texture(&mut self, texture: Texture2D) {
+        self.texture = texture;
+        self.stream = None;
+        self.atlas = None;
+        self.stand_in = false;
+    }
+
+    /// The full-res image of the tileset, read back from the GPU, to pack it in an atlas.
+    /// None for placeholders, atlases and textures of another size.
+    #[cfg(feature = "render")]
+    pub(crate) fn atlas_image(&self) -> Option<Image> {
+        self.fits_atlas().then(|| self.texture.get_texture_data())
+    }
+
+    /// Whether the texture is the full-res image, which `atlas_image()` reads back.
+    #[cfg(feature = "render")]
+    pub(crate) fn fits_atlas(&self) -> bool {
+        !self.is_placeholder() && !self.is_in_atlas() && self.texture.size() == self.image_size
+    }
+
+    /// Tilesets of the same key draw the same image, see `TextureCache`.
+    #[cfg(feature = "render")]
+    pub(crate) fn texture_key(&self) -> Option<TextureKey> {
+        TextureKey::new(&self.tileset, self.padding)
+    }
+
+    /// Draws from `atlas`, of `size` pixels, where the image is at `offset`.
+    #[cfg(feature = "render")]
+    pub(crate) fn set_atlas(&mut self, atlas: Texture2D, offset: Vec2, size: Vec2) {
+        self.atlas = Some((offset, size));
+        self.texture = atlas;
+    }
+
+    /// Whether the tileset draws from an atlas, see `Map::build_atlas()`.
+    pub fn is_in_atlas(&self) -> bool {
+        self.atlas.is_some()
+    }
+
+    /// `source`, in pixels of the full-res image, in pixels of `texture`: where the image
+    /// is in the atlas, or scaled to a placeholder.
+    fn texture_rect(&self, source: Rect) -> Rect {
+        if let Some((offset, _)) = self.atlas {
+            return source.offset(offset);
+        }
+        let scale = self.texture.size() / self.image_size;
+        Rect::new(
+            source.x * scale.x,
+            source.y * scale.y,
+            source.w * scale.x,
+            source.h * scale.y,
+        )
+    }
+
+    /// Swaps in the full-res texture once it's loaded. Some when done.
+    pub(crate) fn poll_stream(&mut self) -> Option<Result<(), MqError>> {
+        self.poll_stream_with(|image| {
+            let texture = Texture2D::from_image(image);
+            texture.set_filter(FilterMode::Nearest);
+            texture
+        })
+    }
+
+    /// Same as `poll_stream()`, making the texture with `upload`.
+    fn poll_stream_with(
+        &mut self,
+        upload: impl FnOnce(&Image) -> Texture2D,
+    ) -> Option<Result<(), MqError>> {
+        let result = self.stream.as_mut()?.poll()?;
+        self.stream = None;
+        Some(result.map(|image| {
+            self.texture = upload(&image);
+            self.stand_in = false;
+        }))
+    }
+
+    /// Drawing offset of the tiles, in pixels of the image, set by `<tileoffset>` in Tiled,
+    /// e.g. to align tall isometric tiles.
+    pub fn tile_offset(&self) -> Vec2 {
+        vec2(self.tileset.offset_x as f32, self.tileset.offset_y as f32)
     }
 
     // Duplicate of get_tile_rectangle_by_id from
     // https://github.com/mapeditor/rs-tiled/pull/87
     // Remove once that is merged.
+    /// In pixels of the full-res image, even when drawn from an atlas.
     pub fn sprite_rect(&self, ix: u32) -> Rect {
         let sw = self.tileset.tile_width as f32;
         let sh = self.tileset.tile_height as f32;
+        if self.padding > 0 {
+            let (column, row) = extruded_cell(&self.tileset, ix);
+            let padding = self.padding as f32;
+            return Rect::new(
+                column as f32 * (sw + padding * 2.0) + padding,
+                row as f32 * (sh + padding * 2.0) + padding,
+                sw,
+                sh,
+            );
+        }
         let sx = (ix % self.tileset.columns) as f32 * (sw + self.tileset.spacing as f32)
             + self.tileset.margin as f32;
         let sy = (ix / self.tileset.columns) as f32 * (sh + self.tileset.spacing as f32)
@@ -96,24 +438,208 @@ impl TileSet {
     }
 
     pub fn spr(&self, sprite: u32, dest: Rect) {
-        let spr_rect = self.sprite_rect(sprite);
+        self.spr_color(sprite, dest, WHITE);
+    }
 
-        draw_texture_ex(
-            &self.texture,
-            dest.x,
-            dest.y,
-            WHITE,
-            DrawTextureParams {
-                dest_size: Some(vec2(dest.w, dest.h)),
-                source: Some(Rect::new(spr_rect.x, spr_rect.y, spr_rect.w, spr_rect.h)),
-                ..Default::default()
-            },
-        );
+    /// Same as `spr()`, tinted with `color`, e.g. for fade-ins or ghost previews.
+    pub fn spr_color(&self, sprite: u32, dest: Rect, color: Color) {
+        let params = DrawTextureParams {
+            dest_size: Some(vec2(dest.w, dest.h)),
+            source: Some(self.sprite_rect(sprite)),
+            ..Default::default()
+        };
+        self.spr_ex_color(params, dest.point(), color);
+    }
+
+    /// Same as `spr()`, mirrored and rotated by `rotation` radians around the center of
+    /// `dest`, e.g. to reuse the east-facing frames of a character for west.
+    pub fn spr_flip(&self, sprite: u32, dest: Rect, flip_x: bool, flip_y: bool, rotation: f32) {
+        let params = DrawParams {
+            color: WHITE,
+            rotation,
+            flip_x,
+            flip_y,
+        };
+        self.spr_params(sprite, dest, params);
+    }
+
+    /// Draws `sprite` into `dest` the way the map draws its tiles, see `DrawParams`.
+    pub fn spr_params(&self, sprite: u32, dest: Rect, params: DrawParams) {
+        let texture_params = DrawTextureParams {
+            dest_size: Some(dest.size()),
+            source: Some(self.sprite_rect(sprite)),
+            rotation: params.rotation,
+            flip_x: params.flip_x,
+            flip_y: params.flip_y,
+            pivot: None,
+        };
+        self.spr_ex_color(texture_params, dest.point(), params.color);
     }
 
     pub fn spr_ex(&self, params: DrawTextureParams, dest: Vec2) {
-        draw_texture_ex(&self.texture, dest[0], dest[1], WHITE, params);
+        self.spr_ex_color(params, dest, WHITE);
     }
+
+    /// `params.source` is in pixels of the full-res image, see `sprite_rect()`.
+    pub fn spr_ex_color(&self, mut params: DrawTextureParams, dest: Vec2, color: Color) {
+        if let Some(source) = &mut params.source {
+            *source = self.texture_rect(*source);
+        }
+        draw_texture_ex(&self.texture, dest[0], dest[1], color, params);
+    }
+
+    /// Draws `sprite` so that its `anchor` point lands at `pos`, in screen pixels,
+    /// rotated by `rotation` radians around that point.
+    /// `anchor` is relative to the sprite size: (0, 0) is top-left, (0.5, 0.5) is the center,
+    /// (0.5, 1) is the bottom middle, i.e. the feet.
+    /// `zoom` scales the sprite from its size in the tileset.
+    pub fn spr_anchored(&self, sprite: u32, pos: Vec2, anchor: Vec2, zoom: f32, rotation: f32) {
+        self.spr_anchored_color(sprite, pos, anchor, zoom, rotation, WHITE);
+    }
+
+    pub fn spr_anchored_color(
+        &self,
+        sprite: u32,
+        pos: Vec2,
+        anchor: Vec2,
+        zoom: f32,
+        rotation: f32,
+        color: Color,
+    ) {
+        let spr_rect = self.sprite_rect(sprite);
+        let size = spr_rect.size() * zoom;
+
+        let params = DrawTextureParams {
+            dest_size: Some(size),
+            source: Some(spr_rect),
+            rotation,
+            pivot: Some(pos),
+            ..Default::default()
+        };
+        self.spr_ex_color(params, pos - anchor * size, color);
+    }
+
+    /// Draws many sprites at once, e.g. bullets, particles or items on the ground.
+    /// Builds a mesh per up to `BATCH_SPRITES` sprites instead of issuing
+    /// a `draw_texture_ex()` per sprite.
+    pub fn spr_batch(&self, sprites: &[(u32, Rect)]) {
+        self.draw_batch(sprites.iter().map(|(sprite, dest)| (*sprite, *dest, WHITE)));
+    }
+
+    /// Same as `spr_batch()`, with a tint color per sprite.
+    pub fn spr_batch_color(&self, sprites: &[(u32, Rect, Color)]) {
+        self.draw_batch(sprites.iter().copied());
+    }
+
+    /// Same as `spr_batch()`, with a color per corner of each sprite: top-left, top-right,
+    /// bottom-right and bottom-left, blended across the sprite, e.g. for smooth lighting.
+    pub fn spr_batch_corners(&self, sprites: &[(u32, Rect, [Color; 4])]) {
+        let sprites = sprites
+            .iter()
+            .map(|(sprite, dest, corners)| (*sprite, *dest, *corners, NO_FLIPS));
+        for mesh in self.batch_meshes(sprites) {
+            draw_mesh(&mesh);
+        }
+    }
+
+    fn draw_batch(&self, sprites: impl Iterator<Item = (u32, Rect, Color)>) {
+        let sprites = sprites.map(|(sprite, dest, color)| (sprite, dest, [color; 4], NO_FLIPS));
+        for mesh in self.batch_meshes(sprites) {
+            draw_mesh(&mesh);
+        }
+    }
+
+    /// Meshes of up to `BATCH_SPRITES` sprites each, for drawing now or later.
+    /// Sprites are (sprite, dest, corner colors, (flip_h, flip_v, flip_d)), flipped like
+    /// in Tiled, see `spr_batch_corners()` for the order of the corners.
+    pub(crate) fn batch_meshes(
+        &self,
+        sprites: impl Iterator<Item = (u32, Rect, [Color; 4], Flips)>,
+    ) -> Vec<Mesh> {
+        // Texture coordinates are relative, placeholders need no scaling.
+        let (offset, texture_size) = self.atlas.unwrap_or((Vec2::ZERO, self.image_size));
+        let mut meshes = vec![];
+        let mut vertices = Vec::with_capacity(BATCH_SPRITES * 4);
+        let mut indices = Vec::with_capacity(BATCH_SPRITES * 6);
+
+        let flush = |meshes: &mut Vec<Mesh>, vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>| {
+            meshes.push(Mesh {
+                vertices: std::mem::take(vertices),
+                indices: std::mem::take(indices),
+                texture: Some(self.texture.clone()),
+            });
+        };
+
+        for (sprite, dest, corners, (flip_h, flip_v, flip_d)) in sprites {
+            let spr_rect = self.sprite_rect(sprite).offset(offset);
+            let uv = |corner: Vec2| {
+                let (mut u, mut v) = (corner.x, corner.y);
+                if flip_v {
+                    v = 1.0 - v;
+                }
+                if flip_h {
+                    u = 1.0 - u;
+                }
+                if flip_d {
+                    (u, v) = (v, u);
+                }
+                (spr_rect.point() + vec2(u, v) * spr_rect.size()) / texture_size
+            };
+
+            let base = vertices.len() as u16;
+            vertices.extend(
+                [
+                    vec2(0.0, 0.0),
+                    vec2(1.0, 0.0),
+                    vec2(1.0, 1.0),
+                    vec2(0.0, 1.0),
+                ]
+                .into_iter()
+                .zip(corners)
+                .map(|(corner, color)| {
+                    let pos = dest.point() + corner * dest.size();
+                    let uv = uv(corner);
+                    vertex(pos.x, pos.y, uv.x, uv.y, color)
+                }),
+            );
+            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+
+            if vertices.len() >= BATCH_SPRITES * 4 {
+                flush(&mut meshes, &mut vertices, &mut indices);
+            }
+        }
+
+        if !vertices.is_empty() {
+            flush(&mut meshes, &mut vertices, &mut indices);
+        }
+        meshes
+    }
+}
+
+pub(crate) fn load_animations(tileset: &tiled::Tileset) -> HashMap<u32, AnimatedTile> {
+    let mut animations = HashMap::new();
+
+    for (tile_id, tile) in tileset.tiles() {
+        if let Some(tiled_animation) = &tile.animation {
+            let frames: Vec<AnimationFrame> =
+                tiled_animation.iter().map(AnimationFrame::from).collect();
+
+            // two passes, sure, but I expect them all not to exceed 10-20 frames.
+            let total_duration = frames
+                .iter()
+                .fold(Duration::from_ticks(0), |sum, val| sum.add(val.duration));
+
+            let animation = AnimatedTile::new(
+                tile_id,
+                Animation {
+                    frames,
+                    duration: total_duration,
+                },
+            );
+            animations.insert(tile_id, animation);
+        }
+    }
+    animations
 }
 
 impl TileSet {
@@ -144,13 +670,269 @@ impl TileSet {
 
 impl TileSet {
     pub fn tile_by_name(&self, name: &str) -> Option<TileId> {
-        for (tile_id, tile) in self.tileset.tiles() {
-            if let Some(PropertyValue::StringValue(name_property)) = tile.properties.get("name") {
-                if name == name_property {
-                    return Some(tile_id);
-                }
+        self.tileset
+            .tiles()
+            .find(|(_, tile)| tile.properties.get_string("name") == Some(name))
+            .map(|(tile_id, _)| tile_id)
+    }
+
+    /// The tiles of Tiled class `class`, e.g. "door", in id order.
+    pub fn tiles_by_class(&self, class: &str) -> Vec<TileId> {
+        let mut tiles: Vec<_> = self
+            .tileset
+            .tiles()
+            .filter(|(_, tile)| tile.user_type.as_deref() == Some(class))
+            .map(|(tile_id, _)| tile_id)
+            .collect();
+        tiles.sort_unstable();
+        tiles
+    }
+
+    /// Tileset-level custom property, e.g. a default "material" of its tiles.
+    pub fn property_string(&self, name: &str) -> Option<&str> {
+        self.tileset.properties.get_string(name)
+    }
+
+    pub fn property_bool(&self, name: &str) -> Option<bool> {
+        self.tileset.properties.get_bool(name)
+    }
+
+    pub fn property_int(&self, name: &str) -> Option<i32> {
+        self.tileset.properties.get_int(name)
+    }
+
+    pub fn property_float(&self, name: &str) -> Option<f32> {
+        self.tileset.properties.get_float(name)
+    }
+
+    pub fn property_color(&self, name: &str) -> Option<Color> {
+        self.tileset.properties.get_color(name)
+    }
+
+    /// A property of a tile, or of the tileset if the tile doesn't set it, see
+    /// `tile_properties()`. The typed accessors below return `None` for other types too.
+    pub fn tile_property(&self, id: TileId, name: &str) -> Option<PropertyValue> {
+        self.tileset
+            .get_tile(id)
+            .and_then(|tile| tile.properties.get(name).cloned())
+            .or_else(|| self.tileset.properties.get(name).cloned())
+    }
+
+    /// E.g. `tile_property_bool(tile_id, "solid")`.
+    pub fn tile_property_bool(&self, id: TileId, name: &str) -> Option<bool> {
+        bool_value(&self.tile_property(id, name)?)
+    }
+
+    pub fn tile_property_int(&self, id: TileId, name: &str) -> Option<i32> {
+        int_value(&self.tile_property(id, name)?)
+    }
+
+    /// Int properties are converted too, same as `PropertiesExt::get_float()`.
+    pub fn tile_property_float(&self, id: TileId, name: &str) -> Option<f32> {
+        float_value(&self.tile_property(id, name)?)
+    }
+
+    /// String and file properties.
+    pub fn tile_property_string(&self, id: TileId, name: &str) -> Option<String> {
+        string_value(&self.tile_property(id, name)?).map(str::to_owned)
+    }
+
+    pub fn tile_property_color(&self, id: TileId, name: &str) -> Option<Color> {
+        color_value(&self.tile_property(id, name)?)
+    }
+
+    /// The properties of a tile, inheriting those of the tileset it doesn't override.
+    pub fn tile_properties(&self, id: TileId) -> Properties {
+        match self.tileset.get_tile(id) {
+            Some(tile) => inherit_properties(&self.tileset.properties, &tile.properties),
+            None => self.tileset.properties.clone(),
+        }
+    }
+}
+
+/// The most common color of a Wang id, 0 being none. Ties go to the lowest color.
+#[cfg(any(feature = "render", feature = "collision"))]
+pub(crate) fn dominant_wang_color(wang_id: [u8; 8]) -> Option<u8> {
+    let mut counts = [0; 256];
+    for color in wang_id {
+        counts[color as usize] += 1;
+    }
+    (1..=255u8)
+        .filter(|color| counts[*color as usize] > 0)
+        .max_by_key(|color| (counts[*color as usize], std::cmp::Reverse(*color)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileHandle;
+    use crate::testing::{tiny_map, tiny_tiled_map, TINY_PNG};
+    use macroquad::math::ivec2;
+    use macroquad::miniquad::{RawId, TextureId};
+
+    #[test]
+    fn test_checkerboard() {
+        let tileset = (*tiny_tiled_map().tilesets()[0]).clone();
+        let image = checkerboard(&tileset);
+        assert_eq!((image.width, image.height), (32, 32));
+        let magenta = Color::from_rgba(255, 0, 255, 255);
+        assert_eq!(image.get_pixel(0, 0), magenta);
+        assert_eq!(image.get_pixel(8, 0), Color::from_rgba(0, 0, 0, 255));
+        assert_eq!(image.get_pixel(8, 8), magenta);
+    }
+
+    #[test]
+    fn test_tiles_by_class() {
+        let map = tiny_map();
+        assert_eq!(map.tilesets["tiny"].tiles_by_class("wall"), vec![2]);
+        assert_eq!(map.tiles_by_class("wall"), vec![TileHandle::new("tiny", 2)]);
+        assert!(map.tiles_by_class("door").is_empty());
+        assert_eq!(map.tile_class_at(0, ivec2(0, 0)).as_deref(), Some("wall"));
+        assert_eq!(map.tile_class_at(0, ivec2(1, 1)), None);
+    }
+
+    #[test]
+    fn test_tile_property() {
+        let map = tiny_map();
+        let tileset = &map.tilesets["tiny"];
+        assert_eq!(tileset.tile_property_bool(2, "solid"), Some(true));
+        assert_eq!(
+            tileset.tile_property_string(2, "material").as_deref(),
+            Some("stone")
+        );
+        assert_eq!(tileset.tile_property_int(3, "night_tile"), Some(1));
+        assert_eq!(tileset.tile_property_float(3, "night_tile"), Some(1.0));
+        // Wrong types and missing properties.
+        assert_eq!(tileset.tile_property_bool(2, "material"), None);
+        assert_eq!(tileset.tile_property_bool(1, "solid"), None);
+    }
+
+    #[test]
+    fn test_extrude_tiles() {
+        // 2x2 tiles of 16 px, every pixel different.
+        let tileset = (*tiny_tiled_map().tilesets()[0]).clone();
+        let mut image = Image::gen_image_color(32, 32, WHITE);
+        for y in 0..32 {
+            for x in 0..32 {
+                image.set_pixel(x, y, Color::from_rgba(x as u8, y as u8, 0, 255));
+            }
+        }
+        let extruded = extrude_tiles(&image, &tileset, 2);
+        assert_eq!((extruded.width, extruded.height), (40, 40));
+
+        // Tile 3, in its cell from (20, 20), padding included, and its copied edges.
+        assert_eq!(extruded.get_pixel(22, 22), image.get_pixel(16, 16));
+        assert_eq!(extruded.get_pixel(20, 20), image.get_pixel(16, 16));
+        assert_eq!(extruded.get_pixel(21, 25), image.get_pixel(16, 19));
+        assert_eq!(extruded.get_pixel(39, 39), image.get_pixel(31, 31));
+        // Tile 2's right padding doesn't bleed tile 3 in.
+        assert_eq!(extruded.get_pixel(19, 25), image.get_pixel(15, 19));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_deferred_swap_in() {
+        use std::sync::mpsc::channel;
+
+        let stand_in =
+            || Texture2D::from_miniquad_texture(TextureId::from_raw_id(RawId::OpenGl(0)));
+        let tiled_tileset = (*tiny_tiled_map().tilesets()[0]).clone();
+        let mut tileset = TileSet::new_stand_in(tiled_tileset.clone(), stand_in());
+
+        // As `new_deferred()` does, the decoding on a thread of its own.
+        let (go, wait) = channel::<()>();
+        tileset.stream = Some(TextureStream::from_future(crate::offload::offload(
+            move || {
+                wait.recv().unwrap();
+                TileSet::decode_image_bytes(&tiled_tileset, TINY_PNG, 0)
+            },
+        )));
+        assert!(tileset.is_placeholder());
+        assert!(tileset
+            .poll_stream_with(|_| panic!("Nothing decoded yet"))
+            .is_none());
+        assert!(tileset.is_placeholder());
+        assert!(tileset.shareable_texture().is_none());
+
+        go.send(()).unwrap();
+        let mut uploaded = None;
+        loop {
+            let swapped = tileset.poll_stream_with(|image| {
+                uploaded = Some((image.width, image.height));
+                stand_in()
+            });
+            if let Some(swapped) = swapped {
+                assert!(swapped.is_ok());
+                break;
             }
+            std::thread::yield_now();
         }
-        None
+        assert_eq!(uploaded, Some((32, 32)));
+        assert!(!tileset.is_placeholder());
+        assert!(tileset.shareable_texture().is_some());
+        assert!(tileset.poll_stream_with(|_| stand_in()).is_none());
+    }
+
+    #[test]
+    fn test_stand_ins_are_not_shared() {
+        let stand_in =
+            || Texture2D::from_miniquad_texture(TextureId::from_raw_id(RawId::OpenGl(0)));
+        let tiled_tileset = (*tiny_tiled_map().tilesets()[0]).clone();
+
+        // The image failed to load: the stand-in is kept, but not under its key.
+        let mut tileset = TileSet::new_stand_in(tiled_tileset.clone(), stand_in());
+        tileset.stream = Some(TextureStream::from_future(async {
+            Err(MqError::UnknownError("missing"))
+        }));
+        assert!(matches!(
+            tileset.poll_stream_with(|_| stand_in()),
+            Some(Err(_))
+        ));
+        assert!(!tileset.is_placeholder());
+        assert!(tileset.shareable_texture().is_none());
+
+        tileset.set_texture(stand_in());
+        assert!(tileset.shareable_texture().is_some());
+        let tileset = TileSet::new(tiled_tileset, stand_in(), HashMap::new());
+        assert!(tileset.shareable_texture().is_some());
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn test_atlas_sources() {
+        let stand_in = Texture2D::from_miniquad_texture(TextureId::from_raw_id(RawId::OpenGl(0)));
+        let tiled_tileset = (*tiny_tiled_map().tilesets()[0]).clone();
+        let mut tileset = TileSet::new(tiled_tileset, stand_in.clone(), HashMap::new());
+        tileset.set_atlas(stand_in, vec2(32.0, 0.0), vec2(64.0, 32.0));
+        assert!(tileset.is_in_atlas());
+
+        // Sources stay in pixels of the image, and are moved to where it is in the atlas.
+        let source = tileset.sprite_rect(3);
+        assert_eq!(source, Rect::new(16.0, 16.0, 16.0, 16.0));
+        assert_eq!(
+            tileset.texture_rect(source),
+            Rect::new(48.0, 16.0, 16.0, 16.0)
+        );
+
+        let sprites = [(3, Rect::new(0.0, 0.0, 16.0, 16.0), [WHITE; 4], NO_FLIPS)];
+        let meshes = tileset.batch_meshes(sprites.into_iter());
+        let uvs: Vec<Vec2> = meshes[0].vertices.iter().map(|vertex| vertex.uv).collect();
+        assert_eq!(
+            uvs,
+            vec![
+                vec2(0.75, 0.5),
+                vec2(1.0, 0.5),
+                vec2(1.0, 1.0),
+                vec2(0.75, 1.0)
+            ]
+        );
+    }
+
+    #[cfg(any(feature = "render", feature = "collision"))]
+    #[test]
+    fn test_dominant_wang_color() {
+        assert_eq!(dominant_wang_color([0; 8]), None);
+        assert_eq!(dominant_wang_color([0, 2, 0, 1, 0, 2, 0, 1]), Some(1));
+        assert_eq!(dominant_wang_color([0, 2, 0, 2, 0, 2, 0, 1]), Some(2));
     }
 }
@@ -0,0 +1,222 @@
+use std::f32::consts::TAU;
+
+use coarsetime::Duration;
+use macroquad::color::Color;
+use macroquad::math::{vec2, Rect, Vec2};
+use macroquad::shapes::{draw_rectangle, draw_triangle};
+
+use crate::clock::MapClock;
+use crate::map::world_px_to_screen;
+
+/// Segments of the iris circle.
+const IRIS_SEGMENTS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WipeDirection {
+    /// The cover moves from the right edge to the left one.
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// What a transition looks like, see `Transitions`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransitionKind {
+    /// Fades the screen to `color`.
+    Fade(Color),
+    /// Covers the screen with `color`, edge to edge.
+    Wipe(WipeDirection, Color),
+    /// Covers the screen with `color` but for a circle shrinking around a point,
+    /// in world pixels, e.g. the player.
+    Iris(Vec2, Color),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionPhase {
+    /// From the scene to covered.
+    Out,
+    /// From covered to the scene.
+    In,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Transition {
+    kind: TransitionKind,
+    phase: TransitionPhase,
+    /// Clock time at the start.
+    start: Duration,
+    duration: Duration,
+}
+
+/// Full-screen transitions, e.g. for teleports and cutscenes: fade out, switch maps,
+/// fade in. Timed by the map clock, so they pause and slow down with the game.
+/// Draw them after the map, see `draw()`.
+#[derive(Clone, Debug, Default)]
+pub struct Transitions {
+    current: Option<Transition>,
+    /// Stays covered after an `Out`, until the next transition.
+    covered: Option<TransitionKind>,
+}
+
+impl Transitions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts covering the screen, replacing the current transition.
+    pub fn start_out(&mut self, clock: &MapClock, kind: TransitionKind, duration: Duration) {
+        self.start(clock, kind, TransitionPhase::Out, duration);
+    }
+
+    /// Starts revealing the screen, replacing the current transition.
+    pub fn start_in(&mut self, clock: &MapClock, kind: TransitionKind, duration: Duration) {
+        self.start(clock, kind, TransitionPhase::In, duration);
+    }
+
+    pub fn start(
+        &mut self,
+        clock: &MapClock,
+        kind: TransitionKind,
+        phase: TransitionPhase,
+        duration: Duration,
+    ) {
+        self.covered = None;
+        self.current = Some(Transition {
+            kind,
+            phase,
+            start: clock.elapsed(),
+            duration,
+        });
+    }
+
+    /// If the current transition is over, e.g. to switch maps after an `Out`.
+    pub fn is_done(&self, clock: &MapClock) -> bool {
+        match self.current {
+            Some(transition) => progress(&transition, clock.elapsed()) >= 1.0,
+            None => true,
+        }
+    }
+
+    /// How much of the screen is covered, from 0 to 1.
+    pub fn coverage(&self, clock: &MapClock) -> f32 {
+        match (self.current, self.covered) {
+            (Some(transition), _) => coverage(&transition, clock.elapsed()),
+            (None, Some(_)) => 1.0,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Draws the transition over `dest`, the screen rect the map was drawn into from
+    /// `source_px`, in world pixels. Finished `In` transitions are dropped, finished `Out`
+    /// ones keep the screen covered.
+    pub fn draw(&mut self, clock: &MapClock, dest: Rect, source_px: Rect) {
+        if let Some(transition) = self.current {
+            if progress(&transition, clock.elapsed()) >= 1.0 {
+                self.current = None;
+                if transition.phase == TransitionPhase::Out {
+                    self.covered = Some(transition.kind);
+                }
+            }
+        }
+        let kind = match (self.current, self.covered) {
+            (Some(transition), _) => transition.kind,
+            (None, Some(kind)) => kind,
+            (None, None) => return,
+        };
+        let coverage = self.coverage(clock);
+        if coverage <= 0.0 {
+            return;
+        }
+
+        match kind {
+            TransitionKind::Fade(color) => {
+                let color = Color {
+                    a: color.a * coverage,
+                    ..color
+                };
+                draw_rectangle(dest.x, dest.y, dest.w, dest.h, color);
+            }
+            TransitionKind::Wipe(direction, color) => {
+                let (w, h) = (dest.w * coverage, dest.h * coverage);
+                let rect = match direction {
+                    WipeDirection::Left => Rect::new(dest.right() - w, dest.y, w, dest.h),
+                    WipeDirection::Right => Rect::new(dest.x, dest.y, w, dest.h),
+                    WipeDirection::Up => Rect::new(dest.x, dest.bottom() - h, dest.w, h),
+                    WipeDirection::Down => Rect::new(dest.x, dest.y, dest.w, h),
+                };
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+            }
+            TransitionKind::Iris(center_px, color) => {
+                let center = world_px_to_screen(center_px, source_px, dest);
+                let corners = [
+                    dest.point(),
+                    vec2(dest.right(), dest.y),
+                    vec2(dest.x, dest.bottom()),
+                    vec2(dest.right(), dest.bottom()),
+                ];
+                let far = corners
+                    .iter()
+                    .map(|corner| corner.distance(center))
+                    .fold(0.0, f32::max);
+                draw_iris(center, far * (1.0 - coverage), far + 1.0, color);
+            }
+        }
+    }
+}
+
+fn progress(transition: &Transition, now: Duration) -> f32 {
+    if now < transition.start {
+        return 0.0;
+    }
+    if transition.duration.as_ticks() == 0 {
+        return 1.0;
+    }
+    let elapsed = (now - transition.start).as_ticks() as f32;
+    (elapsed / transition.duration.as_ticks() as f32).min(1.0)
+}
+
+fn coverage(transition: &Transition, now: Duration) -> f32 {
+    let progress = progress(transition, now);
+    match transition.phase {
+        TransitionPhase::Out => progress,
+        TransitionPhase::In => 1.0 - progress,
+    }
+}
+
+/// Fills the ring between `inner` and `outer` radii around `center`.
+fn draw_iris(center: Vec2, inner: f32, outer: f32, color: Color) {
+    let point = |i: usize, radius: f32| {
+        let angle = i as f32 / IRIS_SEGMENTS as f32 * TAU;
+        center + Vec2::from_angle(angle) * radius
+    };
+    for i in 0..IRIS_SEGMENTS {
+        let (a, b) = (point(i, inner), point(i + 1, inner));
+        let (c, d) = (point(i, outer), point(i + 1, outer));
+        draw_triangle(a, c, d, color);
+        draw_triangle(a, d, b, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let second = Duration::from_secs(1);
+        let mut transition = Transition {
+            kind: TransitionKind::Fade(Color::new(0., 0., 0., 1.)),
+            phase: TransitionPhase::Out,
+            start: second,
+            duration: second,
+        };
+        assert_eq!(coverage(&transition, Duration::from_secs(0)), 0.0);
+        assert_eq!(coverage(&transition, second + second / 2), 0.5);
+        assert_eq!(coverage(&transition, second * 3), 1.0);
+
+        transition.phase = TransitionPhase::In;
+        assert_eq!(coverage(&transition, second + second / 2), 0.5);
+        assert_eq!(coverage(&transition, second * 3), 0.0);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeSet, HashMap};
+
+use macroquad::math::{ivec2, IVec2};
+use tiled::LayerType;
+
+use crate::layer_data::Rows;
+use crate::map::{Map, TileHandle};
+
+/// How often tiles are used in a map, see `Map::tile_usage()`.
+#[derive(Clone, Debug, Default)]
+pub struct TileUsage {
+    /// tileset -> tile id -> number of cells and tile objects using it.
+    pub counts: HashMap<String, HashMap<u32, usize>>,
+    /// Tiles never used, sorted. Frames of used animations count as used.
+    pub unused: Vec<TileHandle>,
+}
+
+impl TileUsage {
+    pub fn count(&self, tileset: &str, tile_id: u32) -> usize {
+        self.counts
+            .get(tileset)
+            .and_then(|counts| counts.get(&tile_id))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl Map {
+    /// Visits every tile of every tile layer, runtime edits included:
+    /// `visit(layer, pos, tileset, tile_id)`.
+    /// `progress(done)` is called after every row of every layer, `done` going from 0 to 1,
+    /// to show a progress bar when scanning big maps.
+    pub fn scan_tiles(
+        &self,
+        mut visit: impl FnMut(usize, IVec2, &str, u32),
+        mut progress: impl FnMut(f32),
+    ) {
+        let rows: Vec<(usize, Rows)> = (0..self.layer_count())
+            .map(|layer| (layer, self.layer_rows(layer)))
+            .collect();
+        let total = rows
+            .iter()
+            .map(|(_, rows)| rows.len())
+            .sum::<usize>()
+            .max(1);
+
+        let mut done = 0;
+        for (layer, rows) in rows {
+            for (y, xs) in rows {
+                for (x, tile) in xs {
+                    if let Some(tile) = tile {
+                        visit(layer, ivec2(x, y), tile.tileset, tile.id);
+                    }
+                }
+                done += 1;
+                progress(done as f32 / total as f32);
+            }
+        }
+    }
+
+    /// Counts tiles used on tile layers and by tile objects, and lists the unused ones,
+    /// e.g. to trim tilesets.
+    pub fn tile_usage(&self) -> TileUsage {
+        self.tile_usage_progress(|_| {})
+    }
+
+    /// Same as `tile_usage()`, reporting progress as `Map::fold_tiles()` does.
+    /// With the "rayon" feature, rows are scanned in parallel.
+    pub fn tile_usage_progress(&self, progress: impl FnMut(f32)) -> TileUsage {
+        type Counts = HashMap<String, HashMap<u32, usize>>;
+        fn add(counts: &mut Counts, tileset: &str, tile_id: u32, count: usize) {
+            *counts
+                .entry(tileset.to_string())
+                .or_default()
+                .entry(tile_id)
+                .or_default() += count;
+        }
+
+        let mut usage = TileUsage {
+            counts: self.fold_tiles(
+                Counts::new,
+                |counts, _, _, tileset, tile_id| add(counts, tileset, tile_id, 1),
+                |mut a, b| {
+                    for (tileset, ids) in b {
+                        for (id, count) in ids {
+                            add(&mut a, &tileset, id, count);
+                        }
+                    }
+                    a
+                },
+                progress,
+            ),
+            unused: vec![],
+        };
+
+        for layer in self.map.layers() {
+            if let LayerType::Objects(objects) = layer.layer_type() {
+                for object in objects.objects() {
+                    if let Some(tile) = object.get_tile() {
+                        add(&mut usage.counts, &tile.get_tileset().name, tile.id(), 1);
+                    }
+                }
+            }
+        }
+
+        for (name, tileset) in &self.tilesets {
+            let counts = usage.counts.get(name);
+            let mut used: BTreeSet<u32> = counts
+                .map(|counts| counts.keys().copied().collect())
+                .unwrap_or_default();
+            for id in used.clone() {
+                let animation = tileset
+                    .tileset
+                    .get_tile(id)
+                    .and_then(|tile| tile.animation.clone());
+                used.extend(animation.iter().flatten().map(|frame| frame.tile_id));
+            }
+            usage.unused.extend(
+                (0..tileset.tileset.tilecount)
+                    .filter(|id| !used.contains(id))
+                    .map(|id| TileHandle::new(name, id)),
+            );
+        }
+        usage
+            .unused
+            .sort_by(|a, b| (&a.tileset, a.id).cmp(&(&b.tileset, b.id)));
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{stand_in_map, tiny_map};
+
+    #[test]
+    fn test_scan_tiles() {
+        let mut map = tiny_map();
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_tile(ground, ivec2(0, 0), None);
+
+        let mut visited = vec![];
+        let mut progress = vec![];
+        map.scan_tiles(
+            |layer, pos, tileset, tile_id| visited.push((layer, pos, tileset.to_string(), tile_id)),
+            |done| progress.push(done),
+        );
+        assert_eq!(visited.len(), 15);
+        assert_eq!(visited[0], (ground, ivec2(1, 0), "tiny".to_string(), 2));
+        assert!(visited.contains(&(ground, ivec2(1, 1), "tiny".to_string(), 0)));
+        // A row at a time, up to 1.
+        assert_eq!(progress, [0.25, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn test_tile_usage() {
+        let mut map = tiny_map();
+        let usage = map.tile_usage();
+        assert_eq!(usage.count("tiny", 2), 12);
+        assert_eq!(usage.count("tiny", 3), 2);
+        assert_eq!(usage.count("tiny", 0), 1);
+        assert_eq!(usage.count("tiny", 1), 1);
+        assert_eq!(usage.count("other", 1), 0);
+        assert!(usage.unused.is_empty());
+
+        // Tile 1 only as a frame of tile 0 still counts as used.
+        let ground = map.layer_by_name("ground").unwrap();
+        map.set_tile(ground, ivec2(2, 2), Some(TileHandle::new("tiny", 2)));
+        let usage = map.tile_usage();
+        assert_eq!(usage.count("tiny", 1), 0);
+        assert!(usage.unused.is_empty());
+        map.set_tile(ground, ivec2(1, 1), Some(TileHandle::new("tiny", 2)));
+        let mut progress = vec![];
+        let usage = map.tile_usage_progress(|done| progress.push(done));
+        assert_eq!(usage.count("tiny", 2), 14);
+        assert_eq!(
+            usage.unused,
+            [TileHandle::new("tiny", 0), TileHandle::new("tiny", 1)]
+        );
+        assert_eq!(progress.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn test_tile_usage_objects() {
+        let map = stand_in_map(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="2" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="3" nextobjectid="3">
+ <tileset firstgid="1" source="tiny.tsx"/>
+ <layer id="1" name="ground" width="2" height="1">
+  <data encoding="csv">
+3,3
+</data>
+ </layer>
+ <objectgroup id="2" name="objects">
+  <object id="1" gid="4" x="0" y="16" width="16" height="16"/>
+  <object id="2" gid="4" x="16" y="16" width="16" height="16"/>
+ </objectgroup>
+</map>"#,
+        );
+        let usage = map.tile_usage();
+        assert_eq!(usage.count("tiny", 2), 2);
+        assert_eq!(usage.count("tiny", 3), 2);
+        assert_eq!(
+            usage.unused,
+            [TileHandle::new("tiny", 0), TileHandle::new("tiny", 1)]
+        );
+    }
+}
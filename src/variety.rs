@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use macroquad::math::IVec2;
+use tiled::TileData;
+
+use crate::rng::MapRng;
+use crate::tileset::TileSet;
+
+/// Bool layer property: substitute tiles with random variants of them, see `VariantGroups`.
+pub const AUTO_VARIETY_PROPERTY: &str = "auto_variety";
+
+/// Groups of interchangeable tiles of a tileset, to break up repetitive grass or floors:
+/// tiles of the same class, or else tiles with the same Wang id in a Wang set.
+/// Variants are weighted by the tile probability set in Tiled.
+#[derive(Clone, Debug, Default)]
+pub struct VariantGroups {
+    /// Each group: (tile id, probability).
+    groups: Vec<Vec<(u32, f32)>>,
+    group_of: HashMap<u32, usize>,
+    /// The tiles of each class, see `TileSet::variants_of()`, sorted by id.
+    by_class: HashMap<String, Vec<(u32, f32)>>,
+    /// The tiles entirely of a color, by (Wang set, color), sorted by id.
+    by_wang_color: HashMap<(usize, u8), Vec<(u32, f32)>>,
+}
+
+impl VariantGroups {
+    pub fn new(tileset: &tiled::Tileset) -> Self {
+        #[derive(PartialEq, Eq, Hash)]
+        enum Key {
+            Class(String),
+            Wang(usize, [u8; 8]),
+        }
+
+        let mut keys: HashMap<Key, Vec<(u32, f32)>> = HashMap::new();
+        for (id, tile) in tileset.tiles() {
+            if let Some(class) = &tile.user_type {
+                keys.entry(Key::Class(class.clone()))
+                    .or_default()
+                    .push((id, tile_probability(tileset, id)));
+            }
+        }
+        for (set, wang_set) in tileset.wang_sets.iter().enumerate() {
+            for (id, wang_tile) in &wang_set.wang_tiles {
+                let has_class = tileset
+                    .get_tile(*id)
+                    .map(|tile| tile.user_type.is_some())
+                    .unwrap_or(false);
+                if !has_class {
+                    keys.entry(Key::Wang(set, wang_tile.wang_id.0))
+                        .or_default()
+                        .push((*id, tile_probability(tileset, *id)));
+                }
+            }
+        }
+
+        let mut groups = Self::default();
+        for (set, wang_set) in tileset.wang_sets.iter().enumerate() {
+            for (id, wang_tile) in &wang_set.wang_tiles {
+                // Positions unused by the kind of Wang set are 0.
+                let wang_id = wang_tile.wang_id.0;
+                let Some(color) = wang_id.iter().copied().find(|color| *color != 0) else {
+                    continue;
+                };
+                if wang_id.iter().all(|c| *c == 0 || *c == color) {
+                    groups
+                        .by_wang_color
+                        .entry((set, color))
+                        .or_default()
+                        .push((*id, tile_probability(tileset, *id)));
+                }
+            }
+        }
+        for (key, mut group) in keys {
+            // Deterministic picks regardless of the HashMap order.
+            group.sort_by_key(|(id, _)| *id);
+            if let Key::Class(class) = key {
+                groups.by_class.insert(class, group.clone());
+            }
+            if group.len() < 2 {
+                continue;
+            }
+            for (id, _) in &group {
+                groups.group_of.insert(*id, groups.groups.len());
+            }
+            groups.groups.push(group);
+        }
+        for tiles in groups.by_wang_color.values_mut() {
+            tiles.sort_by_key(|(id, _)| *id);
+        }
+        groups
+    }
+
+    /// Variants of `tile_id`, itself included: (tile id, probability).
+    pub fn variants(&self, tile_id: u32) -> Option<&[(u32, f32)]> {
+        self.group_of
+            .get(&tile_id)
+            .map(|group| self.groups[*group].as_slice())
+    }
+
+    /// A variant of `tile_id` for the cell `pos`, random but always the same for the cell.
+    pub fn pick(&self, tile_id: u32, pos: IVec2) -> u32 {
+        self.pick_with(tile_id, pos, &MapRng::default())
+    }
+
+    /// Same as `pick()`, under the seed of `rng`, see `Map::rng()`.
+    pub fn pick_with(&self, tile_id: u32, pos: IVec2, rng: &MapRng) -> u32 {
+        let Some(variants) = self.variants(tile_id) else {
+            return tile_id;
+        };
+        let roll = rng.cell_hash(pos, 0) as f32 / u32::MAX as f32;
+        pick_weighted(variants, roll).unwrap_or(tile_id)
+    }
+
+    /// A variant of `tile_id` drawn from `rng`, e.g. for procedural generation,
+    /// where the same seed must give the same map.
+    pub fn pick_random(&self, tile_id: u32, rng: &mut MapRng) -> u32 {
+        let Some(variants) = self.variants(tile_id) else {
+            return tile_id;
+        };
+        pick_weighted(variants, rng.next_f32()).unwrap_or(tile_id)
+    }
+}
+
+/// Interchangeable tiles of a tileset, see `TileSet::random_variant()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VariantKey<'a> {
+    /// The tiles of a class.
+    Class(&'a str),
+    /// The tiles of a Wang set, by index, entirely of a color, 1-based like in Tiled,
+    /// e.g. the plain grass tiles of a terrain.
+    WangColor { wang_set: usize, color: u8 },
+}
+
+impl TileSet {
+    /// The tiles of `key`, with their probability set in Tiled, sorted by id.
+    /// Gathered when the tileset is loaded.
+    pub fn variants_of(&self, key: VariantKey) -> &[(u32, f32)] {
+        let tiles = match key {
+            VariantKey::Class(class) => self.variants.by_class.get(class),
+            VariantKey::WangColor { wang_set, color } => {
+                self.variants.by_wang_color.get(&(wang_set, color))
+            }
+        };
+        tiles.map_or(&[], Vec::as_slice)
+    }
+
+    /// One of the tiles of `key` drawn from `rng`, weighted by their probability,
+    /// for procedural generation and runtime repainting. `None` if there's none.
+    pub fn random_variant(&self, key: VariantKey, rng: &mut MapRng) -> Option<u32> {
+        pick_weighted(self.variants_of(key), rng.next_f32())
+    }
+}
+
+/// The probability of `id` set in Tiled, 1 by default. tiled fills the tiles the TSX
+/// leaves out in with a probability of 0, so tiles without any data count as 1.
+pub(crate) fn tile_probability(tileset: &tiled::Tileset, id: u32) -> f32 {
+    match tileset.get_tile(id) {
+        Some(tile) if *tile != TileData::default() => tile.probability,
+        _ => 1.0,
+    }
+}
+
+/// The tile at `roll`, in `0..1`, of the total weight of `variants`.
+/// `None` if the total is 0.
+pub(crate) fn pick_weighted(variants: &[(u32, f32)], roll: f32) -> Option<u32> {
+    let total: f32 = variants.iter().map(|(_, weight)| weight.max(0.0)).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut roll = roll * total;
+    for (id, weight) in variants {
+        let weight = weight.max(0.0);
+        if roll < weight {
+            return Some(*id);
+        }
+        roll -= weight;
+    }
+    // Rounding errors at the very end.
+    variants
+        .iter()
+        .rev()
+        .find(|(_, weight)| *weight > 0.0)
+        .map(|(id, _)| *id)
+}
+
+/// Integer hash of a cell, uniform enough for picking variants.
+pub(crate) fn hash_pos(pos: IVec2) -> u32 {
+    let mut x = (pos.x as u32).wrapping_mul(0x8DA6_B343) ^ (pos.y as u32).wrapping_mul(0xD816_3841);
+    x = (x ^ (x >> 16)).wrapping_mul(0x7FEB_352D);
+    x = (x ^ (x >> 15)).wrapping_mul(0x846C_A68B);
+    x ^ (x >> 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{terrain_map, tiny_map};
+    use macroquad::math::ivec2;
+
+    #[test]
+    fn test_pick() {
+        let groups = VariantGroups {
+            groups: vec![vec![(1, 3.0), (2, 1.0), (3, 0.0)]],
+            group_of: HashMap::from([(1, 0), (2, 0), (3, 0)]),
+            ..Default::default()
+        };
+        assert_eq!(groups.pick(7, ivec2(0, 0)), 7);
+
+        let mut counts = [0; 4];
+        for y in 0..100 {
+            for x in 0..100 {
+                let pos = ivec2(x, y);
+                let tile = groups.pick(2, pos);
+                assert_eq!(tile, groups.pick(1, pos));
+                counts[tile as usize] += 1;
+            }
+        }
+        assert_eq!(counts[3], 0);
+        // Roughly 3:1.
+        assert!(counts[1] > counts[2] * 2 && counts[1] < counts[2] * 4);
+    }
+
+    #[test]
+    fn test_variants_of() {
+        let map = terrain_map();
+        let tileset = &map.tilesets["terrain"];
+        let grass = VariantKey::WangColor {
+            wang_set: 0,
+            color: 1,
+        };
+        let dirt = VariantKey::WangColor {
+            wang_set: 0,
+            color: 2,
+        };
+        assert_eq!(tileset.variants_of(grass), &[(0, 1.0), (1, 3.0), (2, 0.0)]);
+        assert_eq!(tileset.variants_of(dirt), &[(3, 1.0), (5, 1.0)]);
+        assert_eq!(
+            tileset.variants_of(VariantKey::Class("floor")),
+            &[(6, 1.0), (7, 2.0)]
+        );
+        assert!(tileset
+            .variants_of(VariantKey::WangColor {
+                wang_set: 1,
+                color: 1
+            })
+            .is_empty());
+        // Gathered once.
+        assert!(std::ptr::eq(
+            tileset.variants_of(grass),
+            tileset.variants_of(grass)
+        ));
+        // Tiles with the same Wang id and no class are auto variety groups too.
+        assert_eq!(
+            tileset.variants.variants(3),
+            Some(&[(3, 1.0), (5, 1.0)][..])
+        );
+        assert_eq!(tileset.variants.variants(4), None);
+    }
+
+    #[test]
+    fn test_random_variant() {
+        let map = terrain_map();
+        let tileset = &map.tilesets["terrain"];
+        let grass = VariantKey::WangColor {
+            wang_set: 0,
+            color: 1,
+        };
+        let mut rng = MapRng::new(7);
+        let mut counts = [0; 8];
+        for _ in 0..4000 {
+            counts[tileset.random_variant(grass, &mut rng).unwrap() as usize] += 1;
+            counts[tileset
+                .random_variant(VariantKey::Class("floor"), &mut rng)
+                .unwrap() as usize] += 1;
+        }
+        // Grass 1:3:0, floors 1:2.
+        assert_eq!(counts[2], 0);
+        assert!((2700..3300).contains(&counts[1]), "{:?}", counts);
+        assert!((2400..2950).contains(&counts[7]), "{:?}", counts);
+
+        // The same seed draws the same tiles.
+        let draw = |seed| {
+            let mut rng = MapRng::new(seed);
+            (0..16)
+                .map(|_| tileset.random_variant(grass, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(draw(3), draw(3));
+        assert_ne!(draw(3), draw(4));
+
+        let tiny = tiny_map();
+        let tiny = &tiny.tilesets["tiny"];
+        assert_eq!(
+            tiny.random_variant(VariantKey::Class("wall"), &mut rng),
+            Some(2)
+        );
+        assert_eq!(
+            tiny.random_variant(VariantKey::Class("door"), &mut rng),
+            None
+        );
+        assert_eq!(tiny.random_variant(grass, &mut rng), None);
+
+        assert_eq!(pick_weighted(&[(1, 3.0), (2, 1.0)], 0.7), Some(1));
+        assert_eq!(pick_weighted(&[(1, 3.0), (2, 1.0)], 0.8), Some(2));
+        assert_eq!(pick_weighted(&[(1, 3.0), (2, 0.0)], 1.0), Some(1));
+        assert_eq!(pick_weighted(&[(1, 0.0)], 0.5), None);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+
+use macroquad::color::Color;
+use macroquad::math::{IVec2, Rect, Vec2};
+use macroquad::shapes::draw_triangle;
+
+use crate::animation_controller::Facing;
+use crate::map::{world_px_to_screen, Map};
+use crate::raycast::raycast;
+
+/// What an entity sees, for stealth games: a cone of `angle` radians around `direction`,
+/// `range` tiles long, stopped by blocking tiles, see `cast()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VisionCone {
+    /// The eye, in world tiles, e.g. the center of the entity's cell.
+    pub origin: Vec2,
+    pub direction: Vec2,
+    /// Full width of the cone, in radians.
+    pub angle: f32,
+    /// In tiles.
+    pub range: f32,
+}
+
+/// The result of `VisionCone::cast()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vision {
+    /// Seen cells, blocking ones included: walls are seen, not what's behind them.
+    pub cells: HashSet<IVec2>,
+    /// The visible area as a fan, in world tiles: the origin, then the ends of the rays
+    /// from one edge of the cone to the other.
+    pub polygon: Vec<Vec2>,
+}
+
+impl VisionCone {
+    pub fn new(origin: Vec2, facing: Facing, angle: f32, range: f32) -> Self {
+        Self {
+            origin,
+            direction: facing.to_vec2(),
+            angle,
+            range,
+        }
+    }
+
+    /// Casts rays across the cone, about two per tile at its far end, stopped by
+    /// the cells for which `blocks(cell)`, e.g. `CollisionGrid::is_solid()`.
+    pub fn cast(&self, blocks: impl Fn(IVec2) -> bool) -> Vision {
+        let mut vision = Vision {
+            polygon: vec![self.origin],
+            ..Default::default()
+        };
+        let rays = (self.angle * self.range * 2.0).ceil().max(1.0) as u32 + 1;
+        let start = self.direction.y.atan2(self.direction.x) - self.angle / 2.0;
+        for ray in 0..rays {
+            let angle = start + self.angle * ray as f32 / (rays - 1) as f32;
+            let hit = raycast(self.origin, Vec2::from_angle(angle), self.range, |cell| {
+                vision.cells.insert(cell);
+                blocks(cell)
+            });
+            vision.polygon.push(hit.point);
+        }
+        vision
+    }
+}
+
+impl Vision {
+    pub fn is_visible(&self, cell: IVec2) -> bool {
+        self.cells.contains(&cell)
+    }
+
+    /// Fills the polygon with `color`, e.g. a translucent one, on an orthogonal map.
+    /// `source_px` and `dest` are the same as for `Map::draw_tiles()`.
+    pub fn draw(&self, map: &Map, source_px: Rect, dest: Rect, color: Color) {
+        let to_screen = |world_tiles: Vec2| {
+            world_px_to_screen(world_tiles * map.tile_size_px(), source_px, dest)
+        };
+        let Some((origin, edge)) = self.polygon.split_first() else {
+            return;
+        };
+        let origin = to_screen(*origin);
+        for pair in edge.windows(2) {
+            draw_triangle(origin, to_screen(pair[0]), to_screen(pair[1]), color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macroquad::math::{ivec2, vec2};
+
+    #[test]
+    fn test_cast() {
+        let cone = VisionCone::new(vec2(0.5, 0.5), Facing::East, 1.0, 5.0);
+        // A wall at x = 3, with a gap at y = 0.
+        let vision = cone.cast(|cell| cell.x == 3 && cell.y != 0);
+
+        assert!(vision.is_visible(ivec2(4, 0)));
+        assert!(vision.is_visible(ivec2(3, 1)));
+        assert!(!vision.is_visible(ivec2(4, 2)));
+        // Behind the entity.
+        assert!(!vision.is_visible(ivec2(-1, 0)));
+        assert_eq!(vision.polygon[0], cone.origin);
+        assert!(vision.polygon.len() > 3);
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use macroquad::math::{ivec2, IVec2};
+
+use crate::map::Map;
+use crate::properties::PropertiesExt;
+use crate::tileset::{dominant_wang_color, TileSet};
+
+/// Marks the walls of 2.5D maps, seen from the front and above. A string property,
+/// "top" or "front", of a tile, or of its dominant Wang color. A Wang color may instead
+/// have it true, see `WallPart::from_wang_id()`.
+pub const WALL_PROPERTY: &str = "wall";
+
+/// How far down `Map::wall_base()` looks for the bottom of a wall, in tiles.
+pub const MAX_WALL_ROWS: i32 = 8;
+
+/// Part of a wall of a 2.5D map. The front stands on the ground and blocks, see
+/// `Map::collision_grid()`. The top is overhead: characters walk behind it, and
+/// `Map::draw_tiles_rows()` draws it with the row of the bottom of the front below,
+/// see `Map::wall_base()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WallPart {
+    Top,
+    Front,
+}
+
+impl WallPart {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top" => Some(Self::Top),
+            "front" => Some(Self::Front),
+            _ => None,
+        }
+    }
+
+    /// The part of a tile of Wang color `color` marked as a wall, with no part of
+    /// its own: a front if the color is on the top side of the tile, but not on its
+    /// bottom side, i.e. the bottom edge of the wall, a top otherwise.
+    pub fn from_wang_id(wang_id: [u8; 8], color: u8) -> Self {
+        // Top, top right, right, bottom right, bottom, bottom left, left, top left.
+        let has = |sides: [usize; 3]| sides.iter().any(|side| wang_id[*side] == color);
+        if has([7, 0, 1]) && !has([3, 4, 5]) {
+            Self::Front
+        } else {
+            Self::Top
+        }
+    }
+}
+
+/// The wall parts of the tiles of `tileset`, by tile id, see `TileSet::wall_part()`.
+pub(crate) fn wall_parts(tileset: &tiled::Tileset) -> HashMap<u32, WallPart> {
+    let mut parts = HashMap::new();
+    for id in 0..tileset.tilecount {
+        let tile = tileset.get_tile(id);
+        let name = tile
+            .as_ref()
+            .and_then(|tile| tile.properties.get_string(WALL_PROPERTY))
+            .or_else(|| tileset.properties.get_string(WALL_PROPERTY));
+        let part = match name {
+            Some(name) => WallPart::from_name(name),
+            None => wang_wall_part(tileset, id),
+        };
+        if let Some(part) = part {
+            parts.insert(id, part);
+        }
+    }
+    parts
+}
+
+/// The wall part of the dominant Wang color of the tile `id`, if any.
+fn wang_wall_part(tileset: &tiled::Tileset, id: u32) -> Option<WallPart> {
+    tileset.wang_sets.iter().find_map(|wang_set| {
+        let wang_id = wang_set.wang_tiles.get(&id)?.wang_id.0;
+        let color = dominant_wang_color(wang_id)?;
+        let wang_color = wang_set.wang_colors.get(color as usize - 1)?;
+        let properties = &wang_color.properties;
+        match properties.get_string(WALL_PROPERTY) {
+            Some(part) => WallPart::from_name(part),
+            None if properties.get_bool(WALL_PROPERTY)? => {
+                Some(WallPart::from_wang_id(wang_id, color))
+            }
+            None => None,
+        }
+    })
+}
+
+impl TileSet {
+    /// The wall part of the tile `tile_id`, if any: its `WALL_PROPERTY`, or else the one
+    /// of its dominant Wang color. Found when the tileset is loaded.
+    pub fn wall_part(&self, tile_id: u32) -> Option<WallPart> {
+        self.wall_parts.get(&tile_id).copied()
+    }
+}
+
+impl Map {
+    /// The wall part of the tile at `pos` of `layer`, if any: its `WALL_PROPERTY`
+    /// overridden at runtime, see `set_runtime_property()`, or else the one of its tile,
+    /// see `TileSet::wall_part()`.
+    pub fn wall_part_at(&self, layer: usize, pos: IVec2) -> Option<WallPart> {
+        let tile = self.tile_ref_at(layer, pos)?;
+        if let Some(part) = self
+            .runtime_properties(layer, pos)
+            .and_then(|properties| properties.get_string(WALL_PROPERTY))
+        {
+            return WallPart::from_name(part);
+        }
+        self.tilesets.get(tile.tileset)?.wall_part(tile.id)
+    }
+
+    /// The row a wall tile at `pos` of `layer` stands on, in world tiles: going down
+    /// the top, then the front, up to `MAX_WALL_ROWS`. A top with no front below stands
+    /// on its own row. `None` if it's not a wall.
+    pub fn wall_base(&self, layer: usize, pos: IVec2) -> Option<i32> {
+        let mut part = self.wall_part_at(layer, pos)?;
+        let mut row = pos.y;
+        while row - pos.y < MAX_WALL_ROWS {
+            let below = self.wall_part_at(layer, ivec2(pos.x, row + 1));
+            match (part, below) {
+                (_, Some(WallPart::Front)) | (WallPart::Top, Some(WallPart::Top)) => {
+                    part = below.unwrap_or(part);
+                    row += 1;
+                }
+                _ => break,
+            }
+        }
+        Some(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedded::MemoryReader;
+    use crate::testing::tiny_map;
+    use tiled::PropertyValue;
+
+    #[test]
+    fn test_from_wang_id() {
+        // The bottom edge of a wall of color 1, over color 2.
+        assert_eq!(
+            WallPart::from_wang_id([1, 1, 0, 2, 2, 2, 0, 1], 1),
+            WallPart::Front
+        );
+        assert_eq!(WallPart::from_wang_id([1; 8], 1), WallPart::Top);
+        // Its top edge.
+        assert_eq!(
+            WallPart::from_wang_id([2, 2, 0, 1, 1, 1, 0, 2], 1),
+            WallPart::Top
+        );
+    }
+
+    #[test]
+    fn test_wall_base() {
+        let mut map = tiny_map();
+        let part = |part: &str| Some(PropertyValue::StringValue(part.to_string()));
+        map.set_runtime_property(0, ivec2(0, 0), WALL_PROPERTY, part("top"));
+        map.set_runtime_property(0, ivec2(0, 1), WALL_PROPERTY, part("front"));
+        map.set_runtime_property(0, ivec2(0, 2), WALL_PROPERTY, part("front"));
+        map.set_runtime_property(0, ivec2(1, 0), WALL_PROPERTY, part("top"));
+
+        assert_eq!(map.wall_part_at(0, ivec2(0, 1)), Some(WallPart::Front));
+        assert_eq!(map.wall_base(0, ivec2(0, 0)), Some(2));
+        assert_eq!(map.wall_base(0, ivec2(0, 1)), Some(2));
+        assert_eq!(map.wall_base(0, ivec2(1, 0)), Some(0));
+        assert_eq!(map.wall_base(0, ivec2(1, 1)), None);
+    }
+
+    #[test]
+    fn test_wall_parts() {
+        const WALLS_TSX: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="walls" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <image source="walls.png" width="32" height="32"/>
+ <tile id="0"><properties><property name="wall" value="top"/></properties></tile>
+ <tile id="1"><properties><property name="wall" value="front"/></properties></tile>
+ <wangsets>
+  <wangset name="cliffs" type="mixed" tile="-1">
+   <wangcolor name="cliff" color="#ff0000" tile="-1" probability="1">
+    <properties><property name="wall" type="bool" value="true"/></properties>
+   </wangcolor>
+   <wangcolor name="grass" color="#00ff00" tile="-1" probability="1"/>
+   <wangtile tileid="2" wangid="1,1,1,2,2,2,1,1"/>
+   <wangtile tileid="3" wangid="2,2,2,2,2,2,2,2"/>
+  </wangset>
+ </wangsets>
+</tileset>"##;
+        let reader = MemoryReader::new(&[("walls.tsx", WALLS_TSX.as_bytes())]);
+        let tileset =
+            tiled::Loader::with_cache_and_reader(tiled::DefaultResourceCache::new(), reader)
+                .load_tsx_tileset("walls.tsx")
+                .unwrap();
+
+        let parts = wall_parts(&tileset);
+        assert_eq!(parts.get(&0), Some(&WallPart::Top));
+        assert_eq!(parts.get(&1), Some(&WallPart::Front));
+        // The bottom edge of the cliff.
+        assert_eq!(parts.get(&2), Some(&WallPart::Front));
+        assert_eq!(parts.get(&3), None);
+        assert_eq!(tiny_map().tilesets["tiny"].wall_part(2), None);
+    }
+}
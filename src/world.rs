@@ -0,0 +1,227 @@
+use macroquad::math::{Rect, Vec2};
+
+use crate::animation_controller::AnimationController;
+use crate::camera::PixelCamera;
+#[cfg(feature = "collision")]
+use crate::events::MapEvent;
+use crate::map::Map;
+use crate::rng::MapRng;
+
+/// A map placed in a `World`.
+#[derive(Debug)]
+pub struct WorldMap {
+    pub map: Map,
+    /// The top-left corner of the map in the world, in pixels.
+    pub origin: Vec2,
+}
+
+/// Maps laid out side by side, like Tiled's worlds, e.g. the rooms of a dungeon.
+/// Each map keeps its own pixel coordinates, from its top-left corner; the helpers convert
+/// positions between maps when entities and cameras cross from one map to another.
+#[derive(Debug, Default)]
+pub struct World {
+    pub maps: Vec<WorldMap>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Places `map` with its top-left corner at `origin`, in world pixels.
+    /// Returns its index in `maps`.
+    pub fn add_map(&mut self, map: Map, origin: Vec2) -> usize {
+        self.maps.push(WorldMap { map, origin });
+        self.maps.len() - 1
+    }
+
+    /// The events of every map since the last call, with the index of their map,
+    /// see `Map::drain_events()`.
+    #[cfg(feature = "collision")]
+    pub fn drain_events(&mut self) -> Vec<(usize, MapEvent)> {
+        self.maps
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(index, world_map)| {
+                world_map
+                    .map
+                    .drain_events()
+                    .into_iter()
+                    .map(move |event| (index, event))
+            })
+            .collect()
+    }
+
+    /// Seeds the randomness of every map, see `Map::rng()`, from `seed` and its index,
+    /// e.g. for a new game. Save the maps' `MapRng`s to restore them.
+    pub fn set_seed(&mut self, seed: u64) {
+        let rng = MapRng::new(seed);
+        for (index, world_map) in self.maps.iter_mut().enumerate() {
+            world_map.map.set_rng(rng.derive(index as u64));
+        }
+    }
+
+    /// The bounds of `map` in the world, in pixels.
+    pub fn map_rect(&self, map: usize) -> Rect {
+        let world_map = &self.maps[map];
+        let size = world_map.map.size_px();
+        Rect::new(world_map.origin.x, world_map.origin.y, size.x, size.y)
+    }
+
+    /// The first map containing `world_px`.
+    pub fn map_at(&self, world_px: Vec2) -> Option<usize> {
+        (0..self.maps.len()).find(|map| self.map_rect(*map).contains(world_px))
+    }
+
+    /// Converts pixels of `map` into world pixels.
+    pub fn to_world_px(&self, map: usize, map_px: Vec2) -> Vec2 {
+        map_px + self.maps[map].origin
+    }
+
+    /// Converts world pixels into pixels of `map`.
+    pub fn to_map_px(&self, map: usize, world_px: Vec2) -> Vec2 {
+        world_px - self.maps[map].origin
+    }
+
+    /// Converts pixels of the map `from` into pixels of the map `to`.
+    pub fn rebase(&self, from: usize, to: usize, map_px: Vec2) -> Vec2 {
+        map_px + self.maps[from].origin - self.maps[to].origin
+    }
+
+    /// Moves the positions of `controller`, in tiles of the map `from`, into tiles of
+    /// the map `to`, so the entity keeps walking without a hitch. The maps should have
+    /// the same tile size.
+    pub fn transfer_controller(
+        &self,
+        controller: &mut AnimationController,
+        from: usize,
+        to: usize,
+    ) {
+        let offset =
+            (self.maps[from].origin - self.maps[to].origin) / self.maps[to].map.tile_size_px();
+        controller.translate((offset.x, offset.y));
+    }
+
+    /// Hands the controller of `entity` over from the map `from` to the map `to`, see
+    /// `Map::add_controller()` and `transfer_controller()`. Returns false if `from` has none.
+    #[cfg(feature = "collision")]
+    pub fn move_controller(&mut self, entity: u64, from: usize, to: usize) -> bool {
+        let Some(mut controller) = self.maps[from].map.remove_controller(entity) else {
+            return false;
+        };
+        self.maps[from].map.leave_zones(entity);
+        self.transfer_controller(&mut controller, from, to);
+        self.maps[to].map.add_controller(entity, controller);
+        true
+    }
+
+    /// Moves `camera` from pixels of the map `from` into pixels of the map `to`,
+    /// keeping the same view, even mid-pan. Follow with `PixelCamera::pan_to()`
+    /// for a brief transition to the new target.
+    pub fn transfer_camera(&self, camera: &mut PixelCamera, from: usize, to: usize) {
+        camera.translate(self.maps[from].origin - self.maps[to].origin);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coarsetime::Instant;
+    use macroquad::math::vec2;
+
+    use super::*;
+    use crate::testing::{mock_frames1243, mock_template, tiny_map};
+
+    /// Two 64x64 px maps, the second one right of the first, a tile lower.
+    fn two_rooms() -> World {
+        let mut world = World::new();
+        assert_eq!(world.add_map(tiny_map(), vec2(0., 0.)), 0);
+        assert_eq!(world.add_map(tiny_map(), vec2(64., 16.)), 1);
+        world
+    }
+
+    #[test]
+    fn test_world_px() {
+        let world = two_rooms();
+        assert_eq!(world.map_rect(1), Rect::new(64., 16., 64., 64.));
+        assert_eq!(world.map_at(vec2(10., 50.)), Some(0));
+        assert_eq!(world.map_at(vec2(70., 70.)), Some(1));
+        assert_eq!(world.map_at(vec2(70., 8.)), None);
+        assert_eq!(world.to_world_px(1, vec2(8., 8.)), vec2(72., 24.));
+        assert_eq!(world.to_map_px(1, vec2(72., 24.)), vec2(8., 8.));
+        assert_eq!(world.rebase(0, 1, vec2(70., 20.)), vec2(6., 4.));
+        assert_eq!(world.rebase(1, 0, vec2(6., 4.)), vec2(70., 20.));
+    }
+
+    #[test]
+    fn test_transfer() {
+        let world = two_rooms();
+        let start = Instant::now();
+        let template = mock_template(mock_frames1243(1..=4), 50);
+        let mut controller = AnimationController::new();
+        controller.add_animation(start, &template, (1., 0.), (5., 2.));
+        world.transfer_controller(&mut controller, 0, 1);
+        let frame = controller.update(start).unwrap();
+        assert_eq!(frame.position, (1., 1.));
+
+        // Same view, mid-pan too.
+        let mut camera = PixelCamera::new(vec2(60., 30.), 2.0);
+        camera.pan_to(start, vec2(70., 30.), coarsetime::Duration::from_secs(1));
+        let halfway = start + coarsetime::Duration::from_millis(500);
+        let view = camera.position_at(halfway);
+        world.transfer_camera(&mut camera, 0, 1);
+        assert_eq!(camera.position, vec2(6., 14.));
+        assert!(
+            camera
+                .position_at(halfway)
+                .distance(world.rebase(0, 1, view))
+                < 1e-3
+        );
+    }
+
+    #[test]
+    fn test_set_seed() {
+        let mut world = two_rooms();
+        world.set_seed(7);
+        let rngs: Vec<_> = world.maps.iter().map(|m| m.map.rng().clone()).collect();
+        assert_ne!(rngs[0], rngs[1]);
+        world.set_seed(8);
+        assert_ne!(world.maps[0].map.rng(), &rngs[0]);
+        world.set_seed(7);
+        let again: Vec<_> = world.maps.iter().map(|m| m.map.rng().clone()).collect();
+        assert_eq!(again, rngs);
+    }
+
+    #[cfg(feature = "collision")]
+    #[test]
+    fn test_move_controller() {
+        use crate::map::TileHandle;
+        use macroquad::math::ivec2;
+
+        let mut world = two_rooms();
+        let start = Instant::now();
+        let template = mock_template(mock_frames1243(1..=4), 50);
+        let mut controller = AnimationController::new();
+        controller.add_animation(start, &template, (1., 0.), (5., 2.));
+        world.maps[0].map.add_controller(3, controller);
+
+        assert!(world.move_controller(3, 0, 1));
+        assert!(!world.move_controller(3, 0, 1));
+        assert!(world.maps[0].map.controller(3).is_none());
+        let mut controller = world.maps[1].map.remove_controller(3).unwrap();
+        assert_eq!(controller.update(start).unwrap().position, (1., 1.));
+
+        for world_map in &mut world.maps {
+            world_map.map.enable_events();
+        }
+        let ground = world.maps[1].map.layer_by_name("ground").unwrap();
+        world.maps[1]
+            .map
+            .set_tile(ground, ivec2(1, 1), Some(TileHandle::new("tiny", 2)));
+        let events = world.drain_events();
+        assert!(matches!(
+            &events[..],
+            [(1, MapEvent::TileEdited { pos, .. })] if *pos == ivec2(1, 1)
+        ));
+        assert!(world.drain_events().is_empty());
+    }
+}